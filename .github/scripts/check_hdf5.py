@@ -0,0 +1,24 @@
+"""Opens a file written by the hdf5 example with libhdf5 through h5py."""
+
+import sys
+
+import h5py
+
+
+def text(value):
+    # Fixed length strings are read as bytes
+    return value.decode() if isinstance(value, bytes) else str(value)
+
+
+with h5py.File(sys.argv[1], "r") as f:
+    assert sorted(f.keys()) == ["gnss", "main", "slow"], list(f.keys())
+    assert text(f.attrs["Product"]).startswith("Blackbox flight data recorder")
+    main = f["main"]
+    assert len(main) > 0
+    lengths = {len(dataset) for dataset in main.values()}
+    assert len(lengths) == 1, lengths
+    gyro = main["gyroADC[1]"]
+    assert gyro.dtype == "int64"
+    assert text(gyro.attrs["unit"]) == "deg/s"
+    assert gyro.attrs["scale"] > 0
+    print(f"{len(main)} main fields of {lengths.pop()} values")
@@ -0,0 +1,73 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use fc_blackbox::{
+    synthetic::{SyntheticEncoding, SyntheticLog},
+    BlackboxReader, Header,
+};
+
+fn read_all(log: &[u8]) -> u64 {
+    let mut reader = BlackboxReader::from_bytes(log).unwrap();
+    while reader.next().is_some() {}
+    reader.stats().main_frames
+}
+
+fn header_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("header_parse");
+    for fields in [8, 64] {
+        let header = SyntheticLog {
+            fields,
+            ..Default::default()
+        }
+        .header();
+        group.throughput(Throughput::Bytes(header.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(fields), &header, |b, header| {
+            b.iter(|| Header::parse(header.as_bytes()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn frame_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_decode");
+    for encoding in [
+        SyntheticEncoding::SignedVB,
+        SyntheticEncoding::Tag8_8SVB,
+        SyntheticEncoding::Tag2_3S32,
+        SyntheticEncoding::Tag8_4S16,
+    ] {
+        let synthetic = SyntheticLog {
+            encoding,
+            ..Default::default()
+        };
+        let log = synthetic.generate();
+        group.throughput(Throughput::Elements(synthetic.frames as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{encoding:?}")),
+            &log,
+            |b, log| b.iter(|| read_all(log)),
+        );
+    }
+    group.finish();
+}
+
+fn log_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("log_throughput");
+    group.sample_size(20);
+    for corruption_rate in [0.0, 0.01] {
+        let log = SyntheticLog {
+            frames: 100_000,
+            corruption_rate,
+            ..Default::default()
+        }
+        .generate();
+        group.throughput(Throughput::Bytes(log.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("corruption", corruption_rate),
+            &log,
+            |b, log| b.iter(|| read_all(log)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, header_parse, frame_decode, log_throughput);
+criterion_main!(benches);
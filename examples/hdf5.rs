@@ -0,0 +1,16 @@
+//! Writes a blackbox log to an HDF5 file: `cargo run --features hdf5 --example hdf5 -- LOG.BFL out.h5`
+
+use std::{fs::File, io::BufWriter};
+
+use fc_blackbox::{export::hdf5, BlackboxReader};
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let (Some(input), Some(output)) = (args.next(), args.next()) else {
+        anyhow::bail!("usage: hdf5 <log> <output.h5>");
+    };
+    let bytes = std::fs::read(input)?;
+    let reader = BlackboxReader::from_bytes(&bytes)?;
+    hdf5::write(reader, &mut BufWriter::new(File::create(output)?))?;
+    Ok(())
+}
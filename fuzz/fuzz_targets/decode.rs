@@ -0,0 +1,19 @@
+//! Decodes logs with valid headers and arbitrary frames, also through the index and
+//! decimation paths.
+
+#![no_main]
+
+use fc_blackbox::{fuzz::FuzzLog, BlackboxReader};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|log: FuzzLog| {
+    let bytes = log.to_bytes();
+    let Ok(mut reader) = BlackboxReader::from_bytes(&bytes) else {
+        return;
+    };
+    while reader.next().is_some() {}
+
+    let mut reader = BlackboxReader::from_bytes(&bytes).unwrap();
+    reader.build_index();
+    while reader.next_every_nth(7).is_some() {}
+});
@@ -0,0 +1,12 @@
+//! Reads arbitrary bytes as a file with any number of logs, mostly exercising header parsing.
+
+#![no_main]
+
+use fc_blackbox::MultiSegmentBlackboxReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for mut reader in MultiSegmentBlackboxReader::from_bytes(data).flatten() {
+        while reader.next().is_some() {}
+    }
+});
@@ -0,0 +1,123 @@
+#ifndef FC_BLACKBOX_H
+#define FC_BLACKBOX_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Kind of frame whose fields are queried, passed as a `uint32_t`.
+ */
+typedef enum FcbFieldKind {
+  FCB_FIELD_KIND_MAIN = 0,
+  FCB_FIELD_KIND_SLOW,
+  FCB_FIELD_KIND_GNSS,
+} FcbFieldKind;
+
+/**
+ * Kind of the record returned by [`fcb_reader_next`].
+ */
+typedef enum FcbRecordKind {
+  /**
+   * No records left.
+   */
+  FCB_RECORD_KIND_END = 0,
+  FCB_RECORD_KIND_MAIN,
+  FCB_RECORD_KIND_SLOW,
+  FCB_RECORD_KIND_GNSS,
+  FCB_RECORD_KIND_EVENT,
+  FCB_RECORD_KIND_GARBAGE,
+} FcbRecordKind;
+
+/**
+ * Decoder over a log buffer, created by [`fcb_reader_open`].
+ */
+typedef struct FcbReader FcbReader;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Opens a reader over the first log in `data`, `NULL` if its header can't be parsed.
+ *
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes that stay valid and unchanged until the reader
+ * is freed with [`fcb_reader_free`].
+ */
+struct FcbReader *fcb_reader_open(const uint8_t *data, size_t len);
+
+/**
+ * Frees a reader returned by [`fcb_reader_open`]. Does nothing if `reader` is `NULL`.
+ *
+ * # Safety
+ *
+ * `reader` must be `NULL` or a reader that hasn't been freed yet.
+ */
+void fcb_reader_free(struct FcbReader *reader);
+
+/**
+ * Decodes the next record, [`FcbRecordKind::End`] once the log is exhausted.
+ *
+ * # Safety
+ *
+ * `reader` must be a valid reader.
+ */
+enum FcbRecordKind fcb_reader_next(struct FcbReader *reader);
+
+/**
+ * Values of the last record in field order, or the byte offset and length of garbage.
+ * Writes their count to `len`, events have none. Valid until the next call to
+ * [`fcb_reader_next`].
+ *
+ * # Safety
+ *
+ * `reader` must be a valid reader and `len` a valid pointer.
+ */
+const int64_t *fcb_reader_values(const struct FcbReader *reader, size_t *len);
+
+/**
+ * Time of the last main frame in microseconds, with rollovers of the logged 32-bit time
+ * accounted for.
+ *
+ * # Safety
+ *
+ * `reader` must be a valid reader.
+ */
+int64_t fcb_reader_time(const struct FcbReader *reader);
+
+/**
+ * Number of fields in frames of `kind`, one of [`FcbFieldKind`], 0 if it's out of range.
+ *
+ * # Safety
+ *
+ * `reader` must be a valid reader.
+ */
+size_t fcb_reader_field_count(const struct FcbReader *reader, uint32_t kind);
+
+/**
+ * Name of field `index` in frames of `kind`, one of [`FcbFieldKind`], `NULL` if either is
+ * out of range. Valid until the reader is freed.
+ *
+ * # Safety
+ *
+ * `reader` must be a valid reader.
+ */
+const char *fcb_reader_field_name(const struct FcbReader *reader, uint32_t kind, size_t index);
+
+/**
+ * Message of the last error on this thread, `NULL` if there was none. Valid until the next
+ * failing call.
+ */
+const char *fcb_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FC_BLACKBOX_H */
@@ -0,0 +1,211 @@
+//! Estimation of the craft orientation from gyro and accelerometer, which the logs don't
+//! store directly.
+//!
+//! This is the Mahony filter the firmware uses for its own attitude: gyro rates are
+//! integrated over the real time between main frames and the accelerometer slowly pulls the
+//! estimate towards gravity, correcting the roll and pitch drift. Yaw isn't corrected and
+//! drifts with the gyro bias. Axes are the ones of the logged sensors, with the
+//! accelerometer reading +1 g on Z when level.
+
+use crate::{
+    units::{AngularUnit, FieldScale, Units},
+    BlackboxReader, BlackboxReaderError, BlackboxRecord, FieldKind,
+};
+
+use super::axes;
+
+/// Gaps between main frames longer than this, in seconds, aren't integrated.
+pub(super) const MAX_DT: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    fn normalized(self) -> Self {
+        let norm = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        if norm == 0.0 {
+            return Self::IDENTITY;
+        }
+        Quaternion {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    /// Orientation with the given roll and pitch in radians and no yaw.
+    fn from_roll_pitch(roll: f64, pitch: f64) -> Self {
+        let (sr, cr) = (roll / 2.0).sin_cos();
+        let (sp, cp) = (pitch / 2.0).sin_cos();
+        Quaternion {
+            w: cr * cp,
+            x: sr * cp,
+            y: cr * sp,
+            z: -sr * sp,
+        }
+    }
+
+    /// Roll, pitch and yaw in degrees, applied in yaw, pitch, roll order.
+    pub fn euler(&self) -> [f64; 3] {
+        let Quaternion { w, x, y, z } = *self;
+        let roll = (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+        let pitch = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin();
+        let yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+        [roll.to_degrees(), pitch.to_degrees(), yaw.to_degrees()]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttitudeOptions {
+    /// Proportional gain of the accelerometer correction
+    pub kp: f64,
+    /// Integral gain of the accelerometer correction, estimating the gyro bias
+    pub ki: f64,
+    /// Accelerometer readings further than this from 1 g, like during hard maneuvers, aren't
+    /// used for correction
+    pub acc_tolerance: f64,
+}
+
+impl Default for AttitudeOptions {
+    fn default() -> Self {
+        Self {
+            kp: 0.25,
+            ki: 0.0,
+            acc_tolerance: 0.1,
+        }
+    }
+}
+
+/// Mahony filter state, fed one sample at a time.
+#[derive(Clone, Debug)]
+pub struct AttitudeEstimator {
+    options: AttitudeOptions,
+    q: Quaternion,
+    integral: [f64; 3],
+    initialized: bool,
+}
+
+impl AttitudeEstimator {
+    pub fn new(options: AttitudeOptions) -> Self {
+        Self {
+            options,
+            q: Quaternion::IDENTITY,
+            integral: [0.0; 3],
+            initialized: false,
+        }
+    }
+
+    pub fn attitude(&self) -> Quaternion {
+        self.q
+    }
+
+    /// Integrates `gyro` rates in rad/s over `dt` seconds, corrected by the `acc` reading in
+    /// g. The first accelerometer reading sets the initial roll and pitch.
+    pub fn update(&mut self, gyro: [f64; 3], acc: Option<[f64; 3]>, dt: f64) -> Quaternion {
+        let acc = acc.filter(|a| {
+            let norm = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+            (norm - 1.0).abs() <= self.options.acc_tolerance
+        });
+        if !self.initialized {
+            if let Some([ax, ay, az]) = acc {
+                let roll = ay.atan2(az);
+                let pitch = (-ax).atan2(ay.hypot(az));
+                self.q = Quaternion::from_roll_pitch(roll, pitch);
+                self.initialized = true;
+            }
+        }
+
+        let [mut gx, mut gy, mut gz] = gyro;
+        if let Some(acc) = acc {
+            let norm = (acc[0] * acc[0] + acc[1] * acc[1] + acc[2] * acc[2]).sqrt();
+            let [ax, ay, az] = acc.map(|a| a / norm);
+            let Quaternion { w, x, y, z } = self.q;
+            // Gravity direction according to the current estimate
+            let vx = 2.0 * (x * z - w * y);
+            let vy = 2.0 * (w * x + y * z);
+            let vz = w * w - x * x - y * y + z * z;
+            let error = [ay * vz - az * vy, az * vx - ax * vz, ax * vy - ay * vx];
+            if self.options.ki > 0.0 {
+                for (integral, error) in self.integral.iter_mut().zip(error) {
+                    *integral += self.options.ki * error * dt;
+                }
+            }
+            gx += self.options.kp * error[0] + self.integral[0];
+            gy += self.options.kp * error[1] + self.integral[1];
+            gz += self.options.kp * error[2] + self.integral[2];
+        }
+
+        let (hx, hy, hz) = (gx * dt / 2.0, gy * dt / 2.0, gz * dt / 2.0);
+        let Quaternion { w, x, y, z } = self.q;
+        self.q = Quaternion {
+            w: w - x * hx - y * hy - z * hz,
+            x: x + w * hx + y * hz - z * hy,
+            y: y + w * hy - x * hz + z * hx,
+            z: z + w * hz + x * hy - y * hx,
+        }
+        .normalized();
+        self.q
+    }
+}
+
+/// Estimated orientation at a main frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AttitudeSample {
+    /// Time of the main frame in microseconds
+    pub time: i64,
+    pub quaternion: Quaternion,
+    /// Roll, pitch and yaw in degrees
+    pub euler: [f64; 3],
+}
+
+/// Reads the rest of the log and estimates the orientation at every main frame, from
+/// `gyroADC` and `accSmooth` when logged.
+pub fn attitude(
+    mut reader: BlackboxReader<'_>,
+    options: &AttitudeOptions,
+) -> Result<Vec<AttitudeSample>, BlackboxReaderError> {
+    let header = reader.header.clone();
+    let units = Units::with_angular_unit(&header, AngularUnit::RadiansPerSecond);
+    let scales = units.scales(FieldKind::Main);
+    let gyro = axes(&header, scales, "gyroADC")
+        .ok_or_else(|| BlackboxReaderError::UnknownField("gyroADC[0]".to_owned()))?;
+    let acc = axes(&header, scales, "accSmooth");
+
+    let mut estimator = AttitudeEstimator::new(options.clone());
+    let mut samples = Vec::new();
+    let mut last_time = None;
+    while let Some(record) = reader.next() {
+        let BlackboxRecord::Main(_) = record else {
+            continue;
+        };
+        let (values, time) = (&reader.last_values, reader.last_widened_time);
+        let read = |axes: [(usize, FieldScale); 3]| axes.map(|(ix, scale)| scale.apply(values[ix]));
+        let dt = last_time
+            .map(|last| (time - last) as f64 / 1e6)
+            .filter(|dt| *dt > 0.0 && *dt <= MAX_DT)
+            .unwrap_or(0.0);
+        last_time = Some(time);
+        let quaternion = estimator.update(read(gyro), acc.map(read), dt);
+        samples.push(AttitudeSample {
+            time,
+            quaternion,
+            euler: quaternion.euler(),
+        });
+    }
+    Ok(samples)
+}
@@ -0,0 +1,228 @@
+//! Battery use of a flight: voltage sag under load, consumed capacity and efficiency.
+
+use std::ops::RangeInclusive;
+
+use crate::{
+    units::{FieldScale, Units},
+    BlackboxReader, BlackboxRecord, FieldKind, Header,
+};
+
+use super::distance;
+
+/// Cell count Betaflight stops auto detection at.
+const MAX_CELLS: u8 = 8;
+/// Fully charged cell voltage used when the log has no `vbatcellvoltage` header.
+const DEFAULT_MAX_CELL_VOLTAGE: f64 = 4.3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatteryOptions {
+    /// Number of throttle bins of [`BatteryReport::sag`]
+    pub throttle_bins: usize,
+    /// Raw throttle values mapped to the first and last throttle bin, `rcCommand[3]` by
+    /// default
+    pub throttle_range: RangeInclusive<f64>,
+    /// Number of cells, inferred from the first voltage reading if `None`
+    pub cells: Option<u8>,
+}
+
+impl Default for BatteryOptions {
+    fn default() -> Self {
+        Self {
+            throttle_bins: 10,
+            throttle_range: 1000.0..=2000.0,
+            cells: None,
+        }
+    }
+}
+
+/// Average pack voltage and current of the main frames in one throttle bin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SagBin {
+    /// Throttle at the center of the bin, from 0 to 1
+    pub throttle: f64,
+    pub frames: u64,
+    pub voltage: f64,
+    /// Start voltage minus the average voltage
+    pub sag: f64,
+    pub current: Option<f64>,
+}
+
+/// Battery use of a log returned by [`battery`]. Voltages are in V, currents in A.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BatteryReport {
+    pub cells: Option<u8>,
+    pub start_voltage: Option<f64>,
+    pub min_voltage: Option<f64>,
+    pub end_voltage: Option<f64>,
+    pub min_cell_voltage: Option<f64>,
+    pub end_cell_voltage: Option<f64>,
+    pub average_current: Option<f64>,
+    pub max_current: Option<f64>,
+    /// Capacity used according to the integrated current
+    pub consumed_mah: Option<f64>,
+    /// Capacity used according to the `energyCumulative` field, for firmwares logging it
+    pub logged_mah: Option<f64>,
+    /// Bins with at least one frame, from low to high throttle
+    pub sag: Vec<SagBin>,
+    /// Distance along the GNSS track in meters
+    pub distance: Option<f64>,
+    pub mah_per_km: Option<f64>,
+}
+
+/// Highest cell voltage from the `vbatcellvoltage` header, which is in 0.01 V in recent
+/// firmwares and in 0.1 V in older ones.
+fn max_cell_voltage(header: &Header) -> f64 {
+    match header.settings.vbat_cell_voltage {
+        Some(cell) if cell.max > 100 => cell.max as f64 / 100.0,
+        Some(cell) if cell.max > 0 => cell.max as f64 / 10.0,
+        _ => DEFAULT_MAX_CELL_VOLTAGE,
+    }
+}
+
+/// Cell count the way the flight controller detects it, from the voltage of a battery that
+/// was just plugged in.
+pub fn infer_cells(voltage: f64, max_cell_voltage: f64) -> Option<u8> {
+    if voltage <= 0.0 || max_cell_voltage <= 0.0 {
+        return None;
+    }
+    Some(((voltage / max_cell_voltage) as u8 + 1).min(MAX_CELLS))
+}
+
+fn main_field(header: &Header, units: &Units, names: &[&str]) -> Option<(usize, FieldScale)> {
+    let ix = names
+        .iter()
+        .find_map(|name| header.ip_fields.get(*name))?
+        .ix;
+    Some((ix, units.scales(FieldKind::Main)[ix]))
+}
+
+#[derive(Clone, Copy, Default)]
+struct Bin {
+    frames: u64,
+    voltage: f64,
+    current: f64,
+}
+
+/// Reads the rest of the log and reports its battery use.
+pub fn battery(mut reader: BlackboxReader<'_>, options: &BatteryOptions) -> BatteryReport {
+    let header = reader.header.clone();
+    let units = Units::new(&header);
+    let voltage = main_field(&header, &units, &["vbatLatest", "vbat"]);
+    let current = main_field(&header, &units, &["amperageLatest", "amperage"]);
+    let energy = header.ip_fields.get("energyCumulative").map(|f| f.ix);
+    let throttle = header.ip_fields.get("rcCommand[3]").map(|f| f.ix);
+    let coordinates = header
+        .g_fields
+        .get("GPS_coord[0]")
+        .zip(header.g_fields.get("GPS_coord[1]"))
+        .map(|(lat, lon)| (lat.ix, lon.ix, units.scales(FieldKind::GNSS)[lat.ix]));
+
+    let bins = options.throttle_bins.max(1);
+    let (low, high) = (
+        *options.throttle_range.start(),
+        *options.throttle_range.end(),
+    );
+    let mut sag = vec![Bin::default(); bins];
+    let mut report = BatteryReport::default();
+    let (mut current_sum, mut current_frames) = (0.0, 0);
+    let mut consumed = 0.0;
+    let mut energy_span: Option<(i64, i64)> = None;
+    let mut last_main: Option<(i64, Option<f64>)> = None;
+    let mut last_position: Option<(f64, f64)> = None;
+    let mut distance_sum = 0.0;
+
+    while let Some(record) = reader.next() {
+        match record {
+            BlackboxRecord::Main(_) => {}
+            BlackboxRecord::GNSS(view) => {
+                let Some((lat, lon, scale)) = coordinates else {
+                    continue;
+                };
+                let values = view.values();
+                if values[lat] == 0 && values[lon] == 0 {
+                    continue;
+                }
+                let position = (scale.apply(values[lat]), scale.apply(values[lon]));
+                if let Some(last) = last_position {
+                    distance_sum += distance(last, position);
+                }
+                last_position = Some(position);
+                continue;
+            }
+            _ => continue,
+        }
+        let (values, time) = (&reader.last_values, reader.last_widened_time);
+        let volts = voltage
+            .map(|(ix, scale)| scale.apply(values[ix]))
+            .filter(|v| *v > 0.0);
+        let amps = current.map(|(ix, scale)| scale.apply(values[ix]));
+
+        if let Some(volts) = volts {
+            report.start_voltage.get_or_insert(volts);
+            report.min_voltage = Some(report.min_voltage.map_or(volts, |v| v.min(volts)));
+            report.end_voltage = Some(volts);
+        }
+        if let Some(amps) = amps {
+            current_sum += amps;
+            current_frames += 1;
+            report.max_current = Some(report.max_current.map_or(amps, |a| a.max(amps)));
+        }
+        if let Some((last_time, Some(last_amps))) = last_main {
+            if time > last_time {
+                consumed += last_amps * (time - last_time) as f64 / 3.6e6;
+            }
+        }
+        last_main = Some((time, amps));
+        if let Some(ix) = energy {
+            let (first, _) = energy_span.get_or_insert((values[ix], values[ix]));
+            energy_span = Some((*first, values[ix]));
+        }
+        if let (Some(ix), Some(volts)) = (throttle, volts) {
+            let position = ((values[ix] as f64 - low) / (high - low)).clamp(0.0, 1.0);
+            let bin = &mut sag[((position * bins as f64) as usize).min(bins - 1)];
+            bin.frames += 1;
+            bin.voltage += volts;
+            bin.current += amps.unwrap_or(0.0);
+        }
+    }
+
+    report.cells = options.cells.or_else(|| {
+        report
+            .start_voltage
+            .and_then(|v| infer_cells(v, max_cell_voltage(&header)))
+    });
+    if let Some(cells) = report.cells.filter(|c| *c > 0) {
+        report.min_cell_voltage = report.min_voltage.map(|v| v / cells as f64);
+        report.end_cell_voltage = report.end_voltage.map(|v| v / cells as f64);
+    }
+    if current_frames > 0 {
+        report.average_current = Some(current_sum / current_frames as f64);
+        report.consumed_mah = Some(consumed);
+    }
+    report.logged_mah = energy_span.map(|(first, last)| (last - first) as f64);
+    report.sag = sag
+        .iter()
+        .enumerate()
+        .filter(|(_, bin)| bin.frames > 0)
+        .map(|(i, bin)| {
+            let voltage = bin.voltage / bin.frames as f64;
+            SagBin {
+                throttle: (i as f64 + 0.5) / bins as f64,
+                frames: bin.frames,
+                voltage,
+                sag: report.start_voltage.unwrap_or(voltage) - voltage,
+                current: current.map(|_| bin.current / bin.frames as f64),
+            }
+        })
+        .collect();
+    if last_position.is_some() {
+        report.distance = Some(distance_sum);
+        let mah = report.logged_mah.or(report.consumed_mah);
+        report.mah_per_km = mah
+            .filter(|_| distance_sum > 0.0)
+            .map(|mah| mah / (distance_sum / 1000.0));
+    }
+    report
+}
@@ -0,0 +1,155 @@
+//! Detection of probable crashes, for jumping to the interesting part of long logs.
+//!
+//! An impact is a main frame with the acceleration above [`CrashOptions::impact`]. It's
+//! reported as a crash when the gyro saturates or the craft is disarmed within
+//! [`CrashOptions::window`] after it, which leaves out hard landings and bumps the craft
+//! flew on from.
+
+use crate::{
+    frame::event::Frame,
+    units::{FieldScale, Units},
+    BlackboxReader, BlackboxReaderError, BlackboxRecord, DisarmReason, FieldKind,
+};
+
+use super::axes;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrashOptions {
+    /// Acceleration in g above which a main frame is an impact
+    pub impact: f64,
+    /// Gyro rate in deg/s above which an axis counts as saturated, a little below the 2000
+    /// deg/s range of most gyros
+    pub gyro_saturation: f64,
+    /// Time in microseconds after an impact in which saturation and disarms are looked for.
+    /// Impacts within it belong to the same crash.
+    pub window: i64,
+}
+
+impl Default for CrashOptions {
+    fn default() -> Self {
+        Self {
+            impact: 4.0,
+            gyro_saturation: 1900.0,
+            window: 2_000_000,
+        }
+    }
+}
+
+/// Probable crash found by [`crashes`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Crash {
+    /// Time of the first impact in microseconds
+    pub time: i64,
+    /// Highest acceleration in g within the window
+    pub peak_acceleration: f64,
+    /// Highest gyro rate of any axis in deg/s within the window
+    pub peak_rate: f64,
+    pub gyro_saturated: bool,
+    /// Time and reason of a disarm within the window
+    pub disarm: Option<(i64, DisarmReason)>,
+}
+
+/// Crash detection state, fed one record at a time.
+#[derive(Clone, Debug)]
+pub struct CrashDetector {
+    options: CrashOptions,
+    open: Option<Crash>,
+    crashes: Vec<Crash>,
+}
+
+impl CrashDetector {
+    pub fn new(options: CrashOptions) -> Self {
+        Self {
+            options,
+            open: None,
+            crashes: Vec::new(),
+        }
+    }
+
+    /// Closes the open impact if its window ended before `time`.
+    fn close_before(&mut self, time: i64) {
+        if let Some(crash) = self.open {
+            if time > crash.time + self.options.window || time < crash.time {
+                self.close();
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(crash) = self.open.take() {
+            if crash.gyro_saturated || crash.disarm.is_some() {
+                self.crashes.push(crash);
+            }
+        }
+    }
+
+    /// Adds a main frame with the `acc` reading in g, when logged, and `gyro` rates in deg/s.
+    pub fn push_frame(&mut self, time: i64, acc: Option<[f64; 3]>, gyro: [f64; 3]) {
+        self.close_before(time);
+        let acceleration = acc.map_or(0.0, |[x, y, z]| (x * x + y * y + z * z).sqrt());
+        if self.open.is_none() && acceleration > self.options.impact {
+            self.open = Some(Crash {
+                time,
+                peak_acceleration: 0.0,
+                peak_rate: 0.0,
+                gyro_saturated: false,
+                disarm: None,
+            });
+        }
+        if let Some(crash) = &mut self.open {
+            let rate = gyro.iter().fold(0f64, |max, rate| max.max(rate.abs()));
+            crash.peak_acceleration = crash.peak_acceleration.max(acceleration);
+            crash.peak_rate = crash.peak_rate.max(rate);
+            crash.gyro_saturated |= rate > self.options.gyro_saturation;
+        }
+    }
+
+    /// Adds a disarm, at the time of the last main frame.
+    pub fn push_disarm(&mut self, time: i64, reason: DisarmReason) {
+        self.close_before(time);
+        if let Some(crash) = &mut self.open {
+            crash.disarm.get_or_insert((time, reason));
+        }
+    }
+
+    /// Crashes found so far, including an impact whose window is still open.
+    pub fn finish(mut self) -> Vec<Crash> {
+        self.close();
+        self.crashes
+    }
+}
+
+/// Reads the rest of the log and returns the probable crashes, from `gyroADC`, `accSmooth`
+/// when logged and disarm events.
+pub fn crashes(
+    mut reader: BlackboxReader<'_>,
+    options: &CrashOptions,
+) -> Result<Vec<Crash>, BlackboxReaderError> {
+    let header = reader.header.clone();
+    let firmware = header.firmware_kind();
+    let units = Units::new(&header);
+    let scales = units.scales(FieldKind::Main);
+    let gyro = axes(&header, scales, "gyroADC")
+        .ok_or_else(|| BlackboxReaderError::UnknownField("gyroADC[0]".to_owned()))?;
+    let acc = axes(&header, scales, "accSmooth");
+
+    let mut detector = CrashDetector::new(options.clone());
+    while let Some(record) = reader.next() {
+        match record {
+            BlackboxRecord::Main(_) => {
+                let values = &reader.last_values;
+                let read = |axes: [(usize, FieldScale); 3]| {
+                    axes.map(|(ix, scale)| scale.apply(values[ix]))
+                };
+                detector.push_frame(reader.last_widened_time, acc.map(read), read(gyro));
+            }
+            BlackboxRecord::Event(Frame::Disarm(disarm)) => {
+                let reason = disarm.decoded_reason(firmware);
+                detector.push_disarm(reader.last_widened_time, reason);
+            }
+            _ => {}
+        }
+    }
+    Ok(detector.finish())
+}
@@ -0,0 +1,107 @@
+//! Filters for decoded fields, matching the ones of the firmware so its filtering can be
+//! replayed on raw gyro or D-term values.
+//!
+//! Filters run over whole columns, e.g. the output of
+//! [`Columns::scaled`](crate::Columns::scaled), at the rate from
+//! [`sample_rate`](super::spectrum::sample_rate).
+
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+/// Second order IIR filter, with the coefficients Betaflight computes for its biquad lowpass
+/// and notch filters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+            state: [0.0; 2],
+        }
+    }
+
+    /// Butterworth lowpass with the `cutoff` frequency in Hz, for values sampled at
+    /// `sample_rate` Hz.
+    pub fn lowpass(cutoff: f64, sample_rate: f64) -> Self {
+        let (sin, cos) = (2.0 * PI * cutoff / sample_rate).sin_cos();
+        let alpha = sin / (2.0 * FRAC_1_SQRT_2);
+        let b0 = (1.0 - cos) / 2.0;
+        Self::new([b0, 1.0 - cos, b0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    /// Notch at the `center` frequency in Hz, with the `cutoff` frequency below it where the
+    /// attenuation reaches 3 dB, like the notch settings of Betaflight.
+    pub fn notch(center: f64, cutoff: f64, sample_rate: f64) -> Self {
+        let q = center * cutoff / (center * center - cutoff * cutoff);
+        let (sin, cos) = (2.0 * PI * center / sample_rate).sin_cos();
+        let alpha = sin / (2.0 * q);
+        Self::new(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Filters one sample.
+    pub fn apply(&mut self, input: f64) -> f64 {
+        let output = self.b0 * input + self.state[0];
+        self.state[0] = self.b1 * input - self.a1 * output + self.state[1];
+        self.state[1] = self.b2 * input - self.a2 * output;
+        output
+    }
+
+    /// Clears the history, as if no sample had been filtered yet.
+    pub fn reset(&mut self) {
+        self.state = [0.0; 2];
+    }
+
+    /// Filters every value in order, continuing from the samples filtered before.
+    pub fn run(&mut self, values: &[f64]) -> Vec<f64> {
+        values.iter().map(|v| self.apply(*v)).collect()
+    }
+}
+
+/// Average of every value with the ones before it, over `window` values or fewer at the
+/// start.
+pub fn moving_average(values: &[f64], window: usize) -> Vec<f64> {
+    let window = window.max(1);
+    let mut sum = 0.0;
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            sum += value;
+            if i >= window {
+                sum -= values[i - window];
+            }
+            sum / (i + 1).min(window) as f64
+        })
+        .collect()
+}
+
+/// Change of `values` per second between every main frame and the one before it, using the
+/// real intervals from `time` in microseconds. The first value and values after frames with
+/// the same time are 0.
+pub fn derivative(time: &[i64], values: &[f64]) -> Vec<f64> {
+    let mut derivative = Vec::with_capacity(values.len());
+    derivative.extend(values.first().map(|_| 0.0));
+    derivative.extend(time.windows(2).zip(values.windows(2)).map(|(t, v)| {
+        let dt = (t[1] - t[0]) as f64 / 1e6;
+        if dt > 0.0 {
+            (v[1] - v[0]) / dt
+        } else {
+            0.0
+        }
+    }));
+    derivative
+}
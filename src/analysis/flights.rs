@@ -0,0 +1,133 @@
+//! Splitting of a log into flights, for logs that were recorded over several arms.
+
+use crate::{
+    frame::event::Frame, BlackboxReader, BlackboxRecord, DisarmReason, FieldKind, ReaderStats,
+};
+
+use super::{summary::SummaryBuilder, Summary};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlightOptions {
+    /// Longest time in microseconds between two main frames of the same flight
+    pub max_gap: i64,
+    /// Flights with fewer main frames are dropped, which leaves out the few frames decoded
+    /// from corrupted data at the end of some logs
+    pub min_main_frames: u64,
+}
+
+impl Default for FlightOptions {
+    fn default() -> Self {
+        Self {
+            max_gap: 1_000_000,
+            min_main_frames: 10,
+        }
+    }
+}
+
+/// What ended a [`Flight`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum FlightEnd {
+    Disarm(DisarmReason),
+    /// Logging was paused, the next flight starts when it resumed
+    LoggingResume,
+    /// The next main frame came later than [`FlightOptions::max_gap`], or went back in time
+    Gap,
+    EndOfLog,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Flight {
+    /// Time of the first main frame in microseconds
+    pub start: i64,
+    /// Time of the last main frame in microseconds
+    pub end: i64,
+    pub end_reason: FlightEnd,
+    /// Statistics of the records of the flight
+    pub summary: Summary,
+}
+
+struct OpenFlight {
+    builder: SummaryBuilder,
+    span: Option<(i64, i64)>,
+}
+
+/// Reads the rest of the log and splits it into flights at disarms, logging pauses and gaps
+/// in the main frames.
+pub fn flights(mut reader: BlackboxReader<'_>, options: &FlightOptions) -> Vec<Flight> {
+    let header = reader.header.clone();
+    let firmware = header.firmware_kind();
+    let mut flights = Vec::new();
+    let mut current: Option<OpenFlight> = None;
+    // Stats after the last record of the current flight
+    let mut stats = *reader.stats();
+
+    let mut close = |current: &mut Option<OpenFlight>, stats: &ReaderStats, end_reason| {
+        let Some(flight) = current.take() else {
+            return;
+        };
+        let summary = flight.builder.finish(&header, stats);
+        if let Some((start, end)) = flight
+            .span
+            .filter(|_| summary.main_frames >= options.min_main_frames)
+        {
+            flights.push(Flight {
+                start,
+                end,
+                end_reason,
+                summary,
+            });
+        }
+    };
+
+    while let Some(record) = reader.next() {
+        let kind = match record {
+            BlackboxRecord::Main(view)
+            | BlackboxRecord::Slow(view)
+            | BlackboxRecord::GNSS(view) => view.kind(),
+            BlackboxRecord::Event(event) => {
+                let end_reason = match event {
+                    Frame::Disarm(disarm) => {
+                        Some(FlightEnd::Disarm(disarm.decoded_reason(firmware)))
+                    }
+                    Frame::LoggingResume(_) => Some(FlightEnd::LoggingResume),
+                    Frame::EndOfLog => Some(FlightEnd::EndOfLog),
+                    _ => None,
+                };
+                if let Some(flight) = &mut current {
+                    flight.builder.push_event();
+                }
+                let Some(end_reason) = end_reason else {
+                    continue;
+                };
+                close(&mut current, reader.stats(), end_reason);
+                stats = *reader.stats();
+                continue;
+            }
+            BlackboxRecord::Garbage(_) => continue,
+        };
+        let time = reader.last_widened_time;
+        if kind == FieldKind::Main {
+            let gap = current
+                .as_ref()
+                .and_then(|flight| flight.span)
+                .is_some_and(|(_, end)| time < end || time - end > options.max_gap);
+            if gap {
+                close(&mut current, &stats, FlightEnd::Gap);
+            }
+        }
+        let flight = current.get_or_insert_with(|| OpenFlight {
+            builder: SummaryBuilder::new(&header, stats),
+            span: None,
+        });
+        flight.builder.push(kind, &reader.last_values, time);
+        if kind == FieldKind::Main {
+            let (start, _) = flight.span.get_or_insert((time, time));
+            flight.span = Some((*start, time));
+        }
+        stats = *reader.stats();
+    }
+    close(&mut current, &stats, FlightEnd::EndOfLog);
+    flights
+}
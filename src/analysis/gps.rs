@@ -0,0 +1,189 @@
+//! Metrics of the GNSS track of a flight: distance flown, range from home, speed and
+//! altitude, and a simplified path for drawing on maps.
+
+use crate::{
+    units::{FieldScale, Units},
+    BlackboxReader, BlackboxRecord, FieldKind, Header,
+};
+
+use super::{distance, EARTH_RADIUS};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GpsOptions {
+    /// Largest distance in meters between the track and its simplified polyline
+    pub tolerance: f64,
+}
+
+impl Default for GpsOptions {
+    fn default() -> Self {
+        Self { tolerance: 5.0 }
+    }
+}
+
+/// A GNSS position with fix.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GpsPoint {
+    /// Time of the latest main frame in microseconds
+    pub time: i64,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Meters above sea level
+    pub altitude: Option<f64>,
+    /// Ground speed in m/s
+    pub speed: Option<f64>,
+}
+
+impl GpsPoint {
+    fn position(&self) -> (f64, f64) {
+        (self.latitude, self.longitude)
+    }
+}
+
+/// GNSS metrics of a log returned by [`gps`]. Distances are in meters.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GpsReport {
+    /// Positions with fix
+    pub points: usize,
+    /// Length of the track
+    pub distance: f64,
+    /// Home position from the GNSS home frame, or the first position of the track for logs
+    /// without one
+    pub home: Option<(f64, f64)>,
+    pub max_distance_from_home: Option<f64>,
+    /// Highest ground speed in m/s
+    pub max_speed: Option<f64>,
+    /// Highest altitude above sea level
+    pub max_altitude: Option<f64>,
+    /// The track simplified to within [`GpsOptions::tolerance`]
+    pub polyline: Vec<GpsPoint>,
+}
+
+fn field(header: &Header, units: &Units, name: &str) -> Option<(usize, FieldScale)> {
+    let ix = header.g_fields.get(name)?.ix;
+    Some((ix, units.scales(FieldKind::GNSS)[ix]))
+}
+
+/// Position of `to` in meters east and north of `origin`, good enough over the size of a
+/// flight.
+pub(super) fn project(origin: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+    let x = (to.1 - origin.1).to_radians() * origin.0.to_radians().cos() * EARTH_RADIUS;
+    let y = (to.0 - origin.0).to_radians() * EARTH_RADIUS;
+    (x, y)
+}
+
+/// Distance in meters of `point` from the segment between `start` and `end`, all projected.
+fn segment_distance(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((point.0 - start.0) * dx + (point.1 - start.1) * dy) / length).clamp(0.0, 1.0)
+    };
+    (point.0 - start.0 - t * dx).hypot(point.1 - start.1 - t * dy)
+}
+
+/// Ramer-Douglas-Peucker simplification of a track, keeping its first and last point.
+pub fn simplify(track: &[GpsPoint], tolerance: f64) -> Vec<GpsPoint> {
+    let Some(first) = track.first() else {
+        return Vec::new();
+    };
+    let projected: Vec<_> = track
+        .iter()
+        .map(|p| project(first.position(), p.position()))
+        .collect();
+    let mut keep = vec![false; track.len()];
+    keep[0] = true;
+    keep[track.len() - 1] = true;
+    let mut stack = vec![(0, track.len() - 1)];
+    while let Some((start, end)) = stack.pop() {
+        let farthest = (start + 1..end)
+            .map(|i| {
+                (
+                    i,
+                    segment_distance(projected[i], projected[start], projected[end]),
+                )
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((i, _)) = farthest.filter(|(_, d)| *d > tolerance) {
+            keep[i] = true;
+            stack.push((start, i));
+            stack.push((i, end));
+        }
+    }
+    track
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(p, _)| *p)
+        .collect()
+}
+
+/// Reads the rest of the log and computes the metrics of its GNSS track.
+pub fn gps(mut reader: BlackboxReader<'_>, options: &GpsOptions) -> GpsReport {
+    let header = reader.header.clone();
+    let units = Units::new(&header);
+    let (Some(lat), Some(lon)) = (
+        field(&header, &units, "GPS_coord[0]"),
+        field(&header, &units, "GPS_coord[1]"),
+    ) else {
+        return GpsReport::default();
+    };
+    let altitude = field(&header, &units, "GPS_altitude");
+    let speed = field(&header, &units, "GPS_speed");
+    let home_fields = header
+        .h_fields
+        .get("GPS_home[0]")
+        .zip(header.h_fields.get("GPS_home[1]"))
+        .map(|(lat, lon)| (lat.ix, lon.ix));
+
+    let mut track = Vec::new();
+    let mut home = None;
+    while let Some(record) = reader.next() {
+        let BlackboxRecord::GNSS(view) = record else {
+            continue;
+        };
+        let values = view.values();
+        if values[lat.0] == 0 && values[lon.0] == 0 {
+            continue;
+        }
+        let get = |(ix, scale): (usize, FieldScale)| scale.apply(values[ix]);
+        let (latitude, longitude) = (get(lat), get(lon));
+        let (altitude, speed) = (altitude.map(get), speed.map(get));
+        track.push(GpsPoint {
+            time: reader.last_widened_time,
+            latitude,
+            longitude,
+            altitude,
+            speed,
+        });
+        if home.is_none() {
+            // Home coordinates are logged with the same scale as the positions
+            home = home_fields
+                .and_then(|(lat_ix, lon_ix)| {
+                    let home = reader.gnss_home();
+                    let (lat_raw, lon_raw) = (*home.get(lat_ix)?, *home.get(lon_ix)?);
+                    (lat_raw != 0 || lon_raw != 0)
+                        .then(|| (lat.1.apply(lat_raw), lon.1.apply(lon_raw)))
+                })
+                .or(Some(track[0].position()));
+        }
+    }
+
+    let max = |values: &mut dyn Iterator<Item = f64>| values.reduce(f64::max);
+    GpsReport {
+        points: track.len(),
+        distance: track
+            .windows(2)
+            .map(|pair| distance(pair[0].position(), pair[1].position()))
+            .sum(),
+        home,
+        max_distance_from_home: home
+            .and_then(|home| max(&mut track.iter().map(|p| distance(home, p.position())))),
+        max_speed: max(&mut track.iter().filter_map(|p| p.speed)),
+        max_altitude: max(&mut track.iter().filter_map(|p| p.altitude)),
+        polyline: simplify(&track, options.tolerance),
+    }
+}
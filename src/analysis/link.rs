@@ -0,0 +1,225 @@
+//! Radio link and stick input of a flight: failsafes, signal dropouts, RSSI and link quality,
+//! and how long the craft takes to follow the sticks.
+
+use crate::{
+    units::{FieldScale, Units},
+    BlackboxReader, BlackboxRecord, DebugMode, FailsafePhase, FieldKind,
+};
+
+use super::spectrum::rate;
+
+/// Highest raw `rssi` value, reported by the firmware as 0 to 1023.
+const RSSI_MAX: f64 = 1023.0;
+/// Slot of the uplink link quality in `CRSF_LINK_STATISTICS_UPLINK` debug mode.
+const LINK_QUALITY_SLOT: usize = 2;
+/// Setpoints that barely move don't give a usable latency.
+const MIN_SETPOINT_DEVIATION: f64 = 5.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkOptions {
+    /// Longest delay in milliseconds between setpoint and gyro looked for
+    pub max_latency: f64,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        Self { max_latency: 100.0 }
+    }
+}
+
+/// Time without a valid signal, according to the `rxSignalReceived` slow field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Dropout {
+    /// Time of the last main frame before the signal was lost, in microseconds
+    pub start: i64,
+    /// Time of the last main frame before the signal came back, or of the last main frame
+    /// of the log
+    pub end: i64,
+}
+
+impl Dropout {
+    pub fn duration(&self) -> i64 {
+        self.end - self.start
+    }
+}
+
+/// Lowest and average value of a signal level, in percent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SignalLevel {
+    pub min: f64,
+    pub average: f64,
+}
+
+/// Radio link of a log returned by [`link`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LinkReport {
+    /// Highest absolute `setpoint` per axis in deg/s, the rates the sticks reached
+    pub max_setpoint: [f64; 3],
+    /// Time of every failsafe, when the failsafe phase left idle
+    pub failsafes: Vec<i64>,
+    pub dropouts: Vec<Dropout>,
+    /// From the `rssi` main field, `None` if not logged or always zero
+    pub rssi: Option<SignalLevel>,
+    /// From the debug fields in `CRSF_LINK_STATISTICS_UPLINK` debug mode
+    pub link_quality: Option<SignalLevel>,
+    /// Delay in milliseconds from `setpoint` to `gyroADC` per axis with the highest
+    /// correlation, `None` for axes without enough stick movement
+    pub latency: [Option<f64>; 3],
+}
+
+#[derive(Default)]
+struct Level {
+    min: f64,
+    sum: f64,
+    count: u64,
+}
+
+impl Level {
+    fn push(&mut self, value: f64) {
+        self.min = if self.count == 0 {
+            value
+        } else {
+            self.min.min(value)
+        };
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn finish(&self) -> Option<SignalLevel> {
+        (self.count > 0).then(|| SignalLevel {
+            min: self.min,
+            average: self.sum / self.count as f64,
+        })
+    }
+}
+
+/// Delay in samples of `response` behind `input` with the highest correlation, up to
+/// `max_lag`.
+fn best_lag(input: &[f64], response: &[f64], max_lag: usize) -> Option<usize> {
+    let n = input.len().min(response.len());
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let deviation = |values: &[f64], mean: f64| {
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+    };
+    if n <= max_lag + 1 {
+        return None;
+    }
+    let (input, response) = (&input[..n], &response[..n]);
+    let (input_mean, response_mean) = (mean(input), mean(response));
+    if deviation(input, input_mean) < MIN_SETPOINT_DEVIATION {
+        return None;
+    }
+    (0..=max_lag)
+        .map(|lag| {
+            let pairs = input[..n - lag].iter().zip(&response[lag..]);
+            let covariance: f64 = pairs
+                .map(|(i, r)| (i - input_mean) * (r - response_mean))
+                .sum();
+            (lag, covariance / (n - lag) as f64)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .filter(|(_, covariance)| *covariance > 0.0)
+        .map(|(lag, _)| lag)
+}
+
+/// Reads the rest of the log and reports on its radio link and stick response.
+pub fn link(mut reader: BlackboxReader<'_>, options: &LinkOptions) -> LinkReport {
+    let header = reader.header.clone();
+    let firmware = header.firmware_kind();
+    let units = Units::new(&header);
+    let scales = units.scales(FieldKind::Main);
+    let main = |name: String| header.ip_fields.get(&name).map(|f| (f.ix, scales[f.ix]));
+    let setpoint: [Option<(usize, FieldScale)>; 3] =
+        [0, 1, 2].map(|axis| main(format!("setpoint[{}]", axis)));
+    let gyro = [0, 1, 2].map(|axis| main(format!("gyroADC[{}]", axis)));
+    let rssi = header.ip_fields.get("rssi").map(|f| f.ix);
+    let link_quality = (header.debug_mode() == Some(DebugMode::CrsfLinkStatisticsUplink))
+        .then(|| {
+            header
+                .ip_fields
+                .get(&format!("debug[{}]", LINK_QUALITY_SLOT))
+        })
+        .flatten()
+        .map(|f| f.ix);
+    let failsafe_phase = header.s_fields.get("failsafePhase").map(|f| f.ix);
+    let signal_received = header.s_fields.get("rxSignalReceived").map(|f| f.ix);
+
+    let mut report = LinkReport::default();
+    let mut time = Vec::new();
+    let mut setpoints: [Vec<f64>; 3] = Default::default();
+    let mut rates: [Vec<f64>; 3] = Default::default();
+    let (mut rssi_level, mut link_quality_level) = (Level::default(), Level::default());
+    let mut rssi_logged = false;
+    let mut in_failsafe = false;
+    let mut lost_since = None;
+
+    while let Some(record) = reader.next() {
+        let kind = match record {
+            BlackboxRecord::Main(_) => FieldKind::Main,
+            BlackboxRecord::Slow(_) => FieldKind::Slow,
+            _ => continue,
+        };
+        let (values, now) = (&reader.last_values, reader.last_widened_time);
+        match kind {
+            FieldKind::Main => {
+                time.push(now);
+                for axis in 0..3 {
+                    let Some((ix, scale)) = setpoint[axis] else {
+                        continue;
+                    };
+                    let value = scale.apply(values[ix]);
+                    report.max_setpoint[axis] = report.max_setpoint[axis].max(value.abs());
+                    if let Some((ix, scale)) = gyro[axis] {
+                        setpoints[axis].push(value);
+                        rates[axis].push(scale.apply(values[ix]));
+                    }
+                }
+                if let Some(ix) = rssi {
+                    rssi_logged |= values[ix] != 0;
+                    rssi_level.push(values[ix] as f64 / RSSI_MAX * 100.0);
+                }
+                if let Some(ix) = link_quality {
+                    link_quality_level.push(values[ix] as f64);
+                }
+            }
+            _ => {
+                if let Some(ix) = failsafe_phase {
+                    let phase = FailsafePhase::decode(firmware, values[ix] as u32);
+                    let failsafe = phase != FailsafePhase::Idle;
+                    if failsafe && !in_failsafe {
+                        report.failsafes.push(now);
+                    }
+                    in_failsafe = failsafe;
+                }
+                if let Some(ix) = signal_received {
+                    match (values[ix] != 0, lost_since) {
+                        (false, None) => lost_since = Some(now),
+                        (true, Some(start)) => {
+                            report.dropouts.push(Dropout { start, end: now });
+                            lost_since = None;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    if let Some(start) = lost_since {
+        let end = reader.last_widened_time;
+        report.dropouts.push(Dropout { start, end });
+    }
+
+    report.rssi = rssi_level.finish().filter(|_| rssi_logged);
+    report.link_quality = link_quality_level.finish();
+    if let Some(rate) = rate(&time) {
+        let max_lag = (options.max_latency / 1000.0 * rate).round() as usize;
+        for axis in 0..3 {
+            report.latency[axis] = best_lag(&setpoints[axis], &rates[axis], max_lag)
+                .map(|lag| lag as f64 / rate * 1000.0);
+        }
+    }
+    report
+}
@@ -0,0 +1,47 @@
+//! Analyses of decoded logs, the building blocks of tuning and review tools.
+
+pub mod attitude;
+pub mod battery;
+pub mod crash;
+pub mod filter;
+mod flights;
+pub mod gps;
+pub mod link;
+pub mod noise;
+pub mod path;
+pub mod pid;
+pub mod resample;
+pub mod spectrum;
+mod summary;
+pub mod sync;
+pub mod throttle;
+
+pub use flights::{flights, Flight, FlightEnd, FlightOptions};
+pub use summary::{summary, FieldSummary, Percentiles, Summary};
+
+use crate::{units::FieldScale, Header};
+
+/// Mean radius of the Earth in meters.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Great-circle distance in meters between two `(latitude, longitude)` positions in degrees.
+pub(crate) fn distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (to.1 - from.1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Indices and scales of the three axes of an array field like `gyroADC`, if all are logged.
+pub(crate) fn axes(
+    header: &Header,
+    scales: &[FieldScale],
+    name: &str,
+) -> Option<[(usize, FieldScale); 3]> {
+    let field = |axis: usize| {
+        let ix = header.ip_fields.get(&format!("{}[{}]", name, axis))?.ix;
+        Some((ix, scales[ix]))
+    };
+    Some([field(0)?, field(1)?, field(2)?])
+}
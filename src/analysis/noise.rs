@@ -0,0 +1,149 @@
+//! Vibration and noise metrics of a flight, comparable across flights and frames.
+//!
+//! Noise is what's left of a field after removing the motion below
+//! [`NoiseOptions::cutoff`] with a [`Biquad`] lowpass, reported as its RMS. Stick inputs and
+//! the craft's response stay well below the cutoff, while prop wash, bent props and frame
+//! resonances are above it.
+
+use crate::{BlackboxReader, BlackboxReaderError, DebugMode, Header, OutputLayout};
+
+use super::{filter::Biquad, spectrum::sample_rate};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseOptions {
+    /// Frequency in Hz below which changes are flight rather than noise
+    pub cutoff: f64,
+    /// Gyro rate in deg/s at which a reading counts as clipped
+    pub gyro_clip: f64,
+    /// Motor output from 0 to 1 at which a motor counts as saturated
+    pub motor_saturation: f64,
+}
+
+impl Default for NoiseOptions {
+    fn default() -> Self {
+        Self {
+            cutoff: 50.0,
+            gyro_clip: 1990.0,
+            motor_saturation: 0.99,
+        }
+    }
+}
+
+/// Noise of a log returned by [`noise`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NoiseReport {
+    pub main_frames: usize,
+    /// RMS noise of `gyroADC` per axis in deg/s, after the firmware filters
+    pub gyro: [f64; 3],
+    /// RMS noise of the gyro before filtering, from `gyroUnfilt` or the debug fields in
+    /// `GYRO_SCALED` debug mode, `None` if neither was logged
+    pub gyro_unfiltered: Option<[f64; 3]>,
+    /// RMS noise of every motor output, as a fraction of the output range
+    pub motor_ripple: Vec<f64>,
+    /// Main frames with a gyro axis at or above [`NoiseOptions::gyro_clip`]
+    pub gyro_clipped: u64,
+    /// Main frames with a motor at or above [`NoiseOptions::motor_saturation`]
+    pub motor_saturated: u64,
+    /// Average RMS noise over the gyro axes in deg/s, before filtering when logged, so that it
+    /// reflects the vibrations rather than the filter settings
+    pub score: f64,
+}
+
+/// Names of the three axes of `name`, if they are all logged.
+fn axes(header: &Header, name: &str) -> Option<Vec<String>> {
+    let names: Vec<String> = (0..3).map(|axis| format!("{}[{}]", name, axis)).collect();
+    names
+        .iter()
+        .all(|name| header.ip_fields.contains_key(name))
+        .then_some(names)
+}
+
+/// RMS of `values` above `cutoff`.
+fn rms_noise(values: &[f64], cutoff: f64, rate: Option<f64>) -> f64 {
+    let Some(rate) = rate.filter(|rate| cutoff > 0.0 && cutoff < rate / 2.0) else {
+        return 0.0;
+    };
+    let mut lowpass = Biquad::lowpass(cutoff, rate);
+    let Some(first) = values.first() else {
+        return 0.0;
+    };
+    let mut sum = 0.0;
+    for value in values {
+        // Filtering from the first value on avoids the step response from zero
+        let value = value - first;
+        let noise = value - lowpass.apply(value);
+        sum += noise * noise;
+    }
+    (sum / values.len() as f64).sqrt()
+}
+
+/// Reads the rest of the log and measures its gyro noise, motor ripple and saturation.
+pub fn noise(
+    reader: BlackboxReader<'_>,
+    options: &NoiseOptions,
+) -> Result<NoiseReport, BlackboxReaderError> {
+    let header = reader.header.clone();
+    let gyro = axes(&header, "gyroADC")
+        .ok_or_else(|| BlackboxReaderError::UnknownField("gyroADC[0]".to_owned()))?;
+    // Debug fields of `GYRO_SCALED` are already in deg/s
+    let unfiltered = axes(&header, "gyroUnfilt").or_else(|| {
+        (header.debug_mode() == Some(DebugMode::GyroScaled))
+            .then(|| axes(&header, "debug"))
+            .flatten()
+    });
+    let outputs = OutputLayout::new(&header);
+    let motors: Vec<String> = (0..outputs.motor_count())
+        .map(|i| format!("motor[{}]", i))
+        .collect();
+
+    let names: Vec<&String> = gyro
+        .iter()
+        .chain(unfiltered.iter().flatten())
+        .chain(&motors)
+        .collect();
+    let columns = reader.decode_columns(&names)?;
+    let rate = sample_rate(&columns);
+    let scaled = |names: &[String]| -> Vec<Vec<f64>> {
+        names
+            .iter()
+            .map(|name| columns.scaled(name).unwrap_or_default())
+            .collect()
+    };
+    let noise = |fields: &[Vec<f64>]| -> Vec<f64> {
+        fields
+            .iter()
+            .map(|values| rms_noise(values, options.cutoff, rate))
+            .collect()
+    };
+
+    let gyro = scaled(&gyro);
+    let motors: Vec<Vec<f64>> = motors
+        .iter()
+        .map(|name| {
+            let raw = columns.get(name).unwrap_or_default();
+            raw.iter()
+                .map(|v| outputs.normalize_motor(*v) as f64)
+                .collect()
+        })
+        .collect();
+    let per_axis = |noise: Vec<f64>| [noise[0], noise[1], noise[2]];
+    let gyro_noise = per_axis(noise(&gyro));
+    let unfiltered_noise = unfiltered.map(|names| per_axis(noise(&scaled(&names))));
+    let score = unfiltered_noise.unwrap_or(gyro_noise).iter().sum::<f64>() / 3.0;
+
+    let count = |fields: &[Vec<f64>], over: &dyn Fn(f64) -> bool| {
+        (0..columns.len())
+            .filter(|i| fields.iter().any(|values| over(values[*i])))
+            .count() as u64
+    };
+    Ok(NoiseReport {
+        main_frames: columns.len(),
+        gyro: gyro_noise,
+        gyro_unfiltered: unfiltered_noise,
+        motor_ripple: noise(&motors),
+        gyro_clipped: count(&gyro, &|rate| rate.abs() >= options.gyro_clip),
+        motor_saturated: count(&motors, &|output| output >= options.motor_saturation),
+        score,
+    })
+}
@@ -0,0 +1,207 @@
+//! Flight path in 3D, from GNSS positions, baro altitude and the estimated attitude, for
+//! drawing flights in 3D views.
+//!
+//! Positions are in meters east, north and up (ENU) of the first position with fix. Up comes
+//! from `BaroAlt` when logged, which is smoother than the GNSS altitude over a flight, and
+//! from `GPS_altitude` otherwise.
+
+use crate::{
+    units::{AngularUnit, FieldScale, Units},
+    BlackboxReader, BlackboxRecord, FieldKind, Header,
+};
+
+use super::{
+    attitude::{AttitudeEstimator, AttitudeOptions, MAX_DT},
+    axes,
+    gps::project,
+};
+
+/// Noise of a constant velocity Kalman filter smoothing a [`FlightPath`], as standard
+/// deviations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Smoothing {
+    /// Acceleration of the craft in m/s² not explained by its velocity
+    pub acceleration: f64,
+    /// Error of the GNSS positions in meters
+    pub position: f64,
+    /// Error of the altitude in meters
+    pub altitude: f64,
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Self {
+            acceleration: 3.0,
+            position: 2.5,
+            altitude: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathOptions {
+    /// Kalman smoothing of the positions, `None` for the positions as logged
+    pub smoothing: Option<Smoothing>,
+    pub attitude: AttitudeOptions,
+}
+
+/// Position and orientation of the craft at a GNSS frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PathPoint {
+    /// Time of the latest main frame in microseconds
+    pub time: i64,
+    pub east: f64,
+    pub north: f64,
+    pub up: f64,
+    /// Roll, pitch and yaw in degrees at the latest main frame, `None` without a gyro
+    pub attitude: Option<[f64; 3]>,
+}
+
+/// 3D path of a log returned by [`flight_path`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FlightPath {
+    /// Latitude and longitude in degrees of the first position with fix
+    pub origin: Option<(f64, f64)>,
+    /// Altitude the up axis starts from, in meters above sea level for `GPS_altitude` or
+    /// above the arming point for `BaroAlt`
+    pub origin_altitude: Option<f64>,
+    pub points: Vec<PathPoint>,
+}
+
+/// Constant velocity Kalman filter of one axis.
+#[derive(Clone, Debug)]
+struct Kalman {
+    position: f64,
+    velocity: f64,
+    covariance: [[f64; 2]; 2],
+}
+
+impl Kalman {
+    fn new(position: f64, noise: f64) -> Self {
+        Self {
+            position,
+            velocity: 0.0,
+            // The velocity is unknown at first
+            covariance: [[noise * noise, 0.0], [0.0, 100.0]],
+        }
+    }
+
+    fn update(&mut self, dt: f64, measured: f64, acceleration: f64, noise: f64) -> f64 {
+        let [[p00, p01], [p10, p11]] = self.covariance;
+        let q = acceleration * acceleration;
+        let (dt2, dt3, dt4) = (dt * dt, dt * dt * dt, dt * dt * dt * dt);
+        self.position += self.velocity * dt;
+        let p00 = p00 + dt * (p10 + p01) + dt2 * p11 + q * dt4 / 4.0;
+        let p01 = p01 + dt * p11 + q * dt3 / 2.0;
+        let p10 = p10 + dt * p11 + q * dt3 / 2.0;
+        let p11 = p11 + q * dt2;
+
+        let gain = [p00 / (p00 + noise * noise), p10 / (p00 + noise * noise)];
+        let innovation = measured - self.position;
+        self.position += gain[0] * innovation;
+        self.velocity += gain[1] * innovation;
+        self.covariance = [
+            [(1.0 - gain[0]) * p00, (1.0 - gain[0]) * p01],
+            [p10 - gain[1] * p00, p11 - gain[1] * p01],
+        ];
+        self.position
+    }
+}
+
+fn field(
+    header: &Header,
+    units: &Units,
+    kind: FieldKind,
+    name: &str,
+) -> Option<(usize, FieldScale)> {
+    let ix = match kind {
+        FieldKind::GNSS => header.g_fields.get(name)?.ix,
+        _ => header.ip_fields.get(name)?.ix,
+    };
+    Some((ix, units.scales(kind)[ix]))
+}
+
+/// Reads the rest of the log and reconstructs its path, one point per GNSS frame with fix.
+pub fn flight_path(mut reader: BlackboxReader<'_>, options: &PathOptions) -> FlightPath {
+    let header = reader.header.clone();
+    let units = Units::with_angular_unit(&header, AngularUnit::RadiansPerSecond);
+    let gnss = |name| field(&header, &units, FieldKind::GNSS, name);
+    let (Some(lat), Some(lon)) = (gnss("GPS_coord[0]"), gnss("GPS_coord[1]")) else {
+        return FlightPath::default();
+    };
+    let gps_altitude = gnss("GPS_altitude");
+    let baro = field(&header, &units, FieldKind::Main, "BaroAlt");
+    let scales = units.scales(FieldKind::Main);
+    let gyro = axes(&header, scales, "gyroADC");
+    let acc = axes(&header, scales, "accSmooth");
+
+    let mut estimator = AttitudeEstimator::new(options.attitude.clone());
+    let mut attitude = None;
+    let mut last_main = None;
+    let mut altitude = None;
+    let mut path = FlightPath::default();
+    let mut filters: Option<[Kalman; 3]> = None;
+    let mut last_point: Option<i64> = None;
+    while let Some(record) = reader.next() {
+        let values = match record {
+            BlackboxRecord::Main(_) => None,
+            BlackboxRecord::GNSS(view) => Some(view.values().to_vec()),
+            _ => continue,
+        };
+        let time = reader.last_widened_time;
+        let Some(values) = values else {
+            let values = &reader.last_values;
+            let read = |(ix, scale): (usize, FieldScale)| scale.apply(values[ix]);
+            if let Some(gyro) = gyro {
+                let dt = last_main
+                    .map(|last| (time - last) as f64 / 1e6)
+                    .filter(|dt| *dt > 0.0 && *dt <= MAX_DT)
+                    .unwrap_or(0.0);
+                last_main = Some(time);
+                let quaternion = estimator.update(gyro.map(read), acc.map(|a| a.map(read)), dt);
+                attitude = Some(quaternion.euler());
+            }
+            altitude = baro.map(read);
+            continue;
+        };
+        if values[lat.0] == 0 && values[lon.0] == 0 {
+            continue;
+        }
+        let get = |(ix, scale): (usize, FieldScale)| scale.apply(values[ix]);
+        let position = (get(lat), get(lon));
+        let up = match baro {
+            Some(_) => altitude,
+            None => gps_altitude.map(get),
+        };
+        let Some(up) = up else {
+            continue;
+        };
+        let origin = *path.origin.get_or_insert(position);
+        let origin_altitude = *path.origin_altitude.get_or_insert(up);
+        let (east, north) = project(origin, position);
+        let mut point = PathPoint {
+            time,
+            east,
+            north,
+            up: up - origin_altitude,
+            attitude,
+        };
+        if let Some(smoothing) = &options.smoothing {
+            let noise = [smoothing.position, smoothing.position, smoothing.altitude];
+            let measured = [point.east, point.north, point.up];
+            let dt = last_point.map_or(0.0, |last| (time - last).max(0) as f64 / 1e6);
+            let filters = filters.get_or_insert_with(|| {
+                [0, 1, 2].map(|axis| Kalman::new(measured[axis], noise[axis]))
+            });
+            let [east, north, up] = [0, 1, 2].map(|axis| {
+                filters[axis].update(dt, measured[axis], smoothing.acceleration, noise[axis])
+            });
+            (point.east, point.north, point.up) = (east, north, up);
+        }
+        last_point = Some(time);
+        path.points.push(point);
+    }
+    path
+}
@@ -0,0 +1,200 @@
+//! Breakdown of the PID controller output of a flight, for tuning tools.
+//!
+//! The terms come from the logged `axisP`, `axisI`, `axisD` and `axisF` fields, in the units
+//! of the PID sum the firmware limits with `pidsum_limit`. Most firmwares don't log a D term
+//! for yaw, so its statistics are `None`.
+
+use crate::{BlackboxReader, BlackboxReaderError, Header};
+
+/// PID sum limits of Betaflight when the header doesn't have them, for roll and pitch and for
+/// yaw.
+const DEFAULT_PIDSUM_LIMIT: f64 = 500.0;
+const DEFAULT_PIDSUM_LIMIT_YAW: f64 = 400.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PidOptions {
+    /// Fraction of the PID sum limit of the axis above which the I term counts as wound up
+    pub windup: f64,
+    /// Shortest time in microseconds the I term has to stay above [`PidOptions::windup`] to
+    /// be reported
+    pub min_windup: i64,
+    /// Change of the F term between two main frames above which it counts as a spike
+    pub feedforward_spike: f64,
+}
+
+impl Default for PidOptions {
+    fn default() -> Self {
+        Self {
+            windup: 0.2,
+            min_windup: 100_000,
+            feedforward_spike: 50.0,
+        }
+    }
+}
+
+/// Size of a PID term or of the tracking error over a log.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TermStats {
+    pub mean_abs: f64,
+    pub rms: f64,
+    pub max_abs: f64,
+}
+
+impl TermStats {
+    fn new(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let count = values.len() as f64;
+        Some(Self {
+            mean_abs: values.iter().map(|v| v.abs()).sum::<f64>() / count,
+            rms: (values.iter().map(|v| v * v).sum::<f64>() / count).sqrt(),
+            max_abs: values.iter().fold(0f64, |max, v| max.max(v.abs())),
+        })
+    }
+}
+
+/// Time the I term of an axis stayed above [`PidOptions::windup`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Windup {
+    /// Time of the first main frame above the threshold, in microseconds
+    pub start: i64,
+    /// Time of the last main frame above the threshold
+    pub end: i64,
+    /// Highest absolute I term during the episode
+    pub peak: f64,
+}
+
+impl Windup {
+    pub fn duration(&self) -> i64 {
+        self.end - self.start
+    }
+}
+
+/// PID controller output of one axis.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AxisPid {
+    pub p: Option<TermStats>,
+    pub i: Option<TermStats>,
+    pub d: Option<TermStats>,
+    pub f: Option<TermStats>,
+    /// Share of the P, I, D and F terms in the summed absolute output of the axis, from 0 to 1
+    pub share: [f64; 4],
+    /// `setpoint` minus `gyroADC` in deg/s, `None` if either isn't logged
+    pub error: Option<TermStats>,
+    pub windups: Vec<Windup>,
+    /// Main frames where the F term changed by more than [`PidOptions::feedforward_spike`]
+    pub feedforward_spikes: u64,
+    /// Largest change of the F term between two main frames
+    pub max_feedforward_step: f64,
+}
+
+/// PID breakdown of a log returned by [`pid`], per roll, pitch and yaw axis.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PidReport {
+    pub main_frames: usize,
+    pub axes: [AxisPid; 3],
+}
+
+/// PID sum limit of `axis` from the header, or the Betaflight default.
+fn pidsum_limit(header: &Header, axis: usize) -> f64 {
+    let (name, default) = if axis == 2 {
+        ("pidsum_limit_yaw", DEFAULT_PIDSUM_LIMIT_YAW)
+    } else {
+        ("pidsum_limit", DEFAULT_PIDSUM_LIMIT)
+    };
+    header
+        .other_headers
+        .get(name)
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|limit: &f64| *limit > 0.0)
+        .unwrap_or(default)
+}
+
+/// Episodes of `values` above `threshold` lasting at least `min_duration`.
+fn windups(time: &[i64], values: &[f64], threshold: f64, min_duration: i64) -> Vec<Windup> {
+    let mut windups = Vec::new();
+    let mut open: Option<Windup> = None;
+    for (time, value) in time.iter().zip(values) {
+        let value = value.abs();
+        if value < threshold {
+            windups.extend(open.take().filter(|w| w.duration() >= min_duration));
+            continue;
+        }
+        let windup = open.get_or_insert(Windup {
+            start: *time,
+            end: *time,
+            peak: 0.0,
+        });
+        windup.end = *time;
+        windup.peak = windup.peak.max(value);
+    }
+    windups.extend(open.filter(|w| w.duration() >= min_duration));
+    windups
+}
+
+/// Reads the rest of the log and breaks down the PID output of every axis.
+pub fn pid(
+    reader: BlackboxReader<'_>,
+    options: &PidOptions,
+) -> Result<PidReport, BlackboxReaderError> {
+    let header = reader.header.clone();
+    let term = |term: char, axis: usize| format!("axis{}[{}]", term, axis);
+    let mut names = Vec::new();
+    for axis in 0..3 {
+        names.extend("PIDF".chars().map(|t| term(t, axis)));
+        names.push(format!("setpoint[{}]", axis));
+        names.push(format!("gyroADC[{}]", axis));
+    }
+    names.retain(|name| header.ip_fields.contains_key(name));
+    if !names.iter().any(|name| name.starts_with("axis")) {
+        return Err(BlackboxReaderError::UnknownField("axisP[0]".to_owned()));
+    }
+    let columns = reader.decode_columns(&names)?;
+    let raw = |name: &str| -> Option<Vec<f64>> {
+        columns
+            .get(name)
+            .map(|values| values.iter().map(|v| *v as f64).collect())
+    };
+
+    let mut report = PidReport {
+        main_frames: columns.len(),
+        ..Default::default()
+    };
+    for (axis, out) in report.axes.iter_mut().enumerate() {
+        let terms = "PIDF"
+            .chars()
+            .map(|t| raw(&term(t, axis)))
+            .collect::<Vec<_>>();
+        let [p, i, d, f] = [0, 1, 2, 3].map(|t| terms[t].as_deref().and_then(TermStats::new));
+        (out.p, out.i, out.d, out.f) = (p, i, d, f);
+
+        let total: f64 = [p, i, d, f].iter().flatten().map(|s| s.mean_abs).sum();
+        if total > 0.0 {
+            out.share = [p, i, d, f].map(|s| s.map_or(0.0, |s| s.mean_abs / total));
+        }
+
+        let setpoint = columns.scaled(&format!("setpoint[{}]", axis));
+        let gyro = columns.scaled(&format!("gyroADC[{}]", axis));
+        if let (Some(setpoint), Some(gyro)) = (setpoint, gyro) {
+            let error: Vec<f64> = setpoint.iter().zip(&gyro).map(|(s, g)| s - g).collect();
+            out.error = TermStats::new(&error);
+        }
+
+        if let Some(i) = &terms[1] {
+            let threshold = options.windup * pidsum_limit(&header, axis);
+            out.windups = windups(&columns.time, i, threshold, options.min_windup);
+        }
+        if let Some(f) = &terms[3] {
+            for step in f.windows(2).map(|w| (w[1] - w[0]).abs()) {
+                out.feedforward_spikes += (step > options.feedforward_spike) as u64;
+                out.max_feedforward_step = out.max_feedforward_step.max(step);
+            }
+        }
+    }
+    Ok(report)
+}
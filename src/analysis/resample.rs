@@ -0,0 +1,116 @@
+//! Resampling of decoded fields to a uniform time grid, for tools that assume a fixed sample
+//! rate while main frames come irregularly because of the P interval and lost frames.
+
+use crate::Columns;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Value of the latest main frame at or before the sample
+    ZeroOrderHold,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResampleOptions {
+    /// Samples per second of the output
+    pub rate: f64,
+    pub interpolation: Interpolation,
+    /// Samples between main frames further apart than this, in microseconds, are marked as
+    /// gaps
+    pub max_gap: i64,
+}
+
+impl Default for ResampleOptions {
+    fn default() -> Self {
+        Self {
+            rate: 1000.0,
+            interpolation: Interpolation::Linear,
+            max_gap: 100_000,
+        }
+    }
+}
+
+/// Fields on a uniform time grid, returned by [`resample`]. Values are converted with the
+/// field scales like [`Columns::scaled`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resampled {
+    names: Vec<String>,
+    /// Time of every sample in microseconds, from the first main frame on
+    pub time: Vec<i64>,
+    /// Whether a sample falls between main frames further apart than
+    /// [`ResampleOptions::max_gap`]. Its values are interpolated all the same.
+    pub gap: Vec<bool>,
+    values: Vec<Vec<f64>>,
+}
+
+impl Resampled {
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[f64]> {
+        let ix = self.names.iter().position(|n| n == name)?;
+        Some(&self.values[ix])
+    }
+}
+
+/// Resamples every field of `columns` at [`ResampleOptions::rate`], from the first to the
+/// last main frame. Pick the fields with
+/// [`BlackboxReader::decode_columns`](crate::BlackboxReader::decode_columns).
+pub fn resample(columns: &Columns, options: &ResampleOptions) -> Resampled {
+    let names = columns.names().to_vec();
+    let scaled: Vec<_> = names
+        .iter()
+        .map(|name| columns.scaled(name).unwrap_or_default())
+        .collect();
+    let source = &columns.time;
+    let mut resampled = Resampled {
+        values: vec![Vec::new(); names.len()],
+        names,
+        time: Vec::new(),
+        gap: Vec::new(),
+    };
+    let (Some(&first), Some(&last)) = (source.first(), source.last()) else {
+        return resampled;
+    };
+    if options.rate <= 0.0 {
+        return resampled;
+    }
+
+    let step = 1e6 / options.rate;
+    let mut j = 0;
+    for k in 0.. {
+        let t = first + (k as f64 * step).round() as i64;
+        if t > last {
+            break;
+        }
+        while j + 1 < source.len() && source[j + 1] <= t {
+            j += 1;
+        }
+        let next = (j + 1 < source.len()).then_some(j + 1);
+        resampled.time.push(t);
+        resampled
+            .gap
+            .push(next.is_some_and(|n| source[n] - source[j] > options.max_gap));
+        for (out, values) in resampled.values.iter_mut().zip(&scaled) {
+            let value = match (options.interpolation, next) {
+                (Interpolation::Linear, Some(n)) if source[n] > source[j] => {
+                    let fraction = (t - source[j]) as f64 / (source[n] - source[j]) as f64;
+                    values[j] + (values[n] - values[j]) * fraction
+                }
+                _ => values[j],
+            };
+            out.push(value);
+        }
+    }
+    resampled
+}
@@ -0,0 +1,254 @@
+//! Amplitude spectra of main frame fields, for looking at gyro and D-term noise and at what
+//! the filters leave of it.
+//!
+//! Fields are cut into overlapping windows, each window has its mean removed and is tapered
+//! by the [`Window`] function before the FFT. Amplitudes are single sided and in the unit of
+//! the field, so a sine of amplitude 10 deg/s shows as a peak of about 10 at its frequency.
+
+use std::{f64::consts::PI, ops::RangeInclusive};
+
+use crate::Columns;
+
+/// Taper applied to every window before the FFT.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    #[default]
+    Hann,
+}
+
+impl Window {
+    fn coefficients(&self, len: usize) -> Vec<f64> {
+        match self {
+            Window::Rectangular => vec![1.0; len],
+            Window::Hann => (0..len)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / len as f64).cos())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpectrumOptions {
+    /// Number of samples per window, rounded up to a power of two
+    pub window_size: usize,
+    /// Fraction of a window shared with the next one, from 0 to below 1
+    pub overlap: f64,
+    pub window: Window,
+    /// Number of throttle bins of a [`Spectrogram`]
+    pub throttle_bins: usize,
+    /// Raw throttle values mapped to the first and last throttle bin, `rcCommand[3]` by
+    /// default
+    pub throttle_range: RangeInclusive<f64>,
+}
+
+impl Default for SpectrumOptions {
+    fn default() -> Self {
+        Self {
+            window_size: 1024,
+            overlap: 0.5,
+            window: Window::Hann,
+            throttle_bins: 50,
+            throttle_range: 1000.0..=2000.0,
+        }
+    }
+}
+
+/// Amplitude spectrum averaged over all windows of a field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrum {
+    /// Frequency of every bin in Hz, from 0 to the Nyquist frequency
+    pub frequencies: Vec<f64>,
+    pub amplitudes: Vec<f64>,
+}
+
+impl Spectrum {
+    /// Frequency and amplitude of the highest bin, leaving out the DC bin.
+    pub fn peak(&self) -> Option<(f64, f64)> {
+        self.frequencies
+            .iter()
+            .zip(&self.amplitudes)
+            .skip(1)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(f, a)| (*f, *a))
+    }
+}
+
+/// Amplitude spectra of a field averaged per throttle bin, the matrix behind throttle vs
+/// frequency noise plots.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrogram {
+    pub frequencies: Vec<f64>,
+    /// Throttle at the center of every bin, from 0 to 1
+    pub throttle: Vec<f64>,
+    /// Spectrum of every throttle bin, all zero for bins without windows
+    pub amplitudes: Vec<Vec<f64>>,
+    /// Number of windows averaged in every throttle bin
+    pub windows: Vec<usize>,
+}
+
+/// In-place radix-2 FFT, `re` and `im` have the same power of two length.
+pub(crate) fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Main frame rate of a log in Hz, from the median interval between frames.
+pub fn sample_rate(columns: &Columns) -> Option<f64> {
+    rate(&columns.time)
+}
+
+/// Rate in Hz of samples taken at `time` in microseconds, from their median interval.
+pub(crate) fn rate(time: &[i64]) -> Option<f64> {
+    let mut intervals: Vec<_> = time
+        .windows(2)
+        .map(|t| t[1] - t[0])
+        .filter(|dt| *dt > 0)
+        .collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    let mid = intervals.len() / 2;
+    let (_, median, _) = intervals.select_nth_unstable(mid);
+    Some(1e6 / *median as f64)
+}
+
+/// Windowed FFT with its settings resolved for one log.
+struct Analyzer {
+    size: usize,
+    hop: usize,
+    coefficients: Vec<f64>,
+    frequencies: Vec<f64>,
+}
+
+impl Analyzer {
+    fn new(columns: &Columns, options: &SpectrumOptions) -> Option<Self> {
+        let size = options.window_size.max(2).next_power_of_two();
+        let rate = sample_rate(columns)?;
+        let hop = ((size as f64 * (1.0 - options.overlap.clamp(0.0, 0.99))) as usize).max(1);
+        Some(Self {
+            size,
+            hop,
+            coefficients: options.window.coefficients(size),
+            frequencies: (0..=size / 2)
+                .map(|k| k as f64 * rate / size as f64)
+                .collect(),
+        })
+    }
+
+    /// Calls `f` with the start of every window of `values` and its amplitude spectrum.
+    fn run(&self, values: &[f64], mut f: impl FnMut(usize, &[f64])) {
+        let gain: f64 = self.coefficients.iter().sum();
+        let mut re = vec![0.0; self.size];
+        let mut im = vec![0.0; self.size];
+        let mut amplitudes = vec![0.0; self.size / 2 + 1];
+        for start in (0..values.len().saturating_sub(self.size - 1)).step_by(self.hop) {
+            let window = &values[start..start + self.size];
+            let mean = window.iter().sum::<f64>() / self.size as f64;
+            for ((re, value), c) in re.iter_mut().zip(window).zip(&self.coefficients) {
+                *re = (value - mean) * c;
+            }
+            im.fill(0.0);
+            fft(&mut re, &mut im);
+            for (k, amplitude) in amplitudes.iter_mut().enumerate() {
+                // Energy of the negative frequencies is folded into the positive ones
+                let single_sided = if k == 0 || k == self.size / 2 {
+                    1.0
+                } else {
+                    2.0
+                };
+                *amplitude = re[k].hypot(im[k]) * single_sided / gain;
+            }
+            f(start, &amplitudes);
+        }
+    }
+}
+
+/// Average amplitude spectrum of the `field` column, `None` if the field wasn't decoded or is
+/// shorter than a window.
+pub fn spectrum(columns: &Columns, field: &str, options: &SpectrumOptions) -> Option<Spectrum> {
+    let values = columns.scaled(field)?;
+    let analyzer = Analyzer::new(columns, options)?;
+    let mut sum = vec![0.0; analyzer.frequencies.len()];
+    let mut windows = 0;
+    analyzer.run(&values, |_, amplitudes| {
+        windows += 1;
+        for (sum, amplitude) in sum.iter_mut().zip(amplitudes) {
+            *sum += amplitude;
+        }
+    });
+    (windows > 0).then(|| Spectrum {
+        amplitudes: sum.into_iter().map(|s| s / windows as f64).collect(),
+        frequencies: analyzer.frequencies,
+    })
+}
+
+/// Amplitude spectra of the `field` column binned by the average of the raw `throttle`
+/// column over every window.
+pub fn spectrogram(
+    columns: &Columns,
+    field: &str,
+    throttle: &str,
+    options: &SpectrumOptions,
+) -> Option<Spectrogram> {
+    let values = columns.scaled(field)?;
+    let throttle = columns.get(throttle)?;
+    let analyzer = Analyzer::new(columns, options)?;
+    let bins = options.throttle_bins.max(1);
+    let (low, high) = (
+        *options.throttle_range.start(),
+        *options.throttle_range.end(),
+    );
+    let mut amplitudes = vec![vec![0.0; analyzer.frequencies.len()]; bins];
+    let mut windows = vec![0; bins];
+    analyzer.run(&values, |start, spectrum| {
+        let window = &throttle[start..start + analyzer.size];
+        let average = window.iter().sum::<i64>() as f64 / window.len() as f64;
+        let position = ((average - low) / (high - low)).clamp(0.0, 1.0);
+        let bin = ((position * bins as f64) as usize).min(bins - 1);
+        windows[bin] += 1;
+        for (sum, amplitude) in amplitudes[bin].iter_mut().zip(spectrum) {
+            *sum += amplitude;
+        }
+    });
+    for (row, windows) in amplitudes.iter_mut().zip(&windows) {
+        if *windows > 0 {
+            row.iter_mut().for_each(|a| *a /= *windows as f64);
+        }
+    }
+    Some(Spectrogram {
+        frequencies: analyzer.frequencies,
+        throttle: (0..bins).map(|b| (b as f64 + 0.5) / bins as f64).collect(),
+        amplitudes,
+        windows,
+    })
+}
@@ -0,0 +1,294 @@
+//! Statistics of a whole log, like the ones `blackbox_decode` prints after decoding.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{frame::event, BlackboxReader, FieldKind, FieldView, Header, ReaderStats, Visitor};
+
+/// Values of a field at the 1st, 5th, 25th, 50th, 75th, 95th and 99th percentile, using the
+/// nearest logged value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Percentiles {
+    pub p1: i64,
+    pub p5: i64,
+    pub p25: i64,
+    pub p50: i64,
+    pub p75: i64,
+    pub p95: i64,
+    pub p99: i64,
+}
+
+/// Statistics of the raw values of one field.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FieldSummary {
+    pub name: String,
+    pub min: i64,
+    pub max: i64,
+    pub mean: f64,
+    /// Population standard deviation
+    pub stddev: f64,
+    pub percentiles: Percentiles,
+}
+
+/// Summary of a log returned by [`summary`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Summary {
+    /// Time between the first and the last main frame in microseconds, `None` for logs
+    /// without a `time` field or main frames
+    pub duration: Option<i64>,
+    pub main_frames: u64,
+    pub slow_frames: u64,
+    pub gnss_frames: u64,
+    pub events: u64,
+    /// What the reader had to skip, with [`ReaderStats::lost_percentage`] giving the share
+    /// of main frames lost
+    pub stats: ReaderStats,
+    /// Main, slow and GNSS fields in header order, fields of frames that never appeared are
+    /// left out
+    pub main: Vec<FieldSummary>,
+    pub slow: Vec<FieldSummary>,
+    pub gnss: Vec<FieldSummary>,
+}
+
+impl Summary {
+    /// Share of expected main frames that were lost, from 0 to 100.
+    pub fn lost_percentage(&self) -> f64 {
+        self.stats.lost_percentage()
+    }
+}
+
+/// Running statistics of one field. Values are counted by distinct value rather than kept,
+/// which bounds memory by the range of the field instead of the length of the log.
+#[derive(Default)]
+struct Accumulator {
+    count: u64,
+    sum: f64,
+    sum_of_squares: f64,
+    counts: HashMap<i64, u64>,
+}
+
+impl Accumulator {
+    fn push(&mut self, value: i64) {
+        self.count += 1;
+        self.sum += value as f64;
+        self.sum_of_squares += value as f64 * value as f64;
+        *self.counts.entry(value).or_default() += 1;
+    }
+
+    fn finish(self, name: &str) -> FieldSummary {
+        let mut counts: Vec<_> = self.counts.into_iter().collect();
+        counts.sort_unstable();
+        let count = self.count as f64;
+        let mean = self.sum / count;
+        let percentile = |p: f64| {
+            let rank = ((p / 100.0 * count).ceil() as u64).max(1);
+            let mut seen = 0;
+            counts
+                .iter()
+                .find(|(_, n)| {
+                    seen += n;
+                    seen >= rank
+                })
+                .map_or(0, |(value, _)| *value)
+        };
+        FieldSummary {
+            name: name.to_owned(),
+            min: counts.first().map_or(0, |(value, _)| *value),
+            max: counts.last().map_or(0, |(value, _)| *value),
+            mean,
+            stddev: (self.sum_of_squares / count - mean * mean).max(0.0).sqrt(),
+            percentiles: Percentiles {
+                p1: percentile(1.0),
+                p5: percentile(5.0),
+                p25: percentile(25.0),
+                p50: percentile(50.0),
+                p75: percentile(75.0),
+                p95: percentile(95.0),
+                p99: percentile(99.0),
+            },
+        }
+    }
+}
+
+fn finish<'a>(
+    accumulators: Vec<Accumulator>,
+    names: impl Iterator<Item = &'a str>,
+) -> Vec<FieldSummary> {
+    accumulators
+        .into_iter()
+        .zip(names)
+        .filter(|(accumulator, _)| accumulator.count > 0)
+        .map(|(accumulator, name)| accumulator.finish(name))
+        .collect()
+}
+
+/// [`Summary`] of the records pushed so far, for summarizing parts of a log.
+pub(crate) struct SummaryBuilder {
+    main: Vec<Accumulator>,
+    slow: Vec<Accumulator>,
+    gnss: Vec<Accumulator>,
+    main_frames: u64,
+    slow_frames: u64,
+    gnss_frames: u64,
+    events: u64,
+    time_span: Option<(i64, i64)>,
+    /// Reader stats when the first record was pushed
+    stats: ReaderStats,
+}
+
+impl SummaryBuilder {
+    pub(crate) fn new(header: &Header, stats: ReaderStats) -> Self {
+        let accumulators = |len: usize| (0..len).map(|_| Accumulator::default()).collect();
+        Self {
+            main: accumulators(header.ip_fields_in_order.len()),
+            slow: accumulators(header.s_fields_in_order.len()),
+            gnss: accumulators(header.g_fields_in_order.len()),
+            main_frames: 0,
+            slow_frames: 0,
+            gnss_frames: 0,
+            events: 0,
+            time_span: None,
+            stats,
+        }
+    }
+
+    /// Adds the values of a frame, `time` being the time of the latest main frame.
+    pub(crate) fn push(&mut self, kind: FieldKind, values: &[i64], time: i64) {
+        let accumulators = match kind {
+            FieldKind::Main => {
+                self.main_frames += 1;
+                let (first, _) = self.time_span.get_or_insert((time, time));
+                self.time_span = Some((*first, time));
+                &mut self.main
+            }
+            FieldKind::Slow => {
+                self.slow_frames += 1;
+                &mut self.slow
+            }
+            FieldKind::GNSS => {
+                self.gnss_frames += 1;
+                &mut self.gnss
+            }
+        };
+        for (accumulator, value) in accumulators.iter_mut().zip(values) {
+            accumulator.push(*value);
+        }
+    }
+
+    pub(crate) fn push_event(&mut self) {
+        self.events += 1;
+    }
+
+    /// Summary of the pushed records, `stats` being the reader stats after the last one.
+    pub(crate) fn finish(self, header: &Header, stats: &ReaderStats) -> Summary {
+        let has_time = header.ip_fields.contains_key("time");
+        Summary {
+            duration: self
+                .time_span
+                .filter(|_| has_time)
+                .map(|(first, last)| last - first),
+            main_frames: self.main_frames,
+            slow_frames: self.slow_frames,
+            gnss_frames: self.gnss_frames,
+            events: self.events,
+            stats: stats.since(&self.stats),
+            main: finish(
+                self.main,
+                header.ip_fields_in_order.iter().map(|f| &f.name[..]),
+            ),
+            slow: finish(
+                self.slow,
+                header.s_fields_in_order.iter().map(|f| &f.name[..]),
+            ),
+            gnss: finish(
+                self.gnss,
+                header.g_fields_in_order.iter().map(|f| &f.name[..]),
+            ),
+        }
+    }
+}
+
+impl Visitor for SummaryBuilder {
+    fn main(&mut self, values: FieldView<'_>, time: i64) {
+        self.push(FieldKind::Main, values.values(), time);
+    }
+
+    fn gnss(&mut self, values: FieldView<'_>, time: i64) {
+        self.push(FieldKind::GNSS, values.values(), time);
+    }
+
+    fn slow(&mut self, values: FieldView<'_>, time: i64) {
+        self.push(FieldKind::Slow, values.values(), time);
+    }
+
+    fn event(&mut self, _event: &event::Frame, _time: i64) {
+        self.push_event();
+    }
+}
+
+/// Reads the rest of the log and summarizes every field.
+pub fn summary(mut reader: BlackboxReader<'_>) -> Summary {
+    let header = reader.header.clone();
+    let mut builder = SummaryBuilder::new(&header, *reader.stats());
+    reader.visit(&mut builder);
+    builder.finish(&header, reader.stats())
+}
+
+/// Prints the frame counts and a table of the field statistics.
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(duration) = self.duration {
+            writeln!(f, "Duration: {:.3} s", duration as f64 / 1e6)?;
+        }
+        writeln!(
+            f,
+            "Frames: {} main, {} slow, {} GNSS, {} events",
+            self.main_frames, self.slow_frames, self.gnss_frames, self.events
+        )?;
+        writeln!(
+            f,
+            "Lost: {} iterations ({:.2}%), {} resyncs, {} bytes skipped",
+            self.stats.missing_iterations,
+            self.lost_percentage(),
+            self.stats.resyncs,
+            self.stats.garbage_bytes
+        )?;
+        let name_width = [&self.main, &self.slow, &self.gnss]
+            .into_iter()
+            .flatten()
+            .map(|field| field.name.len())
+            .max()
+            .unwrap_or(0);
+        for (title, fields) in [
+            ("Main", &self.main),
+            ("Slow", &self.slow),
+            ("GNSS", &self.gnss),
+        ] {
+            if fields.is_empty() {
+                continue;
+            }
+            writeln!(
+                f,
+                "\n{:name_width$} {:>11} {:>11} {:>13} {:>13} {:>11} {:>11} {:>11}",
+                title, "min", "max", "mean", "stddev", "p5", "median", "p95",
+            )?;
+            for field in fields {
+                writeln!(
+                    f,
+                    "{:name_width$} {:>11} {:>11} {:>13.3} {:>13.3} {:>11} {:>11} {:>11}",
+                    field.name,
+                    field.min,
+                    field.max,
+                    field.mean,
+                    field.stddev,
+                    field.percentiles.p5,
+                    field.percentiles.p50,
+                    field.percentiles.p95,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+//! Synchronization of a log with footage from an HD camera, by finding the time offset at
+//! which the motion of the camera best matches the gyro of the log.
+//!
+//! The motion can come from the gyro of the camera or from optical flow, in any unit: both
+//! signals are compared after removing their mean, with a correlation normalized over the
+//! part where they overlap. Cameras are rarely mounted along the axes of the flight
+//! controller, so [`sync`] compares the magnitude of the rotation rate, which doesn't depend
+//! on the mounting.
+
+use crate::{BlackboxReader, BlackboxReaderError};
+
+use super::spectrum::fft;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncOptions {
+    /// Rate in Hz both signals are resampled at before comparing them
+    pub rate: f64,
+    /// Largest offset in seconds looked for, in either direction
+    pub max_offset: f64,
+    /// Shortest time in seconds the signals have to overlap at an offset
+    pub min_overlap: f64,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            rate: 100.0,
+            max_offset: 30.0,
+            min_overlap: 5.0,
+        }
+    }
+}
+
+/// Offset found by [`align`] or [`sync`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Sync {
+    /// Seconds to add to the time of the signal to get the time of the reference
+    pub offset: f64,
+    /// Normalized correlation at the offset, from -1 to 1
+    pub correlation: f64,
+}
+
+/// `values` at `time` in seconds linearly interpolated at `len` samples from `start`.
+fn uniform(time: &[f64], values: &[f64], start: f64, rate: f64, len: usize) -> Vec<f64> {
+    let mut j = 0;
+    (0..len)
+        .map(|k| {
+            let t = start + k as f64 / rate;
+            while j + 2 < time.len() && time[j + 1] <= t {
+                j += 1;
+            }
+            let (t0, t1) = (time[j], time[(j + 1).min(time.len() - 1)]);
+            if t1 > t0 {
+                let fraction = ((t - t0) / (t1 - t0)).clamp(0.0, 1.0);
+                values[j] + (values[j + 1] - values[j]) * fraction
+            } else {
+                values[j]
+            }
+        })
+        .collect()
+}
+
+/// Samples of a signal at `rate` from its first to last time, with the mean removed.
+fn prepare(time: &[f64], values: &[f64], rate: f64) -> Option<Vec<f64>> {
+    let len = time.len().min(values.len());
+    let (time, values) = (&time[..len], &values[..len]);
+    let (start, end) = (*time.first()?, *time.last()?);
+    let samples = ((end - start) * rate).floor() as usize + 1;
+    let mut samples = uniform(time, values, start, rate, samples);
+    let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+    samples.iter_mut().for_each(|v| *v -= mean);
+    Some(samples)
+}
+
+/// Finds the offset at which `values` at `time` best matches `reference` at
+/// `reference_time`, times in seconds.
+pub fn align(
+    reference_time: &[f64],
+    reference: &[f64],
+    time: &[f64],
+    values: &[f64],
+    options: &SyncOptions,
+) -> Option<Sync> {
+    let rate = options.rate;
+    let a = prepare(reference_time, reference, rate)?;
+    let b = prepare(time, values, rate)?;
+    let (n, m) = (a.len() as i64, b.len() as i64);
+    let start_offset = reference_time[0] - time[0];
+
+    // Cross-correlation c[k] = sum a[i + k] b[i], from the product of the spectra
+    let size = (a.len() + b.len()).next_power_of_two();
+    let spectrum = |samples: &[f64]| {
+        let mut re = samples.to_vec();
+        re.resize(size, 0.0);
+        let mut im = vec![0.0; size];
+        fft(&mut re, &mut im);
+        (re, im)
+    };
+    let ((a_re, a_im), (b_re, b_im)) = (spectrum(&a), spectrum(&b));
+    // Inverse transform of a × conj(b) as the conjugate of the forward transform of its
+    // conjugate
+    let mut re: Vec<f64> = (0..size)
+        .map(|i| a_re[i] * b_re[i] + a_im[i] * b_im[i])
+        .collect();
+    let mut im: Vec<f64> = (0..size)
+        .map(|i| -(a_im[i] * b_re[i] - a_re[i] * b_im[i]))
+        .collect();
+    fft(&mut re, &mut im);
+    let correlation = |k: i64| re[k.rem_euclid(size as i64) as usize] / size as f64;
+
+    let energy = |samples: &[f64]| {
+        let mut sums = vec![0.0];
+        for v in samples {
+            sums.push(sums.last().unwrap() + v * v);
+        }
+        sums
+    };
+    let (a_energy, b_energy) = (energy(&a), energy(&b));
+    let min_overlap = (options.min_overlap * rate).ceil().max(2.0) as i64;
+    let normalized = |k: i64| {
+        let (first, last) = ((-k).max(0), m.min(n - k));
+        if last - first < min_overlap {
+            return None;
+        }
+        let (first, last) = (first as usize, last as usize);
+        let b = b_energy[last] - b_energy[first];
+        let shifted = (first as i64 + k) as usize..(last as i64 + k) as usize;
+        let a = a_energy[shifted.end] - a_energy[shifted.start];
+        (a > 0.0 && b > 0.0).then(|| correlation(k) / (a * b).sqrt())
+    };
+
+    // offset = start_offset + k / rate
+    let lag = |offset: f64| (offset - start_offset) * rate;
+    let low = (lag(-options.max_offset).ceil() as i64).max(1 - m);
+    let high = (lag(options.max_offset).floor() as i64).min(n - 1);
+    let (k, best) = (low..=high)
+        .filter_map(|k| Some((k, normalized(k)?)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+    // Parabola through the neighbours for an offset between samples
+    let shift = match (normalized(k - 1), normalized(k + 1)) {
+        (Some(before), Some(after)) if before - 2.0 * best + after < 0.0 => {
+            0.5 * (before - after) / (before - 2.0 * best + after)
+        }
+        _ => 0.0,
+    };
+    Some(Sync {
+        offset: start_offset + (k as f64 + shift) / rate,
+        correlation: best,
+    })
+}
+
+/// Reads the rest of the log and finds the offset of a camera motion signal, like the
+/// magnitude of the rotation rate of the camera gyro, at `time` in seconds of the video.
+///
+/// The offset is in seconds of the log since its first main frame, so that a video at
+/// `t` shows the log at `t + offset`.
+pub fn sync(
+    reader: BlackboxReader<'_>,
+    time: &[f64],
+    motion: &[f64],
+    options: &SyncOptions,
+) -> Result<Option<Sync>, BlackboxReaderError> {
+    let names = ["gyroADC[0]", "gyroADC[1]", "gyroADC[2]"];
+    let columns = reader.decode_columns(&names)?;
+    let axes = names.map(|name| columns.scaled(name).unwrap_or_default());
+    let rate: Vec<f64> = (0..columns.len())
+        .map(|i| {
+            axes.iter()
+                .map(|axis| axis[i] * axis[i])
+                .sum::<f64>()
+                .sqrt()
+        })
+        .collect();
+    Ok(align(&columns.seconds(), &rate, time, motion, options))
+}
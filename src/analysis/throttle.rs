@@ -0,0 +1,107 @@
+//! How a flight used its throttle and motors, for prop and motor selection and the stats
+//! screens of log managers.
+
+use std::ops::RangeInclusive;
+
+use crate::{BlackboxReader, BlackboxReaderError, OutputLayout};
+
+use super::spectrum::sample_rate;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThrottleOptions {
+    /// Number of bins of [`ThrottleReport::histogram`]
+    pub bins: usize,
+    /// Raw `rcCommand[3]` values mapped to no and full throttle
+    pub range: RangeInclusive<f64>,
+    /// Throttle from 0 to 1 from which a main frame counts as full throttle
+    pub full_throttle: f64,
+}
+
+impl Default for ThrottleOptions {
+    fn default() -> Self {
+        Self {
+            bins: 20,
+            range: 1000.0..=2000.0,
+            full_throttle: 0.95,
+        }
+    }
+}
+
+/// Throttle and motor usage of a log returned by [`throttle`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ThrottleReport {
+    pub main_frames: usize,
+    /// Fraction of the main frames in every throttle bin, evenly spread from no to full
+    /// throttle
+    pub histogram: Vec<f64>,
+    /// Average throttle from 0 to 1
+    pub average: f64,
+    /// Time in seconds at or above [`ThrottleOptions::full_throttle`]
+    pub full_throttle: f64,
+    /// Average output of every motor from 0 to 1
+    pub motor_load: Vec<f64>,
+    /// Average over all motors
+    pub average_motor_load: f64,
+}
+
+fn average(values: impl ExactSizeIterator<Item = f64>) -> f64 {
+    let count = values.len().max(1) as f64;
+    values.sum::<f64>() / count
+}
+
+/// Reads the rest of the log and reports the throttle distribution and motor load, from
+/// `rcCommand[3]` and the `motor` fields.
+pub fn throttle(
+    reader: BlackboxReader<'_>,
+    options: &ThrottleOptions,
+) -> Result<ThrottleReport, BlackboxReaderError> {
+    let outputs = OutputLayout::new(&reader.header);
+    let motors: Vec<String> = (0..outputs.motor_count())
+        .map(|i| format!("motor[{}]", i))
+        .collect();
+    let throttle_field = "rcCommand[3]".to_owned();
+    let names: Vec<&String> = std::iter::once(&throttle_field).chain(&motors).collect();
+    let columns = reader.decode_columns(&names)?;
+
+    let (low, high) = (*options.range.start(), *options.range.end());
+    let span = (high - low).max(f64::EPSILON);
+    let throttle: Vec<f64> = columns
+        .get(&throttle_field)
+        .unwrap_or_default()
+        .iter()
+        .map(|v| ((*v as f64 - low) / span).clamp(0.0, 1.0))
+        .collect();
+
+    let bins = options.bins.max(1);
+    let mut histogram = vec![0.0; bins];
+    for value in &throttle {
+        histogram[((value * bins as f64) as usize).min(bins - 1)] += 1.0;
+    }
+    histogram
+        .iter_mut()
+        .for_each(|count| *count /= throttle.len().max(1) as f64);
+
+    let at_full = throttle
+        .iter()
+        .filter(|value| **value >= options.full_throttle)
+        .count();
+    let full_throttle = sample_rate(&columns).map_or(0.0, |rate| at_full as f64 / rate);
+
+    let motor_load: Vec<f64> = motors
+        .iter()
+        .map(|name| {
+            let raw = columns.get(name).unwrap_or_default();
+            average(raw.iter().map(|v| outputs.normalize_motor(*v) as f64))
+        })
+        .collect();
+
+    Ok(ThrottleReport {
+        main_frames: columns.len(),
+        histogram,
+        average: average(throttle.iter().copied()),
+        full_throttle,
+        average_motor_load: average(motor_load.iter().copied()),
+        motor_load,
+    })
+}
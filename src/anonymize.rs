@@ -0,0 +1,194 @@
+use thiserror::Error;
+
+use crate::{
+    frame::encode::encode_frame, stream::predictor::AnyGPredictor, BlackboxReader,
+    BlackboxReaderError, BlackboxRecord, ByteSpan, Header, MultiSegmentBlackboxReader,
+};
+
+/// Headers that identify the pilot or their hardware.
+pub const PRIVATE_HEADERS: &[&str] = &[
+    "Craft name",
+    "Pilot name",
+    "pilot_name",
+    "Board serial",
+    "serial_number",
+];
+
+/// What happens to the GNSS position in an anonymized log.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GnssPrivacy {
+    /// Copy GNSS and home frames unchanged.
+    Keep,
+    /// Drop GNSS and home frames, the log keeps its GNSS field definitions.
+    Remove,
+    /// Move the home position to the given latitude and longitude in degrees. The flight path
+    /// keeps its shape around the new home.
+    MoveHome { latitude: f64, longitude: f64 },
+}
+
+#[derive(Clone, Debug)]
+pub struct AnonymizeOptions {
+    /// Headers whose values are cleared, the header lines themselves are kept.
+    pub headers: Vec<String>,
+    pub gnss: GnssPrivacy,
+}
+
+impl Default for AnonymizeOptions {
+    fn default() -> Self {
+        Self {
+            headers: PRIVATE_HEADERS.iter().map(|h| h.to_string()).collect(),
+            gnss: GnssPrivacy::Remove,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AnonymizeError {
+    #[error("log at offset {offset}: {source}")]
+    Reader {
+        offset: usize,
+        source: BlackboxReaderError,
+    },
+    #[error("log at offset {0} has GNSS coordinates that aren't relative to a home position")]
+    AbsoluteCoordinates(usize),
+    #[error("log at offset {0} has GNSS home frames that can't be rewritten")]
+    UnsupportedHome(usize),
+}
+
+/// Copies the logs in `input` with the values of private headers cleared and the GNSS position
+/// removed or moved, e.g. to attach a log to a public bug report. Every other byte, including
+/// the main frames, is kept as it is.
+///
+/// GNSS frames store coordinates relative to the last home frame, so moving the home is done
+/// by rewriting just the home frames, offsetting each by the distance from the first home to
+/// the requested one.
+pub fn anonymize(input: &[u8], options: &AnonymizeOptions) -> Result<Vec<u8>, AnonymizeError> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut copied = 0;
+    for segment in MultiSegmentBlackboxReader::from_bytes(input).segments() {
+        out.extend_from_slice(&input[copied..segment.offset]);
+        let log = &input[segment.offset..segment.offset + segment.len];
+        anonymize_log(log, options, &mut out).map_err(|err| match err {
+            LogError::Reader(source) => AnonymizeError::Reader {
+                offset: segment.offset,
+                source,
+            },
+            LogError::AbsoluteCoordinates => AnonymizeError::AbsoluteCoordinates(segment.offset),
+            LogError::UnsupportedHome => AnonymizeError::UnsupportedHome(segment.offset),
+        })?;
+        copied = segment.offset + segment.len;
+    }
+    out.extend_from_slice(&input[copied..]);
+    Ok(out)
+}
+
+enum LogError {
+    Reader(BlackboxReaderError),
+    AbsoluteCoordinates,
+    UnsupportedHome,
+}
+
+fn write_headers(headers: &[u8], private: &[String], out: &mut Vec<u8>) {
+    for line in headers.split_inclusive(|b| *b == b'\n') {
+        let name = line
+            .strip_prefix(b"H ")
+            .and_then(|line| line.split(|b| *b == b':').next())
+            .and_then(|name| std::str::from_utf8(name).ok());
+        match name {
+            Some(name) if private.iter().any(|p| p == name) => {
+                out.extend_from_slice(b"H ");
+                out.extend_from_slice(name.as_bytes());
+                out.extend_from_slice(b":\n");
+            }
+            _ => out.extend_from_slice(line),
+        }
+    }
+}
+
+/// Offset in 1e-7 degrees added to the latitude and longitude of every home frame.
+fn home_offset(header: &Header, first_home: &[i64], gnss: GnssPrivacy) -> Option<[i64; 2]> {
+    let GnssPrivacy::MoveHome {
+        latitude,
+        longitude,
+    } = gnss
+    else {
+        return None;
+    };
+    let lat = header.h_fields.get("GPS_home[0]")?.ix;
+    let lon = header.h_fields.get("GPS_home[1]")?.ix;
+    Some([
+        (latitude * 1e7).round() as i64 - first_home[lat],
+        (longitude * 1e7).round() as i64 - first_home[lon],
+    ])
+}
+
+fn anonymize_log(
+    log: &[u8],
+    options: &AnonymizeOptions,
+    out: &mut Vec<u8>,
+) -> Result<(), LogError> {
+    let mut reader = BlackboxReader::from_bytes(log).map_err(LogError::Reader)?;
+    let header = reader.header.clone();
+    write_headers(&log[..reader.header_length], &options.headers, out);
+
+    if options.gnss == GnssPrivacy::Keep {
+        out.extend_from_slice(&log[reader.header_length..]);
+        return Ok(());
+    }
+    let relative = ["GPS_coord[0]", "GPS_coord[1]"].iter().all(|name| {
+        header.g_fields.get(*name).is_none_or(|field| {
+            matches!(
+                header.g_field_predictors[field.ix],
+                AnyGPredictor::HomeCoordinates(_)
+            )
+        })
+    });
+    if !relative {
+        return Err(LogError::AbsoluteCoordinates);
+    }
+
+    let mut offset = None;
+    let mut copied = reader.header_length;
+    loop {
+        let is_gnss = reader
+            .next()
+            .map(|record| matches!(record, BlackboxRecord::GNSS(_)));
+        let span = match is_gnss {
+            Some(_) => reader
+                .last_frame_span()
+                .expect("span of the record just read"),
+            None => ByteSpan {
+                offset: log.len(),
+                len: 0,
+            },
+        };
+        // Home frames aren't records, they are found in the bytes before the next record
+        let mut skipped = &log[copied..span.offset];
+        if skipped.first() == Some(&b'H') {
+            let home = reader.gnss_home();
+            let mut frame = Vec::new();
+            encode_frame(b'H', &header.h_field_encodings, home, &mut frame)
+                .map_err(|_| LogError::UnsupportedHome)?;
+            if !skipped.starts_with(&frame) {
+                return Err(LogError::UnsupportedHome);
+            }
+            skipped = &skipped[frame.len()..];
+            let offset = *offset.get_or_insert_with(|| home_offset(&header, home, options.gnss));
+            if let Some([lat, lon]) = offset {
+                let mut moved = home.to_vec();
+                moved[header.h_fields["GPS_home[0]"].ix] += lat;
+                moved[header.h_fields["GPS_home[1]"].ix] += lon;
+                encode_frame(b'H', &header.h_field_encodings, &moved, out)
+                    .map_err(|_| LogError::UnsupportedHome)?;
+            }
+        }
+        out.extend_from_slice(skipped);
+        let Some(is_gnss) = is_gnss else {
+            return Ok(());
+        };
+        if !(is_gnss && options.gnss == GnssPrivacy::Remove) {
+            out.extend_from_slice(&log[span.offset..span.offset + span.len]);
+        }
+        copied = span.offset + span.len;
+    }
+}
@@ -0,0 +1,81 @@
+/// Main frame values of up to a given number of frames stored per field, as returned by
+/// [`BlackboxReader::next_batch`](crate::BlackboxReader::next_batch).
+///
+/// The buffers are kept by the reader and reused for every batch, so after the first batch
+/// decoding doesn't allocate.
+#[derive(Clone, Debug, Default)]
+pub struct Batch {
+    names: Vec<String>,
+    time: Vec<i64>,
+    loop_iteration: Vec<i64>,
+    values: Vec<Vec<i64>>,
+}
+
+impl Batch {
+    pub(crate) fn new(names: Vec<String>) -> Self {
+        Self {
+            values: vec![Vec::new(); names.len()],
+            names,
+            ..Default::default()
+        }
+    }
+
+    /// Empties the batch, keeping room for `capacity` frames.
+    pub(crate) fn clear(&mut self, capacity: usize) {
+        for column in std::iter::once(&mut self.time)
+            .chain([&mut self.loop_iteration])
+            .chain(&mut self.values)
+        {
+            column.clear();
+            column.reserve(capacity);
+        }
+    }
+
+    pub(crate) fn push(&mut self, loop_iteration: i64, time: i64, values: &[i64]) {
+        self.loop_iteration.push(loop_iteration);
+        self.time.push(time);
+        for (column, value) in self.values.iter_mut().zip(values) {
+            column.push(*value);
+        }
+    }
+
+    /// Number of main frames.
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    /// Field names in the order of the columns.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    /// Time of every main frame in microseconds, with rollovers accounted for
+    pub fn time(&self) -> &[i64] {
+        &self.time
+    }
+
+    pub fn loop_iteration(&self) -> &[i64] {
+        &self.loop_iteration
+    }
+
+    /// Raw values of the field at `ix` in [`names`](Self::names), one per main frame.
+    pub fn column(&self, ix: usize) -> &[i64] {
+        &self.values[ix]
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[i64]> {
+        self.index_of(name).map(|ix| self.column(ix))
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = &[i64]> {
+        self.values.iter().map(|column| &column[..])
+    }
+}
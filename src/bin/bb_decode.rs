@@ -0,0 +1,242 @@
+//! Decodes blackbox logs to CSV, JSON or GPX, like Betaflight's `blackbox_decode`.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Parser, ValueEnum};
+use fc_blackbox::{
+    export::{
+        csv::{self, CsvOptions},
+        gpx,
+        json::{self, JsonOptions},
+    },
+    BlackboxReader, DerivedField, MultiSegmentBlackboxReader, SegmentInfo,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+    Gpx,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "bb-decode", version, about)]
+struct Args {
+    /// Log files to decode
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    #[arg(short, long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+    /// Only decode the log with this number, counting from 1
+    #[arg(long)]
+    index: Option<usize>,
+    /// List the logs of every file instead of decoding them
+    #[arg(long)]
+    list: bool,
+    /// Write every log to its own file with the input's extension instead of decoding them
+    #[arg(long)]
+    split: bool,
+    /// Skip the first SECONDS of every log
+    #[arg(long, value_name = "SECONDS")]
+    start: Option<f64>,
+    /// Stop SECONDS into every log
+    #[arg(long, value_name = "SECONDS")]
+    end: Option<f64>,
+    /// Convert every value with a known unit, including gyro and accelerometer readings
+    #[arg(long)]
+    scaled: bool,
+    /// Append GPS values to every CSV row instead of writing them to a separate .gps.csv file
+    #[arg(long)]
+    merge_gps: bool,
+    /// Add a field computed from main frame fields, e.g. `error=abs(gyroADC[0]-setpoint[0])`
+    #[arg(long, value_name = "NAME=EXPR", value_parser = parse_derived)]
+    derive: Vec<DerivedField>,
+    /// Write to standard output instead of files next to the input
+    #[arg(long)]
+    stdout: bool,
+    /// Directory for the output files, the directory of the input by default
+    #[arg(short, long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+}
+
+fn parse_derived(arg: &str) -> Result<DerivedField, String> {
+    let (name, expression) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected NAME=EXPR".to_owned())?;
+    DerivedField::new(name.trim(), expression).map_err(|e| e.to_string())
+}
+
+impl Args {
+    fn output_path(&self, input: &Path, number: usize, extension: &str) -> PathBuf {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let name = format!("{}.{:02}.{}", stem, number, extension);
+        match &self.output_dir {
+            Some(dir) => dir.join(name),
+            None => input.with_file_name(name),
+        }
+    }
+
+    /// Restricts the reader to `--start` and `--end`, relative to the first main frame.
+    fn apply_range<'a>(
+        &self,
+        reader: BlackboxReader<'a>,
+        segment: &SegmentInfo,
+    ) -> BlackboxReader<'a> {
+        if self.start.is_none() && self.end.is_none() {
+            return reader;
+        }
+        let Some((first, _)) = segment.time_span else {
+            return reader;
+        };
+        let at = |seconds: f64| first + (seconds * 1e6) as i64;
+        reader.range(
+            self.start.map_or(i64::MIN, at),
+            self.end.map_or(i64::MAX, at),
+        )
+    }
+
+    fn write(
+        &self,
+        input: &Path,
+        number: usize,
+        extension: &str,
+        write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> io::Result<()> {
+        if self.stdout {
+            let mut out = io::stdout().lock();
+            return write(&mut out);
+        }
+        let path = self.output_path(input, number, extension);
+        let mut out = BufWriter::new(File::create(&path)?);
+        write(&mut out)?;
+        out.flush()?;
+        eprintln!("Wrote {}", path.display());
+        Ok(())
+    }
+
+    fn decode(
+        &self,
+        input: &Path,
+        bytes: &[u8],
+        number: usize,
+        segment: &SegmentInfo,
+    ) -> io::Result<()> {
+        let reader = || -> io::Result<BlackboxReader> {
+            let mut reader = BlackboxReader::from_bytes(&bytes[segment.offset..])
+                .expect("header was parsed when listing the segments");
+            for field in &self.derive {
+                reader = reader
+                    .derive(field)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            }
+            Ok(self.apply_range(reader, segment))
+        };
+        match self.format {
+            Format::Csv => {
+                let options = CsvOptions {
+                    merge_gnss: self.merge_gps,
+                    scaled: self.scaled,
+                };
+                self.write(input, number, "csv", |mut out| {
+                    csv::write(reader()?, &mut out, options)
+                })?;
+                let has_gnss = !reader()?.header.g_fields_in_order.is_empty();
+                if has_gnss && !self.merge_gps && !self.stdout {
+                    self.write(input, number, "gps.csv", |mut out| {
+                        csv::write_gnss(reader()?, &mut out)
+                    })?;
+                }
+                Ok(())
+            }
+            Format::Json => {
+                let options = JsonOptions {
+                    scaled: self.scaled,
+                    fields: None,
+                    events: true,
+                };
+                self.write(input, number, "json", |mut out| {
+                    json::write(reader()?, &mut out, &options)
+                })
+            }
+            Format::Gpx => self.write(input, number, "gps.gpx", |mut out| {
+                gpx::write(reader()?, &mut out)
+            }),
+        }
+    }
+}
+
+fn list(input: &Path, segments: &[SegmentInfo]) {
+    println!("{}", input.display());
+    for (i, segment) in segments.iter().enumerate() {
+        match (&segment.header, segment.duration()) {
+            (Ok(header), duration) => println!(
+                "  {:2}: {} {} bytes, {:.1} s",
+                i + 1,
+                header.firmware_revision().unwrap_or("unknown firmware"),
+                segment.len,
+                duration.unwrap_or(0) as f64 / 1e6
+            ),
+            (Err(e), _) => println!("  {:2}: {}", i + 1, e),
+        }
+    }
+}
+
+fn split(args: &Args, input: &Path, bytes: &[u8], segments: &[SegmentInfo]) -> io::Result<()> {
+    let extension = input.extension().unwrap_or_default().to_string_lossy();
+    for (i, segment) in segments.iter().enumerate() {
+        let number = i + 1;
+        if args.index.is_none_or(|index| index == number) {
+            args.write(input, number, &extension, |out| {
+                out.write_all(&bytes[segment.range()])
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn run(args: &Args, input: &Path) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    let segments = MultiSegmentBlackboxReader::open(input, &mut bytes)?.segments();
+    if args.list {
+        list(input, &segments);
+        return Ok(());
+    }
+    if let Some(index) = args.index.filter(|i| *i == 0 || *i > segments.len()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("no log {}, the file has {}", index, segments.len()),
+        ));
+    }
+    if args.split {
+        return split(args, input, &bytes, &segments);
+    }
+
+    for (i, segment) in segments.iter().enumerate() {
+        let number = i + 1;
+        if args.index.is_some_and(|index| index != number) {
+            continue;
+        }
+        match &segment.header {
+            Ok(_) => args.decode(input, &bytes, number, segment)?,
+            Err(e) => eprintln!("{}: skipping log {}: {}", input.display(), number, e),
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let mut code = ExitCode::SUCCESS;
+    for input in &args.files {
+        if let Err(e) = run(&args, input) {
+            eprintln!("{}: {}", input.display(), e);
+            code = ExitCode::FAILURE;
+        }
+    }
+    code
+}
@@ -0,0 +1,103 @@
+//! Recovery of logs from raw SD card images and flash dumps, where the filesystem that knew
+//! where each log starts and ends is damaged or missing.
+
+use std::ops::Range;
+
+use nom::FindSubstring;
+
+use crate::{
+    BlackboxReader, BlackboxRecord, Header, ReaderOptions, ReaderStats, RecoveryPolicy,
+    SEGMENT_START,
+};
+
+/// How [`MultiSegmentBlackboxReader::carve`](crate::MultiSegmentBlackboxReader::carve) decides
+/// where a log ends.
+#[derive(Clone, Copy, Debug)]
+pub struct CarveOptions {
+    /// Longest run of bytes without a valid frame a log may contain, like erased flash pages,
+    /// sector padding, or a cluster of another file in a fragmented one. A log without an
+    /// `End of log` event ends at its last frame before a longer run. Replaces the
+    /// `max_garbage_bytes` of the reader's recovery policy.
+    pub max_gap: usize,
+}
+
+impl Default for CarveOptions {
+    fn default() -> Self {
+        Self { max_gap: 64 * 1024 }
+    }
+}
+
+/// Log recovered by [`MultiSegmentBlackboxReader::carve`](crate::MultiSegmentBlackboxReader::carve).
+#[derive(Debug)]
+pub struct CarvedLog {
+    /// Offset of the log's first header from the start of the image
+    pub offset: usize,
+    /// Length of the log, up to the end of its last valid frame
+    pub len: usize,
+    /// Whether the log ends with an `End of log` event, otherwise it was cut off by a gap,
+    /// another log, or the end of the image
+    pub complete: bool,
+    pub header: Header,
+    /// What the reader had to skip inside the log
+    pub stats: ReaderStats,
+    /// `time` of the first and last main frames
+    pub time_span: Option<(i64, i64)>,
+}
+
+impl CarvedLog {
+    /// Bytes of the log in the image, a standalone log that can be written to its own file.
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// Decodes every log in `image` with a header that parses, see [`CarveOptions`].
+pub(crate) fn carve(
+    image: &[u8],
+    reader_options: &ReaderOptions,
+    options: CarveOptions,
+) -> Vec<CarvedLog> {
+    let reader_options = ReaderOptions {
+        recovery: RecoveryPolicy {
+            max_garbage_bytes: Some(options.max_gap),
+            ..reader_options.recovery
+        },
+        ..reader_options.clone()
+    };
+
+    let mut logs = Vec::new();
+    let mut pos = 0;
+    while let Some(found) = (&image[pos..]).find_substring(SEGMENT_START) {
+        let offset = pos + found;
+        pos = offset + 1;
+        let Ok(mut reader) = BlackboxReader::with_options(&image[offset..], reader_options.clone())
+        else {
+            continue;
+        };
+        let mut len = reader.bytes_read();
+        let mut time_span = None;
+        while let Some(record) = reader.next() {
+            let is_main = match record {
+                BlackboxRecord::Garbage(_) => continue,
+                BlackboxRecord::Main(_) => true,
+                _ => false,
+            };
+            if is_main {
+                let time = reader.last_time;
+                time_span = Some(time_span.map_or((time, time), |(first, _)| (first, time)));
+            }
+            if let Some(span) = reader.last_frame_span() {
+                len = span.offset + span.len;
+            }
+        }
+        logs.push(CarvedLog {
+            offset,
+            len,
+            complete: reader.trailing_bytes().is_some(),
+            stats: *reader.stats(),
+            header: reader.header,
+            time_span,
+        });
+    }
+    logs
+}
@@ -0,0 +1,71 @@
+use crate::units::FieldScale;
+
+/// Main frame values of a whole log stored per field, as returned by
+/// [`BlackboxReader::decode_columns`](crate::BlackboxReader::decode_columns).
+#[derive(Clone, Debug)]
+pub struct Columns {
+    names: Vec<String>,
+    scales: Vec<FieldScale>,
+    /// Time of every main frame in microseconds, with rollovers accounted for
+    pub time: Vec<i64>,
+    values: Vec<Vec<i64>>,
+}
+
+impl Columns {
+    pub(crate) fn new(names: Vec<String>, scales: Vec<FieldScale>, capacity: usize) -> Self {
+        Self {
+            values: vec![Vec::with_capacity(capacity); names.len()],
+            names,
+            scales,
+            time: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn push(&mut self, time: i64, values: &[i64]) {
+        self.time.push(time);
+        for (column, value) in self.values.iter_mut().zip(values) {
+            column.push(*value);
+        }
+    }
+
+    /// Number of main frames.
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    /// Field names in the order they were requested.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    /// Raw values of a field, one per main frame.
+    pub fn get(&self, name: &str) -> Option<&[i64]> {
+        self.index_of(name).map(|ix| &self.values[ix][..])
+    }
+
+    /// Values of a field converted with its scale from [`Units`](crate::units::Units), raw
+    /// values as floats for fields without a known unit.
+    pub fn scaled(&self, name: &str) -> Option<Vec<f64>> {
+        let ix = self.index_of(name)?;
+        let scale = self.scales[ix];
+        Some(self.values[ix].iter().map(|v| scale.apply(*v)).collect())
+    }
+
+    pub fn scale(&self, name: &str) -> Option<FieldScale> {
+        self.index_of(name).map(|ix| self.scales[ix])
+    }
+
+    /// Time of every main frame in seconds since the first one.
+    pub fn seconds(&self) -> Vec<f64> {
+        let first = self.time.first().copied().unwrap_or(0);
+        self.time.iter().map(|t| (t - first) as f64 / 1e6).collect()
+    }
+}
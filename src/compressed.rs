@@ -0,0 +1,79 @@
+//! Transparent reading of logs shared as gzip, zstd or ZIP files.
+
+use std::{
+    borrow::Cow,
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+};
+
+/// Container of a log file, recognized from its first bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Zip,
+}
+
+impl Compression {
+    pub fn detect(bytes: &[u8]) -> Self {
+        match bytes {
+            [0x1f, 0x8b, ..] => Compression::Gzip,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Compression::Zstd,
+            [b'P', b'K', 3, 4, ..] => Compression::Zip,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Whether a file name inside an archive looks like a log.
+fn is_log_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    [".bbl", ".bfl", ".txt"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+/// Appends the log in a ZIP archive to `buf`: the first file with a log extension, or the
+/// first file if none has one.
+fn unzip(input: impl Read + Seek, buf: &mut Vec<u8>) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(input)?;
+    let files: Vec<_> = (0..archive.len())
+        .filter(|i| archive.by_index(*i).is_ok_and(|file| file.is_file()))
+        .collect();
+    let ix = files
+        .iter()
+        .copied()
+        .find(|i| archive.name_for_index(*i).is_some_and(is_log_name))
+        .or(files.first().copied())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "ZIP archive has no files"))?;
+    archive.by_index(ix)?.read_to_end(buf)?;
+    Ok(())
+}
+
+/// Reads a possibly compressed log from `input` into `buf`, decompressing while reading.
+pub fn read_into(input: impl Read + Seek, buf: &mut Vec<u8>) -> io::Result<()> {
+    let mut input = BufReader::new(input);
+    match Compression::detect(input.fill_buf()?) {
+        Compression::None => input.read_to_end(buf).map(drop),
+        Compression::Gzip => flate2::bufread::MultiGzDecoder::new(input)
+            .read_to_end(buf)
+            .map(drop),
+        Compression::Zstd => zstd::stream::read::Decoder::with_buffer(input)?
+            .read_to_end(buf)
+            .map(drop),
+        Compression::Zip => {
+            input.seek(SeekFrom::Start(0))?;
+            unzip(input, buf)
+        }
+    }
+}
+
+/// Decompresses a log held in memory, borrowing it if it isn't compressed.
+pub fn decompress(bytes: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+    if Compression::detect(bytes) == Compression::None {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    let mut buf = Vec::new();
+    read_into(Cursor::new(bytes), &mut buf)?;
+    Ok(Cow::Owned(buf))
+}
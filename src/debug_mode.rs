@@ -0,0 +1,218 @@
+use std::fmt;
+
+use crate::FirmwareKind;
+
+macro_rules! debug_modes {
+    ($($id:literal => $variant:ident $name:literal,)*) => {
+        /// What Betaflight logs in the `debug[]` fields, from the `debug_mode` header.
+        ///
+        /// Ids are those of Betaflight 4.0 to 4.3, which only appended new modes. Later versions
+        /// and other firmware number their modes differently and decode as `Other`.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize))]
+        pub enum DebugMode {
+            $($variant,)*
+            /// Raw `debug_mode` value that isn't known for the firmware.
+            Other(u16),
+        }
+
+        impl DebugMode {
+            fn from_betaflight_id(id: u16) -> Self {
+                match id {
+                    $($id => Self::$variant,)*
+                    id => Self::Other(id),
+                }
+            }
+
+            /// Value of the `debug_mode` header.
+            pub fn id(&self) -> u16 {
+                match self {
+                    $(Self::$variant => $id,)*
+                    Self::Other(id) => *id,
+                }
+            }
+
+            /// Name used by the firmware CLI, e.g. `GYRO_SCALED`.
+            pub fn name(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($name),)*
+                    Self::Other(_) => None,
+                }
+            }
+        }
+    };
+}
+
+debug_modes! {
+    0 => None "NONE",
+    1 => Cycletime "CYCLETIME",
+    2 => Battery "BATTERY",
+    3 => GyroFiltered "GYRO_FILTERED",
+    4 => Accelerometer "ACCELEROMETER",
+    5 => Pidloop "PIDLOOP",
+    6 => GyroScaled "GYRO_SCALED",
+    7 => RcInterpolation "RC_INTERPOLATION",
+    8 => Anglerate "ANGLERATE",
+    9 => EscSensor "ESC_SENSOR",
+    10 => Scheduler "SCHEDULER",
+    11 => Stack "STACK",
+    12 => EscSensorRpm "ESC_SENSOR_RPM",
+    13 => EscSensorTmp "ESC_SENSOR_TMP",
+    14 => Altitude "ALTITUDE",
+    15 => Fft "FFT",
+    16 => FftTime "FFT_TIME",
+    17 => FftFreq "FFT_FREQ",
+    18 => RxFrskySpi "RX_FRSKY_SPI",
+    19 => RxSfhssSpi "RX_SFHSS_SPI",
+    20 => GyroRaw "GYRO_RAW",
+    21 => DualGyroRaw "DUAL_GYRO_RAW",
+    22 => DualGyroDiff "DUAL_GYRO_DIFF",
+    23 => Max7456Signal "MAX7456_SIGNAL",
+    24 => Max7456Spiclock "MAX7456_SPICLOCK",
+    25 => Sbus "SBUS",
+    26 => Fport "FPORT",
+    27 => Rangefinder "RANGEFINDER",
+    28 => RangefinderQuality "RANGEFINDER_QUALITY",
+    29 => LidarTf "LIDAR_TF",
+    30 => AdcInternal "ADC_INTERNAL",
+    31 => RunawayTakeoff "RUNAWAY_TAKEOFF",
+    32 => Sdio "SDIO",
+    33 => CurrentSensor "CURRENT_SENSOR",
+    34 => Usb "USB",
+    35 => Smartaudio "SMARTAUDIO",
+    36 => Rth "RTH",
+    37 => ItermRelax "ITERM_RELAX",
+    38 => AcroTrainer "ACRO_TRAINER",
+    39 => RcSmoothing "RC_SMOOTHING",
+    40 => RxSignalLoss "RX_SIGNAL_LOSS",
+    41 => RcSmoothingRate "RC_SMOOTHING_RATE",
+    42 => AntiGravity "ANTI_GRAVITY",
+    43 => DynLpf "DYN_LPF",
+    44 => RxSpektrumSpi "RX_SPEKTRUM_SPI",
+    45 => DshotRpmTelemetry "DSHOT_RPM_TELEMETRY",
+    46 => RpmFilter "RPM_FILTER",
+    47 => DMin "D_MIN",
+    48 => AcCorrection "AC_CORRECTION",
+    49 => AcError "AC_ERROR",
+    50 => DualGyroScaled "DUAL_GYRO_SCALED",
+    51 => DshotRpmErrors "DSHOT_RPM_ERRORS",
+    52 => CrsfLinkStatisticsUplink "CRSF_LINK_STATISTICS_UPLINK",
+    53 => CrsfLinkStatisticsPwr "CRSF_LINK_STATISTICS_PWR",
+    54 => CrsfLinkStatisticsDown "CRSF_LINK_STATISTICS_DOWN",
+    55 => Baro "BARO",
+    56 => GpsRescueThrottlePid "GPS_RESCUE_THROTTLE_PID",
+    57 => DynIdle "DYN_IDLE",
+    58 => FfLimit "FF_LIMIT",
+    59 => FfInterpolated "FF_INTERPOLATED",
+    60 => BlackboxOutput "BLACKBOX_OUTPUT",
+    61 => GyroSample "GYRO_SAMPLE",
+    62 => RxTiming "RX_TIMING",
+    63 => DLpf "D_LPF",
+    64 => VtxTramp "VTX_TRAMP",
+    65 => Ghst "GHST",
+}
+
+/// Meaning of one `debug[]` slot in a given [`DebugMode`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugField {
+    /// Label in the style of the main frame field names, e.g. `gyroScaled[roll]`.
+    pub name: &'static str,
+    /// Unit after multiplying the raw value by `scale`, `None` for raw sensor readings and
+    /// counters.
+    pub unit: Option<&'static str>,
+    pub scale: f64,
+}
+
+impl DebugField {
+    const fn new(name: &'static str, unit: Option<&'static str>, scale: f64) -> Self {
+        Self { name, unit, scale }
+    }
+
+    const fn raw(name: &'static str) -> Self {
+        Self::new(name, None, 1.0)
+    }
+}
+
+const DEG_S: Option<&str> = Some("deg/s");
+const US: Option<&str> = Some("us");
+
+impl DebugMode {
+    /// Decodes the `debug_mode` header value. Only Betaflight's numbering is known.
+    pub fn decode(firmware: FirmwareKind, id: u16) -> Self {
+        match firmware {
+            FirmwareKind::Betaflight => Self::from_betaflight_id(id),
+            _ => Self::Other(id),
+        }
+    }
+
+    /// What `debug[slot]` holds, `None` for unused slots and modes not described here.
+    pub fn field(&self, slot: usize) -> Option<DebugField> {
+        let fields: &[DebugField] = match self {
+            Self::Cycletime => &[
+                DebugField::new("cycleTime", US, 1.0),
+                DebugField::new("cpuLoad", Some("%"), 0.1),
+            ],
+            Self::Pidloop => &[
+                DebugField::new("waitTime", US, 1.0),
+                DebugField::new("subUpdateTime", US, 1.0),
+                DebugField::new("pidUpdateTime", US, 1.0),
+                DebugField::new("motorUpdateTime", US, 1.0),
+            ],
+            Self::GyroFiltered => &[
+                DebugField::new("gyroFiltered[roll]", DEG_S, 1.0),
+                DebugField::new("gyroFiltered[pitch]", DEG_S, 1.0),
+                DebugField::new("gyroFiltered[yaw]", DEG_S, 1.0),
+            ],
+            Self::GyroScaled => &[
+                DebugField::new("gyroScaled[roll]", DEG_S, 1.0),
+                DebugField::new("gyroScaled[pitch]", DEG_S, 1.0),
+                DebugField::new("gyroScaled[yaw]", DEG_S, 1.0),
+            ],
+            Self::GyroRaw => &[
+                DebugField::raw("gyroRaw[roll]"),
+                DebugField::raw("gyroRaw[pitch]"),
+                DebugField::raw("gyroRaw[yaw]"),
+            ],
+            Self::Accelerometer => &[
+                DebugField::raw("accRaw[x]"),
+                DebugField::raw("accRaw[y]"),
+                DebugField::raw("accRaw[z]"),
+            ],
+            Self::DshotRpmTelemetry => &[
+                DebugField::new("dshotRpm[0]", Some("eRPM"), 100.0),
+                DebugField::new("dshotRpm[1]", Some("eRPM"), 100.0),
+                DebugField::new("dshotRpm[2]", Some("eRPM"), 100.0),
+                DebugField::new("dshotRpm[3]", Some("eRPM"), 100.0),
+            ],
+            Self::RpmFilter => &[
+                DebugField::new("rpmFilter[0]", Some("Hz"), 1.0),
+                DebugField::new("rpmFilter[1]", Some("Hz"), 1.0),
+                DebugField::new("rpmFilter[2]", Some("Hz"), 1.0),
+                DebugField::new("rpmFilter[3]", Some("Hz"), 1.0),
+            ],
+            Self::DMin => &[
+                DebugField::new("dMinGyroFactor[roll]", Some("%"), 1.0),
+                DebugField::new("dMinSetpointFactor[roll]", Some("%"), 1.0),
+                DebugField::raw("dTerm[roll]"),
+                DebugField::raw("dTerm[pitch]"),
+            ],
+            Self::FfLimit => &[
+                DebugField::raw("feedforwardInput[roll]"),
+                DebugField::raw("feedforwardInput[pitch]"),
+                DebugField::raw("feedforwardLimited[roll]"),
+            ],
+            _ => &[],
+        };
+        fields.get(slot).copied()
+    }
+}
+
+/// Formats the CLI name, or the raw id for unknown modes.
+impl fmt::Display for DebugMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.id()),
+        }
+    }
+}
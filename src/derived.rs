@@ -0,0 +1,351 @@
+use std::{iter::Peekable, str::CharIndices};
+
+use thiserror::Error;
+
+use crate::{BlackboxReaderError, Header};
+
+/// Why the expression of a [`DerivedField`] couldn't be parsed.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum ExpressionError {
+    #[error("expression ended unexpectedly")]
+    UnexpectedEnd,
+    #[error("unexpected `{found}` at character {position}")]
+    UnexpectedChar { position: usize, found: char },
+    #[error("unknown function {0}")]
+    UnknownFunction(String),
+    #[error("{function} takes {expected} arguments")]
+    ArgumentCount {
+        function: &'static str,
+        expected: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Function {
+    Abs,
+    Sqrt,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Function::Abs => "abs",
+            Function::Sqrt => "sqrt",
+            Function::Min => "min",
+            Function::Max => "max",
+        }
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Function::Abs | Function::Sqrt => 1,
+            Function::Min | Function::Max => 2,
+        }
+    }
+
+    fn apply(&self, args: &[f64]) -> f64 {
+        match self {
+            Function::Abs => args[0].abs(),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Min => args[0].min(args[1]),
+            Function::Max => args[0].max(args[1]),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Parsed expression, with fields referenced by name `F` and resolved to their index in
+/// the main frame values.
+#[derive(Clone, Debug, PartialEq)]
+enum Expr<F> {
+    Number(f64),
+    Field(F),
+    Neg(Box<Expr<F>>),
+    Binary(Operator, Box<Expr<F>>, Box<Expr<F>>),
+    Call(Function, Vec<Expr<F>>),
+}
+
+impl Expr<String> {
+    fn resolve(&self, ix: &impl Fn(&str) -> Option<usize>) -> Result<Expr<usize>, String> {
+        Ok(match self {
+            Expr::Number(value) => Expr::Number(*value),
+            Expr::Field(name) => Expr::Field(ix(name).ok_or_else(|| name.clone())?),
+            Expr::Neg(operand) => Expr::Neg(Box::new(operand.resolve(ix)?)),
+            Expr::Binary(op, left, right) => Expr::Binary(
+                *op,
+                Box::new(left.resolve(ix)?),
+                Box::new(right.resolve(ix)?),
+            ),
+            Expr::Call(function, args) => Expr::Call(
+                *function,
+                args.iter()
+                    .map(|arg| arg.resolve(ix))
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+}
+
+impl Expr<usize> {
+    fn evaluate(&self, values: &[i64]) -> f64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Field(ix) => values[*ix] as f64,
+            Expr::Neg(operand) => -operand.evaluate(values),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.evaluate(values), right.evaluate(values));
+                match op {
+                    Operator::Add => left + right,
+                    Operator::Sub => left - right,
+                    Operator::Mul => left * right,
+                    Operator::Div => left / right,
+                }
+            }
+            Expr::Call(function, args) => {
+                let args: Vec<_> = args.iter().map(|arg| arg.evaluate(values)).collect();
+                function.apply(&args)
+            }
+        }
+    }
+
+    fn fields(&self, out: &mut Vec<usize>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Field(ix) => out.push(*ix),
+            Expr::Neg(operand) => operand.fields(out),
+            Expr::Binary(_, left, right) => {
+                left.fields(out);
+                right.fields(out);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|arg| arg.fields(out)),
+        }
+    }
+}
+
+/// Recursive descent parser, operators have the usual precedence and associate to the left.
+struct Parser<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            chars: input.char_indices().peekable(),
+        }
+    }
+
+    /// Next character that isn't whitespace, without consuming it.
+    fn peek(&mut self) -> Option<(usize, char)> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    fn unexpected(&mut self) -> ExpressionError {
+        match self.peek() {
+            Some((position, found)) => ExpressionError::UnexpectedChar { position, found },
+            None => ExpressionError::UnexpectedEnd,
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ExpressionError> {
+        match self.peek() {
+            Some((_, c)) if c == expected => {
+                self.chars.next();
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn parse(mut self) -> Result<Expr<String>, ExpressionError> {
+        let expr = self.sum()?;
+        match self.peek() {
+            None => Ok(expr),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr<String>, ExpressionError> {
+        let mut left = self.product()?;
+        while let Some((_, c @ ('+' | '-'))) = self.peek() {
+            self.chars.next();
+            let op = if c == '+' {
+                Operator::Add
+            } else {
+                Operator::Sub
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+        Ok(left)
+    }
+
+    fn product(&mut self) -> Result<Expr<String>, ExpressionError> {
+        let mut left = self.unary()?;
+        while let Some((_, c @ ('*' | '/'))) = self.peek() {
+            self.chars.next();
+            let op = if c == '*' {
+                Operator::Mul
+            } else {
+                Operator::Div
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr<String>, ExpressionError> {
+        if let Some((_, '-')) = self.peek() {
+            self.chars.next();
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr<String>, ExpressionError> {
+        match self.peek() {
+            Some((_, '(')) => {
+                self.chars.next();
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some((start, c)) if c.is_ascii_digit() || c == '.' => {
+                let end = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                self.input[start..end]
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| ExpressionError::UnexpectedChar {
+                        position: start,
+                        found: c,
+                    })
+            }
+            Some((start, c)) if c.is_ascii_alphabetic() || c == '_' => {
+                let input = self.input;
+                let mut end = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                if let Some((_, '(')) = self.peek() {
+                    return self.call(&input[start..end]);
+                }
+                // Array fields like `gyroADC[0]`
+                if let Some((_, '[')) = self.chars.peek() {
+                    self.chars.next();
+                    if self.take_while(|c| c.is_ascii_digit()) == end + 1 {
+                        return Err(self.unexpected());
+                    }
+                    self.expect(']')?;
+                    end = self.position();
+                }
+                Ok(Expr::Field(input[start..end].to_owned()))
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Expr<String>, ExpressionError> {
+        let function = Function::parse(name)
+            .ok_or_else(|| ExpressionError::UnknownFunction(name.to_owned()))?;
+        self.expect('(')?;
+        let mut args = vec![self.sum()?];
+        while let Some((_, ',')) = self.peek() {
+            self.chars.next();
+            args.push(self.sum()?);
+        }
+        self.expect(')')?;
+        if args.len() != function.arity() {
+            return Err(ExpressionError::ArgumentCount {
+                function: function.name(),
+                expected: function.arity(),
+            });
+        }
+        Ok(Expr::Call(function, args))
+    }
+
+    /// Consumes the characters matching `f` and returns the position after them.
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> usize {
+        while self.chars.next_if(|(_, c)| f(*c)).is_some() {}
+        self.position()
+    }
+
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.input.len(), |(i, _)| *i)
+    }
+}
+
+/// Field computed from the main frame fields of every record, registered with
+/// [`BlackboxReader::derive`](crate::BlackboxReader::derive).
+///
+/// Expressions combine field names like `gyroADC[0]` and numbers with `+`, `-`, `*`, `/`,
+/// parentheses and the functions `abs`, `sqrt`, `min` and `max`, e.g.
+/// `abs(gyroADC[0] - setpoint[0])`. Fields are used with their raw values, as in the
+/// records.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivedField {
+    name: String,
+    expression: Expr<String>,
+}
+
+impl DerivedField {
+    pub fn new(name: impl Into<String>, expression: &str) -> Result<Self, ExpressionError> {
+        Ok(Self {
+            name: name.into(),
+            expression: Parser::new(expression).parse()?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Resolves the field names against the main fields of `header`.
+    pub(crate) fn resolve(&self, header: &Header) -> Result<ResolvedField, BlackboxReaderError> {
+        let ix = |name: &str| header.ip_fields.get(name).map(|f| f.ix);
+        let expression = self
+            .expression
+            .resolve(&ix)
+            .map_err(BlackboxReaderError::UnknownField)?;
+        Ok(ResolvedField {
+            name: self.name.clone(),
+            expression,
+        })
+    }
+}
+
+/// [`DerivedField`] evaluated on main frame values in header field order.
+#[derive(Clone, Debug)]
+pub(crate) struct ResolvedField {
+    pub name: String,
+    expression: Expr<usize>,
+}
+
+impl ResolvedField {
+    pub fn evaluate(&self, values: &[i64]) -> f64 {
+        self.expression.evaluate(values)
+    }
+
+    /// Header indices of the fields the expression uses.
+    pub fn fields(&self) -> Vec<usize> {
+        let mut fields = Vec::new();
+        self.expression.fields(&mut fields);
+        fields
+    }
+}
@@ -0,0 +1,110 @@
+use std::{fmt, sync::Mutex};
+
+/// How much a [`Diagnostic`] matters to someone looking at the decoded data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Severity {
+    /// Unusual, but nothing was lost.
+    Info,
+    /// Some data was dropped or may be decoded wrong.
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DiagnosticKind {
+    /// Bytes skipped to get to the next valid frame, including rejected frames.
+    Garbage { len: usize },
+    /// Event with an unknown code dropped by [`RecoveryAction::Skip`](crate::RecoveryAction).
+    IgnoredUnknownEvent { code: u8 },
+    /// GNSS home frame in a log without home fields in its header.
+    EmptyHomeFrame,
+    /// Field list header like `Field I signed` with a different number of entries than the
+    /// field names. Fields are only decoded up to the shortest list.
+    FieldListLength {
+        header: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// Data frame with `found` values instead of the `expected` number of fields, fitted by
+    /// [`FieldCountAction::Fit`](crate::FieldCountAction).
+    FieldCountMismatch {
+        frame: char,
+        expected: usize,
+        found: usize,
+    },
+    /// Header that appeared more than once, the last value is used.
+    DuplicateHeader { name: String },
+    /// Free-text header like `Craft name` with invalid UTF-8, where the invalid bytes were
+    /// replaced with U+FFFD.
+    LossyHeaderValue { name: String },
+    /// Malformed header line dropped by [`RecoveryAction::Skip`](crate::RecoveryAction).
+    SkippedHeaderLine,
+    /// Header of another log in the frame data, usually because the logger restarted without
+    /// the flash being erased. Reading stops there.
+    LogRestarted,
+    /// Bytes between frames stripped as [`SerialArtifacts`](crate::SerialArtifacts).
+    SerialArtifacts { len: usize },
+    /// Header setting a predictor of `field` is based on is missing or unparsable, decoded
+    /// with `value` instead, e.g. the firmware default `minthrottle`.
+    PredictorFallback {
+        field: String,
+        header: &'static str,
+        value: i64,
+    },
+}
+
+/// Recoverable problem the reader ran into, at `offset` bytes from the start of the log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    pub offset: usize,
+    pub kind: DiagnosticKind,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            DiagnosticKind::EmptyHomeFrame
+            | DiagnosticKind::DuplicateHeader { .. }
+            | DiagnosticKind::LogRestarted
+            | DiagnosticKind::SerialArtifacts { .. } => Severity::Info,
+            DiagnosticKind::Garbage { .. }
+            | DiagnosticKind::IgnoredUnknownEvent { .. }
+            | DiagnosticKind::FieldListLength { .. }
+            | DiagnosticKind::FieldCountMismatch { .. }
+            | DiagnosticKind::LossyHeaderValue { .. }
+            | DiagnosticKind::SkippedHeaderLine
+            | DiagnosticKind::PredictorFallback { .. } => Severity::Warning,
+        }
+    }
+}
+
+/// Receives the problems the reader recovered from, passed to the reader in
+/// [`ReaderOptions`](crate::ReaderOptions). Reading goes on the same with or without it,
+/// e.g. for a warnings panel next to the decoded data.
+pub trait Diagnostics: Send + Sync {
+    fn report(&self, diagnostic: Diagnostic);
+}
+
+impl<F> Diagnostics for F
+where
+    F: Fn(Diagnostic) + Send + Sync,
+{
+    fn report(&self, diagnostic: Diagnostic) {
+        self(diagnostic)
+    }
+}
+
+/// Keeps every diagnostic, in the order they were reported.
+impl Diagnostics for Mutex<Vec<Diagnostic>> {
+    fn report(&self, diagnostic: Diagnostic) {
+        self.lock().unwrap().push(diagnostic);
+    }
+}
+
+impl fmt::Debug for dyn Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Diagnostics")
+    }
+}
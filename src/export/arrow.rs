@@ -0,0 +1,148 @@
+//! Apache Arrow record batches with one typed column per field.
+//!
+//! `time` columns are `Int64`, other fields `Int32` or `UInt32` depending on whether the
+//! header declares them signed. The header lines are kept in the schema metadata.
+
+use std::{collections::HashMap, sync::Arc};
+
+use arrow_array::{ArrayRef, Int32Array, Int64Array, RecordBatch, UInt32Array};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+
+use crate::{BlackboxReader, BlackboxRecord, FieldKind, Header};
+
+/// Rows per record batch used by [`record_batches`] and the Parquet writer.
+pub const DEFAULT_BATCH_SIZE: usize = 64 * 1024;
+
+fn data_type(name: &str, signed: bool) -> DataType {
+    match (name, signed) {
+        ("time", _) => DataType::Int64,
+        (_, true) => DataType::Int32,
+        (_, false) => DataType::UInt32,
+    }
+}
+
+/// Schema of the records of `kind` returned by `reader`, taking
+/// [`select_fields`](BlackboxReader::select_fields) into account.
+pub fn schema(reader: &BlackboxReader<'_>, kind: FieldKind) -> SchemaRef {
+    let header = &reader.header;
+    let fields: Vec<Field> = match kind {
+        FieldKind::Main => reader
+            .main_field_names()
+            .map(|name| {
+                let signed = header.ip_fields[name].signed;
+                Field::new(name, data_type(name, signed), false)
+            })
+            .collect(),
+        FieldKind::Slow => header
+            .s_fields_in_order
+            .iter()
+            .map(|f| Field::new(&f.name, data_type(&f.name, f.signed), false))
+            .collect(),
+        FieldKind::GNSS => header
+            .g_fields_in_order
+            .iter()
+            .map(|f| Field::new(&f.name, data_type(&f.name, f.signed), false))
+            .collect(),
+    };
+    Arc::new(Schema::new_with_metadata(fields, metadata(header)))
+}
+
+fn metadata(header: &Header) -> HashMap<String, String> {
+    let mut metadata = header.other_headers.clone();
+    metadata.insert("Product".to_owned(), header.product().to_owned());
+    metadata.insert("Data version".to_owned(), header.data_version().to_owned());
+    if let Some(revision) = header.firmware_revision() {
+        metadata.insert("Firmware revision".to_owned(), revision.to_owned());
+    }
+    metadata
+}
+
+/// Collects rows column by column until a batch is full.
+struct BatchBuilder {
+    schema: SchemaRef,
+    columns: Vec<Vec<i64>>,
+    rows: usize,
+}
+
+impl BatchBuilder {
+    fn new(schema: SchemaRef) -> Self {
+        let columns = vec![Vec::new(); schema.fields().len()];
+        Self {
+            schema,
+            columns,
+            rows: 0,
+        }
+    }
+
+    fn push(&mut self, values: &[i64]) {
+        for (column, value) in self.columns.iter_mut().zip(values) {
+            column.push(*value);
+        }
+        self.rows += 1;
+    }
+
+    fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        let arrays = self
+            .schema
+            .fields()
+            .iter()
+            .zip(self.columns.iter_mut())
+            .map(|(field, column)| {
+                let values = std::mem::take(column).into_iter();
+                let array: ArrayRef = match field.data_type() {
+                    DataType::Int64 => Arc::new(Int64Array::from_iter_values(values)),
+                    DataType::Int32 => {
+                        Arc::new(Int32Array::from_iter_values(values.map(|v| v as i32)))
+                    }
+                    _ => Arc::new(UInt32Array::from_iter_values(values.map(|v| v as u32))),
+                };
+                array
+            })
+            .collect();
+        self.rows = 0;
+        RecordBatch::try_new(self.schema.clone(), arrays)
+    }
+}
+
+/// Calls `f` with batches of up to `batch_size` records of `kind`.
+pub(crate) fn for_each_batch<E: From<ArrowError>>(
+    mut reader: BlackboxReader<'_>,
+    kind: FieldKind,
+    batch_size: usize,
+    mut f: impl FnMut(RecordBatch) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut builder = BatchBuilder::new(schema(&reader, kind));
+    while let Some(record) = reader.next() {
+        let values = match record {
+            BlackboxRecord::Main(values)
+            | BlackboxRecord::Slow(values)
+            | BlackboxRecord::GNSS(values) => values,
+            _ => continue,
+        };
+        if values.kind() != kind {
+            continue;
+        }
+        builder.push(&values);
+        if builder.rows >= batch_size.max(1) {
+            f(builder.finish()?)?;
+        }
+    }
+    if builder.rows > 0 {
+        f(builder.finish()?)?;
+    }
+    Ok(())
+}
+
+/// Decodes every record of `kind` into batches of up to `batch_size` rows.
+pub fn record_batches(
+    reader: BlackboxReader<'_>,
+    kind: FieldKind,
+    batch_size: usize,
+) -> Result<Vec<RecordBatch>, ArrowError> {
+    let mut batches = Vec::new();
+    for_each_batch(reader, kind, batch_size, |batch| {
+        batches.push(batch);
+        Ok::<_, ArrowError>(())
+    })?;
+    Ok(batches)
+}
@@ -0,0 +1,256 @@
+//! CSV in the layout written by Betaflight's `blackbox_decode`, so that tools built around it
+//! (PIDtoolbox, PlasmaTree, ...) can read logs decoded by this crate.
+//!
+//! Rows are main frames followed by the latest slow frame values, with the units
+//! `blackbox_decode` uses by default: time in microseconds, battery voltage and current in
+//! volts and amps, and gyro and accelerometer values left raw. An `energyCumulative (mAh)`
+//! column follows the main frame fields when the log has current readings, then the fields
+//! registered with [`BlackboxReader::derive`]. Slow frame flags are written as names.
+
+use std::io::{self, Write};
+
+use crate::{
+    units::{FieldScale, Unit, Units},
+    BlackboxReader, BlackboxRecord, FailsafePhase, FieldKind, Header, MergedReader, StateFlags,
+};
+
+/// Options for [`write`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// Appends the latest GNSS frame values to every row, like `blackbox_decode --merge-gps`.
+    /// Otherwise they can be written separately with [`write_gnss`].
+    pub merge_gnss: bool,
+    /// Also converts the fields `blackbox_decode` leaves raw by default, like gyro and
+    /// accelerometer readings, to the units of [`Units`]. Time stays in microseconds.
+    pub scaled: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Raw,
+    Scaled(FieldScale, usize),
+    FlightModes,
+    StateFlags,
+    FailsafePhase,
+}
+
+struct Column {
+    label: String,
+    format: Format,
+}
+
+impl Column {
+    fn new(kind: FieldKind, name: &str, scale: FieldScale, scaled: bool) -> Self {
+        let base = name.split('[').next().unwrap_or(name);
+        let (unit, format) = match (kind, base) {
+            (_, "time") => (Some("us"), Format::Raw),
+            (FieldKind::Main, "vbatLatest" | "vbat") | (FieldKind::Slow, "sagCompensatedVBat") => {
+                (Some("V"), Format::Scaled(scale, 3))
+            }
+            (FieldKind::Main, "amperageLatest" | "amperage") => {
+                (Some("A"), Format::Scaled(scale, 3))
+            }
+            (FieldKind::Main, "BaroAlt") => (Some("m"), Format::Scaled(scale, 2)),
+            (FieldKind::Slow, "flightModeFlags" | "stateFlags" | "failsafePhase") => (
+                Some("flags"),
+                match base {
+                    "flightModeFlags" => Format::FlightModes,
+                    "stateFlags" => Format::StateFlags,
+                    _ => Format::FailsafePhase,
+                },
+            ),
+            (FieldKind::GNSS, "GPS_coord") => (None, Format::Scaled(scale, 7)),
+            (FieldKind::GNSS, "GPS_altitude") => (Some("m"), Format::Scaled(scale, 1)),
+            (FieldKind::GNSS, "GPS_speed") => (Some("m/s"), Format::Scaled(scale, 2)),
+            (FieldKind::GNSS, "GPS_ground_course") => (Some("deg"), Format::Scaled(scale, 1)),
+            (_, _) if scaled && scale.unit != Unit::Raw => {
+                (scale.unit.symbol(), Format::Scaled(scale, 3))
+            }
+            _ => (None, Format::Raw),
+        };
+        let label = match unit {
+            Some(unit) => format!("{} ({})", name, unit),
+            None => name.to_owned(),
+        };
+        Self { label, format }
+    }
+
+    fn write(&self, out: &mut impl Write, header: &Header, raw: i64) -> io::Result<()> {
+        let firmware = header.firmware_kind();
+        match self.format {
+            Format::Raw => write!(out, "{}", raw),
+            Format::Scaled(scale, decimals) => write!(out, "{:.*}", decimals, scale.apply(raw)),
+            Format::FlightModes => write!(out, "{}", header.flight_modes(raw as u32)),
+            Format::StateFlags => write!(out, "{}", StateFlags::decode(firmware, raw as u32)),
+            Format::FailsafePhase => {
+                write!(out, "{}", FailsafePhase::decode(firmware, raw as u32))
+            }
+        }
+    }
+}
+
+fn columns<'h>(
+    header: &'h Header,
+    units: &Units,
+    kind: FieldKind,
+    names: impl Iterator<Item = &'h str>,
+    scaled: bool,
+) -> Vec<Column> {
+    let ix = |name: &str| match kind {
+        FieldKind::Main => header.ip_fields.get(name).map(|f| f.ix),
+        FieldKind::Slow => header.s_fields.get(name).map(|f| f.ix),
+        FieldKind::GNSS => header.g_fields.get(name).map(|f| f.ix),
+    };
+    names
+        .map(|name| {
+            let scale = ix(name).map_or(FieldScale::RAW, |ix| units.scales(kind)[ix]);
+            Column::new(kind, name, scale, scaled)
+        })
+        .collect()
+}
+
+fn write_labels<'a>(out: &mut impl Write, labels: impl Iterator<Item = &'a str>) -> io::Result<()> {
+    for (i, label) in labels.enumerate() {
+        if i > 0 {
+            write!(out, ", ")?;
+        }
+        write!(out, "{}", label)?;
+    }
+    writeln!(out)
+}
+
+fn write_values(
+    out: &mut impl Write,
+    header: &Header,
+    cols: &[Column],
+    values: &[i64],
+) -> io::Result<()> {
+    for (i, (column, raw)) in cols.iter().zip(values).enumerate() {
+        if i > 0 {
+            write!(out, ", ")?;
+        }
+        column.write(out, header, *raw)?;
+    }
+    Ok(())
+}
+
+/// Accumulates the charge drawn from the battery, from the current and time of every row.
+struct EnergyMeter {
+    time: usize,
+    amperage: usize,
+    scale: FieldScale,
+    last: Option<(i64, f64)>,
+    milliamp_hours: f64,
+}
+
+impl EnergyMeter {
+    fn new(header: &Header, units: &Units, names: &[String]) -> Option<Self> {
+        let position = |name: &str| names.iter().position(|n| n == name);
+        let amperage = ["amperageLatest", "amperage"]
+            .into_iter()
+            .find(|name| position(name).is_some())?;
+        Some(Self {
+            time: position("time")?,
+            amperage: position(amperage)?,
+            scale: units.scales(FieldKind::Main)[header.ip_fields[amperage].ix],
+            last: None,
+            milliamp_hours: 0.0,
+        })
+    }
+
+    fn update(&mut self, row: &[i64]) -> i64 {
+        let (time, amps) = (row[self.time], self.scale.apply(row[self.amperage]));
+        if let Some((last_time, last_amps)) = self.last {
+            let hours = (time - last_time).max(0) as f64 / 3_600_000_000.0;
+            self.milliamp_hours += (last_amps + amps) / 2.0 * hours * 1000.0;
+        }
+        self.last = Some((time, amps));
+        self.milliamp_hours.round() as i64
+    }
+}
+
+/// Writes main frames merged with slow (and optionally GNSS) frame values.
+pub fn write(
+    reader: BlackboxReader<'_>,
+    out: &mut impl Write,
+    options: CsvOptions,
+) -> io::Result<()> {
+    let header = reader.header.clone();
+    let units = Units::new(&header);
+    let main_names: Vec<String> = reader.main_field_names().map(str::to_owned).collect();
+    let main_len = main_names.len();
+
+    let mut cols = columns(
+        &header,
+        &units,
+        FieldKind::Main,
+        main_names.iter().map(|n| &n[..]),
+        options.scaled,
+    );
+    cols.extend(columns(
+        &header,
+        &units,
+        FieldKind::Slow,
+        header.s_fields_in_order.iter().map(|f| &f.name[..]),
+        options.scaled,
+    ));
+    let mut reader = MergedReader::new(reader);
+    if options.merge_gnss {
+        reader = reader.with_gnss();
+        cols.extend(columns(
+            &header,
+            &units,
+            FieldKind::GNSS,
+            header.g_fields_in_order.iter().map(|f| &f.name[..]),
+            options.scaled,
+        ));
+    }
+
+    let mut energy = EnergyMeter::new(&header, &units, &main_names);
+    let mut labels: Vec<&str> = cols.iter().map(|c| &c.label[..]).collect();
+    let derived: Vec<String> = reader.derived_names().map(str::to_owned).collect();
+    labels.splice(main_len..main_len, derived.iter().map(|n| &n[..]));
+    if energy.is_some() {
+        labels.insert(main_len, "energyCumulative (mAh)");
+    }
+    write_labels(out, labels.into_iter())?;
+
+    let (main_cols, other_cols) = cols.split_at(main_len);
+    while let Some(row) = reader.next() {
+        write_values(out, &header, main_cols, &row[..main_len])?;
+        if let Some(energy) = &mut energy {
+            write!(out, ", {}", energy.update(&row))?;
+        }
+        for value in row.derived() {
+            write!(out, ", {}", value)?;
+        }
+        if !other_cols.is_empty() {
+            write!(out, ", ")?;
+            write_values(out, &header, other_cols, &row[main_len..])?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Writes GNSS frames on their own, like the `.gps.csv` file of `blackbox_decode`.
+pub fn write_gnss(mut reader: BlackboxReader<'_>, out: &mut impl Write) -> io::Result<()> {
+    let header = reader.header.clone();
+    let units = Units::new(&header);
+    let cols = columns(
+        &header,
+        &units,
+        FieldKind::GNSS,
+        header.g_fields_in_order.iter().map(|f| &f.name[..]),
+        false,
+    );
+    write_labels(out, cols.iter().map(|c| &c.label[..]))?;
+
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::GNSS(values) = record {
+            write_values(out, &header, &cols, &values)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! GPX track of the GNSS positions, for mapping tools and flight log sites.
+
+use std::io::{self, Write};
+
+use super::track::{read_track, xml_escape};
+use crate::BlackboxReader;
+
+/// Writes the GNSS frames with a fix as a single GPX track. Points have times only when the
+/// log has a start time.
+pub fn write(reader: BlackboxReader<'_>, out: &mut impl Write) -> io::Result<()> {
+    let name = reader.header.craft_name().map(xml_escape);
+    let track = read_track(reader);
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<gpx version="1.1" creator="fc-blackbox" xmlns="http://www.topografix.com/GPX/1/1">"#
+    )?;
+    writeln!(out, "<trk>")?;
+    if let Some(name) = name {
+        writeln!(out, "<name>{}</name>", name)?;
+    }
+    writeln!(out, "<trkseg>")?;
+    for point in track {
+        write!(
+            out,
+            r#"<trkpt lat="{:.7}" lon="{:.7}"><ele>{:.1}</ele>"#,
+            point.latitude, point.longitude, point.altitude
+        )?;
+        if let Some(time) = point.time {
+            write!(out, "<time>{}</time>", time.to_rfc3339())?;
+        }
+        writeln!(out, "</trkpt>")?;
+    }
+    writeln!(out, "</trkseg>")?;
+    writeln!(out, "</trk>")?;
+    writeln!(out, "</gpx>")
+}
@@ -0,0 +1,145 @@
+//! Gyro and accelerometer data for [Gyroflow](https://gyroflow.xyz), as samples or as a
+//! `.gcsv` file.
+
+use std::io::{self, Write};
+
+use crate::{BlackboxReader, BlackboxRecord, Header};
+
+const GYRO_FIELDS: [&str; 3] = ["gyroADC[0]", "gyroADC[1]", "gyroADC[2]"];
+const ACC_FIELDS: [&str; 3] = ["accSmooth[0]", "accSmooth[1]", "accSmooth[2]"];
+
+/// Headers holding the gyro sensor alignment, depending on the firmware version.
+const GYRO_ALIGN_HEADERS: [&str; 3] = ["gyro_1_align", "gyro_align", "align_gyro"];
+
+/// Gyro and accelerometer readings of one main frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GyroSample {
+    /// Seconds, with 32-bit rollovers of the logged time accounted for.
+    pub time: f64,
+    /// Rad/s around the flight controller's roll, pitch and yaw axes.
+    pub gyro: [f64; 3],
+    /// Acceleration in g, `None` if the log has no accelerometer fields.
+    pub acc: Option<[f64; 3]>,
+}
+
+/// Gyroflow orientation string of the gyro, mapping the logged axes the way the sensor
+/// alignment in the header (`CW90`, `CW180FLIP`, ...) rotates them. Lowercase letters are
+/// negated axes, `XYZ` if the header has no alignment.
+pub fn orientation(header: &Header) -> &'static str {
+    let align = GYRO_ALIGN_HEADERS
+        .iter()
+        .find_map(|name| header.get_u32(name).ok());
+    match align {
+        Some(2) => "YxZ",
+        Some(3) => "xyZ",
+        Some(4) => "yXZ",
+        Some(5) => "xYz",
+        Some(6) => "YXz",
+        Some(7) => "Xyz",
+        Some(8) => "yxz",
+        _ => "XYZ",
+    }
+}
+
+fn positions<const N: usize>(
+    values: &crate::FieldView<'_>,
+    names: [&str; N],
+) -> Option<[usize; N]> {
+    let mut positions = [0; N];
+    for (position, name) in positions.iter_mut().zip(names) {
+        *position = values.index_of(name)?;
+    }
+    Some(positions)
+}
+
+/// Calls `f` with the widened time and the raw gyro and accelerometer values of every main
+/// frame.
+fn for_each_raw(
+    mut reader: BlackboxReader<'_>,
+    mut f: impl FnMut(i64, [i64; 3], Option<[i64; 3]>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut fields = None;
+    while let Some(record) = reader.next() {
+        let BlackboxRecord::Main(values) = record else {
+            continue;
+        };
+        let (gyro, acc) = match fields {
+            Some(fields) => fields,
+            None => {
+                let gyro = positions(&values, GYRO_FIELDS).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "log has no gyroADC fields")
+                })?;
+                *fields.insert((gyro, positions(&values, ACC_FIELDS)))
+            }
+        };
+        let (gyro, acc) = (
+            gyro.map(|ix| values[ix]),
+            acc.map(|acc| acc.map(|ix| values[ix])),
+        );
+        f(reader.last_widened_time, gyro, acc)?;
+    }
+    Ok(())
+}
+
+/// Scales converting raw gyro values to rad/s and accelerometer values to g.
+fn scales(header: &Header) -> (f64, f64) {
+    let gyro = (header.raw_gyro_scale as f64).to_radians();
+    let acc_1g = header
+        .settings
+        .acc_1g
+        .filter(|v| *v > 0)
+        .map_or(1.0, f64::from);
+    (gyro, 1.0 / acc_1g)
+}
+
+/// Reads the gyro and accelerometer values of every main frame.
+pub fn samples(reader: BlackboxReader<'_>) -> io::Result<Vec<GyroSample>> {
+    let (gyro_scale, acc_scale) = scales(&reader.header);
+    let mut samples = Vec::new();
+    for_each_raw(reader, |time, gyro, acc| {
+        samples.push(GyroSample {
+            time: time as f64 * 1e-6,
+            gyro: gyro.map(|v| v as f64 * gyro_scale),
+            acc: acc.map(|acc| acc.map(|v| v as f64 * acc_scale)),
+        });
+        Ok(())
+    })?;
+    Ok(samples)
+}
+
+/// Writes a Gyroflow `.gcsv` file with the raw values and their scales.
+pub fn write_gcsv(reader: BlackboxReader<'_>, out: &mut impl Write) -> io::Result<()> {
+    let header = &reader.header;
+    let (gyro_scale, acc_scale) = scales(header);
+
+    writeln!(out, "GYROFLOW IMU LOG")?;
+    writeln!(out, "version,1.3")?;
+    writeln!(out, "id,fc-blackbox")?;
+    writeln!(out, "orientation,{}", orientation(header))?;
+    if let Some(revision) = header.firmware_revision() {
+        writeln!(out, "fwversion,{}", revision)?;
+    }
+    if let Some(start) = header.log_start_datetime() {
+        writeln!(out, "timestamp,{}", start.timestamp())?;
+    }
+    writeln!(out, "vendor,{:?}", header.firmware_kind())?;
+    writeln!(out, "tscale,0.000001")?;
+    writeln!(out, "gscale,{}", gyro_scale)?;
+    writeln!(out, "ascale,{}", acc_scale)?;
+
+    let mut first = None;
+    let mut wrote_labels = false;
+    for_each_raw(reader, |time, [gx, gy, gz], acc| {
+        if !wrote_labels {
+            let acc_labels = if acc.is_some() { ",ax,ay,az" } else { "" };
+            writeln!(out, "t,gx,gy,gz{}", acc_labels)?;
+            wrote_labels = true;
+        }
+        let t = time - *first.get_or_insert(time);
+        write!(out, "{},{},{},{}", t, gx, gy, gz)?;
+        if let Some([ax, ay, az]) = acc {
+            write!(out, ",{},{},{}", ax, ay, az)?;
+        }
+        writeln!(out)
+    })
+}
@@ -0,0 +1,393 @@
+//! HDF5 files for numerical tools like MATLAB, h5py and Julia, written without linking the
+//! HDF5 library.
+//!
+//! Every field is a 1D `int64` dataset of the values as logged in the `/main`, `/slow` or
+//! `/gnss` group, with `unit`, `scale` and `offset` attributes to convert them to physical
+//! units (`value * scale + offset`). The headers are attributes of the root group.
+//!
+//! Files use the original HDF5 format (superblock version 0, symbol table groups and
+//! contiguous storage), which every HDF5 version can read.
+
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use crate::{
+    units::{FieldScale, Units},
+    BlackboxReader, BlackboxRecord, FieldKind, Header,
+};
+
+const SIGNATURE: &[u8] = b"\x89HDF\r\n\x1a\n";
+const UNDEFINED: u64 = u64::MAX;
+/// Children of a group B-tree node, twice the internal node K of the superblock.
+const BTREE_CHILDREN: usize = 32;
+/// Local heap free list offset for an empty free list.
+const HEAP_NO_FREE_BLOCK: u64 = 1;
+const SUPERBLOCK_SIZE: usize = 96;
+const SYMBOL_ENTRY_SIZE: usize = 40;
+
+const MSG_DATASPACE: u16 = 0x0001;
+const MSG_DATATYPE: u16 = 0x0003;
+const MSG_FILL_VALUE: u16 = 0x0005;
+const MSG_LAYOUT: u16 = 0x0008;
+const MSG_ATTRIBUTE: u16 = 0x000c;
+const MSG_SYMBOL_TABLE: u16 = 0x0011;
+
+fn pad8(buf: &mut Vec<u8>) {
+    buf.resize(buf.len().next_multiple_of(8), 0);
+}
+
+fn u64_le(buf: &mut Vec<u8>, value: u64) {
+    buf.extend(value.to_le_bytes());
+}
+
+/// Attribute values.
+enum Value<'a> {
+    Str(&'a str),
+    F64(f64),
+}
+
+impl Value<'_> {
+    fn datatype(&self) -> Vec<u8> {
+        let mut dt = Vec::new();
+        match self {
+            // Version 1, class 3: null terminated UTF-8
+            Value::Str(s) => {
+                dt.extend([0x13, 0x10, 0, 0]);
+                dt.extend((s.len() as u32 + 1).to_le_bytes());
+            }
+            Value::F64(_) => dt.extend(F64_DATATYPE),
+        }
+        dt
+    }
+
+    fn data(&self) -> Vec<u8> {
+        match self {
+            Value::Str(s) => [s.as_bytes(), &[0]].concat(),
+            Value::F64(v) => v.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+/// Version 1, class 1: little-endian IEEE double.
+const F64_DATATYPE: [u8; 20] = [
+    0x11, 0x20, 63, 0, 8, 0, 0, 0, // class, bit field, size
+    0, 0, 64, 0, // bit offset, precision
+    52, 11, 0, 52, // exponent location and size, mantissa location and size
+    0xff, 0x03, 0, 0, // exponent bias
+];
+
+/// Version 1, class 0: little-endian signed 64-bit integer.
+const I64_DATATYPE: [u8; 12] = [0x10, 0x08, 0, 0, 8, 0, 0, 0, 0, 0, 64, 0];
+
+fn dataspace(dims: &[u64]) -> Vec<u8> {
+    let mut space = vec![1, dims.len() as u8, 0, 0, 0, 0, 0, 0];
+    for dim in dims {
+        u64_le(&mut space, *dim);
+    }
+    space
+}
+
+fn attribute(name: &str, value: &Value) -> Vec<u8> {
+    let datatype = value.datatype();
+    let space = dataspace(&[]);
+    let mut msg = vec![1, 0];
+    msg.extend((name.len() as u16 + 1).to_le_bytes());
+    msg.extend((datatype.len() as u16).to_le_bytes());
+    msg.extend((space.len() as u16).to_le_bytes());
+    msg.extend(name.as_bytes());
+    msg.push(0);
+    pad8(&mut msg);
+    msg.extend(&datatype);
+    pad8(&mut msg);
+    msg.extend(&space);
+    pad8(&mut msg);
+    msg.extend(value.data());
+    msg
+}
+
+/// In-memory HDF5 file, objects are appended after everything they point to.
+struct File {
+    buf: Vec<u8>,
+}
+
+impl File {
+    fn address(&self) -> u64 {
+        self.buf.len() as u64
+    }
+
+    /// Appends a version 1 object header, returning its address.
+    fn object_header(&mut self, messages: &[(u16, Vec<u8>)]) -> u64 {
+        let address = self.address();
+        let mut body = Vec::new();
+        for (kind, data) in messages {
+            let size = data.len().next_multiple_of(8);
+            body.extend(kind.to_le_bytes());
+            body.extend((size as u16).to_le_bytes());
+            body.extend([0; 4]);
+            body.extend(data);
+            pad8(&mut body);
+        }
+        self.buf.extend([1, 0]);
+        self.buf.extend((messages.len() as u16).to_le_bytes());
+        self.buf.extend(1u32.to_le_bytes());
+        self.buf.extend((body.len() as u32).to_le_bytes());
+        pad8(&mut self.buf);
+        self.buf.extend(body);
+        address
+    }
+
+    fn dataset(&mut self, values: &[i64], attributes: &[(&str, Value)]) -> u64 {
+        let data = self.address();
+        for value in values {
+            self.buf.extend(value.to_le_bytes());
+        }
+        let mut layout = vec![3, 1];
+        u64_le(
+            &mut layout,
+            if values.is_empty() { UNDEFINED } else { data },
+        );
+        u64_le(&mut layout, values.len() as u64 * 8);
+
+        let mut messages = vec![
+            (MSG_DATASPACE, dataspace(&[values.len() as u64])),
+            (MSG_DATATYPE, I64_DATATYPE.to_vec()),
+            // Version 2, late allocation, fill value not defined
+            (MSG_FILL_VALUE, vec![2, 2, 2, 0]),
+            (MSG_LAYOUT, layout),
+        ];
+        messages.extend(
+            attributes
+                .iter()
+                .map(|(name, value)| (MSG_ATTRIBUTE, attribute(name, value))),
+        );
+        self.object_header(&messages)
+    }
+
+    /// Appends a group of `children`, returning the addresses of its object header, B-tree and
+    /// local heap. `leaf_k` is the symbol table node K of the superblock.
+    fn group(
+        &mut self,
+        children: &mut [Child],
+        attributes: &[(&str, Value)],
+        leaf_k: usize,
+    ) -> (u64, u64, u64) {
+        // Symbol table nodes are searched by name
+        children.sort_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+
+        let mut heap_data = vec![0; 8];
+        let name_offsets: Vec<u64> = children
+            .iter()
+            .map(|child| {
+                let offset = heap_data.len() as u64;
+                heap_data.extend(child.name.as_bytes());
+                heap_data.push(0);
+                pad8(&mut heap_data);
+                offset
+            })
+            .collect();
+        let heap = self.address();
+        self.buf.extend(b"HEAP\0\0\0\0");
+        u64_le(&mut self.buf, heap_data.len() as u64);
+        u64_le(&mut self.buf, HEAP_NO_FREE_BLOCK);
+        u64_le(&mut self.buf, heap + 32);
+        self.buf.extend(&heap_data);
+
+        let node = self.address();
+        self.buf.extend(b"SNOD\x01\0");
+        self.buf.extend((children.len() as u16).to_le_bytes());
+        for (child, name_offset) in children.iter().zip(&name_offsets) {
+            u64_le(&mut self.buf, *name_offset);
+            u64_le(&mut self.buf, child.header);
+            match child.scratch {
+                Some((btree, heap)) => {
+                    self.buf.extend(1u32.to_le_bytes());
+                    self.buf.extend([0; 4]);
+                    u64_le(&mut self.buf, btree);
+                    u64_le(&mut self.buf, heap);
+                }
+                None => self.buf.extend([0; 24]),
+            }
+        }
+        let node_size = 8 + 2 * leaf_k * SYMBOL_ENTRY_SIZE;
+        self.buf.resize(node as usize + node_size, 0);
+
+        let btree = self.address();
+        self.buf.extend(b"TREE\0\0");
+        self.buf.extend(1u16.to_le_bytes());
+        u64_le(&mut self.buf, UNDEFINED);
+        u64_le(&mut self.buf, UNDEFINED);
+        // Keys around the single child: the empty name and the last name in the node
+        u64_le(&mut self.buf, 0);
+        u64_le(&mut self.buf, node);
+        u64_le(&mut self.buf, name_offsets.last().copied().unwrap_or(0));
+        let btree_size = 24 + BTREE_CHILDREN * 8 + (BTREE_CHILDREN + 1) * 8;
+        self.buf.resize(btree as usize + btree_size, 0);
+
+        let mut symbol_table = Vec::new();
+        u64_le(&mut symbol_table, btree);
+        u64_le(&mut symbol_table, heap);
+        let mut messages = vec![(MSG_SYMBOL_TABLE, symbol_table)];
+        messages.extend(
+            attributes
+                .iter()
+                .map(|(name, value)| (MSG_ATTRIBUTE, attribute(name, value))),
+        );
+        (self.object_header(&messages), btree, heap)
+    }
+}
+
+/// Link from a group to a dataset or, with its B-tree and heap addresses, another group.
+struct Child {
+    name: String,
+    header: u64,
+    scratch: Option<(u64, u64)>,
+}
+
+/// Writes every main, slow and GNSS field of `reader` as a dataset.
+pub fn write(mut reader: BlackboxReader<'_>, out: &mut impl Write) -> io::Result<()> {
+    let header = reader.header.clone();
+    let units = Units::new(&header);
+    let kinds = [FieldKind::Main, FieldKind::Slow, FieldKind::GNSS];
+    let mut columns: Vec<Vec<Vec<i64>>> = kinds
+        .iter()
+        .map(|kind| vec![Vec::new(); units.scales(*kind).len()])
+        .collect();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(view)
+        | BlackboxRecord::Slow(view)
+        | BlackboxRecord::GNSS(view) = record
+        {
+            let kind = kinds.iter().position(|k| *k == view.kind()).unwrap_or(0);
+            for (column, value) in columns[kind].iter_mut().zip(view.values()) {
+                column.push(*value);
+            }
+        }
+    }
+
+    let names = |kind: FieldKind| -> Vec<&str> {
+        match kind {
+            FieldKind::Main => header
+                .ip_fields_in_order
+                .iter()
+                .map(|f| &f.name[..])
+                .collect(),
+            FieldKind::Slow => header
+                .s_fields_in_order
+                .iter()
+                .map(|f| &f.name[..])
+                .collect(),
+            FieldKind::GNSS => header
+                .g_fields_in_order
+                .iter()
+                .map(|f| &f.name[..])
+                .collect(),
+        }
+    };
+    let leaf_k = kinds
+        .iter()
+        .map(|kind| names(*kind).len())
+        .chain([kinds.len()])
+        .max()
+        .unwrap_or(0)
+        .div_ceil(2)
+        .max(4);
+
+    let mut file = File {
+        buf: vec![0; SUPERBLOCK_SIZE],
+    };
+    let mut groups = Vec::new();
+    for (kind, columns) in kinds.iter().zip(&columns) {
+        let scales = units.scales(*kind);
+        let mut children: Vec<Child> = dataset_names(&names(*kind))
+            .into_iter()
+            .zip(columns)
+            .zip(scales)
+            .map(|((name, values), scale)| Child {
+                name,
+                header: file.dataset(values, &scale_attributes(scale)),
+                scratch: None,
+            })
+            .collect();
+        let (group, btree, heap) = file.group(&mut children, &[], leaf_k);
+        let name = match kind {
+            FieldKind::Main => "main",
+            FieldKind::Slow => "slow",
+            FieldKind::GNSS => "gnss",
+        };
+        groups.push(Child {
+            name: name.to_owned(),
+            header: group,
+            scratch: Some((btree, heap)),
+        });
+    }
+    let (root, btree, heap) = file.group(&mut groups, &header_attributes(&header), leaf_k);
+
+    let eof = file.address();
+    let superblock = &mut file.buf[..SUPERBLOCK_SIZE];
+    superblock[..8].copy_from_slice(SIGNATURE);
+    // Superblock, free space, root group entry, reserved, shared header message versions,
+    // 8 byte offsets and lengths
+    superblock[8..16].copy_from_slice(&[0, 0, 0, 0, 0, 8, 8, 0]);
+    superblock[16..18].copy_from_slice(&(leaf_k as u16).to_le_bytes());
+    superblock[18..20].copy_from_slice(&(BTREE_CHILDREN as u16 / 2).to_le_bytes());
+    for (i, address) in [0, UNDEFINED, eof, UNDEFINED, 0, root].iter().enumerate() {
+        let at = 24 + i * 8;
+        superblock[at..at + 8].copy_from_slice(&address.to_le_bytes());
+    }
+    // Root group symbol table entry, after the link name offset and object header address
+    superblock[72..76].copy_from_slice(&1u32.to_le_bytes());
+    superblock[80..88].copy_from_slice(&btree.to_le_bytes());
+    superblock[88..96].copy_from_slice(&heap.to_le_bytes());
+    out.write_all(&file.buf)
+}
+
+/// Link names for the fields of a group. `/` separates the path of a link, so it's replaced,
+/// and names that end up the same get a `_2`, `_3`... suffix to stay unique.
+fn dataset_names(fields: &[&str]) -> Vec<String> {
+    let mut used = HashSet::new();
+    fields
+        .iter()
+        .map(|field| {
+            let base = field.replace('/', "_");
+            let name = (1..)
+                .map(|n| match n {
+                    1 => base.clone(),
+                    n => format!("{base}_{n}"),
+                })
+                .find(|name| !used.contains(name))
+                .unwrap_or_default();
+            used.insert(name.clone());
+            name
+        })
+        .collect()
+}
+
+fn scale_attributes(scale: &FieldScale) -> [(&'static str, Value<'static>); 3] {
+    [
+        ("unit", Value::Str(scale.unit.symbol().unwrap_or(""))),
+        ("scale", Value::F64(scale.scale)),
+        ("offset", Value::F64(scale.offset)),
+    ]
+}
+
+fn header_attributes(header: &Header) -> Vec<(&str, Value<'_>)> {
+    let mut attributes = vec![
+        ("Product", Value::Str(header.product())),
+        ("Data version", Value::Str(header.data_version())),
+    ];
+    for (name, value) in [
+        ("Firmware type", header.firmware_type()),
+        ("Firmware revision", header.firmware_revision()),
+        ("Craft name", header.craft_name()),
+    ] {
+        if let Some(value) = value {
+            attributes.push((name, Value::Str(value)));
+        }
+    }
+    let mut other: Vec<_> = header.other_headers.iter().collect();
+    other.sort();
+    attributes.extend(other.into_iter().map(|(k, v)| (&k[..], Value::Str(v))));
+    attributes
+}
@@ -0,0 +1,189 @@
+//! InfluxDB line protocol, for ingesting flights into time series databases and Grafana.
+//!
+//! Every frame is a line of the `<prefix>_main`, `<prefix>_slow` or `<prefix>_gnss`
+//! measurement, tagged with the craft name and firmware revision when the log has them.
+//! Timestamps are in nanoseconds: the log start datetime plus the time since the first main
+//! frame, or the time since the flight controller booted for logs without a start datetime.
+
+use std::io::{self, Write};
+
+use crate::{
+    units::{FieldScale, Units},
+    BlackboxReader, BlackboxRecord, FieldKind, Header,
+};
+
+/// Options for [`write`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfluxOptions {
+    /// Converts values to physical units with [`Units`] and writes them as floats, instead of
+    /// writing the integers as logged.
+    pub scaled: bool,
+    /// Start of the measurement names.
+    pub measurement_prefix: String,
+    /// Tags added to every line, after the ones from the header.
+    pub tags: Vec<(String, String)>,
+}
+
+impl Default for InfluxOptions {
+    fn default() -> Self {
+        Self {
+            scaled: false,
+            measurement_prefix: "blackbox".to_owned(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Escapes the characters that are special in measurement names, tag keys and values, and
+/// field keys.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Measurement and tags, the start of every line of one frame kind.
+fn line_start(kind: &str, header: &Header, options: &InfluxOptions) -> String {
+    let mut line = escape(&format!("{}_{}", options.measurement_prefix, kind));
+    let tags = [
+        ("craft", header.craft_name()),
+        ("firmware", header.firmware_revision()),
+    ];
+    let tags = tags
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .chain(options.tags.iter().map(|(k, v)| (&k[..], &v[..])));
+    for (key, value) in tags {
+        line += &format!(",{}={}", escape(key), escape(value));
+    }
+    line
+}
+
+struct Measurement {
+    start: String,
+    /// Escaped field keys and scales in header field order
+    fields: Vec<(String, FieldScale)>,
+}
+
+impl Measurement {
+    fn new<'a>(
+        kind: &str,
+        names: impl Iterator<Item = &'a str>,
+        scales: &[FieldScale],
+        header: &Header,
+        options: &InfluxOptions,
+    ) -> Self {
+        Self {
+            start: line_start(kind, header, options),
+            fields: names.map(escape).zip(scales.iter().copied()).collect(),
+        }
+    }
+
+    fn write(
+        &self,
+        out: &mut impl Write,
+        values: &[i64],
+        scaled: bool,
+        timestamp: i64,
+    ) -> io::Result<()> {
+        write!(out, "{}", self.start)?;
+        for (i, ((key, scale), value)) in self.fields.iter().zip(values).enumerate() {
+            let separator = if i == 0 { ' ' } else { ',' };
+            match scaled {
+                true => {
+                    let value = scale.apply(*value);
+                    if value.is_finite() {
+                        write!(out, "{}{}={}", separator, key, value)?;
+                    }
+                }
+                false => write!(out, "{}{}={}i", separator, key, value)?,
+            }
+        }
+        writeln!(out, " {}", timestamp)
+    }
+}
+
+/// Writes every main, slow and GNSS frame of `reader` as a line.
+pub fn write(
+    mut reader: BlackboxReader<'_>,
+    out: &mut impl Write,
+    options: &InfluxOptions,
+) -> io::Result<()> {
+    let header = reader.header.clone();
+    let units = Units::new(&header);
+    let measurement = |kind: FieldKind, name: &str, names: Vec<&str>| {
+        Measurement::new(
+            name,
+            names.into_iter(),
+            units.scales(kind),
+            &header,
+            options,
+        )
+    };
+    let main = measurement(
+        FieldKind::Main,
+        "main",
+        header
+            .ip_fields_in_order
+            .iter()
+            .map(|f| &f.name[..])
+            .collect(),
+    );
+    let slow = measurement(
+        FieldKind::Slow,
+        "slow",
+        header
+            .s_fields_in_order
+            .iter()
+            .map(|f| &f.name[..])
+            .collect(),
+    );
+    let gnss = measurement(
+        FieldKind::GNSS,
+        "gnss",
+        header
+            .g_fields_in_order
+            .iter()
+            .map(|f| &f.name[..])
+            .collect(),
+    );
+
+    let start = header
+        .log_start_datetime()
+        .and_then(|datetime| datetime.timestamp_nanos_opt());
+    let mut first_time = None;
+    let mut values = Vec::new();
+    while let Some(record) = reader.next() {
+        let kind = match record {
+            BlackboxRecord::Main(view)
+            | BlackboxRecord::Slow(view)
+            | BlackboxRecord::GNSS(view) => {
+                values.clear();
+                values.extend_from_slice(view.values());
+                view.kind()
+            }
+            _ => continue,
+        };
+        let time = reader.last_widened_time;
+        let measurement = match kind {
+            FieldKind::Main => {
+                first_time.get_or_insert(time);
+                &main
+            }
+            FieldKind::Slow => &slow,
+            FieldKind::GNSS => &gnss,
+        };
+        let timestamp = match (start, first_time) {
+            (Some(start), Some(first)) => start + (time - first) * 1000,
+            (Some(start), None) => start,
+            (None, _) => time * 1000,
+        };
+        measurement.write(out, &values, options.scaled, timestamp)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! Newline delimited JSON, one object per line: a `header` object first, then one object per
+//! record, for piping into `jq`, databases or web viewers.
+//!
+//! Every object has a `type` key: `header`, `main`, `slow`, `gnss`, `event` or `garbage`.
+//! Frame objects map field names to values, with the fields registered with
+//! [`BlackboxReader::derive`] after the others in main frames. Events are written as their
+//! debug representation.
+
+use std::io::{self, Write};
+
+use crate::{
+    units::{ScaledRecord, Units},
+    BlackboxReader, BlackboxRecord, FieldKind, FieldView, Header,
+};
+
+/// Options for [`write`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JsonOptions {
+    /// Converts values to physical units with [`Units`] instead of writing them as logged.
+    pub scaled: bool,
+    /// Only writes the named fields of every frame kind, all of them if `None`.
+    pub fields: Option<Vec<String>>,
+    /// Also writes events and garbage records.
+    pub events: bool,
+}
+
+impl JsonOptions {
+    fn includes(&self, name: &str) -> bool {
+        self.fields
+            .as_ref()
+            .is_none_or(|fields| fields.iter().any(|f| f == name))
+    }
+}
+
+fn write_str(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")
+}
+
+fn write_f64(out: &mut impl Write, value: f64) -> io::Result<()> {
+    if value.is_finite() {
+        write!(out, "{}", value)
+    } else {
+        write!(out, "null")
+    }
+}
+
+fn write_names<'a>(out: &mut impl Write, names: impl Iterator<Item = &'a str>) -> io::Result<()> {
+    write!(out, "[")?;
+    for (i, name) in names.enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write_str(out, name)?;
+    }
+    write!(out, "]")
+}
+
+fn write_header(out: &mut impl Write, header: &Header) -> io::Result<()> {
+    write!(out, "{{\"type\":\"header\",\"product\":")?;
+    write_str(out, header.product())?;
+    write!(out, ",\"data_version\":")?;
+    write_str(out, header.data_version())?;
+    for (key, value) in [
+        ("firmware_type", header.firmware_type()),
+        ("firmware_revision", header.firmware_revision()),
+        ("craft_name", header.craft_name()),
+    ] {
+        if let Some(value) = value {
+            write!(out, ",\"{}\":", key)?;
+            write_str(out, value)?;
+        }
+    }
+
+    write!(out, ",\"fields\":{{\"main\":")?;
+    write_names(out, header.ip_fields_in_order.iter().map(|f| &f.name[..]))?;
+    write!(out, ",\"slow\":")?;
+    write_names(out, header.s_fields_in_order.iter().map(|f| &f.name[..]))?;
+    write!(out, ",\"gnss\":")?;
+    write_names(out, header.g_fields_in_order.iter().map(|f| &f.name[..]))?;
+
+    write!(out, "}},\"headers\":{{")?;
+    let mut headers: Vec<_> = header.other_headers.iter().collect();
+    headers.sort();
+    for (i, (name, value)) in headers.into_iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write_str(out, name)?;
+        write!(out, ":")?;
+        write_str(out, value)?;
+    }
+    writeln!(out, "}}}}")
+}
+
+fn write_frame(
+    out: &mut impl Write,
+    view: FieldView<'_>,
+    derived_names: &[String],
+    units: &Units,
+    options: &JsonOptions,
+) -> io::Result<()> {
+    let kind = match view.kind() {
+        FieldKind::Main => "main",
+        FieldKind::Slow => "slow",
+        FieldKind::GNSS => "gnss",
+    };
+    write!(out, "{{\"type\":\"{}\"", kind)?;
+    let scaled: Option<ScaledRecord> = options.scaled.then(|| units.scale(view));
+    for (position, (name, raw)) in view.iter().enumerate() {
+        if !options.includes(name) {
+            continue;
+        }
+        write!(out, ",")?;
+        write_str(out, name)?;
+        write!(out, ":")?;
+        match &scaled {
+            Some(scaled) => write_f64(out, scaled.get(position).unwrap_or(f64::NAN))?,
+            None => write!(out, "{}", raw)?,
+        }
+    }
+    for (name, value) in derived_names.iter().zip(view.derived()) {
+        if options.includes(name) {
+            write!(out, ",")?;
+            write_str(out, name)?;
+            write!(out, ":")?;
+            write_f64(out, *value)?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+/// Writes the header and every record of `reader`.
+pub fn write(
+    mut reader: BlackboxReader<'_>,
+    out: &mut impl Write,
+    options: &JsonOptions,
+) -> io::Result<()> {
+    let header = reader.header.clone();
+    let units = Units::new(&header);
+    let derived_names: Vec<String> = reader.derived_names().map(str::to_owned).collect();
+    write_header(out, &header)?;
+
+    while let Some(record) = reader.next() {
+        match record {
+            BlackboxRecord::Main(view)
+            | BlackboxRecord::Slow(view)
+            | BlackboxRecord::GNSS(view) => {
+                write_frame(out, view, &derived_names, &units, options)?
+            }
+            BlackboxRecord::Event(event) if options.events => {
+                write!(out, "{{\"type\":\"event\",\"event\":")?;
+                write_str(out, &format!("{:?}", event))?;
+                writeln!(out, "}}")?;
+            }
+            BlackboxRecord::Garbage(span) if options.events => writeln!(
+                out,
+                "{{\"type\":\"garbage\",\"offset\":{},\"len\":{}}}",
+                span.offset, span.len
+            )?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,192 @@
+//! KML and KMZ flight paths for Google Earth.
+
+use std::io::{self, Write};
+
+use super::track::{read_track, xml_escape, TrackPoint};
+use crate::BlackboxReader;
+
+/// Number of colors the speed range is split into.
+const SPEED_COLORS: usize = 8;
+
+/// Options for [`write`] and [`write_kmz`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KmlOptions {
+    /// Draws a wall from the path down to the ground.
+    pub extrude: bool,
+    /// Colors the path from blue at the lowest ground speed to red at the highest.
+    pub color_by_speed: bool,
+}
+
+impl Default for KmlOptions {
+    fn default() -> Self {
+        Self {
+            extrude: true,
+            color_by_speed: false,
+        }
+    }
+}
+
+/// KML color (`aabbggrr`) of speed bucket `bucket`, from blue to red.
+fn speed_color(bucket: usize) -> String {
+    let t = bucket as f64 / (SPEED_COLORS - 1) as f64;
+    let red = (255.0 * t).round() as u8;
+    let blue = 255 - red;
+    format!("ff{:02x}00{:02x}", blue, red)
+}
+
+fn write_path(
+    out: &mut impl Write,
+    points: &[TrackPoint],
+    style: &str,
+    options: &KmlOptions,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "<Placemark><styleUrl>#{}</styleUrl><LineString>",
+        style
+    )?;
+    writeln!(out, "<extrude>{}</extrude>", options.extrude as u8)?;
+    writeln!(out, "<altitudeMode>absolute</altitudeMode>")?;
+    write!(out, "<coordinates>")?;
+    for point in points {
+        write!(
+            out,
+            "{:.7},{:.7},{:.1} ",
+            point.longitude, point.latitude, point.altitude
+        )?;
+    }
+    writeln!(out, "</coordinates>")?;
+    writeln!(out, "</LineString></Placemark>")
+}
+
+/// Writes the GNSS frames with a fix as a KML document.
+pub fn write(
+    reader: BlackboxReader<'_>,
+    out: &mut impl Write,
+    options: KmlOptions,
+) -> io::Result<()> {
+    let name = reader
+        .header
+        .craft_name()
+        .map_or_else(|| "Flight".to_owned(), xml_escape);
+    let track = read_track(reader);
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(out, "<Document><name>{}</name>", name)?;
+    let line_style = |out: &mut dyn Write, id: &str, color: &str| {
+        writeln!(
+            out,
+            "<Style id=\"{}\"><LineStyle><color>{}</color><width>3</width></LineStyle>\
+             <PolyStyle><color>7f{}</color></PolyStyle></Style>",
+            id,
+            color,
+            &color[2..]
+        )
+    };
+
+    let speeds: Vec<f64> = track.iter().filter_map(|p| p.speed).collect();
+    if options.color_by_speed && speeds.len() == track.len() && !track.is_empty() {
+        let min = speeds.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = speeds.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let bucket = |speed: f64| {
+            let t = if max > min {
+                (speed - min) / (max - min)
+            } else {
+                0.0
+            };
+            ((t * SPEED_COLORS as f64) as usize).min(SPEED_COLORS - 1)
+        };
+        for b in 0..SPEED_COLORS {
+            line_style(out, &format!("speed{}", b), &speed_color(b))?;
+        }
+
+        // Consecutive points of the same color share a path, which starts at the last point
+        // of the previous one so that there are no gaps
+        let mut start = 0;
+        for end in 1..=track.len() {
+            let current = bucket(speeds[start]);
+            if end == track.len() || bucket(speeds[end]) != current {
+                let to = (end + 1).min(track.len());
+                write_path(
+                    out,
+                    &track[start..to],
+                    &format!("speed{}", current),
+                    &options,
+                )?;
+                start = end;
+            }
+        }
+    } else {
+        line_style(out, "path", "ff00ffff")?;
+        write_path(out, &track, "path", &options)?;
+    }
+
+    writeln!(out, "</Document>")?;
+    writeln!(out, "</kml>")
+}
+
+/// Bitwise CRC-32 as used by zip files.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Writes the KML document zipped as `doc.kml`, uncompressed.
+pub fn write_kmz(
+    reader: BlackboxReader<'_>,
+    out: &mut impl Write,
+    options: KmlOptions,
+) -> io::Result<()> {
+    let mut kml = Vec::new();
+    write(reader, &mut kml, options)?;
+
+    const NAME: &[u8] = b"doc.kml";
+    let size = u32::try_from(kml.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "KML too large for zip"))?;
+    let crc = crc32(&kml);
+    // Version needed, flags, method (stored), modification time and date
+    let common = |out: &mut dyn Write| -> io::Result<()> {
+        out.write_all(&20u16.to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&crc.to_le_bytes())?;
+        out.write_all(&size.to_le_bytes())?;
+        out.write_all(&size.to_le_bytes())?;
+        out.write_all(&(NAME.len() as u16).to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())
+    };
+
+    out.write_all(&0x0403_4b50u32.to_le_bytes())?;
+    common(out)?;
+    out.write_all(NAME)?;
+    out.write_all(&kml)?;
+
+    let directory_offset = 30 + NAME.len() as u32 + size;
+    out.write_all(&0x0201_4b50u32.to_le_bytes())?;
+    out.write_all(&20u16.to_le_bytes())?;
+    common(out)?;
+    // Comment length, disk number, internal and external attributes, local header offset
+    out.write_all(&[0; 2 + 2 + 2 + 4 + 4])?;
+    out.write_all(NAME)?;
+
+    let directory_size = 46 + NAME.len() as u32;
+    out.write_all(&0x0605_4b50u32.to_le_bytes())?;
+    out.write_all(&[0; 4])?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&directory_size.to_le_bytes())?;
+    out.write_all(&directory_offset.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())
+}
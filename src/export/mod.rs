@@ -0,0 +1,34 @@
+//! Writers for the formats other log analysis tools read.
+
+#[cfg(feature = "csv")]
+pub mod csv;
+
+#[cfg(feature = "gpx")]
+pub mod gpx;
+
+#[cfg(feature = "gyroflow")]
+pub mod gyroflow;
+
+#[cfg(feature = "kml")]
+pub mod kml;
+
+#[cfg(any(feature = "gpx", feature = "kml"))]
+mod track;
+
+#[cfg(feature = "ulog")]
+pub mod ulog;
+
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+
+#[cfg(feature = "influx")]
+pub mod influx;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
+#[cfg(feature = "parquet")]
+pub mod parquet;
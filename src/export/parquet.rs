@@ -0,0 +1,24 @@
+//! Parquet files built from the [`arrow`](super::arrow) record batches.
+
+use std::io::Write;
+
+use parquet::{arrow::ArrowWriter, errors::ParquetError};
+
+use super::arrow::{for_each_batch, schema, DEFAULT_BATCH_SIZE};
+use crate::{BlackboxReader, FieldKind};
+
+/// Writes every record of `kind` as a Parquet file, one row group per
+/// [`DEFAULT_BATCH_SIZE`] rows.
+pub fn write<W: Write + Send>(
+    reader: BlackboxReader<'_>,
+    kind: FieldKind,
+    out: W,
+) -> Result<(), ParquetError> {
+    let mut writer = ArrowWriter::try_new(out, schema(&reader, kind), None)?;
+    for_each_batch(reader, kind, DEFAULT_BATCH_SIZE, |batch| {
+        writer.write(&batch)?;
+        writer.flush()
+    })?;
+    writer.close()?;
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! GNSS track shared by the flight path exporters.
+
+use chrono::{DateTime, Duration, FixedOffset};
+
+use crate::{
+    units::{FieldScale, Units},
+    BlackboxReader, BlackboxRecord, FieldKind, Header,
+};
+
+/// Position of a GNSS frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TrackPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Meters above sea level.
+    pub altitude: f64,
+    /// Ground speed in m/s.
+    pub speed: Option<f64>,
+    /// Wall-clock time, when the log has a start time.
+    pub time: Option<DateTime<FixedOffset>>,
+}
+
+fn field(header: &Header, units: &Units, name: &str) -> Option<(usize, FieldScale)> {
+    let ix = header.g_fields.get(name)?.ix;
+    Some((ix, units.scales(FieldKind::GNSS)[ix]))
+}
+
+/// Reads the positions of every GNSS frame, skipping frames without a fix.
+pub(crate) fn read_track(mut reader: BlackboxReader<'_>) -> Vec<TrackPoint> {
+    let header = reader.header.clone();
+    let units = Units::new(&header);
+    let (Some(lat), Some(lon)) = (
+        field(&header, &units, "GPS_coord[0]"),
+        field(&header, &units, "GPS_coord[1]"),
+    ) else {
+        return Vec::new();
+    };
+    let altitude = field(&header, &units, "GPS_altitude");
+    let speed = field(&header, &units, "GPS_speed");
+    let time = header.g_fields.get("time").map(|f| f.ix);
+    let start = header.log_start_datetime();
+
+    let mut first_time = None;
+    let mut track = Vec::new();
+    while let Some(record) = reader.next() {
+        let values = match record {
+            BlackboxRecord::Main(_) => {
+                first_time.get_or_insert(reader.last_time);
+                continue;
+            }
+            BlackboxRecord::GNSS(values) => values,
+            _ => continue,
+        };
+        let get = |(ix, scale): (usize, FieldScale)| scale.apply(values[ix]);
+        if values[lat.0] == 0 && values[lon.0] == 0 {
+            continue;
+        }
+        let time = match (start, time, first_time) {
+            (Some(start), Some(time), Some(first_time)) => {
+                Some(start + Duration::microseconds(values[time] - first_time))
+            }
+            _ => None,
+        };
+        track.push(TrackPoint {
+            latitude: get(lat),
+            longitude: get(lon),
+            altitude: altitude.map_or(0.0, get),
+            speed: speed.map(get),
+            time,
+        });
+    }
+    track
+}
+
+/// Escapes text for XML element content and attribute values.
+pub(crate) fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,262 @@
+//! PX4 ULog files, for FlightPlot, PlotJuggler and the other tools of the PX4 ecosystem.
+//!
+//! Main, slow and GNSS frames are logged as the `blackbox_main`, `blackbox_slow` and
+//! `blackbox_gnss` messages, timestamped with the time of the latest main frame. Fields with
+//! a known unit are converted with [`Units`], indexed fields like `gyroADC[0]` become arrays
+//! and events are written as log messages.
+
+use std::io::{self, Write};
+
+use crate::{
+    units::{FieldScale, Unit, Units},
+    BlackboxReader, BlackboxRecord, FieldKind, Header,
+};
+
+const MAGIC: &[u8] = b"ULog\x01\x12\x35";
+const VERSION: u8 = 1;
+/// `LOG_INFO` level of logged strings, as an ASCII digit.
+const LOG_INFO: u8 = b'6';
+
+#[derive(Clone, Copy, PartialEq)]
+enum FieldType {
+    Int32,
+    UInt32,
+    Float,
+    Double,
+}
+
+impl FieldType {
+    fn new(signed: bool, scale: FieldScale) -> Self {
+        match scale.unit {
+            Unit::Raw if signed => FieldType::Int32,
+            Unit::Raw => FieldType::UInt32,
+            // Coordinates need more than the 7 significant digits of a float
+            Unit::Degrees => FieldType::Double,
+            _ => FieldType::Float,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            FieldType::Int32 => "int32_t",
+            FieldType::UInt32 => "uint32_t",
+            FieldType::Float => "float",
+            FieldType::Double => "double",
+        }
+    }
+
+    fn write(&self, scale: FieldScale, raw: i64, out: &mut Vec<u8>) {
+        match self {
+            FieldType::Int32 => out.extend((raw as i32).to_le_bytes()),
+            FieldType::UInt32 => out.extend((raw as u32).to_le_bytes()),
+            FieldType::Float => out.extend((scale.apply(raw) as f32).to_le_bytes()),
+            FieldType::Double => out.extend(scale.apply(raw).to_le_bytes()),
+        }
+    }
+}
+
+/// A ULog message definition for the frames of one kind.
+struct Subscription {
+    id: u16,
+    name: &'static str,
+    /// Header field index, type and scale of every logged field, in message order
+    fields: Vec<(usize, FieldType, FieldScale)>,
+    format: String,
+}
+
+impl Subscription {
+    fn new<'a>(
+        id: u16,
+        name: &'static str,
+        fields: impl Iterator<Item = (&'a str, bool)>,
+        scales: &[FieldScale],
+    ) -> Self {
+        let mut subscription = Subscription {
+            id,
+            name,
+            fields: Vec::new(),
+            format: format!("{}:uint64_t timestamp;", name),
+        };
+        // Runs of `name[0]`, `name[1]`, ... of one type are written as arrays
+        let mut array: Option<(String, FieldType, usize)> = None;
+        for (ix, (field, signed)) in fields.enumerate() {
+            if field == "time" {
+                continue;
+            }
+            let ty = FieldType::new(signed, scales[ix]);
+            let (base, index) = match field.strip_suffix(']').and_then(|f| f.split_once('[')) {
+                Some((base, index)) => (base, index.parse::<usize>().ok()),
+                None => (field, None),
+            };
+            let continues = matches!(
+                (&array, index),
+                (Some((b, t, len)), Some(i)) if b == base && *t == ty && *len == i
+            );
+            if continues {
+                array.as_mut().expect("array being continued").2 += 1;
+            } else {
+                subscription.close_array(array.take());
+                match index {
+                    Some(0) => array = Some((base.to_owned(), ty, 1)),
+                    _ => subscription.format += &format!("{} {};", ty.name(), sanitize(field)),
+                }
+            }
+            subscription.fields.push((ix, ty, scales[ix]));
+        }
+        subscription.close_array(array);
+        subscription
+    }
+
+    fn close_array(&mut self, array: Option<(String, FieldType, usize)>) {
+        if let Some((base, ty, len)) = array {
+            self.format += &format!("{}[{}] {};", ty.name(), len, sanitize(&base));
+        }
+    }
+}
+
+/// ULog field names only have letters, digits and underscores.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn message(out: &mut impl Write, kind: u8, payload: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "ULog message too long"))?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(&[kind])?;
+    out.write_all(payload)
+}
+
+fn key_value(out: &mut impl Write, kind: u8, key: &str, value: &[u8]) -> io::Result<()> {
+    let mut payload = vec![key.len() as u8];
+    payload.extend_from_slice(key.as_bytes());
+    payload.extend_from_slice(value);
+    message(out, kind, &payload)
+}
+
+fn write_definitions(
+    header: &Header,
+    subscriptions: &[Subscription],
+    out: &mut impl Write,
+) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION])?;
+    out.write_all(&0u64.to_le_bytes())?;
+    // Flag bits: no compatible or incompatible flags, no appended data
+    message(out, b'B', &[0; 40])?;
+
+    let firmware = header.firmware_type().unwrap_or(header.product());
+    let info = [
+        ("sys_name", Some(firmware)),
+        ("ver_sw", header.firmware_revision()),
+        ("vehicle_name", header.craft_name()),
+    ];
+    for (key, value) in info {
+        if let Some(value) = value {
+            let key = format!("char[{}] {}", value.len(), key);
+            key_value(out, b'I', &key, value.as_bytes())?;
+        }
+    }
+    let mut parameters: Vec<_> = header
+        .other_headers
+        .iter()
+        .filter_map(|(name, value)| Some((name, value.trim().parse::<i32>().ok()?)))
+        .collect();
+    parameters.sort();
+    for (name, value) in parameters {
+        let key = format!("int32_t {}", sanitize(name));
+        key_value(out, b'P', &key, &value.to_le_bytes())?;
+    }
+
+    for subscription in subscriptions {
+        message(out, b'F', subscription.format.as_bytes())?;
+    }
+    for subscription in subscriptions {
+        let mut payload = vec![0];
+        payload.extend(subscription.id.to_le_bytes());
+        payload.extend(subscription.name.as_bytes());
+        message(out, b'A', &payload)?;
+    }
+    Ok(())
+}
+
+/// Writes the records of `reader` as a ULog file.
+pub fn write(mut reader: BlackboxReader<'_>, out: &mut impl Write) -> io::Result<()> {
+    let header = reader.header.clone();
+    let units = Units::new(&header);
+    let subscriptions = [
+        Subscription::new(
+            0,
+            "blackbox_main",
+            header
+                .ip_fields_in_order
+                .iter()
+                .map(|f| (&f.name[..], f.signed)),
+            units.scales(FieldKind::Main),
+        ),
+        Subscription::new(
+            1,
+            "blackbox_slow",
+            header
+                .s_fields_in_order
+                .iter()
+                .map(|f| (&f.name[..], f.signed)),
+            units.scales(FieldKind::Slow),
+        ),
+        Subscription::new(
+            2,
+            "blackbox_gnss",
+            header
+                .g_fields_in_order
+                .iter()
+                .map(|f| (&f.name[..], f.signed)),
+            units.scales(FieldKind::GNSS),
+        ),
+    ];
+    let subscriptions: Vec<_> = subscriptions
+        .into_iter()
+        .filter(|s| !s.fields.is_empty())
+        .collect();
+    write_definitions(&header, &subscriptions, out)?;
+
+    let mut payload = Vec::new();
+    let mut values = Vec::new();
+    while let Some(record) = reader.next() {
+        let kind = match record {
+            BlackboxRecord::Main(view)
+            | BlackboxRecord::Slow(view)
+            | BlackboxRecord::GNSS(view) => {
+                values.clear();
+                values.extend_from_slice(view.values());
+                view.kind()
+            }
+            BlackboxRecord::Event(event) => {
+                payload.clear();
+                payload.push(LOG_INFO);
+                payload.extend((reader.last_widened_time.max(0) as u64).to_le_bytes());
+                payload.extend(format!("{:?}", event).as_bytes());
+                message(out, b'L', &payload)?;
+                continue;
+            }
+            BlackboxRecord::Garbage(_) => continue,
+        };
+        let id = match kind {
+            FieldKind::Main => 0,
+            FieldKind::Slow => 1,
+            FieldKind::GNSS => 2,
+        };
+        let Some(subscription) = subscriptions.iter().find(|s| s.id == id) else {
+            continue;
+        };
+        payload.clear();
+        payload.extend(id.to_le_bytes());
+        payload.extend((reader.last_widened_time.max(0) as u64).to_le_bytes());
+        for (ix, ty, scale) in &subscription.fields {
+            ty.write(*scale, values[*ix], &mut payload);
+        }
+        message(out, b'D', &payload)?;
+    }
+    Ok(())
+}
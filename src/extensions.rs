@@ -0,0 +1,119 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// Values a [`CustomPredictor`] can predict from. All slices are in header field order for
+/// the frame type being decoded.
+pub struct PredictorContext<'a> {
+    /// Index of the field being decoded.
+    pub field_ix: usize,
+    /// Frame before `previous`. Same as `previous` right after an I-frame.
+    pub previous_2: &'a [i64],
+    pub previous: &'a [i64],
+    /// Frame being decoded. Only fields before `field_ix` are already predicted, the others
+    /// hold stale values.
+    pub current: &'a [i64],
+}
+
+/// Predictor for a predictor id this crate doesn't know, e.g. from a firmware fork.
+///
+/// Used for I, P and G frame fields, see [`Extensions::with_predictor`].
+pub trait CustomPredictor: Send + Sync {
+    /// Returns the field value for the raw decoded `value`.
+    fn predict(&self, value: i64, context: &PredictorContext<'_>) -> i64;
+}
+
+impl<F> CustomPredictor for F
+where
+    F: Fn(i64, &PredictorContext<'_>) -> i64 + Send + Sync,
+{
+    fn predict(&self, value: i64, context: &PredictorContext<'_>) -> i64 {
+        self(value, context)
+    }
+}
+
+/// Why a [`CustomEncoding`] couldn't decode its values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// More bytes are needed.
+    Incomplete,
+    /// The bytes can't be the start of valid values, the reader will treat them as garbage.
+    Invalid,
+}
+
+/// Decoder for an encoding id this crate doesn't know.
+pub trait CustomEncoding: Send + Sync {
+    /// Decodes `count` values from the start of `input`, appending them to `values`.
+    /// Returns the number of bytes used.
+    fn decode(
+        &self,
+        input: &[u8],
+        count: usize,
+        values: &mut Vec<i64>,
+    ) -> Result<usize, DecodeError>;
+}
+
+impl<F> CustomEncoding for F
+where
+    F: Fn(&[u8], usize, &mut Vec<i64>) -> Result<usize, DecodeError> + Send + Sync,
+{
+    fn decode(
+        &self,
+        input: &[u8],
+        count: usize,
+        values: &mut Vec<i64>,
+    ) -> Result<usize, DecodeError> {
+        self(input, count, values)
+    }
+}
+
+/// Decoding support for predictors and encodings missing from this crate, passed to the
+/// reader in [`ReaderOptions`](crate::ReaderOptions).
+#[derive(Clone, Default)]
+pub struct Extensions {
+    predictors: HashMap<u16, Arc<dyn CustomPredictor>>,
+    encodings: HashMap<u16, (usize, Arc<dyn CustomEncoding>)>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `predictor` for fields with predictor `id` in the header. Ids the crate already
+    /// supports can't be overridden.
+    pub fn with_predictor(mut self, id: u16, predictor: impl CustomPredictor + 'static) -> Self {
+        self.predictors.insert(id, Arc::new(predictor));
+        self
+    }
+
+    /// Uses `decoder` for fields with encoding `id` in the header. Up to `group_size`
+    /// consecutive fields with that encoding are decoded by a single call, like Betaflight
+    /// groups fields for its tagged encodings. Ids the crate already supports can't be
+    /// overridden.
+    pub fn with_encoding(
+        mut self,
+        id: u16,
+        group_size: usize,
+        decoder: impl CustomEncoding + 'static,
+    ) -> Self {
+        self.encodings
+            .insert(id, (group_size.max(1), Arc::new(decoder)));
+        self
+    }
+
+    pub(crate) fn encoding(&self, id: u16) -> Option<(usize, Arc<dyn CustomEncoding>)> {
+        self.encodings.get(&id).cloned()
+    }
+
+    pub(crate) fn predictor(&self, id: u16) -> Option<Arc<dyn CustomPredictor>> {
+        self.predictors.get(&id).cloned()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("predictors", &self.predictors.keys())
+            .field("encodings", &self.encodings.keys())
+            .finish()
+    }
+}
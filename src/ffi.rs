@@ -0,0 +1,220 @@
+//! C API for embedding the decoder in C and C++ programs, `include/fc_blackbox.h` is
+//! generated from this module with `cbindgen`.
+//!
+//! A reader is opened over a caller-owned buffer, then [`fcb_reader_next`] is called until it
+//! returns [`FcbRecordKind::End`], reading the values of every frame with
+//! [`fcb_reader_values`]. Functions that fail return `NULL` and leave a message for
+//! [`fcb_last_error`].
+//!
+//! The crate itself only builds as a Rust library, the shared and static C libraries are
+//! built on demand with
+//!
+//! ```sh
+//! cargo rustc --release --lib --features ffi --crate-type cdylib --crate-type staticlib
+//! ```
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CString},
+    ptr, slice,
+};
+
+use crate::{BlackboxReader, BlackboxRecord};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Decoder over a log buffer, created by [`fcb_reader_open`].
+pub struct FcbReader {
+    reader: BlackboxReader<'static>,
+    kind: FcbRecordKind,
+    values: Vec<i64>,
+    /// Field names per [`FcbFieldKind`], NUL terminated for C.
+    names: [Vec<CString>; 3],
+}
+
+/// Kind of the record returned by [`fcb_reader_next`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FcbRecordKind {
+    /// No records left.
+    End = 0,
+    Main,
+    Slow,
+    Gnss,
+    Event,
+    Garbage,
+}
+
+/// Kind of frame whose fields are queried, passed as a `uint32_t`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FcbFieldKind {
+    Main = 0,
+    Slow,
+    Gnss,
+}
+
+impl FcbReader {
+    /// Field names for a [`FcbFieldKind`] passed from C as a plain integer, since an
+    /// out-of-range value would be an invalid enum.
+    fn field_names(&self, kind: u32) -> Option<&[CString]> {
+        self.names.get(kind as usize).map(Vec::as_slice)
+    }
+}
+
+fn c_names<'a>(names: impl Iterator<Item = &'a str>) -> Vec<CString> {
+    names
+        .map(|name| CString::new(name).unwrap_or_default())
+        .collect()
+}
+
+/// Opens a reader over the first log in `data`, `NULL` if its header can't be parsed.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes that stay valid and unchanged until the reader
+/// is freed with [`fcb_reader_free`].
+#[no_mangle]
+pub unsafe extern "C" fn fcb_reader_open(data: *const u8, len: usize) -> *mut FcbReader {
+    if data.is_null() {
+        set_last_error("data is NULL".to_owned());
+        return ptr::null_mut();
+    }
+    let bytes: &'static [u8] = slice::from_raw_parts(data, len);
+    match BlackboxReader::from_bytes(bytes) {
+        Ok(reader) => {
+            let header = &reader.header;
+            let names = [
+                c_names(header.ip_fields_in_order.iter().map(|f| &f.name[..])),
+                c_names(header.s_fields_in_order.iter().map(|f| &f.name[..])),
+                c_names(header.g_fields_in_order.iter().map(|f| &f.name[..])),
+            ];
+            Box::into_raw(Box::new(FcbReader {
+                reader,
+                kind: FcbRecordKind::End,
+                values: Vec::new(),
+                names,
+            }))
+        }
+        Err(err) => {
+            set_last_error(err.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a reader returned by [`fcb_reader_open`]. Does nothing if `reader` is `NULL`.
+///
+/// # Safety
+///
+/// `reader` must be `NULL` or a reader that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fcb_reader_free(reader: *mut FcbReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Decodes the next record, [`FcbRecordKind::End`] once the log is exhausted.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader.
+#[no_mangle]
+pub unsafe extern "C" fn fcb_reader_next(reader: *mut FcbReader) -> FcbRecordKind {
+    let reader = &mut *reader;
+    reader.values.clear();
+    reader.kind = match reader.reader.next() {
+        None => FcbRecordKind::End,
+        Some(BlackboxRecord::Main(view)) => {
+            reader.values.extend_from_slice(view.values());
+            FcbRecordKind::Main
+        }
+        Some(BlackboxRecord::Slow(view)) => {
+            reader.values.extend_from_slice(view.values());
+            FcbRecordKind::Slow
+        }
+        Some(BlackboxRecord::GNSS(view)) => {
+            reader.values.extend_from_slice(view.values());
+            FcbRecordKind::Gnss
+        }
+        Some(BlackboxRecord::Event(_)) => FcbRecordKind::Event,
+        Some(BlackboxRecord::Garbage(span)) => {
+            reader.values.extend([span.offset as i64, span.len as i64]);
+            FcbRecordKind::Garbage
+        }
+    };
+    reader.kind
+}
+
+/// Values of the last record in field order, or the byte offset and length of garbage.
+/// Writes their count to `len`, events have none. Valid until the next call to
+/// [`fcb_reader_next`].
+///
+/// # Safety
+///
+/// `reader` must be a valid reader and `len` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn fcb_reader_values(
+    reader: *const FcbReader,
+    len: *mut usize,
+) -> *const i64 {
+    let reader = &*reader;
+    *len = reader.values.len();
+    reader.values.as_ptr()
+}
+
+/// Time of the last main frame in microseconds, with rollovers of the logged 32-bit time
+/// accounted for.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader.
+#[no_mangle]
+pub unsafe extern "C" fn fcb_reader_time(reader: *const FcbReader) -> i64 {
+    (*reader).reader.last_widened_time
+}
+
+/// Number of fields in frames of `kind`, one of [`FcbFieldKind`], 0 if it's out of range.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader.
+#[no_mangle]
+pub unsafe extern "C" fn fcb_reader_field_count(reader: *const FcbReader, kind: u32) -> usize {
+    let reader = &*reader;
+    reader.field_names(kind).map_or(0, |names| names.len())
+}
+
+/// Name of field `index` in frames of `kind`, one of [`FcbFieldKind`], `NULL` if either is
+/// out of range. Valid until the reader is freed.
+///
+/// # Safety
+///
+/// `reader` must be a valid reader.
+#[no_mangle]
+pub unsafe extern "C" fn fcb_reader_field_name(
+    reader: *const FcbReader,
+    kind: u32,
+    index: usize,
+) -> *const c_char {
+    let reader = &*reader;
+    reader
+        .field_names(kind)
+        .and_then(|names| names.get(index))
+        .map_or(ptr::null(), |name| name.as_ptr())
+}
+
+/// Message of the last error on this thread, `NULL` if there was none. Valid until the next
+/// failing call.
+#[no_mangle]
+pub extern "C" fn fcb_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
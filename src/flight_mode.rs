@@ -0,0 +1,263 @@
+use std::fmt;
+
+use crate::{FirmwareKind, Quirks};
+
+/// Betaflight logs the active mode boxes (`rcModeActivationMask`), indexed by box id.
+const BETAFLIGHT_MODES: [&str; 32] = [
+    "ARM",
+    "ANGLE",
+    "HORIZON",
+    "MAG",
+    "HEADFREE",
+    "PASSTHRU",
+    "FAILSAFE",
+    "GPS RESCUE",
+    "ANTI GRAVITY",
+    "HEADADJ",
+    "CAMSTAB",
+    "BEEPER",
+    "LEDLOW",
+    "CALIB",
+    "OSD DISABLE",
+    "TELEMETRY",
+    "SERVO1",
+    "SERVO2",
+    "SERVO3",
+    "BLACKBOX",
+    "AIR MODE",
+    "3D",
+    "FPV ANGLE MIX",
+    "BLACKBOX ERASE",
+    "CAMERA CONTROL 1",
+    "CAMERA CONTROL 2",
+    "CAMERA CONTROL 3",
+    "FLIP OVER AFTER CRASH",
+    "PREARM",
+    "BEEP GPS SATELLITE COUNT",
+    "VTX PIT MODE",
+    "PARALYZE",
+];
+
+/// INAV logs its `flightModeFlags`.
+const INAV_MODES: [&str; 17] = [
+    "ANGLE",
+    "HORIZON",
+    "HEADING",
+    "NAV ALTHOLD",
+    "NAV RTH",
+    "NAV POSHOLD",
+    "HEADFREE",
+    "NAV LAUNCH",
+    "MANUAL",
+    "FAILSAFE",
+    "AUTO TUNE",
+    "NAV WP",
+    "NAV COURSE HOLD",
+    "FLAPERON",
+    "TURN ASSISTANT",
+    "TURTLE",
+    "SOARING",
+];
+
+const BETAFLIGHT_STATE_FLAGS: [&str; 5] = [
+    "GPS_FIX_HOME",
+    "GPS_FIX",
+    "CALIBRATE_MAG",
+    "SMALL_ANGLE",
+    "FIXED_WING",
+];
+
+const INAV_STATE_FLAGS: [&str; 27] = [
+    "GPS_FIX_HOME",
+    "GPS_FIX",
+    "CALIBRATE_MAG",
+    "SMALL_ANGLE",
+    "FIXED_WING_LEGACY",
+    "ANTI_WINDUP",
+    "FLAPERON_AVAILABLE",
+    "NAV_MOTOR_STOP_OR_IDLE",
+    "COMPASS_CALIBRATED",
+    "ACCELEROMETER_CALIBRATED",
+    "PWM_DRIVER_AVAILABLE",
+    "NAV_CRUISE_BRAKING",
+    "NAV_CRUISE_BRAKING_BOOST",
+    "NAV_CRUISE_BRAKING_LOCKED",
+    "NAV_EXTRA_ARMING_SAFETY_BYPASSED",
+    "AIRMODE_ACTIVE",
+    "ESC_SENSOR_ENABLED",
+    "AIRPLANE",
+    "MULTIROTOR",
+    "ROVER",
+    "BOAT",
+    "ALTITUDE_CONTROL",
+    "MOVE_FORWARD_ONLY",
+    "SET_REVERSIBLE_MOTORS_FORWARD",
+    "FW_HEADING_USE_YAW",
+    "ANTI_WINDUP_DEACTIVATED",
+    "LANDING_DETECTED",
+];
+
+macro_rules! named_bits {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub struct $name {
+            bits: u32,
+            table: &'static [&'static str],
+        }
+
+        impl $name {
+            pub fn bits(&self) -> u32 {
+                self.bits
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.bits == 0
+            }
+
+            pub fn contains(&self, name: &str) -> bool {
+                self.names().any(|n| n == name)
+            }
+
+            /// Names of the set bits, bits without a known name are skipped.
+            pub fn names(&self) -> impl Iterator<Item = &'static str> {
+                let (bits, table) = (self.bits, self.table);
+                table
+                    .iter()
+                    .enumerate()
+                    .filter(move |(bit, _)| bits & (1 << bit) != 0)
+                    .map(|(_, name)| *name)
+            }
+
+            /// Set bits without a known name.
+            pub fn unknown_bits(&self) -> u32 {
+                let known = u32::MAX
+                    .checked_shr(32 - self.table.len() as u32)
+                    .unwrap_or(0);
+                self.bits & !known
+            }
+        }
+
+        /// Formats like `blackbox_decode`: names separated by `|`, or `0` if nothing is set.
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                if self.is_empty() {
+                    return write!(f, "0");
+                }
+                let mut first = true;
+                for name in self.names() {
+                    if !first {
+                        write!(f, "|")?;
+                    }
+                    write!(f, "{}", name)?;
+                    first = false;
+                }
+                let unknown = self.unknown_bits();
+                if unknown != 0 {
+                    if !first {
+                        write!(f, "|")?;
+                    }
+                    write!(f, "{:#x}", unknown)?;
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+named_bits!(
+    /// Flight mode bitmask, as logged in flight mode events and the `flightModeFlags` slow
+    /// field, with the bits named according to the firmware that wrote it.
+    FlightModes
+);
+
+impl FlightModes {
+    pub fn decode(firmware: FirmwareKind, bits: u32) -> Self {
+        let table: &[&str] = if Quirks::new(firmware, None).betaflight_flight_modes {
+            &BETAFLIGHT_MODES
+        } else {
+            &INAV_MODES
+        };
+        Self { bits, table }
+    }
+}
+
+named_bits!(
+    /// `stateFlags` slow field, with the bits named according to the firmware that wrote it.
+    StateFlags
+);
+
+impl StateFlags {
+    pub fn decode(firmware: FirmwareKind, bits: u32) -> Self {
+        let table: &[&str] = match firmware {
+            FirmwareKind::INAV => &INAV_STATE_FLAGS,
+            _ => &BETAFLIGHT_STATE_FLAGS,
+        };
+        Self { bits, table }
+    }
+}
+
+/// `failsafePhase` slow field. Variants cover the phases of both Betaflight and INAV, which
+/// number them differently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum FailsafePhase {
+    Idle,
+    RxLossDetected,
+    RxLossIdle,
+    ReturnToHome,
+    Landing,
+    Landed,
+    RxLossMonitoring,
+    RxLossRecovered,
+    GpsRescue,
+    Other(u32),
+}
+
+impl FailsafePhase {
+    pub fn decode(firmware: FirmwareKind, value: u32) -> Self {
+        use FailsafePhase::*;
+
+        let table: &[FailsafePhase] = match firmware {
+            FirmwareKind::INAV => &[
+                Idle,
+                RxLossDetected,
+                RxLossIdle,
+                ReturnToHome,
+                Landing,
+                Landed,
+                RxLossMonitoring,
+                RxLossRecovered,
+            ],
+            _ => &[
+                Idle,
+                RxLossDetected,
+                Landing,
+                Landed,
+                RxLossMonitoring,
+                RxLossRecovered,
+                GpsRescue,
+            ],
+        };
+        table.get(value as usize).copied().unwrap_or(Other(value))
+    }
+}
+
+/// Formats the name `blackbox_decode` uses, e.g. `RX_LOSS_DETECTED`.
+impl fmt::Display for FailsafePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Idle => "IDLE",
+            Self::RxLossDetected => "RX_LOSS_DETECTED",
+            Self::RxLossIdle => "RX_LOSS_IDLE",
+            Self::ReturnToHome => "RETURN_TO_HOME",
+            Self::Landing => "LANDING",
+            Self::Landed => "LANDED",
+            Self::RxLossMonitoring => "RX_LOSS_MONITORING",
+            Self::RxLossRecovered => "RX_LOSS_RECOVERED",
+            Self::GpsRescue => "GPS_RESCUE",
+            Self::Other(value) => return write!(f, "{}", value),
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -1,115 +1,46 @@
-use nom::{bytes::streaming::tag, IResult};
+use nom::IResult;
 
 use crate::frame::FieldEncoding;
 
-use super::Field;
-
-#[derive(Debug)]
-pub struct OwnedIFrame {
-    pub buf: Vec<i64>,
-}
-
-#[derive(Debug)]
-pub struct OwnedPFrame {
-    pub buf: Vec<i64>,
-}
-
-#[derive(Debug)]
-pub struct OwnedSFrame {
-    pub buf: Vec<i64>,
-}
-
-#[derive(Debug)]
-pub struct OwnedGFrame {
-    pub buf: Vec<i64>,
-}
-
-#[derive(Debug)]
-pub struct OwnedHFrame {
-    pub buf: Vec<i64>,
-}
-
-fn parse_owned_frame_payload<'a: 'f, 'f, 'i: 'a>(
-    field_encodings: &'a [FieldEncoding],
-) -> impl Fn(&'i [u8]) -> IResult<&'i [u8], Vec<i64>> + 'f {
-    move |input: &'i [u8]| {
-        let mut input = input;
-        let mut ret = Vec::with_capacity(field_encodings.len());
-
-        for encoding in field_encodings {
+use super::{BitReader, Field};
+
+/// Decodes the values of a data frame, after its marker byte, into `values`, replacing what
+/// it held. Reusing the same buffer for every frame avoids an allocation per frame.
+pub(crate) fn parse_frame_payload<'i>(
+    field_encodings: &[FieldEncoding],
+    input: &'i [u8],
+    values: &mut Vec<i64>,
+) -> IResult<&'i [u8], ()> {
+    let mut input = input;
+    values.clear();
+    // Consecutive bit-packed fields are read as one run, then padded to a whole byte
+    let mut bits: Option<BitReader> = None;
+
+    for encoding in field_encodings {
+        let value = if encoding.is_bit_packed() {
+            let bits = bits.get_or_insert_with(|| BitReader::new(input));
+            encoding.parse_bits(bits)?
+        } else {
+            if let Some(bits) = bits.take() {
+                input = bits.align();
+            }
             let (remaining_input, value) = encoding.parse(input)?;
             input = remaining_input;
-            match value {
-                Field::Signed(v) => ret.push(v as i64),
-                Field::Unsigned(v) => ret.push(v as i64),
-                Field::SignedTriple(values) => {
-                    for v in values.iter().copied() {
-                        ret.push(v as i64);
-                    }
-                }
-                Field::SignedQuadruple(values) => {
-                    for v in values.iter().copied() {
-                        ret.push(v as i64);
-                    }
-                }
-                Field::SignedOctuple(values, values_n) => {
-                    for v in &values[..values_n] {
-                        ret.push(*v as i64);
-                    }
-                }
-            };
-        }
-
-        Ok((input, ret))
+            value
+        };
+        match value {
+            Field::Values(v) => values.extend(v),
+            Field::Signed(v) => values.push(v as i64),
+            Field::Unsigned(v) => values.push(v as i64),
+            Field::SignedTriple(v) => values.extend(v.iter().map(|v| *v as i64)),
+            Field::SignedQuadruple(v) => values.extend(v.iter().map(|v| *v as i64)),
+            Field::SignedOctuple(v, n) => values.extend(v[..n].iter().map(|v| *v as i64)),
+        };
     }
-}
-
-pub(crate) fn parse_owned_iframe<'a: 'f, 'f, 'i: 'a>(
-    field_encodings: &'a [FieldEncoding],
-) -> impl Fn(&'i [u8]) -> IResult<&'i [u8], OwnedIFrame> + 'f {
-    move |input: &'i [u8]| {
-        let (input, _) = tag("I")(input)?;
-        let (input, ret) = parse_owned_frame_payload(field_encodings)(input)?;
-        Ok((input, OwnedIFrame { buf: ret }))
-    }
-}
-
-pub(crate) fn parse_owned_pframe<'a: 'f, 'f, 'i: 'a>(
-    field_encodings: &'a [FieldEncoding],
-) -> impl Fn(&'i [u8]) -> IResult<&'i [u8], OwnedPFrame> + 'f {
-    move |input: &'i [u8]| {
-        let (input, _) = tag("P")(input)?;
-        let (input, ret) = parse_owned_frame_payload(field_encodings)(input)?;
-        Ok((input, OwnedPFrame { buf: ret }))
-    }
-}
-
-pub(crate) fn parse_owned_sframe<'a: 'f, 'f, 'i: 'a>(
-    field_encodings: &'a [FieldEncoding],
-) -> impl Fn(&'i [u8]) -> IResult<&'i [u8], OwnedSFrame> + 'f {
-    move |input: &'i [u8]| {
-        let (input, _) = tag("S")(input)?;
-        let (input, ret) = parse_owned_frame_payload(field_encodings)(input)?;
-        Ok((input, OwnedSFrame { buf: ret }))
-    }
-}
 
-pub(crate) fn parse_owned_gframe<'a: 'f, 'f, 'i: 'a>(
-    field_encodings: &'a [FieldEncoding],
-) -> impl Fn(&'i [u8]) -> IResult<&'i [u8], OwnedGFrame> + 'f {
-    move |input: &'i [u8]| {
-        let (input, _) = tag("G")(input)?;
-        let (input, ret) = parse_owned_frame_payload(field_encodings)(input)?;
-        Ok((input, OwnedGFrame { buf: ret }))
+    if let Some(bits) = bits {
+        input = bits.align();
     }
-}
 
-pub(crate) fn parse_owned_hframe<'a: 'f, 'f, 'i: 'a>(
-    field_encodings: &'a [FieldEncoding],
-) -> impl Fn(&'i [u8]) -> IResult<&'i [u8], OwnedHFrame> + 'f {
-    move |input: &'i [u8]| {
-        let (input, _) = tag("H")(input)?;
-        let (input, ret) = parse_owned_frame_payload(field_encodings)(input)?;
-        Ok((input, OwnedHFrame { buf: ret }))
-    }
+    Ok((input, ()))
 }
@@ -0,0 +1,162 @@
+//! Writing frame payloads, the inverse of the parsers in the parent module.
+
+use super::FieldEncoding;
+
+/// The encoding has no writer, like the legacy bit-packed and custom ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct UnsupportedEncoding;
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn zigzag_encode(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn fits(value: i32, bits: u32) -> bool {
+    let limit = 1i32 << (bits - 1);
+    (-limit..limit).contains(&value)
+}
+
+/// Selector and little-endian bytes of the smallest of 8, 16, 24 and 32 bits holding `value`.
+fn sized(value: i32) -> (u8, &'static [u8]) {
+    const SIZES: [&[u8]; 4] = [&[0], &[0, 1], &[0, 1, 2], &[0, 1, 2, 3]];
+    let selector = [8, 16, 24]
+        .iter()
+        .position(|bits| fits(value, *bits))
+        .unwrap_or(3);
+    (selector as u8, SIZES[selector])
+}
+
+fn write_sized(out: &mut Vec<u8>, values: [i32; 3]) {
+    for value in values {
+        let (_, bytes) = sized(value);
+        let le = value.to_le_bytes();
+        out.extend(bytes.iter().map(|i| le[*i as usize]));
+    }
+}
+
+impl FieldEncoding {
+    /// Number of consecutive field values the encoding covers.
+    pub(crate) fn field_count(&self) -> usize {
+        match self {
+            FieldEncoding::Tag8_8SVB(n) => *n,
+            FieldEncoding::Tag2_3S32(_) | FieldEncoding::Tag2_3SVariable(_) => 3,
+            FieldEncoding::Tag8_4S16(_) | FieldEncoding::Tag8_4S16V1(_) => 4,
+            FieldEncoding::Custom(custom) => custom.fields,
+            _ => 1,
+        }
+    }
+
+    /// Appends the first [`field_count`](Self::field_count) `values` to `out`.
+    pub(crate) fn encode(
+        &self,
+        values: &[i64],
+        out: &mut Vec<u8>,
+    ) -> Result<(), UnsupportedEncoding> {
+        let v = |i: usize| values[i] as i32;
+        match self {
+            FieldEncoding::Null => {}
+            FieldEncoding::UnsignedVB => write_varint(out, values[0] as u32),
+            FieldEncoding::SignedVB => write_varint(out, zigzag_encode(v(0))),
+            FieldEncoding::Negative14BitVB => write_varint(out, (-v(0)) as u32 & 0x3fff),
+            FieldEncoding::Tag8_8SVB(1) => write_varint(out, zigzag_encode(v(0))),
+            FieldEncoding::Tag8_8SVB(n) => {
+                let selectors = (0..*n)
+                    .filter(|i| values[*i] != 0)
+                    .fold(0u8, |s, i| s | (1 << i));
+                out.push(selectors);
+                for i in (0..*n).filter(|i| values[*i] != 0) {
+                    write_varint(out, zigzag_encode(v(i)));
+                }
+            }
+            FieldEncoding::Tag2_3S32(_) => {
+                let [a, b, c] = [v(0), v(1), v(2)];
+                let all_fit = |bits| [a, b, c].iter().all(|x| fits(*x, bits));
+                if all_fit(2) {
+                    out.push(((a as u8 & 0x03) << 4) | ((b as u8 & 0x03) << 2) | (c as u8 & 0x03));
+                } else if all_fit(4) {
+                    out.push(0x40 | (a as u8 & 0x0f));
+                    out.push(((b as u8 & 0x0f) << 4) | (c as u8 & 0x0f));
+                } else if all_fit(6) {
+                    out.push(0x80 | (a as u8 & 0x3f));
+                    out.push(b as u8 & 0x3f);
+                    out.push(c as u8 & 0x3f);
+                } else {
+                    out.push(0xc0 | sized(a).0 | (sized(b).0 << 2) | (sized(c).0 << 4));
+                    write_sized(out, [a, b, c]);
+                }
+            }
+            FieldEncoding::Tag2_3SVariable(_) => {
+                let [a, b, c] = [v(0), v(1), v(2)];
+                if [a, b, c].iter().all(|x| fits(*x, 2)) {
+                    out.push(((a as u8 & 0x03) << 4) | ((b as u8 & 0x03) << 2) | (c as u8 & 0x03));
+                } else if fits(a, 5) && fits(b, 5) && fits(c, 4) {
+                    out.push(0x40 | ((a as u8 & 0x1f) << 1) | ((b as u8 >> 4) & 0x01));
+                    out.push(((b as u8 & 0x0f) << 4) | (c as u8 & 0x0f));
+                } else if fits(a, 8) && fits(b, 7) && fits(c, 7) {
+                    out.push(0x80 | ((a as u8 >> 2) & 0x3f));
+                    out.push(((a as u8 & 0x03) << 6) | ((b as u8 >> 1) & 0x3f));
+                    out.push(((b as u8 & 0x01) << 7) | (c as u8 & 0x7f));
+                } else {
+                    out.push(0xc0 | sized(a).0 | (sized(b).0 << 2) | (sized(c).0 << 4));
+                    write_sized(out, [a, b, c]);
+                }
+            }
+            FieldEncoding::Tag8_4S16(_) => {
+                let mut selectors = 0u8;
+                let mut nibbles = Vec::with_capacity(16);
+                for i in 0..4 {
+                    let value = v(i);
+                    let (selector, count) = match value {
+                        0 => (0, 0),
+                        _ if fits(value, 4) => (1, 1),
+                        _ if fits(value, 8) => (2, 2),
+                        _ if fits(value, 16) => (3, 4),
+                        _ => return Err(UnsupportedEncoding),
+                    };
+                    selectors |= selector << (i * 2);
+                    nibbles.extend((0..count).rev().map(|n| (value >> (n * 4)) as u8 & 0x0f));
+                }
+                out.push(selectors);
+                out.extend(
+                    nibbles
+                        .chunks(2)
+                        .map(|pair| (pair[0] << 4) | pair.get(1).copied().unwrap_or(0)),
+                );
+            }
+            FieldEncoding::Tag8_4S16V1(_)
+            | FieldEncoding::EliasDeltaU32
+            | FieldEncoding::EliasDeltaS32
+            | FieldEncoding::EliasGammaU32
+            | FieldEncoding::EliasGammaS32
+            | FieldEncoding::Custom(_) => return Err(UnsupportedEncoding),
+        }
+        Ok(())
+    }
+}
+
+/// Appends a frame with the given marker byte, encoding `values` in field order.
+pub(crate) fn encode_frame(
+    marker: u8,
+    encodings: &[FieldEncoding],
+    values: &[i64],
+    out: &mut Vec<u8>,
+) -> Result<(), UnsupportedEncoding> {
+    out.push(marker);
+    let mut values = values;
+    for encoding in encodings {
+        let count = encoding.field_count();
+        if values.len() < count {
+            return Err(UnsupportedEncoding);
+        }
+        encoding.encode(values, out)?;
+        values = &values[count..];
+    }
+    Ok(())
+}
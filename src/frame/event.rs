@@ -1,16 +1,20 @@
+use std::fmt;
+
 use nom::{
-    bytes::streaming::tag,
-    error::{ErrorKind, ParseError},
+    bytes::{complete::take_till, streaming::tag},
     number::{
         complete::be_u8,
-        streaming::{le_f32, le_u8},
+        streaming::{le_f32, le_i16, le_i8, le_u32, le_u8},
     },
+    sequence::tuple,
     IResult,
 };
 
 use super::{take_varint, zigzag_decode};
+use crate::{FirmwareKind, FlightModes};
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Frame {
     SyncBeep(SyncBeep),
     FlightMode(FlightMode),
@@ -18,54 +22,246 @@ pub enum Frame {
     Disarm(Disarm),
     InFlightAdjustment(InFlightAdjustment),
     LoggingResume(LoggingResume),
+    AutotuneCycleStart(AutotuneCycleStart),
+    AutotuneCycleResult(AutotuneCycleResult),
+    AutotuneTargets(AutotuneTargets),
+    GtuneCycleResult(GtuneCycleResult),
+    TwitchTest(TwitchTest),
+    Unknown(UnknownEvent),
     EndOfLog,
 }
 
-#[allow(unused)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SyncBeep {
-    time: u32,
+    pub time: u32,
 }
 
-#[derive(Debug)]
-#[allow(unused)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct FlightMode {
-    flags: u32,
-    old_flags: u32,
+    pub flags: u32,
+    pub old_flags: u32,
+}
+
+impl FlightMode {
+    pub fn modes(&self, firmware: FirmwareKind) -> FlightModes {
+        FlightModes::decode(firmware, self.flags)
+    }
+
+    pub fn old_modes(&self, firmware: FirmwareKind) -> FlightModes {
+        FlightModes::decode(firmware, self.old_flags)
+    }
 }
 
-#[allow(unused)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Disarm {
-    reason: u32,
+    pub reason: u32,
+}
+
+impl Disarm {
+    pub fn decoded_reason(&self, firmware: FirmwareKind) -> DisarmReason {
+        DisarmReason::decode(firmware, self.reason)
+    }
+}
+
+/// Why the craft was disarmed, from the firmware specific code of a [`Disarm`] event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DisarmReason {
+    ArmingDisabled,
+    Failsafe,
+    ThrottleTimeout,
+    Sticks,
+    Switch,
+    Switch3D,
+    KillSwitch,
+    CrashProtection,
+    RunawayTakeoff,
+    GpsRescue,
+    SerialCommand,
+    Navigation,
+    Landing,
+    System,
+    Unknown(u32),
+}
+
+impl DisarmReason {
+    pub fn decode(firmware: FirmwareKind, code: u32) -> Self {
+        use DisarmReason::*;
+        match firmware {
+            FirmwareKind::INAV => match code {
+                1 => ThrottleTimeout,
+                2 => Sticks,
+                3 => Switch3D,
+                4 => Switch,
+                5 => KillSwitch,
+                6 => Failsafe,
+                7 => Navigation,
+                8 => Landing,
+                code => Unknown(code),
+            },
+            _ => match code {
+                0 => ArmingDisabled,
+                1 => Failsafe,
+                2 => ThrottleTimeout,
+                3 => Sticks,
+                4 => Switch,
+                5 => CrashProtection,
+                6 => RunawayTakeoff,
+                7 => GpsRescue,
+                8 => SerialCommand,
+                9 => Landing,
+                255 => System,
+                code => Unknown(code),
+            },
+        }
+    }
+}
+
+impl fmt::Display for DisarmReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DisarmReason::ArmingDisabled => "arming disabled",
+            DisarmReason::Failsafe => "failsafe",
+            DisarmReason::ThrottleTimeout => "throttle timeout",
+            DisarmReason::Sticks => "sticks",
+            DisarmReason::Switch => "switch",
+            DisarmReason::Switch3D => "3D switch",
+            DisarmReason::KillSwitch => "kill switch",
+            DisarmReason::CrashProtection => "crash protection",
+            DisarmReason::RunawayTakeoff => "runaway takeoff",
+            DisarmReason::GpsRescue => "GPS rescue",
+            DisarmReason::SerialCommand => "serial command",
+            DisarmReason::Navigation => "navigation",
+            DisarmReason::Landing => "landing",
+            DisarmReason::System => "system",
+            DisarmReason::Unknown(code) => return write!(f, "unknown ({})", code),
+        };
+        f.write_str(name)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Adjustment {
     Float(f32),
     Int(i32),
 }
 
-#[allow(unused)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct InFlightAdjustment {
-    function: u8,
-    adjustment: Adjustment,
+    /// Adjustment function id, without the float flag bit
+    pub function: u8,
+    pub adjustment: Adjustment,
 }
 
-#[allow(unused)]
-#[derive(Debug)]
+/// Logging continues after a pause, from this loop iteration and time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct LoggingResume {
-    iteration: u32,
-    time: u32,
+    pub iteration: u32,
+    pub time: u32,
 }
 
-#[allow(unused)]
-#[derive(Debug)]
+impl LoggingResume {
+    /// Iterations and microseconds missing since the last main frame before the pause,
+    /// e.g. `BlackboxReader::last_loop_iteration` and `last_time` when the event is read.
+    pub fn gap_since(&self, loop_iteration: i64, time: i64) -> ResumeGap {
+        ResumeGap {
+            iterations: self.iteration as i64 - loop_iteration,
+            time: self.time as i64 - time,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResumeGap {
+    pub iterations: i64,
+    pub time: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IMUFailure {
-    error_code: u32,
+    pub error_code: u32,
+}
+
+/// Legacy Cleanflight autotune event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AutotuneCycleStart {
+    pub phase: u8,
+    pub cycle: u8,
+    pub rising: bool,
+    pub p: u8,
+    pub i: u8,
+    pub d: u8,
+}
+
+/// Legacy Cleanflight autotune event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AutotuneCycleResult {
+    pub flags: u8,
+    pub p: u8,
+    pub i: u8,
+    pub d: u8,
+}
+
+/// Legacy Cleanflight autotune event, angles in decidegrees.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AutotuneTargets {
+    pub current_angle: i16,
+    pub target_angle: i8,
+    pub target_angle_at_peak: i8,
+    pub first_peak_angle: i16,
+    pub second_peak_angle: i16,
+}
+
+/// Legacy Cleanflight G-Tune event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GtuneCycleResult {
+    pub axis: u8,
+    pub gyro_average: i32,
+    pub new_p: i16,
 }
 
+/// Step of Betaflight's twitch test, with the time the stage took in microseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TwitchTest {
+    /// 1: response, 2: half setpoint, 3: setpoint, 4: negative setpoint, 5: initial setpoint
+    pub stage: u8,
+    pub time: u32,
+}
+
+/// Event with a code this crate doesn't know the payload of.
+///
+/// As the payload length isn't known, it is assumed to reach up to the next byte that
+/// looks like a frame marker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UnknownEvent {
+    pub code: u8,
+    pub payload: Vec<u8>,
+}
+
+impl UnknownEvent {
+    /// Copies the payload from `frame`, the bytes of the whole event including its marker and
+    /// code.
+    pub(crate) fn read_payload(&mut self, frame: &[u8]) {
+        self.payload = frame[2..].to_vec();
+    }
+}
+
+/// Leaves the payload of unknown events empty, so events that are only parsed to find the
+/// next frame, like while resyncing, don't allocate. See [`UnknownEvent::read_payload`].
 pub(crate) fn parse_event(input: &[u8]) -> IResult<&[u8], Frame> {
     let (input, _) = tag("E")(input)?;
     let (input, event_code) = le_u8(input)?;
@@ -100,6 +296,43 @@ pub(crate) fn parse_event(input: &[u8]) -> IResult<&[u8], Frame> {
                 )
             }
         }
+        10 => {
+            let (input, (phase, cycle, p, i, d)) =
+                tuple((le_u8, le_u8, le_u8, le_u8, le_u8))(input)?;
+            (
+                input,
+                Frame::AutotuneCycleStart(AutotuneCycleStart {
+                    phase,
+                    cycle: cycle & 0x7f,
+                    rising: cycle & 0x80 != 0,
+                    p,
+                    i,
+                    d,
+                }),
+            )
+        }
+        11 => {
+            let (input, (flags, p, i, d)) = tuple((le_u8, le_u8, le_u8, le_u8))(input)?;
+            (
+                input,
+                Frame::AutotuneCycleResult(AutotuneCycleResult { flags, p, i, d }),
+            )
+        }
+        12 => {
+            let (input, (current_angle, target_angle, target_angle_at_peak)) =
+                tuple((le_i16, le_i8, le_i8))(input)?;
+            let (input, (first_peak_angle, second_peak_angle)) = tuple((le_i16, le_i16))(input)?;
+            (
+                input,
+                Frame::AutotuneTargets(AutotuneTargets {
+                    current_angle,
+                    target_angle,
+                    target_angle_at_peak,
+                    first_peak_angle,
+                    second_peak_angle,
+                }),
+            )
+        }
         14 => {
             let (input, iteration) = take_varint(input)?;
             let (input, time) = take_varint(input)?;
@@ -113,6 +346,23 @@ pub(crate) fn parse_event(input: &[u8]) -> IResult<&[u8], Frame> {
             let (input, reason) = take_varint(input)?;
             (input, Frame::Disarm(Disarm { reason }))
         }
+        20 => {
+            let (input, axis) = le_u8(input)?;
+            let (input, gyro_average) = take_varint(input)?;
+            let (input, new_p) = le_i16(input)?;
+            (
+                input,
+                Frame::GtuneCycleResult(GtuneCycleResult {
+                    axis,
+                    gyro_average: zigzag_decode(gyro_average),
+                    new_p,
+                }),
+            )
+        }
+        21 => {
+            let (input, (stage, time)) = tuple((le_u8, le_u32))(input)?;
+            (input, Frame::TwitchTest(TwitchTest { stage, time }))
+        }
         30 => {
             let (input, flags) = take_varint(input)?;
             let (input, old_flags) = take_varint(input)?;
@@ -127,11 +377,15 @@ pub(crate) fn parse_event(input: &[u8]) -> IResult<&[u8], Frame> {
             let (input, _) = tag("End of log\0")(input)?;
             (input, Frame::EndOfLog)
         }
-        _ => {
-            return Err(nom::Err::Failure(nom::error::Error::from_error_kind(
+        code => {
+            let (input, _) = take_till(|b| b"IPSGHE".contains(&b))(input)?;
+            (
                 input,
-                ErrorKind::Tag,
-            )))
+                Frame::Unknown(UnknownEvent {
+                    code,
+                    payload: Vec::new(),
+                }),
+            )
         }
     };
 
@@ -1,23 +1,24 @@
-use chrono::{DateTime, Utc};
 use nom::{
     bytes::streaming::{tag, take_until},
     combinator::{map, map_res},
     IResult,
 };
+use std::{borrow::Cow, collections::HashMap, convert::TryInto, str::FromStr};
+
 use num_rational::Ratio;
 
 use crate::stream::predictor::FieldPredictor;
 
 use super::{
     parse_dec_as_bool_list, parse_dec_as_encoding_list, parse_dec_as_predictor_list, parse_i16_dec,
-    parse_str, parse_str_list, parse_u16_dec, parse_u16_ratio_dec_or_inverse_dec, parse_u32_dec,
-    parse_u32_hex, RawFieldEncoding,
+    parse_lossy_str, parse_str, parse_str_list, parse_u16_dec, parse_u16_ratio_dec_or_inverse_dec,
+    parse_u32_dec, parse_u32_hex, RawFieldEncoding,
 };
 
 #[allow(unused)]
 #[derive(Debug)]
 pub(crate) enum Frame<'f> {
-    Product(&'f str),
+    Product(Cow<'f, str>),
     DataVersion(&'f str),
     FieldIName(Vec<&'f str>),
     FieldISignedness(Vec<bool>),
@@ -39,12 +40,12 @@ pub(crate) enum Frame<'f> {
     FieldHSignedness(Vec<bool>),
     FieldHEncoding(Vec<RawFieldEncoding>),
     FieldHPredictor(Vec<FieldPredictor>),
-    FirmwareType(&'f str),
-    FirmwareRevision(&'f str, &'f str, &'f str, &'f str),
-    FirmwareDate(DateTime<Utc>),
-    BoardInformation(BoardInformation<'f>),
-    LogStart(DateTime<Utc>),
-    CraftName(&'f str),
+    FirmwareType(Cow<'f, str>),
+    FirmwareRevision(Cow<'f, str>),
+    FirmwareDate(Cow<'f, str>),
+    BoardInformation(Cow<'f, str>),
+    LogStart(Cow<'f, str>),
+    CraftName(Cow<'f, str>),
     IInterval(i16),
     PInterval(Ratio<u16>),
     PRatio(u16),
@@ -80,54 +81,291 @@ pub(crate) enum Frame<'f> {
     DTermLowpassHz(u16),
     DTermLowpassDynHz(u16, u16),
 
-    UnkownHeader(&'f str, &'f str),
+    UnkownHeader(&'f str, Cow<'f, str>),
 }
 
-#[allow(unused)]
-#[derive(Debug)]
-pub struct BoardInformation<'f> {
-    manufacturer_id: &'f str,
-    board_name: &'f str,
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum FirmwareKind {
+    Betaflight,
+    INAV,
+    EmuFlight,
+    Quicksilver,
+    Cleanflight,
+    Baseflight,
+    Unknown,
 }
 
-#[allow(unused)]
-#[derive(Debug)]
+impl FirmwareKind {
+    /// Guesses the firmware from the `Firmware revision` header, falling back to `Firmware type`
+    /// which forks usually leave as "Cleanflight".
+    pub fn detect(firmware_type: Option<&str>, firmware_revision: Option<&str>) -> Self {
+        let from_name = |name: &str| match name.to_ascii_lowercase().as_str() {
+            "betaflight" => Some(FirmwareKind::Betaflight),
+            "inav" => Some(FirmwareKind::INAV),
+            "emuflight" => Some(FirmwareKind::EmuFlight),
+            "quicksilver" => Some(FirmwareKind::Quicksilver),
+            "cleanflight" => Some(FirmwareKind::Cleanflight),
+            "baseflight" => Some(FirmwareKind::Baseflight),
+            _ => None,
+        };
+
+        firmware_revision
+            .and_then(|r| r.split_whitespace().next())
+            .and_then(from_name)
+            .or_else(|| firmware_type.and_then(from_name))
+            .unwrap_or(FirmwareKind::Unknown)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FirmwareVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl FirmwareVersion {
+    pub fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses the version out of a `Firmware revision` header such as
+    /// `Betaflight 4.2.11 (948ba6339) STM32F7X2`.
+    pub fn from_revision(revision: &str) -> Option<Self> {
+        let mut parts = revision.split_whitespace().nth(1)?.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+        let patch = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+        Some(Self::new(major, minor, patch))
+    }
+}
+
+impl std::fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BoardInformation {
+    pub manufacturer_id: String,
+    pub board_name: String,
+}
+
+impl BoardInformation {
+    /// Splits a `Board information` header such as `AIKO AIKONF7`.
+    pub fn parse(value: &str) -> Self {
+        let (manufacturer_id, board_name) = value.trim().split_once(' ').unwrap_or(("", value));
+        Self {
+            manufacturer_id: manufacturer_id.to_owned(),
+            board_name: board_name.trim().to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct VBatCellVoltage {
-    min: u16,
-    warning: u16,
-    max: u16,
+    pub min: u16,
+    pub warning: u16,
+    pub max: u16,
 }
 
-#[allow(unused)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct CurrentSensor {
-    offset: u16,
-    scale: i16,
+    pub offset: i16,
+    pub scale: i16,
 }
 
-#[allow(unused)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct RollPitchYaw<T: Clone + Copy> {
-    roll: T,
-    pitch: T,
-    yaw: T,
+    pub roll: T,
+    pub pitch: T,
+    pub yaw: T,
 }
 
-#[allow(unused, clippy::upper_case_acronyms)]
-#[derive(Clone, Copy, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct PID<T: Clone + Copy> {
-    p: T,
-    i: T,
-    d: T,
+    pub p: T,
+    pub i: T,
+    pub d: T,
+}
+
+/// Flight controller settings with a known meaning, decoded from their header lines. The raw
+/// values stay available in [`Header::other_headers`](crate::Header::other_headers).
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HeaderSettings {
+    pub min_throttle: Option<u16>,
+    pub max_throttle: Option<u16>,
+    /// Lowest and highest motor output.
+    pub motor_output: Option<(u16, u16)>,
+    pub acc_1g: Option<u16>,
+    pub vbat_scale: Option<u8>,
+    pub vbat_cell_voltage: Option<VBatCellVoltage>,
+    pub vbat_ref: Option<u16>,
+    pub current_sensor: Option<CurrentSensor>,
+    pub gyro_sync_denom: Option<u8>,
+    pub pid_process_denom: Option<u8>,
+    pub throttle_mid: Option<u8>,
+    pub throttle_expo: Option<u8>,
+    pub tpa_rate: Option<u8>,
+    pub tpa_breakpoint: Option<u16>,
+    pub rc_rates: Option<RollPitchYaw<u8>>,
+    pub rc_expo: Option<RollPitchYaw<u8>>,
+    pub rates: Option<RollPitchYaw<u8>>,
+    pub rate_limits: Option<RollPitchYaw<u16>>,
+    pub roll_pid: Option<PID<f32>>,
+    pub pitch_pid: Option<PID<f32>>,
+    pub yaw_pid: Option<PID<f32>>,
+    pub level_pid: Option<PID<f32>>,
+    pub mag_p: Option<f32>,
+    pub d_min: Option<RollPitchYaw<u8>>,
+    pub d_min_gain: Option<u8>,
+    pub d_min_advance: Option<u8>,
+    pub dterm_filter_type: Option<u8>,
+    pub dterm_lowpass_hz: Option<u16>,
+    /// Minimum and maximum cutoff of the dynamic D-term lowpass.
+    pub dterm_lowpass_dyn_hz: Option<(u16, u16)>,
+}
+
+impl HeaderSettings {
+    /// Decodes the settings found in `headers`, skipping values that don't parse.
+    pub(crate) fn from_headers(headers: &HashMap<String, String>) -> Self {
+        let mut settings = Self::default();
+        for (name, value) in headers {
+            match parse_setting(name, value) {
+                Some(Frame::MinThrottle(v)) => settings.min_throttle = Some(v),
+                Some(Frame::MaxThrottle(v)) => settings.max_throttle = Some(v),
+                Some(Frame::MotorOutput(min, max)) => settings.motor_output = Some((min, max)),
+                Some(Frame::Acc1G(v)) => settings.acc_1g = Some(v),
+                Some(Frame::VBatScale(v)) => settings.vbat_scale = Some(v),
+                Some(Frame::VBatCellVoltage(v)) => settings.vbat_cell_voltage = Some(v),
+                Some(Frame::VBatRef(v)) => settings.vbat_ref = Some(v),
+                Some(Frame::CurrentSensor(v)) => settings.current_sensor = Some(v),
+                Some(Frame::GyroSyncDenom(v)) => settings.gyro_sync_denom = Some(v),
+                Some(Frame::PidProcessDenom(v)) => settings.pid_process_denom = Some(v),
+                Some(Frame::ThrottleMid(v)) => settings.throttle_mid = Some(v),
+                Some(Frame::ThrottleExpo(v)) => settings.throttle_expo = Some(v),
+                Some(Frame::TPARate(v)) => settings.tpa_rate = Some(v),
+                Some(Frame::TPABreakpoint(v)) => settings.tpa_breakpoint = Some(v),
+                Some(Frame::RCRates(v)) => settings.rc_rates = Some(v),
+                Some(Frame::RCExpo(v)) => settings.rc_expo = Some(v),
+                Some(Frame::Rates(v)) => settings.rates = Some(v),
+                Some(Frame::RateLimits(v)) => settings.rate_limits = Some(v),
+                Some(Frame::RollPID(v)) => settings.roll_pid = Some(v),
+                Some(Frame::PitchPID(v)) => settings.pitch_pid = Some(v),
+                Some(Frame::YawPID(v)) => settings.yaw_pid = Some(v),
+                Some(Frame::LevelPID(v)) => settings.level_pid = Some(v),
+                Some(Frame::MagP(v)) => settings.mag_p = Some(v),
+                Some(Frame::DMin(v)) => settings.d_min = Some(v),
+                Some(Frame::DMinGain(v)) => settings.d_min_gain = Some(v),
+                Some(Frame::DMinAdvance(v)) => settings.d_min_advance = Some(v),
+                Some(Frame::DTermFilterType(v)) => settings.dterm_filter_type = Some(v),
+                Some(Frame::DTermLowpassHz(v)) => settings.dterm_lowpass_hz = Some(v),
+                Some(Frame::DTermLowpassDynHz(min, max)) => {
+                    settings.dterm_lowpass_dyn_hz = Some((min, max))
+                }
+                _ => {}
+            }
+        }
+        settings
+    }
+}
+
+/// First value of a comma separated setting.
+fn setting_value<T: FromStr>(value: &str) -> Option<T> {
+    value.split(',').next()?.trim().parse().ok()
+}
+
+/// First `N` values of a comma separated setting. Firmwares append extra values over time,
+/// e.g. INAV logs a fourth, feed forward, term for PIDs, so longer lists are accepted.
+fn setting_values<T: FromStr, const N: usize>(value: &str) -> Option<[T; N]> {
+    let values = value
+        .split(',')
+        .take(N)
+        .map(|v| v.trim().parse().ok())
+        .collect::<Option<Vec<T>>>()?;
+    values.try_into().ok()
+}
+
+fn roll_pitch_yaw<T: FromStr + Clone + Copy>(value: &str) -> Option<RollPitchYaw<T>> {
+    let [roll, pitch, yaw] = setting_values(value)?;
+    Some(RollPitchYaw { roll, pitch, yaw })
+}
+
+fn pid(value: &str) -> Option<PID<f32>> {
+    let [p, i, d] = setting_values(value)?;
+    Some(PID { p, i, d })
+}
+
+/// Parses a setting header that [`parse_header`] leaves as [`Frame::UnkownHeader`].
+pub(crate) fn parse_setting(name: &str, value: &str) -> Option<Frame<'static>> {
+    Some(match name {
+        "minthrottle" => Frame::MinThrottle(setting_value(value)?),
+        "maxthrottle" => Frame::MaxThrottle(setting_value(value)?),
+        "motorOutput" => {
+            let [min, max] = setting_values(value)?;
+            Frame::MotorOutput(min, max)
+        }
+        "acc_1G" => Frame::Acc1G(setting_value(value)?),
+        "vbat_scale" => Frame::VBatScale(setting_value(value)?),
+        "vbatcellvoltage" => {
+            let [min, warning, max] = setting_values(value)?;
+            Frame::VBatCellVoltage(VBatCellVoltage { min, warning, max })
+        }
+        "vbatref" => Frame::VBatRef(setting_value(value)?),
+        "currentSensor" => {
+            let [offset, scale] = setting_values(value)?;
+            Frame::CurrentSensor(CurrentSensor { offset, scale })
+        }
+        "gyro_sync_denom" => Frame::GyroSyncDenom(setting_value(value)?),
+        "pid_process_denom" => Frame::PidProcessDenom(setting_value(value)?),
+        "thr_mid" => Frame::ThrottleMid(setting_value(value)?),
+        "thr_expo" => Frame::ThrottleExpo(setting_value(value)?),
+        "tpa_rate" => Frame::TPARate(setting_value(value)?),
+        "tpa_breakpoint" => Frame::TPABreakpoint(setting_value(value)?),
+        "rc_rates" => Frame::RCRates(roll_pitch_yaw(value)?),
+        "rc_expo" => Frame::RCExpo(roll_pitch_yaw(value)?),
+        "rates" => Frame::Rates(roll_pitch_yaw(value)?),
+        "rate_limits" => Frame::RateLimits(roll_pitch_yaw(value)?),
+        "rollPID" => Frame::RollPID(pid(value)?),
+        "pitchPID" => Frame::PitchPID(pid(value)?),
+        "yawPID" => Frame::YawPID(pid(value)?),
+        "levelPID" => Frame::LevelPID(pid(value)?),
+        "magPID" => Frame::MagP(setting_value(value)?),
+        "d_min" => Frame::DMin(roll_pitch_yaw(value)?),
+        "d_min_gain" => Frame::DMinGain(setting_value(value)?),
+        "d_min_advance" => Frame::DMinAdvance(setting_value(value)?),
+        "dterm_filter_type" => Frame::DTermFilterType(setting_value(value)?),
+        "dterm_lowpass_hz" => Frame::DTermLowpassHz(setting_value(value)?),
+        "dterm_lowpass_dyn_hz" => {
+            let [min, max] = setting_values(value)?;
+            Frame::DTermLowpassDynHz(min, max)
+        }
+        _ => return None,
+    })
 }
 
-pub(crate) fn parse_header(input: &[u8]) -> IResult<&[u8], Frame> {
+pub(crate) fn parse_header(input: &[u8]) -> IResult<&[u8], Frame<'_>> {
     let (input, _) = tag("H ")(input)?;
     let (input, name) = map_res(take_until(":"), super::str_from_bytes)(input)?;
     let (input, _) = tag(":")(input)?;
 
     let (input, header_frame) = match name {
-        "Product" => map(parse_str, Frame::Product)(input),
+        "Product" => map(parse_lossy_str, Frame::Product)(input),
         "Data version" => map(parse_str, Frame::DataVersion)(input),
         "I interval" => map(parse_i16_dec, Frame::IInterval)(input),
         "P interval" => map(parse_u16_ratio_dec_or_inverse_dec, Frame::PInterval)(input),
@@ -152,11 +390,15 @@ pub(crate) fn parse_header(input: &[u8]) -> IResult<&[u8], Frame> {
         "Field H signed" => map(parse_dec_as_bool_list, Frame::FieldHSignedness)(input),
         "Field H encoding" => map(parse_dec_as_encoding_list, Frame::FieldHEncoding)(input),
         "Field H predictor" => map(parse_dec_as_predictor_list, Frame::FieldHPredictor)(input),
-        "gyro_scale" => map(parse_u32_hex, |x| {
-            Frame::GyroScale(f32::from_bits(x))
-        })(input),
+        "Firmware type" => map(parse_lossy_str, Frame::FirmwareType)(input),
+        "Firmware revision" => map(parse_lossy_str, Frame::FirmwareRevision)(input),
+        "Firmware date" => map(parse_lossy_str, Frame::FirmwareDate)(input),
+        "Board information" => map(parse_lossy_str, Frame::BoardInformation)(input),
+        "Log start datetime" => map(parse_lossy_str, Frame::LogStart)(input),
+        "Craft name" => map(parse_lossy_str, Frame::CraftName)(input),
+        "gyro_scale" => map(parse_u32_hex, |x| Frame::GyroScale(f32::from_bits(x)))(input),
         "looptime" => map(parse_u32_dec, Frame::LoopTime)(input),
-        name => map(parse_str, |v| Frame::UnkownHeader(name, v))(input),
+        name => map(parse_lossy_str, |v| Frame::UnkownHeader(name, v))(input),
     }?;
 
     let (input, _) = tag("\n")(input)?;
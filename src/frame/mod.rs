@@ -1,4 +1,4 @@
-use std::mem::size_of_val;
+use std::{borrow::Cow, fmt, mem::size_of_val, sync::Arc};
 
 use nom::{
     branch::alt,
@@ -15,13 +15,18 @@ use nom::{
 use num_rational::Ratio;
 use num_traits::{WrappingShl, WrappingShr};
 
-use crate::stream::predictor::FieldPredictor;
+use crate::{
+    extensions::{CustomEncoding, DecodeError},
+    stream::predictor::FieldPredictor,
+    Quirks,
+};
 
 pub(crate) mod data;
+pub(crate) mod encode;
 pub mod event;
 pub(crate) mod header;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub(crate) enum RawFieldEncoding {
     SignedVB,
     UnsignedVB,
@@ -29,17 +34,18 @@ pub(crate) enum RawFieldEncoding {
     Tag8_8SVB,
     Tag2_3S32,
     Tag8_4S16,
+    #[default]
     Null,
     Tag2_3SVariable,
+    EliasDeltaU32,
+    EliasDeltaS32,
+    EliasGammaU32,
+    EliasGammaS32,
+    /// Unknown encoding id, only usable through [`Extensions`](crate::Extensions).
+    Custom(u16),
 }
 
-impl Default for RawFieldEncoding {
-    fn default() -> Self {
-        RawFieldEncoding::Null
-    }
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub(crate) enum FieldEncoding {
     SignedVB,
     UnsignedVB,
@@ -47,13 +53,44 @@ pub(crate) enum FieldEncoding {
     Tag8_8SVB(usize),
     Tag2_3S32(usize),
     Tag8_4S16(usize),
+    /// Data version 1 layout, where two 4-bit values share a single byte and selector
+    Tag8_4S16V1(usize),
+    #[default]
     Null,
     Tag2_3SVariable(usize),
+    /// Legacy bit-packed encodings, consecutive fields share bytes
+    EliasDeltaU32,
+    EliasDeltaS32,
+    EliasGammaU32,
+    EliasGammaS32,
+    Custom(CustomFieldEncoding),
+}
+
+/// Encoding registered through [`Extensions`](crate::Extensions), covering `fields`
+/// consecutive fields.
+#[derive(Clone)]
+pub(crate) struct CustomFieldEncoding {
+    pub id: u16,
+    pub fields: usize,
+    pub decoder: Arc<dyn CustomEncoding>,
+}
+
+impl PartialEq for CustomFieldEncoding {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.fields == other.fields
+            && Arc::ptr_eq(&self.decoder, &other.decoder)
+    }
 }
 
-impl Default for FieldEncoding {
-    fn default() -> Self {
-        FieldEncoding::Null
+impl Eq for CustomFieldEncoding {}
+
+impl fmt::Debug for CustomFieldEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomFieldEncoding")
+            .field("id", &self.id)
+            .field("fields", &self.fields)
+            .finish_non_exhaustive()
     }
 }
 
@@ -61,8 +98,9 @@ impl Default for FieldEncoding {
 
 // }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum Field {
+    Values(Vec<i64>),
     Unsigned(u32),
     Signed(i32),
     SignedTriple([i32; 3]),
@@ -84,8 +122,80 @@ fn sign_extend_14bit(word: u16) -> i32 {
     }
 }
 
+/// Reads the three values of a Tag2_3S32 or Tag2_3SVariable group with 8, 16, 24 or 32-bit
+/// values, whose sizes are selected by the low six bits of `selectors`, two bits per value
+/// starting with the first one in the lowest bits.
+fn read_byte_sized_triple(selectors: u8, input: &[u8]) -> IResult<&[u8], Field> {
+    fn read_value(selector: u8, input: &[u8]) -> IResult<&[u8], i32> {
+        match selector & 0b11 {
+            0b00 => map(le_i8, i32::from)(input),
+            0b01 => map(le_i16, i32::from)(input),
+            0b10 => le_i24(input),
+            _ => le_i32(input),
+        }
+    }
+
+    let (input, value1) = read_value(selectors, input)?;
+    let (input, value2) = read_value(selectors >> 2, input)?;
+    let (input, value3) = read_value(selectors >> 4, input)?;
+    Ok((input, Field::SignedTriple([value1, value2, value3])))
+}
+
+impl RawFieldEncoding {
+    /// Whether values are read as unsigned 32-bit numbers, which may not fit in an `i32`.
+    pub(crate) fn is_unsigned(&self) -> bool {
+        matches!(
+            self,
+            RawFieldEncoding::UnsignedVB
+                | RawFieldEncoding::EliasDeltaU32
+                | RawFieldEncoding::EliasGammaU32
+        )
+    }
+
+    /// Cleanflight logs Elias gamma U32 with id 10, which Betaflight later reused for
+    /// Tag2_3SVariable.
+    pub(crate) fn with_quirks(self, quirks: &Quirks) -> Self {
+        match self {
+            RawFieldEncoding::Tag2_3SVariable if quirks.cleanflight_encoding_ids => {
+                RawFieldEncoding::EliasGammaU32
+            }
+            encoding => encoding,
+        }
+    }
+}
+
 impl FieldEncoding {
+    pub(crate) fn is_bit_packed(&self) -> bool {
+        matches!(
+            self,
+            FieldEncoding::EliasDeltaU32
+                | FieldEncoding::EliasDeltaS32
+                | FieldEncoding::EliasGammaU32
+                | FieldEncoding::EliasGammaS32
+        )
+    }
+
+    /// Reads a bit-packed field, continuing from where the previous one ended.
+    pub(crate) fn parse_bits<'a>(
+        &self,
+        bits: &mut BitReader<'a>,
+    ) -> Result<Field, nom::Err<Error<&'a [u8]>>> {
+        Ok(match self {
+            FieldEncoding::EliasDeltaU32 => Field::Unsigned(bits.read_elias_delta()?),
+            FieldEncoding::EliasDeltaS32 => Field::Signed(zigzag_decode(bits.read_elias_delta()?)),
+            FieldEncoding::EliasGammaU32 => Field::Unsigned(bits.read_elias_gamma()?),
+            FieldEncoding::EliasGammaS32 => Field::Signed(zigzag_decode(bits.read_elias_gamma()?)),
+            _ => unreachable!("{:?} is byte aligned", self),
+        })
+    }
+
     pub(crate) fn parse<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], Field> {
+        if self.is_bit_packed() {
+            let mut bits = BitReader::new(input);
+            let field = self.parse_bits(&mut bits)?;
+            return Ok((bits.align(), field));
+        }
+
         Ok(match self {
             FieldEncoding::Null => (input, Field::Unsigned(0)),
             FieldEncoding::UnsignedVB => {
@@ -136,27 +246,7 @@ impl FieldEncoding {
                             ]),
                         )
                     }
-                    0b11 => {
-                        let selector1 = byte1 & 0b11;
-                        let selector2 = (byte1 >> 2) & 0b11;
-                        let selector3 = (byte1 >> 4) & 0b11;
-
-                        fn read_value(selector: u8, input: &[u8]) -> IResult<&[u8], i32> {
-                            match selector {
-                                0b00 => map(le_i8, i32::from)(input),
-                                0b01 => map(le_i16, i32::from)(input),
-                                0b10 => le_i24(input),
-                                0b11 => le_i32(input),
-                                _ => unreachable!(),
-                            }
-                        }
-
-                        let (input, value1) = read_value(selector1, input)?;
-                        let (input, value2) = read_value(selector2, input)?;
-                        let (input, value3) = read_value(selector3, input)?;
-
-                        (input, Field::SignedTriple([value1, value2, value3]))
-                    }
+                    0b11 => read_byte_sized_triple(byte1, input)?,
                     _ => {
                         unreachable!()
                     }
@@ -188,7 +278,7 @@ impl FieldEncoding {
                 let nibbles = nibbles;
 
                 let total_nibbles: u8 = nibbles.iter().sum();
-                let total_bytes = (total_nibbles + 1) / 2;
+                let total_bytes = total_nibbles.div_ceil(2);
 
                 let (input, bytes) = take(total_bytes)(input)?;
                 let mut current_nibble = 0;
@@ -205,7 +295,7 @@ impl FieldEncoding {
                         v <<= 4;
                         v |= ({
                             let b = bytes[(read_pos_nibbles_msn / 2) as usize];
-                            if read_pos_nibbles_msn % 2 == 0 {
+                            if read_pos_nibbles_msn.is_multiple_of(2) {
                                 b >> 4
                             } else {
                                 b
@@ -228,6 +318,42 @@ impl FieldEncoding {
 
                 (input, Field::SignedQuadruple(values))
             }
+            FieldEncoding::Tag8_4S16V1(_) => {
+                let (mut input, mut selectors) = be_u8(input)?;
+                let mut values = [0i16; 4];
+
+                let mut i = 0;
+                while i < 4 {
+                    match selectors & 0b11 {
+                        0b00 => {}
+                        0b01 => {
+                            let (remaining_input, byte) = be_u8(input)?;
+                            input = remaining_input;
+                            values[i] = sign_extend((byte & 0x0f) as i16, 4);
+                            // The next value is in the high nibble, its selector is skipped
+                            i += 1;
+                            selectors >>= 2;
+                            if i < 4 {
+                                values[i] = sign_extend((byte >> 4) as i16, 4);
+                            }
+                        }
+                        0b10 => {
+                            let (remaining_input, value) = le_i8(input)?;
+                            input = remaining_input;
+                            values[i] = value as i16;
+                        }
+                        _ => {
+                            let (remaining_input, value) = le_i16(input)?;
+                            input = remaining_input;
+                            values[i] = value;
+                        }
+                    }
+                    selectors >>= 2;
+                    i += 1;
+                }
+
+                (input, Field::SignedQuadruple(values))
+            }
             FieldEncoding::Tag8_8SVB(fields_n) => {
                 let mut values = [0i32; 8];
 
@@ -239,30 +365,108 @@ impl FieldEncoding {
                 } else {
                     let (mut input, selectors) = be_u8(input)?;
 
-                    for (i, value) in values.iter_mut().enumerate().take(*fields_n) {
+                    // Fields not in the selectors stay 0, which also decodes to 0
+                    let mut varints = [0u32; 8];
+                    for (i, varint) in varints.iter_mut().enumerate().take(*fields_n) {
                         if selectors & (1 << i) != 0 {
-                            let (remaining_input, varint) = take_varint(input)?;
+                            let (remaining_input, value) = take_varint(input)?;
                             input = remaining_input;
-                            *value = zigzag_decode(varint);
+                            *varint = value;
                         }
                     }
+                    values = varints.map(zigzag_decode);
 
                     (input, Field::SignedOctuple(values, *fields_n))
                 }
             }
-            e => unimplemented!("{:?}", e),
+            FieldEncoding::EliasDeltaU32
+            | FieldEncoding::EliasDeltaS32
+            | FieldEncoding::EliasGammaU32
+            | FieldEncoding::EliasGammaS32 => unreachable!(),
+            FieldEncoding::Custom(custom) => {
+                let mut values = Vec::with_capacity(custom.fields);
+                match custom.decoder.decode(input, custom.fields, &mut values) {
+                    Ok(len) if len <= input.len() && values.len() == custom.fields => {
+                        (&input[len..], Field::Values(values))
+                    }
+                    Err(DecodeError::Incomplete) => {
+                        return Err(nom::Err::Incomplete(nom::Needed::Unknown))
+                    }
+                    _ => return Err(nom::Err::Error(Error::new(input, ErrorKind::Verify))),
+                }
+            }
+            FieldEncoding::Tag2_3SVariable(_) => {
+                let (input, byte1) = be_u8(input)?;
+
+                match byte1 >> 6 {
+                    // 2 bits per field: ss11 2233
+                    0b00 => (
+                        input,
+                        Field::SignedTriple([
+                            sign_extend((byte1 >> 4) as i32, 2),
+                            sign_extend((byte1 >> 2) as i32, 2),
+                            sign_extend(byte1 as i32, 2),
+                        ]),
+                    ),
+                    // 5, 5 and 4 bits: ss11 1112 2222 3333
+                    0b01 => {
+                        let (input, byte2) = be_u8(input)?;
+                        (
+                            input,
+                            Field::SignedTriple([
+                                sign_extend((byte1 >> 1) as i32, 5),
+                                sign_extend((((byte1 & 0x01) << 4) | (byte2 >> 4)) as i32, 5),
+                                sign_extend(byte2 as i32, 4),
+                            ]),
+                        )
+                    }
+                    // 8, 7 and 7 bits: ss11 1111 1122 2222 2333 3333
+                    0b10 => {
+                        let (input, byte2) = be_u8(input)?;
+                        let (input, byte3) = be_u8(input)?;
+                        (
+                            input,
+                            Field::SignedTriple([
+                                sign_extend((((byte1 & 0x3f) << 2) | (byte2 >> 6)) as i32, 8),
+                                sign_extend((((byte2 & 0x3f) << 1) | (byte3 >> 7)) as i32, 7),
+                                sign_extend(byte3 as i32, 7),
+                            ]),
+                        )
+                    }
+                    0b11 => read_byte_sized_triple(byte1, input)?,
+                    _ => {
+                        unreachable!()
+                    }
+                }
+            }
         })
     }
 }
 
+/// Kind of a frame read from the body of a log. The values of data frames are decoded into
+/// the buffer passed to [`crate::stream::data::parse_next_frame`].
 #[derive(Debug)]
 pub(crate) enum BodyFrame {
     Event(event::Frame),
-    IFrame(data::OwnedIFrame),
-    PFrame(data::OwnedPFrame),
-    SFrame(data::OwnedSFrame),
-    GFrame(data::OwnedGFrame),
-    HFrame(data::OwnedHFrame),
+    IFrame,
+    PFrame,
+    SFrame,
+    GFrame,
+    HFrame,
+}
+
+impl BodyFrame {
+    /// Byte the frame starts with.
+    pub(crate) fn marker(&self) -> u8 {
+        match self {
+            BodyFrame::Event(_) => b'E',
+            BodyFrame::IFrame => b'I',
+            BodyFrame::PFrame => b'P',
+            BodyFrame::SFrame => b'S',
+            BodyFrame::GFrame => b'G',
+            BodyFrame::HFrame => b'H',
+        }
+    }
 }
 
 pub(crate) fn parse_body_frame(input: &[u8]) -> IResult<&[u8], BodyFrame> {
@@ -271,15 +475,24 @@ pub(crate) fn parse_body_frame(input: &[u8]) -> IResult<&[u8], BodyFrame> {
 }
 
 fn i16_from_dec(bytes: &[u8]) -> Result<i16, ()> {
-    std::str::from_utf8(bytes).map_err(|_| ())?.parse().map_err(|_| ())
+    std::str::from_utf8(bytes)
+        .map_err(|_| ())?
+        .parse()
+        .map_err(|_| ())
 }
 
 fn u16_from_dec(bytes: &[u8]) -> Result<u16, ()> {
-    std::str::from_utf8(bytes).map_err(|_| ())?.parse().map_err(|_| ())
+    std::str::from_utf8(bytes)
+        .map_err(|_| ())?
+        .parse()
+        .map_err(|_| ())
 }
 
 fn u32_from_dec(bytes: &[u8]) -> Result<u32, ()> {
-    std::str::from_utf8(bytes).map_err(|_| ())?.parse().map_err(|_| ())
+    std::str::from_utf8(bytes)
+        .map_err(|_| ())?
+        .parse()
+        .map_err(|_| ())
 }
 
 fn u32_from_hex(bytes: &[u8]) -> Result<u32, ()> {
@@ -304,8 +517,13 @@ fn field_encoding_from_dec(bytes: &[u8]) -> Result<RawFieldEncoding, ()> {
         7 => RawFieldEncoding::Tag2_3S32,
         8 => RawFieldEncoding::Tag8_4S16,
         9 => RawFieldEncoding::Null,
+        // Elias gamma U32 in Cleanflight, see RawFieldEncoding::with_quirks
         10 => RawFieldEncoding::Tag2_3SVariable,
-        _ => return Err(()),
+        // Ids of FlightLogFieldEncoding in Cleanflight's blackbox_fielddefs.h
+        4 => RawFieldEncoding::EliasDeltaU32,
+        5 => RawFieldEncoding::EliasDeltaS32,
+        11 => RawFieldEncoding::EliasGammaS32,
+        id => RawFieldEncoding::Custom(id),
     })
 }
 
@@ -324,7 +542,7 @@ fn field_predictor_from_dec(bytes: &[u8]) -> Result<FieldPredictor, ()> {
         9 => FieldPredictor::VBatRef,
         10 => FieldPredictor::LastMainFrameTime,
         11 => FieldPredictor::MinMotor,
-        _ => return Err(()),
+        id => FieldPredictor::Custom(id),
     })
 }
 
@@ -332,12 +550,17 @@ fn parse_str(input: &[u8]) -> IResult<&[u8], &str> {
     map_res(take_until("\n"), str_from_bytes)(input)
 }
 
+/// Free text, where corrupted bytes are replaced instead of failing the whole line.
+fn parse_lossy_str(input: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    map(take_until("\n"), String::from_utf8_lossy)(input)
+}
+
 fn parse_i16_dec(input: &[u8]) -> IResult<&[u8], i16> {
     map_res(take_until("\n"), i16_from_dec)(input)
 }
 
 fn parse_u16_ratio_dec(input: &[u8]) -> IResult<&[u8], Ratio<u16>> {
-    let (input, numer) = map_res(take_until("/"), u16_from_dec)(input)?;
+    let (input, numer) = map_res(is_not("/\n"), u16_from_dec)(input)?;
     let (input, _) = tag("/")(input)?;
     let (input, denom) = map_res(take_until("\n"), u16_from_dec)(input)?;
     Ok((input, Ratio::new(numer, denom)))
@@ -389,7 +612,53 @@ fn parse_dec_as_predictor_list(input: &[u8]) -> IResult<&[u8], Vec<FieldPredicto
     parse_list(input, field_predictor_from_dec)
 }
 
+/// Continuation bits of the bytes of a word.
+const VARINT_CONTINUATION: u64 = 0x8080_8080_8080_8080;
+/// Value bits of the five bytes a 32-bit varint takes at most.
+const VARINT_VALUE: u64 = 0x7f_7f7f_7f7f;
+
 fn take_varint(input: &[u8]) -> IResult<&[u8], u32> {
+    // Most varints are read from the middle of a log, where a whole word is available
+    match input.get(..8) {
+        Some(word) => take_varint_word(input, u64::from_le_bytes(word.try_into().unwrap())),
+        None => take_varint_bytes(input),
+    }
+}
+
+/// Decodes the varint at the start of `word`, the first 8 bytes of `input`, without
+/// branching on every byte.
+#[inline]
+fn take_varint_word(input: &[u8], word: u64) -> IResult<&[u8], u32> {
+    let last_byte = (!word & VARINT_CONTINUATION).trailing_zeros() as usize / 8;
+    if last_byte >= 5 {
+        return Err(nom::Err::Failure(Error::from_error_kind(
+            &input[5..],
+            ErrorKind::TooLarge,
+        )));
+    }
+    let bytes = word & (u64::MAX >> (56 - last_byte * 8)) & VARINT_VALUE;
+    Ok((&input[last_byte + 1..], gather_varint_bits(bytes) as u32))
+}
+
+/// Packs the low 7 bits of each of the five lowest bytes together.
+#[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+#[inline]
+fn gather_varint_bits(bytes: u64) -> u64 {
+    // SAFETY: the target feature is enabled at compile time
+    unsafe { std::arch::x86_64::_pext_u64(bytes, VARINT_VALUE) }
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "bmi2")))]
+#[inline]
+fn gather_varint_bits(bytes: u64) -> u64 {
+    (bytes & 0x7f)
+        | (bytes >> 1 & 0x3f80)
+        | (bytes >> 2 & 0x1f_c000)
+        | (bytes >> 3 & 0xfe0_0000)
+        | (bytes >> 4 & 0x7_f000_0000)
+}
+
+fn take_varint_bytes(input: &[u8]) -> IResult<&[u8], u32> {
     let mut res: u32 = 0;
     let mut input = input;
 
@@ -408,6 +677,86 @@ fn take_varint(input: &[u8]) -> IResult<&[u8], u32> {
     )))
 }
 
+/// Reads values bit by bit, most significant bit of each byte first, as used by the
+/// Elias encodings of old Cleanflight/Baseflight logs.
+pub(crate) struct BitReader<'a> {
+    input: &'a [u8],
+    bit: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Self {
+        Self { input, bit: 0 }
+    }
+
+    fn read_bit(&mut self) -> Result<u32, nom::Err<Error<&'a [u8]>>> {
+        let byte = self
+            .input
+            .get(self.bit / 8)
+            .ok_or(nom::Err::Incomplete(nom::Needed::new(1)))?;
+        let bit = (byte >> (7 - self.bit % 8)) & 1;
+        self.bit += 1;
+        Ok(bit as u32)
+    }
+
+    fn read_bits(&mut self, n: u32) -> Result<u32, nom::Err<Error<&'a [u8]>>> {
+        let mut value = 0u64;
+        for _ in 0..n {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Ok(value as u32)
+    }
+
+    /// Input following the last byte any bits were read from.
+    pub(crate) fn align(self) -> &'a [u8] {
+        &self.input[self.bit.div_ceil(8)..]
+    }
+
+    fn too_large(&self) -> nom::Err<Error<&'a [u8]>> {
+        nom::Err::Failure(Error::from_error_kind(
+            &self.input[self.bit / 8..],
+            ErrorKind::TooLarge,
+        ))
+    }
+
+    /// Values are encoded plus one, `u32::MAX` as `u32::MAX - 1` followed by a one bit.
+    fn finish_elias(&mut self, encoded: u64) -> Result<u32, nom::Err<Error<&'a [u8]>>> {
+        let value = (encoded - 1) as u32;
+        if value == u32::MAX - 1 && self.read_bit()? == 1 {
+            return Ok(u32::MAX);
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn read_elias_delta(&mut self) -> Result<u32, nom::Err<Error<&'a [u8]>>> {
+        let mut length_bits = 0;
+        while self.read_bit()? == 0 {
+            length_bits += 1;
+            if length_bits > 5 {
+                return Err(self.too_large());
+            }
+        }
+        let length = (1 << length_bits) | self.read_bits(length_bits)?;
+        if length > 32 {
+            return Err(self.too_large());
+        }
+        let encoded = (1u64 << (length - 1)) | self.read_bits(length - 1)? as u64;
+        self.finish_elias(encoded)
+    }
+
+    pub(crate) fn read_elias_gamma(&mut self) -> Result<u32, nom::Err<Error<&'a [u8]>>> {
+        let mut length_bits = 0;
+        while self.read_bit()? == 0 {
+            length_bits += 1;
+            if length_bits > 31 {
+                return Err(self.too_large());
+            }
+        }
+        let encoded = (1u64 << length_bits) | self.read_bits(length_bits)? as u64;
+        self.finish_elias(encoded)
+    }
+}
+
 #[inline]
 fn zigzag_decode(from: u32) -> i32 {
     ((from >> 1) ^ (-((from & 1) as i32)) as u32) as i32
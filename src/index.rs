@@ -0,0 +1,159 @@
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+/// First bytes of an index file written by [`Index::write`].
+const MAGIC: &[u8; 8] = b"FCBBIDX\0";
+const VERSION: u32 = 2;
+
+/// Position of an I-frame within a log, as recorded by [`BlackboxReader::build_index`].
+///
+/// [`BlackboxReader::build_index`]: crate::BlackboxReader::build_index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyFrame {
+    /// Offset from the start of the log, on the same basis as `BlackboxReader::bytes_read`
+    pub offset: usize,
+    pub loop_iteration: i64,
+    /// `time` with 32-bit rollovers accounted for, like
+    /// [`BlackboxReader::last_widened_time`](crate::BlackboxReader::last_widened_time)
+    pub time: i64,
+}
+
+/// Identifies the log an index was built from, so an index file isn't used with another log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct LogId {
+    pub(crate) len: u64,
+    /// FNV-1a hash of the header bytes
+    pub(crate) header_hash: u64,
+}
+
+impl LogId {
+    pub(crate) fn new(log: &[u8], header_length: usize) -> Self {
+        let header_hash = log[..header_length]
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+                (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+            });
+        Self {
+            len: log.len() as u64,
+            header_hash,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum IndexError {
+    #[error("couldn't read or write the index file")]
+    Io(#[from] io::Error),
+    #[error("not an index file or an unsupported version")]
+    Format,
+    #[error("the index was built from another log")]
+    Mismatch,
+}
+
+/// I-frames of a single log in the order they appear.
+///
+/// Building an index scans the whole log, so it can be saved next to the log with
+/// [`Index::save`] and handed to later readers of the same log with
+/// [`BlackboxReader::set_index`], which then seek right away.
+///
+/// [`BlackboxReader::set_index`]: crate::BlackboxReader::set_index
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+    log: LogId,
+    keyframes: Vec<KeyFrame>,
+}
+
+impl Index {
+    pub(crate) fn new(log: LogId, keyframes: Vec<KeyFrame>) -> Self {
+        Self { log, keyframes }
+    }
+
+    pub(crate) fn log(&self) -> LogId {
+        self.log
+    }
+
+    pub fn keyframes(&self) -> &[KeyFrame] {
+        &self.keyframes
+    }
+
+    /// Last I-frame at or before `time`, or the first one if `time` precedes all of them.
+    pub fn keyframe_for_time(&self, time: i64) -> Option<KeyFrame> {
+        let ix = self.keyframes.partition_point(|k| k.time <= time);
+        self.keyframes.get(ix.saturating_sub(1)).copied()
+    }
+
+    /// Last I-frame at or before `loop_iteration`, or the first one if it precedes all of them.
+    pub fn keyframe_for_iteration(&self, loop_iteration: i64) -> Option<KeyFrame> {
+        let ix = self
+            .keyframes
+            .partition_point(|k| k.loop_iteration <= loop_iteration);
+        self.keyframes.get(ix.saturating_sub(1)).copied()
+    }
+
+    /// Path of the index file kept next to the log at `log_path`, with `.idx` appended.
+    pub fn sidecar_path(log_path: impl AsRef<Path>) -> PathBuf {
+        let mut path = log_path.as_ref().as_os_str().to_owned();
+        path.push(".idx");
+        path.into()
+    }
+
+    /// Writes the index in a compact little-endian binary format.
+    pub fn write(&self, mut out: impl Write) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(36 + self.keyframes.len() * 24);
+        buf.extend(MAGIC);
+        buf.extend(VERSION.to_le_bytes());
+        buf.extend(self.log.len.to_le_bytes());
+        buf.extend(self.log.header_hash.to_le_bytes());
+        buf.extend((self.keyframes.len() as u64).to_le_bytes());
+        for keyframe in &self.keyframes {
+            buf.extend((keyframe.offset as u64).to_le_bytes());
+            buf.extend(keyframe.loop_iteration.to_le_bytes());
+            buf.extend(keyframe.time.to_le_bytes());
+        }
+        out.write_all(&buf)
+    }
+
+    /// Reads an index written by [`Index::write`].
+    pub fn read(mut input: impl Read) -> Result<Self, IndexError> {
+        let mut buf = Vec::new();
+        input.read_to_end(&mut buf)?;
+        let rest = buf.strip_prefix(MAGIC).ok_or(IndexError::Format)?;
+        let mut words = rest[4.min(rest.len())..]
+            .chunks_exact(8)
+            .map(|w| u64::from_le_bytes(w.try_into().unwrap()));
+        if rest.get(..4) != Some(&VERSION.to_le_bytes()[..]) {
+            return Err(IndexError::Format);
+        }
+        let mut next = || words.next().ok_or(IndexError::Format);
+        let log = LogId {
+            len: next()?,
+            header_hash: next()?,
+        };
+        let count = next()?;
+        if (rest.len() - 28) as u64 != count.saturating_mul(24) {
+            return Err(IndexError::Format);
+        }
+        let keyframes = (0..count)
+            .map(|_| {
+                Ok(KeyFrame {
+                    offset: next()? as usize,
+                    loop_iteration: next()? as i64,
+                    time: next()? as i64,
+                })
+            })
+            .collect::<Result<_, IndexError>>()?;
+        Ok(Self { log, keyframes })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write(std::fs::File::create(path)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, IndexError> {
+        Self::read(std::fs::File::open(path)?)
+    }
+}
@@ -1,82 +1,448 @@
-use frame::event;
-use itertools::Itertools;
+use chrono::{DateTime, Duration, FixedOffset};
+use frame::{event, BodyFrame};
+use index::LogId;
 use nom::FindSubstring;
+use std::{ops::Range, sync::Arc};
 use stream::{
     data::parse_next_frame,
-    header::{parse_headers, Header},
+    header::{parse_headers, ParseHeadersError},
     predictor::{LogProcessor, LogRecord},
 };
 use thiserror::Error;
 
 extern crate itertools;
 
+pub mod analysis;
+mod anonymize;
+mod batch;
+mod carve;
+mod columns;
+#[cfg(feature = "compressed")]
+pub mod compressed;
+mod debug_mode;
+mod derived;
+mod diagnostics;
+pub mod export;
+mod extensions;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod flight_mode;
 pub mod frame;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+mod index;
+mod merged;
+pub mod msp;
+mod outputs;
+#[cfg(feature = "python")]
+pub mod python;
+mod quirks;
+mod record;
+mod recovery;
+#[cfg(feature = "serde")]
+mod serialize;
+mod session;
+mod stats;
 pub(crate) mod stream;
+#[cfg(feature = "bench")]
+pub mod synthetic;
+mod transcode;
+pub mod units;
+mod visit;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+
+pub use anonymize::{anonymize, AnonymizeError, AnonymizeOptions, GnssPrivacy, PRIVATE_HEADERS};
+pub use batch::Batch;
+pub use carve::{CarveOptions, CarvedLog};
+pub use columns::Columns;
+pub use debug_mode::{DebugField, DebugMode};
+pub use derived::{DerivedField, ExpressionError};
+pub use diagnostics::{Diagnostic, DiagnosticKind, Diagnostics, Severity};
+pub use extensions::{CustomEncoding, CustomPredictor, DecodeError, Extensions, PredictorContext};
+pub use flight_mode::{FailsafePhase, FlightModes, StateFlags};
+pub use frame::event::DisarmReason;
+pub use frame::header::{
+    BoardInformation, CurrentSensor, FirmwareKind, FirmwareVersion, HeaderSettings, RollPitchYaw,
+    VBatCellVoltage, PID,
+};
+pub use index::{Index, IndexError, KeyFrame};
+pub use merged::{GnssAlignment, MergedReader, MergedRecord};
+pub use outputs::{MotorProtocol, OutputLayout};
+pub use quirks::Quirks;
+pub use record::{FieldKind, FieldView, MainFrame, MainFrameLayout};
+pub use recovery::{FieldCountAction, RecoveryAction, RecoveryPolicy, SerialArtifacts, XOFF, XON};
+pub use session::{SegmentTiming, SessionReader, SessionRecord};
+pub use stats::{FrameCounts, FrameStats, ReaderStats};
+pub use stream::header::{
+    GNSSField, GNSSHomeField, Header, HeaderValueError, HeaderViolation, IPField, SlowField,
+};
+pub use transcode::{transcode, TranscodeError};
+pub use visit::Visitor;
 
 #[allow(unused)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BlackboxRecord<'a> {
-    Main(&'a [i64]),
-    GNSS(&'a [i64]),
+    Main(FieldView<'a>),
+    GNSS(FieldView<'a>),
+    Slow(FieldView<'a>),
+    Event(event::Frame),
+    Garbage(ByteSpan),
+}
+
+/// [`BlackboxRecord`] that doesn't borrow from the reader, for keeping or sending records
+/// elsewhere. Frame values are in the same order as in the borrowed record.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum OwnedRecord {
+    Main(Vec<i64>),
+    GNSS(Vec<i64>),
     Slow(Vec<i64>),
     Event(event::Frame),
-    Garbage(usize),
+    Garbage(ByteSpan),
+}
+
+impl BlackboxRecord<'_> {
+    pub fn to_owned_record(&self) -> OwnedRecord {
+        match self {
+            BlackboxRecord::Main(values) => OwnedRecord::Main(values.to_vec()),
+            BlackboxRecord::GNSS(values) => OwnedRecord::GNSS(values.to_vec()),
+            BlackboxRecord::Slow(values) => OwnedRecord::Slow(values.to_vec()),
+            BlackboxRecord::Event(event) => OwnedRecord::Event(event.clone()),
+            BlackboxRecord::Garbage(span) => OwnedRecord::Garbage(*span),
+        }
+    }
+}
+
+/// Record returned by [`BlackboxReader::next_timed`], with the loop iteration and time of the
+/// last main frame, which is the record itself for main frames.
+pub struct TimedRecord<'a> {
+    pub loop_iteration: i64,
+    /// Microseconds, with rollovers accounted for like
+    /// [`BlackboxReader::last_widened_time`].
+    pub time: i64,
+    /// `time` on the clock of the `Log start datetime` header, counted from the first main
+    /// frame of the log. `None` without the header, or before any main frame was read.
+    pub wall_clock: Option<DateTime<FixedOffset>>,
+    pub record: BlackboxRecord<'a>,
+}
+
+/// What a [`BlackboxRecord`] holds apart from its borrow of the reader, so the reader can be
+/// used before the record is rebuilt with [`BlackboxReader::record`].
+pub(crate) enum Taken {
+    View(FieldKind),
+    Event(event::Frame),
+    Garbage(ByteSpan),
+}
+
+impl From<BlackboxRecord<'_>> for Taken {
+    fn from(record: BlackboxRecord<'_>) -> Self {
+        match record {
+            BlackboxRecord::Main(view)
+            | BlackboxRecord::GNSS(view)
+            | BlackboxRecord::Slow(view) => Taken::View(view.kind()),
+            BlackboxRecord::Event(event) => Taken::Event(event),
+            BlackboxRecord::Garbage(span) => Taken::Garbage(span),
+        }
+    }
+}
+
+/// Region of the log, relative to the start of the bytes given to the reader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ByteSpan {
+    pub offset: usize,
+    pub len: usize,
+}
+
+enum ScannedFrame {
+    Frame(usize, BodyFrame),
+    Garbage(ByteSpan),
+}
+
+/// How the reader finds its way back to valid frames after corrupted data, when the
+/// [`RecoveryPolicy`] asks it to resync.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResyncStrategy {
+    /// Retry parsing starting from every following byte.
+    ByteByByte,
+    /// Scan forward for an I-frame or event marker that parses, is followed by another
+    /// plausible frame and, for I-frames, doesn't go back in `loopIteration`.
+    NextKeyframe,
+}
+
+/// Largest plausible change between consecutive main frames. Decoded frames outside these
+/// limits are most likely corrupted and are reported as garbage instead, like Betaflight's own
+/// decoder does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameLimits {
+    /// Maximum increase of `loopIteration`; it must never decrease.
+    pub max_iteration_jump: i64,
+    /// Maximum increase of `time` in microseconds.
+    pub max_time_jump: i64,
+    /// How far `time` may go backwards, in microseconds.
+    pub max_time_rewind: i64,
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        Self {
+            max_iteration_jump: 5000,
+            max_time_jump: 10_000_000,
+            max_time_rewind: 0,
+        }
+    }
+}
+
+impl FrameLimits {
+    fn allows(&self, (last_iteration, last_time): (i64, i64), iteration: i64, time: i64) -> bool {
+        (last_iteration..=last_iteration + self.max_iteration_jump).contains(&iteration)
+            && (last_time - self.max_time_rewind..=last_time + self.max_time_jump).contains(&time)
+    }
 }
 
-#[derive(Copy, Clone, PartialEq)]
-pub enum Strictness {
-    Strict,
-    Lenient,
+#[derive(Clone, Debug)]
+pub struct ReaderOptions {
+    pub recovery: RecoveryPolicy,
+    pub resync: ResyncStrategy,
+    /// Main frame validation, `None` to return every decoded frame.
+    pub frame_limits: Option<FrameLimits>,
+    pub extensions: Extensions,
+    /// Receives the problems the reader recovered from.
+    pub diagnostics: Option<Arc<dyn Diagnostics>>,
+    /// Cross-checks the header before decoding anything, failing with every inconsistency
+    /// found in [`BlackboxReaderError::InvalidHeader`]. Without it, the reader fails at the
+    /// first problem it can't decode around, and ignores the others.
+    pub validate_header: bool,
+    /// Strips what a serial link injected between frames, for logs captured through one.
+    pub serial_artifacts: Option<SerialArtifacts>,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        Self {
+            recovery: RecoveryPolicy::lenient(),
+            resync: ResyncStrategy::ByteByByte,
+            frame_limits: Some(FrameLimits::default()),
+            extensions: Extensions::default(),
+            diagnostics: None,
+            validate_header: false,
+            serial_artifacts: None,
+        }
+    }
+}
+
+impl ReaderOptions {
+    pub(crate) fn report(&self, offset: usize, kind: DiagnosticKind) {
+        let diagnostic = Diagnostic { offset, kind };
+        #[cfg(feature = "tracing")]
+        match diagnostic.severity() {
+            Severity::Info => tracing::debug!(offset, kind = ?diagnostic.kind, "recovered"),
+            Severity::Warning => tracing::info!(offset, kind = ?diagnostic.kind, "recovered"),
+        }
+        if let Some(diagnostics) = &self.diagnostics {
+            diagnostics.report(diagnostic);
+        }
+    }
+}
+
+impl From<RecoveryPolicy> for ReaderOptions {
+    fn from(recovery: RecoveryPolicy) -> Self {
+        Self {
+            recovery,
+            ..Default::default()
+        }
+    }
+}
+
+fn is_frame_marker(byte: Option<&u8>) -> bool {
+    matches!(
+        byte,
+        Some(b'I') | Some(b'P') | Some(b'S') | Some(b'G') | Some(b'H') | Some(b'E') | None
+    )
+}
+
+/// Widens a 32-bit `time` value to the rollover period closest to `last`.
+fn widen_time(last: i64, time: i64) -> i64 {
+    const PERIOD: i64 = 1 << 32;
+    let time = last.div_euclid(PERIOD) * PERIOD + (time as u32) as i64;
+    if time < last - PERIOD / 2 {
+        time + PERIOD
+    } else if time > last + PERIOD / 2 && time >= PERIOD {
+        time - PERIOD
+    } else {
+        time
+    }
+}
+
+/// Whether `remaining` after `frame` starts with another frame as it should. Anything may
+/// follow the `End of log` event, usually erased flash.
+fn is_followed_by_frame(frame: &BodyFrame, remaining: &[u8]) -> bool {
+    matches!(frame, BodyFrame::Event(event::Frame::EndOfLog)) || is_frame_marker(remaining.first())
+}
+
+fn is_unknown_event(frame: &BodyFrame) -> bool {
+    matches!(frame, BodyFrame::Event(event::Frame::Unknown(_)))
 }
 
 pub struct BlackboxReader<'a> {
-    strictness: Strictness,
+    options: ReaderOptions,
     last_values: Vec<i64>,
+    bytes: &'a [u8],
     remaining_bytes: &'a [u8],
     original_length: usize,
+    header_length: usize,
+    index: Option<Index>,
+    garbage_start: Option<usize>,
+    /// Set when the recovery policy gave up on the rest of the log.
+    stopped: bool,
+    /// Why the garbage limits of the recovery policy stopped reading.
+    stop_error: Option<BlackboxReaderError>,
+    last_frame_span: Option<ByteSpan>,
+    /// Offset right after the `End of log` event, once it was read.
+    end_of_log: Option<usize>,
+    /// Offset of the header of another log in the frame data, where reading stops.
+    next_segment: Option<usize>,
+    range: Option<(i64, i64)>,
+    projection: Option<Vec<usize>>,
+    derived: Vec<derived::ResolvedField>,
+    derived_values: Vec<f64>,
     pub header: Header,
     processor: LogProcessor,
+    /// Raw values of the last data frame, before prediction. Reused for every frame.
+    frame_values: Vec<i64>,
+    batch: Batch,
     pub last_loop_iteration: i64,
     pub last_time: i64,
-    loop_iteration_field_ix: usize,
-    time_field_ix: usize,
+    /// `last_time` with 32-bit rollovers accounted for, so it keeps increasing in logs longer
+    /// than ~71 minutes.
+    pub last_widened_time: i64,
+    /// `time` of the first main frame of the log, once it's known.
+    first_time: Option<i64>,
+    /// `loopIteration` and `time` the next main frame is validated against, `None` when the
+    /// main frame history can't be trusted and only an I-frame can be accepted.
+    last_valid_main: Option<(i64, i64)>,
+    /// `loopIteration` missing iterations are counted from, `None` after a seek.
+    expected_from: Option<i64>,
+    /// Set by [`next_every_nth`](Self::next_every_nth) when P-frames don't need predicting
+    /// until the next I-frame.
+    skip_p_frames: bool,
+    /// Set once a P-frame was skipped, the main frame history is wrong until the next I-frame.
+    stale_history: bool,
+    /// Main frames [`next_every_nth`](Self::next_every_nth) passes over before returning one.
+    every_nth_pass: usize,
+    stats: ReaderStats,
+    frame_stats: FrameStats,
+    loop_iteration_field_ix: Option<usize>,
+    time_field_ix: Option<usize>,
+    /// Entered while decoding, inside the span of its segment when read from a multi-log file.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 #[derive(Error, Debug)]
-#[cfg_attr(test, derive(serde::Serialize))]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize))]
 pub enum BlackboxReaderError {
-    #[error("couldn't parse header")]
-    ParseHeader,
-    #[error("loopIteration or time I/P fields have not been found")]
-    NoLoopIterationAndTime,
+    #[error("header {0} is missing")]
+    MissingHeader(&'static str),
+    /// A header line that couldn't be parsed, with [`RecoveryAction::Abort`] for header
+    /// anomalies. `offset` is where the line starts in the log.
+    #[error("couldn't parse header line {line:?} at byte {offset}")]
+    MalformedHeader { line: String, offset: usize },
     #[error("log is truncated")]
     Incomplete,
+    #[error("field {0} is not present in the log")]
+    UnknownField(String),
+    #[error("predictor {predictor} of field {field} is not supported")]
+    UnsupportedPredictor { field: String, predictor: String },
+    #[error("predictor of field {field} needs the {header} header")]
+    MissingHeaderForPredictor { field: String, header: &'static str },
+    #[error("encoding {encoding} of field {field} is not supported")]
+    UnsupportedEncoding { field: String, encoding: u16 },
+    #[error("gave up after {len} bytes of garbage at byte {offset}")]
+    GarbageRun { offset: usize, len: usize },
+    #[error("gave up after skipping {garbage_bytes} bytes, more than {max_fraction} of the log")]
+    GarbageFraction {
+        garbage_bytes: u64,
+        max_fraction: f64,
+    },
+    #[error("header is inconsistent: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    InvalidHeader(Vec<HeaderViolation>),
+}
+
+impl BlackboxReaderError {
+    fn malformed_header(input: &[u8], offset: usize) -> Self {
+        // The line ends the header block if it has no newline, so keep just its start
+        let line = input.split(|b| *b == b'\n').next().unwrap_or_default();
+        BlackboxReaderError::MalformedHeader {
+            line: String::from_utf8_lossy(&line[..line.len().min(256)]).into_owned(),
+            offset,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum OpenError {
+    #[error("couldn't read the log file")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Reader(#[from] BlackboxReaderError),
+}
+
+/// Reads a log file into `buf`, decompressing it with the `compressed` feature.
+fn read_log_file(path: &std::path::Path, buf: &mut Vec<u8>) -> std::io::Result<()> {
+    let file = std::fs::File::open(path)?;
+    #[cfg(feature = "compressed")]
+    return compressed::read_into(file, buf);
+    #[cfg(not(feature = "compressed"))]
+    return std::io::Read::read_to_end(&mut &file, buf).map(drop);
 }
 
 impl<'a> BlackboxReader<'a> {
     pub fn new(
         bytes: &'a [u8],
-        strictness: Strictness,
+        recovery: RecoveryPolicy,
+    ) -> Result<BlackboxReader<'a>, BlackboxReaderError> {
+        Self::with_options(bytes, recovery.into())
+    }
+
+    pub fn with_options(
+        bytes: &'a [u8],
+        options: ReaderOptions,
     ) -> Result<BlackboxReader<'a>, BlackboxReaderError> {
         let original_length = bytes.len();
-        let (remaining_bytes, header) = parse_headers(bytes).map_err(|e| match e {
-            nom::Err::Error(_e) => BlackboxReaderError::ParseHeader,
-            nom::Err::Failure(_e) => BlackboxReaderError::ParseHeader,
+        let parsed = parse_headers(bytes, &options).map_err(|e| match e {
+            nom::Err::Failure(ParseHeadersError::HeaderBuildError(e)) => e.into(),
+            nom::Err::Error(ParseHeadersError::Nom(input, _))
+            | nom::Err::Failure(ParseHeadersError::Nom(input, _)) => {
+                BlackboxReaderError::malformed_header(input, original_length - input.len())
+            }
+            nom::Err::Error(ParseHeadersError::HeaderBuildError(e)) => e.into(),
+            nom::Err::Error(ParseHeadersError::Invalid(violations))
+            | nom::Err::Failure(ParseHeadersError::Invalid(violations)) => {
+                BlackboxReaderError::InvalidHeader(violations)
+            }
             nom::Err::Incomplete(_) => BlackboxReaderError::Incomplete,
-        })?;
-
-        let loop_iteration_field_ix = header
-            .ip_fields_in_order
-            .iter()
-            .find_position(|f| f.name == "loopIteration")
-            .ok_or(BlackboxReaderError::NoLoopIterationAndTime)?
-            .0;
+        });
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &parsed {
+            tracing::info!(%error, "couldn't parse header");
+        }
+        let (remaining_bytes, header) = parsed?;
+        let header_length = original_length - remaining_bytes.len();
+        let next_segment = remaining_bytes
+            .find_substring(SEGMENT_START)
+            .map(|pos| header_length + pos);
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "decode",
+            firmware = header.firmware_revision(),
+            header_length,
+        );
 
-        let time_field_ix = header
-            .ip_fields_in_order
-            .iter()
-            .find_position(|f| f.name == "time")
-            .ok_or(BlackboxReaderError::NoLoopIterationAndTime)?
-            .0;
+        // Both are optional, but without them `last_loop_iteration` and `last_time` stay at 0
+        let loop_iteration_field_ix = header.ip_fields.get("loopIteration").map(|f| f.ix);
+        let time_field_ix = header.ip_fields.get("time").map(|f| f.ix);
 
         let last_values = Vec::with_capacity(
             header
@@ -87,8 +453,23 @@ impl<'a> BlackboxReader<'a> {
         );
 
         Ok(BlackboxReader {
+            bytes,
+            header_length,
             remaining_bytes,
             original_length,
+            index: None,
+            garbage_start: None,
+            stopped: false,
+            stop_error: None,
+            last_frame_span: None,
+            end_of_log: None,
+            next_segment,
+            range: None,
+            projection: None,
+            derived: Vec::new(),
+            derived_values: Vec::new(),
+            frame_values: Vec::new(),
+            batch: Batch::default(),
             processor: LogProcessor::new(&header),
             last_values,
             loop_iteration_field_ix,
@@ -96,98 +477,1096 @@ impl<'a> BlackboxReader<'a> {
             header,
             last_loop_iteration: 0,
             last_time: 0,
-            strictness,
+            last_widened_time: 0,
+            first_time: None,
+            last_valid_main: None,
+            expected_from: None,
+            skip_p_frames: false,
+            stale_history: false,
+            every_nth_pass: 0,
+            stats: ReaderStats::default(),
+            frame_stats: FrameStats::default(),
+            options,
+            #[cfg(feature = "tracing")]
+            span,
         })
     }
 
     pub fn from_bytes(bytes: &'a [u8]) -> Result<BlackboxReader<'a>, BlackboxReaderError> {
-        Self::new(bytes, Strictness::Lenient)
+        Self::new(bytes, RecoveryPolicy::lenient())
+    }
+
+    /// Reads the log file at `path` into `buf` and reads the first log in it. gzip, zstd and
+    /// ZIP files are decompressed with the `compressed` feature.
+    pub fn open(
+        path: impl AsRef<std::path::Path>,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<BlackboxReader<'a>, OpenError> {
+        buf.clear();
+        read_log_file(path.as_ref(), buf)?;
+        Ok(Self::from_bytes(buf)?)
     }
 
     #[allow(clippy::should_implement_trait)]
-    pub fn next(&mut self) -> Option<BlackboxRecord> {
+    pub fn next(&mut self) -> Option<BlackboxRecord<'_>> {
+        #[cfg(feature = "tracing")]
+        let _span = self.span.clone().entered();
         loop {
-            match parse_next_frame(&self.header, self.remaining_bytes) {
-                Ok((remaining_bytes, frame)) => {
-                    if self.strictness == Strictness::Lenient {
-                        match remaining_bytes.first() {
-                            Some(b'I') | Some(b'P') | Some(b'S') | Some(b'G') | Some(b'H') | Some(b'E') | None => {
-                                // Next frame looks valid or it's an EOF
+            let scanned = self.next_frame()?;
+            // Only main frames move the time, so this applies to everything else
+            let in_range = self.in_range();
+            let (offset, frame) = match scanned {
+                ScannedFrame::Frame(offset, frame) => (offset, frame),
+                ScannedFrame::Garbage(span) if in_range => {
+                    self.last_frame_span = Some(span);
+                    return Some(BlackboxRecord::Garbage(span));
+                }
+                ScannedFrame::Garbage(_) => continue,
+            };
+            let span = ByteSpan {
+                offset,
+                len: self.bytes_read() - offset,
+            };
+            let is_iframe = matches!(frame, BodyFrame::IFrame);
+            if let BodyFrame::PFrame = frame {
+                if self.skip_p_frames || self.stale_history {
+                    self.stale_history = true;
+                    self.stats.main_frames += 1;
+                    self.frame_stats.count(b'P', |counts| &mut counts.valid);
+                    // Neither can be checked without the frame's values
+                    self.last_valid_main = None;
+                    self.expected_from = None;
+                    continue;
+                }
+            }
+            self.stale_history &= !is_iframe;
+            if matches!(frame, BodyFrame::HFrame) && self.header.h_fields.is_empty() {
+                self.options.report(offset, DiagnosticKind::EmptyHomeFrame);
+            }
+            // Main frames still have to pass validation
+            let marker = frame.marker();
+            if !matches!(marker, b'I' | b'P') {
+                self.frame_stats.count(marker, |counts| &mut counts.valid);
+            }
+            let kind = match self.processor.process_frame(frame, &self.frame_values) {
+                Some(LogRecord::Main(values)) => {
+                    let iteration = self.loop_iteration_field_ix.map_or(0, |ix| values[ix]);
+                    let raw_time = self.time_field_ix.map_or(0, |ix| values[ix]);
+                    let time = widen_time(self.last_widened_time, raw_time);
+                    if let Some(limits) = self.options.frame_limits {
+                        // P-frames are only as good as the frames they are predicted from
+                        let valid = match self.last_valid_main {
+                            Some(last) => limits.allows(last, iteration, time),
+                            None => is_iframe,
+                        };
+                        if !valid {
+                            self.stats.corrupted_frames += 1;
+                            // Without a valid frame before, P-frames can't be checked at all
+                            match self.last_valid_main {
+                                None if !is_iframe => self
+                                    .frame_stats
+                                    .count(marker, |counts| &mut counts.desynced),
+                                _ => self.frame_stats.count(marker, |counts| &mut counts.corrupt),
                             }
-                            _ => {
-                                // Skip the parsed frame
-                                // Continue from the second byte of the parsed frame, because if it's invalid,
-                                // we can't be sure what size it was and where next frame starts
-                                self.remaining_bytes = &self.remaining_bytes[1..];
-                                continue;
+                            match self.options.recovery.implausible_frame {
+                                RecoveryAction::Abort => {
+                                    #[cfg(feature = "tracing")]
+                                    tracing::info!(offset, "stopped at an implausible frame");
+                                    self.remaining_bytes = &self.bytes[offset..];
+                                    self.stopped = true;
+                                    continue;
+                                }
+                                RecoveryAction::Skip => {}
+                                RecoveryAction::Resync => self.last_valid_main = None,
                             }
+                            self.garbage_start.get_or_insert(offset);
+                            continue;
+                        }
+                        self.last_valid_main = Some((iteration, time));
+                    }
+                    self.stats.main_frames += 1;
+                    self.frame_stats.count(marker, |counts| &mut counts.valid);
+                    if let Some(last) = self.expected_from.filter(|last| *last < iteration) {
+                        self.stats.missing_iterations +=
+                            self.header.logged_iterations(last + 1..iteration);
+                    }
+                    self.expected_from = Some(iteration);
+                    self.last_loop_iteration = iteration;
+                    self.last_time = raw_time;
+                    self.last_widened_time = time;
+                    self.first_time.get_or_insert(time);
+                    for (value, field) in self.derived_values.iter_mut().zip(&self.derived) {
+                        *value = field.evaluate(values);
+                    }
+                    self.last_values.clear();
+                    match &self.projection {
+                        Some(projection) => self
+                            .last_values
+                            .extend(projection.iter().map(|ix| values[*ix])),
+                        None => self.last_values.extend_from_slice(values),
+                    }
+                    FieldKind::Main
+                }
+                Some(LogRecord::GNSS(values)) => {
+                    self.last_values.clear();
+                    self.last_values.extend_from_slice(values);
+                    FieldKind::GNSS
+                }
+                Some(LogRecord::Slow(values)) => {
+                    self.last_values.clear();
+                    self.last_values.extend_from_slice(values);
+                    FieldKind::Slow
+                }
+                Some(LogRecord::Event(mut event)) => {
+                    // Anything after this is padding or leftovers of older logs
+                    if let event::Frame::EndOfLog = event {
+                        self.end_of_log = Some(self.bytes_read());
+                        self.stopped = true;
+                    }
+                    if let event::Frame::LoggingResume(resume) = &event {
+                        let time = widen_time(self.last_widened_time, resume.time.into());
+                        let resumed = (resume.iteration.into(), time);
+                        if self.last_valid_main.is_some() {
+                            self.last_valid_main = Some(resumed);
                         }
+                        self.expected_from = Some(resumed.0);
+                    }
+                    if !in_range {
+                        continue;
+                    }
+                    // Only now the payload is needed
+                    if let event::Frame::Unknown(unknown) = &mut event {
+                        unknown.read_payload(&self.bytes[span.offset..][..span.len]);
                     }
-                    self.remaining_bytes = remaining_bytes;
-                    if let Some(record) = self.processor.process_frame(frame) {
-                        return Some(match record {
-                            LogRecord::Main(values) => {
-                                self.last_loop_iteration = values[self.loop_iteration_field_ix];
-                                self.last_time = values[self.time_field_ix];
-                                self.last_values.clear();
-                                self.last_values.extend_from_slice(values);
-                                BlackboxRecord::Main(&self.last_values)
+                    self.last_frame_span = Some(span);
+                    return Some(BlackboxRecord::Event(event));
+                }
+                None => continue,
+            };
+
+            if let Some((_, end)) = self.range {
+                if self.last_widened_time > end {
+                    self.remaining_bytes = &self.remaining_bytes[self.remaining_bytes.len()..];
+                    return None;
+                }
+            }
+            if !self.in_range() {
+                continue;
+            }
+
+            self.last_frame_span = Some(span);
+            let values = self.view(kind);
+            return Some(match kind {
+                FieldKind::Main => BlackboxRecord::Main(values),
+                FieldKind::GNSS => BlackboxRecord::GNSS(values),
+                FieldKind::Slow => BlackboxRecord::Slow(values),
+            });
+        }
+    }
+
+    /// Same as [`next`](Self::next), with the loop iteration and times of the record.
+    pub fn next_timed(&mut self) -> Option<TimedRecord<'_>> {
+        let taken = Taken::from(self.next()?);
+        let wall_clock = self
+            .header
+            .log_start_datetime()
+            .zip(self.first_time)
+            .map(|(start, first)| start + Duration::microseconds(self.last_widened_time - first));
+        Some(TimedRecord {
+            loop_iteration: self.last_loop_iteration,
+            time: self.last_widened_time,
+            wall_clock,
+            record: self.record(taken),
+        })
+    }
+
+    pub(crate) fn record(&self, taken: Taken) -> BlackboxRecord<'_> {
+        match taken {
+            Taken::View(FieldKind::Main) => BlackboxRecord::Main(self.view(FieldKind::Main)),
+            Taken::View(FieldKind::GNSS) => BlackboxRecord::GNSS(self.view(FieldKind::GNSS)),
+            Taken::View(FieldKind::Slow) => BlackboxRecord::Slow(self.view(FieldKind::Slow)),
+            Taken::Event(event) => BlackboxRecord::Event(event),
+            Taken::Garbage(span) => BlackboxRecord::Garbage(span),
+        }
+    }
+
+    /// Values of the last record of `kind`.
+    fn view(&self, kind: FieldKind) -> FieldView<'_> {
+        match (&self.projection, kind) {
+            (Some(projection), FieldKind::Main) => {
+                FieldView::projected(&self.header, projection, &self.last_values)
+                    .with_derived(&self.derived_values)
+            }
+            (None, FieldKind::Main) => FieldView::new(&self.header, kind, &self.last_values)
+                .with_derived(&self.derived_values),
+            _ => FieldView::new(&self.header, kind, &self.last_values),
+        }
+    }
+
+    /// Reads the rest of the log, handing every record to `visitor` instead of returning it.
+    /// Nothing is kept between records, so memory use doesn't grow with the log, e.g. when
+    /// computing statistics of a large flash dump.
+    pub fn visit(&mut self, visitor: &mut impl Visitor) {
+        while let Some(record) = self.next() {
+            let kind = match record {
+                BlackboxRecord::Main(view)
+                | BlackboxRecord::GNSS(view)
+                | BlackboxRecord::Slow(view) => view.kind(),
+                BlackboxRecord::Event(event) => {
+                    visitor.event(&event, self.last_widened_time);
+                    continue;
+                }
+                BlackboxRecord::Garbage(span) => {
+                    visitor.garbage(span);
+                    continue;
+                }
+            };
+            let (view, time) = (self.view(kind), self.last_widened_time);
+            match kind {
+                FieldKind::Main => visitor.main(view, time),
+                FieldKind::GNSS => visitor.gnss(view, time),
+                FieldKind::Slow => visitor.slow(view, time),
+            }
+        }
+    }
+
+    /// Bytes of the frame, or the skipped region for garbage, behind the record last returned
+    /// by [`next`](Self::next). Offsets are on the same basis as [`bytes_read`](Self::bytes_read).
+    pub fn last_frame_span(&self) -> Option<ByteSpan> {
+        self.last_frame_span
+    }
+
+    /// Number of bytes after the `End of log` event, `None` if it wasn't read yet.
+    /// Reading stops at the event.
+    pub fn trailing_bytes(&self) -> Option<usize> {
+        self.end_of_log.map(|end| self.original_length - end)
+    }
+
+    /// Offset of the header of another log found in the frame data, usually because the logger
+    /// restarted without the flash being erased. Reading stops there,
+    /// [`MultiSegmentBlackboxReader`] reads it as the next log.
+    pub fn next_segment(&self) -> Option<usize> {
+        self.next_segment
+    }
+
+    /// Why reading stopped before the end of the log, when it hit a garbage limit of the
+    /// [`RecoveryPolicy`].
+    pub fn stop_error(&self) -> Option<&BlackboxReaderError> {
+        self.stop_error.as_ref()
+    }
+
+    /// Frame loss and corruption seen so far.
+    pub fn stats(&self) -> &ReaderStats {
+        &self.stats
+    }
+
+    /// Valid, corrupt and desynced frames seen so far, by frame type.
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    /// Values of the last GNSS home frame (`GPS_home[0]`, `GPS_home[1]`, and home altitude when
+    /// logged), in H field order.
+    pub fn gnss_home(&self) -> &[i64] {
+        self.processor.gnss_home()
+    }
+
+    /// Names of the values in main frame records, taking [`select_fields`](Self::select_fields)
+    /// into account.
+    pub(crate) fn main_field_names(&self) -> impl Iterator<Item = &str> {
+        let fields = &self.header.ip_fields_in_order;
+        let names: Box<dyn Iterator<Item = &str>> = match &self.projection {
+            Some(projection) => Box::new(projection.iter().map(|ix| &fields[*ix].name[..])),
+            None => Box::new(fields.iter().map(|f| &f.name[..])),
+        };
+        names
+    }
+
+    /// Only computes the named main frame fields (and the ones they are predicted from);
+    /// main frame records then contain just these values, in the given order.
+    ///
+    /// Frames still have to be fully parsed, but predicting and copying the skipped fields
+    /// is avoided. GNSS and slow frames are not affected.
+    pub fn select_fields<S: AsRef<str>>(
+        mut self,
+        names: &[S],
+    ) -> Result<Self, BlackboxReaderError> {
+        let projection = names
+            .iter()
+            .map(|name| {
+                self.header
+                    .ip_fields
+                    .get(name.as_ref())
+                    .map(|f| f.ix)
+                    .ok_or_else(|| BlackboxReaderError::UnknownField(name.as_ref().to_owned()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.projection = Some(projection);
+        self.processor.retain_fields(&self.needed_fields());
+        Ok(self)
+    }
+
+    /// Main frame fields that have to be predicted with [`select_fields`](Self::select_fields).
+    fn needed_fields(&self) -> Vec<bool> {
+        let mut needed = vec![false; self.header.ip_fields_in_order.len()];
+        let derived = self.derived.iter().flat_map(|field| field.fields());
+        for ix in self
+            .projection
+            .iter()
+            .flatten()
+            .copied()
+            .chain(derived)
+            .chain(self.loop_iteration_field_ix)
+            .chain(self.time_field_ix)
+        {
+            needed[ix] = true;
+        }
+        needed
+    }
+
+    /// Computes `field` for every main frame, available from [`FieldView::derived`] of main
+    /// records and from [`derived_values`](Self::derived_values). Exporters write derived
+    /// fields next to the main frame fields. Should be called before reading, like
+    /// [`select_fields`](Self::select_fields).
+    pub fn derive(mut self, field: &DerivedField) -> Result<Self, BlackboxReaderError> {
+        self.derived.push(field.resolve(&self.header)?);
+        self.derived_values.push(f64::NAN);
+        if self.projection.is_some() {
+            // Fields skipped so far may be needed now
+            self.processor = LogProcessor::new(&self.header);
+            self.processor.retain_fields(&self.needed_fields());
+        }
+        Ok(self)
+    }
+
+    /// Names of the fields registered with [`derive`](Self::derive).
+    pub fn derived_names(&self) -> impl Iterator<Item = &str> {
+        self.derived.iter().map(|field| &field.name[..])
+    }
+
+    /// Values of the derived fields for the last main frame, in the order they were
+    /// registered. `NaN` until a main frame was read.
+    pub fn derived_values(&self) -> &[f64] {
+        &self.derived_values
+    }
+
+    /// Decodes the named main frame fields of the rest of the log into a vector per field, for
+    /// plotting and spectral analysis.
+    pub fn decode_columns<S: AsRef<str>>(
+        self,
+        names: &[S],
+    ) -> Result<Columns, BlackboxReaderError> {
+        let scales = units::Units::new(&self.header);
+        let scales = names
+            .iter()
+            .filter_map(|name| self.header.ip_fields.get(name.as_ref()))
+            .map(|f| scales.scales(FieldKind::Main)[f.ix])
+            .collect();
+        // Every field takes at least a byte in most frames
+        let capacity = self.remaining_bytes.len() / self.header.ip_fields_in_order.len().max(1);
+        let mut reader = self.select_fields(names)?;
+        let names = names.iter().map(|n| n.as_ref().to_owned()).collect();
+        let mut columns = Columns::new(names, scales, capacity);
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(_) = record {
+                columns.push(reader.last_widened_time, &reader.last_values);
+            }
+        }
+        Ok(columns)
+    }
+
+    /// Decodes up to `n` main frames into per-field buffers reused from the previous batch,
+    /// for exporters and signal processing reading the log in bulk. Other records are
+    /// skipped. Columns follow the order of main records, taking
+    /// [`select_fields`](Self::select_fields) into account. An empty batch means the end of
+    /// the log.
+    pub fn next_batch(&mut self, n: usize) -> &Batch {
+        // Fields may have been selected since the last batch
+        let names = self.batch.names().iter().map(|name| &name[..]);
+        if self.batch.names().is_empty() || !names.eq(self.main_field_names()) {
+            self.batch = Batch::new(self.main_field_names().map(ToOwned::to_owned).collect());
+        }
+        self.batch.clear(n);
+        while self.batch.len() < n {
+            match self.next() {
+                Some(BlackboxRecord::Main(_)) => self.batch.push(
+                    self.last_loop_iteration,
+                    self.last_widened_time,
+                    &self.last_values,
+                ),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        &self.batch
+    }
+
+    /// Returns every `n`th main frame, starting with the next one, and skips other records.
+    /// Meant for previews and thumbnails of long logs rather than analysis.
+    ///
+    /// When the frame to return comes after the next I-frame, the P-frames before that I-frame
+    /// are parsed but not predicted, and with an [`index`](Self::index) they aren't read at
+    /// all. Where the I-frame is expected comes from the I and P intervals in the header, so
+    /// when frames are missing from the log, the frame to return can land on a P-frame that
+    /// can't be predicted anymore, and the next I-frame is returned instead. The spacing of
+    /// returned frames then stretches by up to the I-frame interval.
+    /// [`ReaderStats::missing_iterations`] and frame validation don't cover skipped frames, and
+    /// if [`next`](Self::next) is called afterwards, it continues from the next I-frame.
+    pub fn next_every_nth(&mut self, n: usize) -> Option<BlackboxRecord<'_>> {
+        let mut pass = self.every_nth_pass;
+        loop {
+            if self.keyframe_comes_first(pass) {
+                match self.last_keyframe_within(pass) {
+                    Some((keyframe, skipped)) => {
+                        pass -= skipped;
+                        self.seek_to(keyframe);
+                    }
+                    None => self.skip_p_frames = true,
+                }
+            }
+            let main_frames = self.stats.main_frames;
+            let is_main = match self.next() {
+                Some(BlackboxRecord::Main(_)) => true,
+                Some(_) => false,
+                None => {
+                    self.skip_p_frames = false;
+                    return None;
+                }
+            };
+            self.skip_p_frames = false;
+            // Includes the frame just returned and P-frames skipped before it
+            let read = (self.stats.main_frames - main_frames) as usize;
+            if is_main && read > pass {
+                break;
+            }
+            pass = pass.saturating_sub(read);
+        }
+        self.every_nth_pass = n.max(1) - 1;
+        Some(BlackboxRecord::Main(self.view(FieldKind::Main)))
+    }
+
+    /// Whether the next I-frame comes before the main frame after the next `pass` ones.
+    /// Always false with a stale history, P-frames are skipped until the next I-frame anyway
+    /// and `last_loop_iteration` is behind.
+    fn keyframe_comes_first(&self, pass: usize) -> bool {
+        if self.stale_history || self.loop_iteration_field_ix.is_none() || pass == 0 {
+            return false;
+        }
+        let i_interval = i64::from(self.header.i_interval().max(1));
+        let keyframe = (self.last_loop_iteration.div_euclid(i_interval) + 1) * i_interval;
+        let before = self
+            .header
+            .logged_iterations(self.last_loop_iteration + 1..keyframe);
+        pass as u64 >= before
+    }
+
+    /// Furthest indexed I-frame after the reading position that comes at most `pass` main
+    /// frames later, with the number of main frames before it, if there's an index.
+    fn last_keyframe_within(&self, pass: usize) -> Option<(KeyFrame, usize)> {
+        let keyframes = self.index.as_ref()?.keyframes();
+        let ix = keyframes.partition_point(|k| k.offset < self.bytes_read());
+        keyframes[ix..]
+            .iter()
+            .filter(|k| k.loop_iteration > self.last_loop_iteration)
+            .map(|k| {
+                let range = self.last_loop_iteration + 1..k.loop_iteration;
+                (*k, self.header.logged_iterations(range) as usize)
+            })
+            .take_while(|(_, skipped)| *skipped <= pass)
+            .last()
+    }
+
+    /// Restricts decoding to the part of the log with `time` between `start` and `end`
+    /// inclusive, as in [`last_widened_time`](Self::last_widened_time), jumping to the closest preceding I-frame first.
+    /// Records other than main frames are returned if the last main frame was in range.
+    /// Logs without a `time` field are treated as if every frame was at time 0.
+    pub fn range(mut self, start: i64, end: i64) -> Self {
+        self.range = Some((start, end));
+        self.seek_to_time(start);
+        self
+    }
+
+    fn in_range(&self) -> bool {
+        self.range
+            .is_none_or(|(start, end)| (start..=end).contains(&self.last_widened_time))
+    }
+
+    /// Parses the next frame, recovering from corrupted data according to the reader options.
+    /// Returns the frame together with its offset from the start of the log, or the region
+    /// that had to be skipped to get to it.
+    fn next_frame(&mut self) -> Option<ScannedFrame> {
+        let policy = self.options.recovery;
+        loop {
+            if self.stopped {
+                return self.take_garbage().map(ScannedFrame::Garbage);
+            }
+            if let Some(error) = self.garbage_exceeded() {
+                #[cfg(feature = "tracing")]
+                tracing::info!(%error, "stopped after too much garbage");
+                self.stop_error = Some(error);
+                self.stopped = true;
+                continue;
+            }
+            if self.at_next_segment() {
+                // Garbage before the header comes first
+                if let Some(span) = self.take_garbage() {
+                    return Some(ScannedFrame::Garbage(span));
+                }
+                #[cfg(feature = "tracing")]
+                tracing::info!(
+                    offset = self.bytes_read(),
+                    "stopped at the header of another log"
+                );
+                self.options
+                    .report(self.bytes_read(), DiagnosticKind::LogRestarted);
+                self.stopped = true;
+                continue;
+            }
+            if self.garbage_start.is_none() {
+                self.strip_serial_artifacts();
+            }
+            match parse_next_frame(&self.header, self.remaining_bytes, &mut self.frame_values) {
+                Ok((remaining_bytes, frame)) => {
+                    let next_frame = self.skip_serial_artifacts(remaining_bytes);
+                    let action = if is_unknown_event(&frame) {
+                        // Unknown events accept almost anything, so don't trust them right after
+                        // garbage and require a valid frame after them
+                        let suspicious = !is_frame_marker(next_frame.first())
+                            || self.garbage_start.is_some()
+                            || !self.is_valid_frame(next_frame);
+                        match policy.unknown_event {
+                            RecoveryAction::Resync if !suspicious => None,
+                            action => Some(action),
+                        }
+                    } else if !is_followed_by_frame(&frame, next_frame)
+                        || (self.field_count_mismatch(&frame).is_some()
+                            && policy.field_count_mismatch == FieldCountAction::Reject)
+                    {
+                        Some(policy.undecodable_frame)
+                    } else {
+                        None
+                    };
+                    if action.is_some() {
+                        self.frame_stats
+                            .count(frame.marker(), |counts| &mut counts.corrupt);
+                    }
+                    match action {
+                        Some(RecoveryAction::Abort) => {
+                            #[cfg(feature = "tracing")]
+                            tracing::info!(offset = self.bytes_read(), "stopped at a bad frame");
+                            self.stopped = true
+                        }
+                        Some(RecoveryAction::Skip) => {
+                            if let BodyFrame::Event(event::Frame::Unknown(unknown)) = &frame {
+                                let kind =
+                                    DiagnosticKind::IgnoredUnknownEvent { code: unknown.code };
+                                self.options.report(self.bytes_read(), kind);
                             }
-                            LogRecord::GNSS(values) => {
-                                self.last_values.clear();
-                                self.last_values.extend_from_slice(values);
-                                BlackboxRecord::GNSS(&self.last_values)
+                            self.skip_to(remaining_bytes)
+                        }
+                        // Continue from the second byte of the parsed frame, because if it's
+                        // invalid, we can't be sure what size it was and where next frame starts
+                        Some(RecoveryAction::Resync) => self.resync(&self.remaining_bytes[1..]),
+                        None => {
+                            // A frame running into the header of another log was cut off by the
+                            // restart
+                            let next_segment = self.clamp_to_segment(remaining_bytes);
+                            if next_segment.len() != remaining_bytes.len() {
+                                self.skip_to(next_segment);
+                                continue;
                             }
-                            LogRecord::Slow(values) => BlackboxRecord::Slow(values),
-                            LogRecord::Event(event) => BlackboxRecord::Event(event),
-                        });
+                            // Report skipped bytes first, the frame will be parsed again on the
+                            // next call
+                            if let Some(span) = self.take_garbage() {
+                                return Some(ScannedFrame::Garbage(span));
+                            }
+                            let offset = self.bytes_read();
+                            if let Some(expected) = self.field_count_mismatch(&frame) {
+                                let kind = DiagnosticKind::FieldCountMismatch {
+                                    frame: char::from(frame.marker()),
+                                    expected,
+                                    found: self.frame_values.len(),
+                                };
+                                self.options.report(offset, kind);
+                                self.frame_values.resize(expected, 0);
+                            }
+                            self.remaining_bytes = remaining_bytes;
+                            return Some(ScannedFrame::Frame(offset, frame));
+                        }
                     }
                 }
-                Err(e) => match e {
-                    nom::Err::Error(e) => match self.strictness {
-                        Strictness::Strict => return None,
-                        Strictness::Lenient => {
-                            if !e.input.is_empty() {
-                                self.remaining_bytes = &e.input[1..];
-                            }
+                Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                    if let Some(marker) = self.remaining_bytes.first() {
+                        self.frame_stats
+                            .count(*marker, |counts| &mut counts.corrupt);
+                    }
+                    match policy.undecodable_frame {
+                        RecoveryAction::Abort => {
+                            #[cfg(feature = "tracing")]
+                            tracing::info!(offset = self.bytes_read(), "stopped at a bad frame");
+                            self.stopped = true
                         }
-                    },
-                    nom::Err::Failure(e) => match self.strictness {
-                        Strictness::Strict => return None,
-                        Strictness::Lenient => {
-                            if !e.input.is_empty() {
-                                self.remaining_bytes = &e.input[1..];
+                        RecoveryAction::Skip | RecoveryAction::Resync => {
+                            match e.input.split_first() {
+                                Some((_, rest)) => self.resync(rest),
+                                // A frame failing at the very end, there's nothing to resync to
+                                None => self.skip_to(e.input),
                             }
                         }
-                    },
-                    nom::Err::Incomplete(_) => {
+                    }
+                }
+                Err(nom::Err::Incomplete(_)) if self.next_segment > Some(self.bytes_read()) => {
+                    self.skip_to(self.clamp_to_segment(&[]));
+                }
+                Err(nom::Err::Incomplete(_)) => {
+                    return self.take_garbage().map(ScannedFrame::Garbage);
+                }
+            }
+        }
+    }
+
+    /// Number of fields of `frame` if its decoded values don't match it.
+    fn field_count_mismatch(&self, frame: &BodyFrame) -> Option<usize> {
+        self.processor
+            .field_count(frame)
+            .filter(|count| *count != self.frame_values.len())
+    }
+
+    /// Error to stop with if more bytes were skipped than the recovery policy allows.
+    fn garbage_exceeded(&self) -> Option<BlackboxReaderError> {
+        let policy = self.options.recovery;
+        let offset = self.garbage_start?;
+        let len = self.bytes_read() - offset;
+        if policy.max_garbage_bytes.is_some_and(|max| len > max) {
+            return Some(BlackboxReaderError::GarbageRun { offset, len });
+        }
+        let garbage_bytes = self.stats.garbage_bytes + len as u64;
+        let log_length = (self.original_length - self.header_length) as f64;
+        match policy.max_garbage_fraction {
+            Some(max_fraction) if garbage_bytes as f64 > max_fraction * log_length => {
+                Some(BlackboxReaderError::GarbageFraction {
+                    garbage_bytes,
+                    max_fraction,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Drops everything up to `to` as garbage.
+    fn skip_to(&mut self, to: &'a [u8]) {
+        if self.garbage_start.is_none() {
+            self.garbage_start = Some(self.bytes_read());
+        }
+        self.remaining_bytes = self.clamp_to_segment(to);
+    }
+
+    /// `to`, or the header of the next log if it comes first.
+    fn clamp_to_segment(&self, to: &'a [u8]) -> &'a [u8] {
+        match self.next_segment {
+            Some(start) if self.original_length - to.len() > start => &self.bytes[start..],
+            _ => to,
+        }
+    }
+
+    fn at_next_segment(&self) -> bool {
+        self.next_segment == Some(self.bytes_read())
+    }
+
+    /// Whether `input` is empty or starts with a frame followed by a frame marker.
+    fn is_valid_frame(&self, input: &[u8]) -> bool {
+        input.is_empty()
+            || parse_next_frame(&self.header, input, &mut Vec::new()).is_ok_and(|(remaining, _)| {
+                is_frame_marker(self.skip_serial_artifacts(remaining).first())
+            })
+    }
+
+    /// `input` after the serial link artifacts it starts with, if a frame comes next.
+    fn skip_serial_artifacts<'b>(&self, input: &'b [u8]) -> &'b [u8] {
+        let Some(artifacts) = &self.options.serial_artifacts else {
+            return input;
+        };
+        let stripped = artifacts.strip(input);
+        if is_frame_marker(stripped.first()) {
+            stripped
+        } else {
+            input
+        }
+    }
+
+    /// Moves past the serial link artifacts between the last frame and the next one.
+    fn strip_serial_artifacts(&mut self) {
+        let offset = self.bytes_read();
+        let stripped = self.clamp_to_segment(self.skip_serial_artifacts(self.remaining_bytes));
+        let len = self.remaining_bytes.len() - stripped.len();
+        if len > 0 {
+            self.remaining_bytes = stripped;
+            self.stats.serial_artifact_bytes += len as u64;
+            self.options
+                .report(offset, DiagnosticKind::SerialArtifacts { len });
+        }
+    }
+
+    fn take_garbage(&mut self) -> Option<ByteSpan> {
+        let offset = self.garbage_start.take()?;
+        let len = self.bytes_read() - offset;
+        self.stats.resyncs += 1;
+        self.stats.garbage_bytes += len as u64;
+        self.options.report(offset, DiagnosticKind::Garbage { len });
+        Some(ByteSpan { offset, len })
+    }
+
+    fn resync(&mut self, from: &'a [u8]) {
+        if self.garbage_start.is_none() {
+            self.garbage_start = Some(self.bytes_read());
+        }
+        let to = match self.options.resync {
+            ResyncStrategy::ByteByByte => from,
+            ResyncStrategy::NextKeyframe => self.find_keyframe(from),
+        };
+        self.remaining_bytes = self.clamp_to_segment(to);
+    }
+
+    fn find_keyframe(&self, mut input: &'a [u8]) -> &'a [u8] {
+        let mut values = Vec::new();
+        while let Some(pos) = input.iter().position(|b| *b == b'I' || *b == b'E') {
+            input = &input[pos..];
+            match parse_next_frame(&self.header, input, &mut values) {
+                Ok((remaining_bytes, frame)) => {
+                    // loopIteration is never predicted in I-frames, so the raw value can be used
+                    let monotonic = match &frame {
+                        BodyFrame::IFrame => self
+                            .loop_iteration_field_ix
+                            .is_none_or(|ix| values[ix] >= self.last_loop_iteration),
+                        frame => !is_unknown_event(frame),
+                    };
+                    if monotonic
+                        && is_followed_by_frame(&frame, self.skip_serial_artifacts(remaining_bytes))
+                    {
+                        return input;
+                    }
+                }
+                Err(nom::Err::Incomplete(_)) => return input,
+                Err(_) => {}
+            }
+            input = &input[1..];
+        }
+        &input[input.len()..]
+    }
+
+    /// Estimates the time covered by the log from its first main frame and the last I-frame
+    /// found by scanning backwards from the end, without decoding anything in between.
+    /// Moves the reading position.
+    pub(crate) fn estimate_time_span(&mut self) -> Option<(i64, i64)> {
+        let time_ix = self.time_field_ix?;
+        let first = loop {
+            if let BlackboxRecord::Main(_) = self.next()? {
+                break self.last_widened_time;
+            }
+        };
+
+        let data = &self.bytes[self.header_length..];
+        let data = match data.find_substring(END_OF_LOG) {
+            Some(pos) => &data[..pos],
+            None => data,
+        };
+        let mut processor = LogProcessor::new(&self.header);
+        let (mut values, mut next_values) = (Vec::new(), Vec::new());
+        let last = (0..data.len())
+            .rev()
+            .filter(|pos| data[*pos] == b'I')
+            .find_map(|pos| {
+                let (remaining, frame) =
+                    parse_next_frame(&self.header, &data[pos..], &mut values).ok()?;
+                // Random bytes in P-frames can look like an I-frame, require a valid frame after it
+                if !matches!(frame, BodyFrame::IFrame) || !is_frame_marker(remaining.first()) {
+                    return None;
+                }
+                if !remaining.is_empty() {
+                    let (remaining, _) =
+                        parse_next_frame(&self.header, remaining, &mut next_values).ok()?;
+                    if !is_frame_marker(remaining.first()) {
                         return None;
                     }
-                },
+                }
+                values.resize(processor.field_count(&frame)?, 0);
+                match processor.process_frame(frame, &values) {
+                    Some(LogRecord::Main(values)) => {
+                        Some(widen_time(first, values[time_ix])).filter(|last| *last >= first)
+                    }
+                    _ => None,
+                }
+            })
+            .unwrap_or(first);
+
+        Some((first, last))
+    }
+
+    /// Scans the whole log once and records the position of every I-frame.
+    /// The current reading position is preserved.
+    pub fn build_index(&mut self) -> &Index {
+        if self.index.is_none() {
+            let position = self.remaining_bytes;
+            let garbage_start = self.garbage_start.take();
+            let stopped = std::mem::take(&mut self.stopped);
+            let last_loop_iteration = std::mem::take(&mut self.last_loop_iteration);
+            let (stats, frame_stats) = (self.stats, self.frame_stats);
+            self.remaining_bytes = &self.bytes[self.header_length..];
+
+            let mut processor = LogProcessor::new(&self.header);
+            let mut keyframes = Vec::new();
+            let mut time = 0;
+            while let Some(scanned) = self.next_frame() {
+                if let ScannedFrame::Frame(_, BodyFrame::Event(event::Frame::EndOfLog)) = scanned {
+                    break;
+                }
+                if let ScannedFrame::Frame(offset, frame @ BodyFrame::IFrame) = scanned {
+                    if let Some(LogRecord::Main(values)) =
+                        processor.process_frame(frame, &self.frame_values)
+                    {
+                        let loop_iteration =
+                            self.loop_iteration_field_ix.map_or(0, |ix| values[ix]);
+                        self.last_loop_iteration = loop_iteration;
+                        time = widen_time(time, self.time_field_ix.map_or(0, |ix| values[ix]));
+                        keyframes.push(KeyFrame {
+                            offset,
+                            loop_iteration,
+                            time,
+                        });
+                    }
+                }
             }
+
+            self.remaining_bytes = position;
+            self.garbage_start = garbage_start;
+            self.stopped = stopped;
+            self.last_loop_iteration = last_loop_iteration;
+            self.stats = stats;
+            self.frame_stats = frame_stats;
+            self.index = Some(Index::new(self.log_id(), keyframes));
+        }
+
+        self.index.as_ref().unwrap()
+    }
+
+    pub fn index(&self) -> Option<&Index> {
+        self.index.as_ref()
+    }
+
+    fn log_id(&self) -> LogId {
+        LogId::new(self.bytes, self.header_length)
+    }
+
+    /// Uses an index built earlier from the same log, like one loaded with [`Index::load`],
+    /// instead of scanning the log again.
+    pub fn set_index(&mut self, index: Index) -> Result<&Index, IndexError> {
+        if index.log() != self.log_id() {
+            return Err(IndexError::Mismatch);
+        }
+        Ok(self.index.insert(index))
+    }
+
+    /// Loads the index from `path` if it was built from this log, or builds it and saves it
+    /// there otherwise, so only the first open of a log pays for the scan.
+    pub fn load_or_build_index(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<&Index, IndexError> {
+        let path = path.as_ref();
+        match Index::load(path).and_then(|index| self.set_index(index).map(|_| ())) {
+            Ok(()) => {}
+            Err(IndexError::Io(e)) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e.into())
+            }
+            Err(_) => {
+                self.index = None;
+                self.build_index().save(path)?;
+            }
+        }
+        Ok(self.index.as_ref().unwrap())
+    }
+
+    /// Moves the reading position to the closest I-frame at or before `time`, building the
+    /// index first if needed. The next main record returned will be that I-frame.
+    pub fn seek_to_time(&mut self, time: i64) -> Option<KeyFrame> {
+        let keyframe = self.build_index().keyframe_for_time(time)?;
+        self.seek_to(keyframe);
+        Some(keyframe)
+    }
+
+    /// Same as [`seek_to_time`](Self::seek_to_time), but by loop iteration.
+    pub fn seek_to_iteration(&mut self, loop_iteration: i64) -> Option<KeyFrame> {
+        let keyframe = self.build_index().keyframe_for_iteration(loop_iteration)?;
+        self.seek_to(keyframe);
+        Some(keyframe)
+    }
+
+    fn seek_to(&mut self, keyframe: KeyFrame) {
+        // Seeking always goes through the index
+        if let Some(first) = self
+            .index
+            .as_ref()
+            .and_then(|index| index.keyframes().first())
+        {
+            self.first_time.get_or_insert(first.time);
         }
+        self.remaining_bytes = &self.bytes[keyframe.offset..];
+        self.garbage_start = None;
+        self.stopped = false;
+        self.stop_error = None;
+        self.last_frame_span = None;
+        self.last_valid_main = None;
+        self.expected_from = None;
+        self.last_loop_iteration = keyframe.loop_iteration;
+        self.last_time = (keyframe.time as u32).into();
+        self.last_widened_time = keyframe.time;
     }
 
     pub fn bytes_read(&self) -> usize {
         self.original_length - self.remaining_bytes.len()
     }
+
+    /// Fraction of the input consumed so far, from 0 to 1. Bytes after the `End of log` event
+    /// or the header of the next log count as consumed, since they won't be read.
+    pub fn progress(&self) -> f32 {
+        if self.end_of_log.is_some() || (self.stopped && self.at_next_segment()) {
+            return 1.0;
+        }
+        self.bytes_read() as f32 / self.original_length as f32
+    }
+}
+
+/// Summary of a log segment found by [`MultiSegmentBlackboxReader::segments`].
+#[derive(Debug)]
+pub struct SegmentInfo {
+    /// Offset of the segment's first header from the start of the input
+    pub offset: usize,
+    /// Length of the segment, up to its `End of log` event or, if there's none, the start of
+    /// the next segment or the end of input
+    pub len: usize,
+    /// Bytes between the `End of log` event and the next segment, usually erased flash
+    pub trailing_bytes: usize,
+    pub header: Result<Header, BlackboxReaderError>,
+    /// Approximate `time` of the first and last main frames
+    pub time_span: Option<(i64, i64)>,
+}
+
+impl SegmentInfo {
+    /// Approximate duration of the segment in microseconds.
+    pub fn duration(&self) -> Option<i64> {
+        self.time_span.map(|(first, last)| last - first)
+    }
+
+    /// Bytes of the segment in the input, from its first header up to its `End of log` event.
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+pub(crate) const SEGMENT_START: &[u8] = b"H Product:Blackbox";
+const END_OF_LOG: &[u8] = b"E\xffEnd of log\0";
+
+/// Length of `segment` up to and including its `End of log` event, if it has one.
+fn log_length(segment: &[u8]) -> usize {
+    segment
+        .find_substring(END_OF_LOG)
+        .map_or(segment.len(), |pos| pos + END_OF_LOG.len())
 }
 
 pub struct MultiSegmentBlackboxReader<'a> {
+    bytes: &'a [u8],
     remaining_bytes: &'a [u8],
-    strictness: Strictness,
+    original_length: usize,
+    options: ReaderOptions,
 }
 
 impl<'a> MultiSegmentBlackboxReader<'a> {
-    pub fn new(bytes: &'a [u8], strictness: Strictness) -> Self {
+    pub fn new(bytes: &'a [u8], recovery: RecoveryPolicy) -> Self {
+        Self::with_options(bytes, recovery.into())
+    }
+
+    pub fn with_options(bytes: &'a [u8], options: ReaderOptions) -> Self {
         Self {
+            bytes,
             remaining_bytes: bytes,
-            strictness,
+            original_length: bytes.len(),
+            options,
         }
     }
 
+    /// Fraction of the input scanned for segments so far, from 0 to 1.
+    /// Progress within a segment is reported by [`BlackboxReader::progress`].
+    pub fn progress(&self) -> f32 {
+        if self.original_length == 0 {
+            return 1.0;
+        }
+        1.0 - self.remaining_bytes.len() as f32 / self.original_length as f32
+    }
+
     pub fn from_bytes(bytes: &'a [u8]) -> Self {
-        Self::new(bytes, Strictness::Lenient)
+        Self::new(bytes, RecoveryPolicy::lenient())
+    }
+
+    /// Reads the log file at `path` into `buf`, decompressing it like
+    /// [`BlackboxReader::open`].
+    pub fn open(path: impl AsRef<std::path::Path>, buf: &'a mut Vec<u8>) -> std::io::Result<Self> {
+        buf.clear();
+        read_log_file(path.as_ref(), buf)?;
+        Ok(Self::from_bytes(buf))
+    }
+
+    /// Lists all segments in the input with their headers and approximate time span, without
+    /// decoding them. Independent of the iteration state.
+    pub fn segments(&self) -> Vec<SegmentInfo> {
+        let mut offsets = Vec::new();
+        let mut pos = 0;
+        while let Some(found) = (&self.bytes[pos..]).find_substring(SEGMENT_START) {
+            offsets.push(pos + found);
+            pos += found + 1;
+        }
+
+        offsets
+            .iter()
+            .enumerate()
+            .map(|(i, &offset)| {
+                let end = offsets.get(i + 1).copied().unwrap_or(self.bytes.len());
+                let segment = &self.bytes[offset..end];
+                let trailing_bytes = segment.len() - log_length(segment);
+                let segment = &segment[..segment.len() - trailing_bytes];
+                let (header, time_span) =
+                    match BlackboxReader::with_options(segment, self.options.clone()) {
+                        Ok(mut reader) => {
+                            let time_span = reader.estimate_time_span();
+                            (Ok(reader.header), time_span)
+                        }
+                        Err(e) => (Err(e), None),
+                    };
+                SegmentInfo {
+                    offset,
+                    len: segment.len(),
+                    trailing_bytes,
+                    header,
+                    time_span,
+                }
+            })
+            .collect()
+    }
+
+    /// Bytes of every segment found by [`segments`](Self::segments), each a standalone log
+    /// that can be written to its own file. Erased flash between the segments is left out.
+    pub fn split(&self) -> Vec<&'a [u8]> {
+        self.segments()
+            .iter()
+            .map(|segment| &self.bytes[segment.range()])
+            .collect()
+    }
+
+    /// Recovers every log with a readable header from a raw SD card image or flash dump, like
+    /// one taken after the filesystem was damaged. Unlike [`segments`](Self::segments), each log
+    /// is decoded to find where it ends, so padding and unrelated data after it are left out.
+    pub fn carve(&self, options: CarveOptions) -> Vec<CarvedLog> {
+        carve::carve(self.bytes, &self.options, options)
     }
 
     pub fn successful_only(self) -> impl Iterator<Item = BlackboxReader<'a>> {
@@ -199,13 +1578,26 @@ impl<'a> Iterator for MultiSegmentBlackboxReader<'a> {
     type Item = Result<BlackboxReader<'a>, BlackboxReaderError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let pos = self
-            .remaining_bytes
-            .find_substring(&b"H Product:Blackbox"[..])?;
+        let pos = match self.remaining_bytes.find_substring(SEGMENT_START) {
+            Some(pos) => pos,
+            None => {
+                self.remaining_bytes = &self.remaining_bytes[self.remaining_bytes.len()..];
+                return None;
+            }
+        };
         self.remaining_bytes = &self.remaining_bytes[pos..];
-        let reader = BlackboxReader::new(self.remaining_bytes, self.strictness);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "segment",
+            offset = self.original_length - self.remaining_bytes.len()
+        )
+        .entered();
+        let reader = BlackboxReader::with_options(self.remaining_bytes, self.options.clone());
         if let Ok(reader) = &reader {
-            self.remaining_bytes = &self.remaining_bytes[reader.bytes_read()..];
+            // Jump over the log and its trailing padding when it ends before the next segment
+            let next_segment = reader.next_segment().unwrap_or(self.remaining_bytes.len());
+            let data = &self.remaining_bytes[reader.bytes_read()..next_segment];
+            self.remaining_bytes = &self.remaining_bytes[reader.bytes_read() + log_length(data)..];
         } else {
             self.remaining_bytes = &self.remaining_bytes[1..];
         }
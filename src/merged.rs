@@ -0,0 +1,246 @@
+use std::{collections::VecDeque, ops::Deref};
+
+use crate::{BlackboxReader, BlackboxRecord, Header};
+
+/// Main frame values followed by the latest slow frame values (and GNSS values, when
+/// enabled), like the rows written by `blackbox_decode`.
+#[derive(Clone, Copy)]
+pub struct MergedRecord<'a> {
+    names: &'a [String],
+    values: &'a [i64],
+    derived: &'a [f64],
+}
+
+impl<'a> MergedRecord<'a> {
+    pub fn names(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.names.iter().map(|n| &n[..])
+    }
+
+    pub fn values(&self) -> &'a [i64] {
+        self.values
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<i64> {
+        self.index_of(name).map(|ix| self.values[ix])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, i64)> + 'a {
+        self.names().zip(self.values.iter().copied())
+    }
+
+    /// Values of the [derived fields](crate::BlackboxReader::derive) for the main frame.
+    pub fn derived(&self) -> &'a [f64] {
+        self.derived
+    }
+}
+
+impl<'a> Deref for MergedRecord<'a> {
+    type Target = [i64];
+
+    fn deref(&self) -> &Self::Target {
+        self.values
+    }
+}
+
+/// How GNSS values are aligned with main frames by [`MergedReader`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GnssAlignment {
+    /// Values of the most recent GNSS frame.
+    Latest,
+    /// Latitude, longitude and altitude linearly interpolated between the GNSS frames
+    /// around the main frame, other values from the most recent one.
+    ///
+    /// Rows are held back until the next GNSS frame is decoded.
+    Interpolate,
+}
+
+const INTERPOLATED_GNSS_FIELDS: [&str; 3] = ["GPS_coord[0]", "GPS_coord[1]", "GPS_altitude"];
+
+/// Reader producing a single row per main frame, with slow (and optionally GNSS) frame
+/// values carried over until the next frame of that kind.
+///
+/// Values of frame kinds that haven't been seen yet are zero. Events and garbage are skipped.
+pub struct MergedReader<'a> {
+    reader: BlackboxReader<'a>,
+    names: Vec<String>,
+    main_len: usize,
+    slow_len: usize,
+    gnss: Option<GnssAlignment>,
+    row: Vec<i64>,
+    /// Time and values of the last GNSS frame, when interpolating
+    last_fix: Option<(i64, Vec<i64>)>,
+    /// Rows waiting for the next GNSS frame, with their main frame time and derived values
+    pending: VecDeque<(i64, Vec<i64>, Vec<f64>)>,
+    ready: VecDeque<(Vec<i64>, Vec<f64>)>,
+    current: (Vec<i64>, Vec<f64>),
+}
+
+impl<'a> MergedReader<'a> {
+    pub fn new(reader: BlackboxReader<'a>) -> Self {
+        let header = &reader.header;
+        let mut names: Vec<String> = reader.main_field_names().map(str::to_owned).collect();
+        let main_len = names.len();
+        names.extend(header.s_fields_in_order.iter().map(|f| f.name.clone()));
+        let slow_len = header.s_fields_in_order.len();
+        let row = vec![0; names.len()];
+
+        Self {
+            reader,
+            names,
+            main_len,
+            slow_len,
+            gnss: None,
+            row,
+            last_fix: None,
+            pending: VecDeque::new(),
+            ready: VecDeque::new(),
+            current: Default::default(),
+        }
+    }
+
+    /// Also appends the latest GNSS frame values to every row.
+    pub fn with_gnss(self) -> Self {
+        self.with_gnss_alignment(GnssAlignment::Latest)
+    }
+
+    /// Also appends GNSS frame values to every row, aligned as requested.
+    ///
+    /// Interpolation falls back to [`GnssAlignment::Latest`] if GNSS frames have no time.
+    pub fn with_gnss_alignment(mut self, alignment: GnssAlignment) -> Self {
+        let gnss_fields = &self.reader.header.g_fields_in_order;
+        if self.gnss.is_none() {
+            self.names
+                .extend(gnss_fields.iter().map(|f| f.name.clone()));
+            self.row.resize(self.names.len(), 0);
+        }
+        let has_time = self.reader.header.g_fields.contains_key("time");
+        self.gnss = Some(match alignment {
+            GnssAlignment::Interpolate if has_time => GnssAlignment::Interpolate,
+            _ => GnssAlignment::Latest,
+        });
+        self
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.reader.header
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Names of the values in [`MergedRecord::derived`].
+    pub fn derived_names(&self) -> impl Iterator<Item = &str> {
+        self.reader.derived_names()
+    }
+
+    pub fn into_inner(self) -> BlackboxReader<'a> {
+        self.reader
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<MergedRecord<'_>> {
+        if self.gnss == Some(GnssAlignment::Interpolate) {
+            self.current = self.next_interpolated()?;
+            return Some(MergedRecord {
+                names: &self.names,
+                values: &self.current.0,
+                derived: &self.current.1,
+            });
+        }
+
+        let (main, slow) = (self.main_len, self.main_len + self.slow_len);
+        loop {
+            match self.reader.next()? {
+                BlackboxRecord::Main(values) => {
+                    self.row[..main].copy_from_slice(&values);
+                    break;
+                }
+                BlackboxRecord::Slow(values) => self.row[main..slow].copy_from_slice(&values),
+                BlackboxRecord::GNSS(values) if self.gnss.is_some() => {
+                    self.row[slow..].copy_from_slice(&values)
+                }
+                _ => {}
+            }
+        }
+
+        Some(MergedRecord {
+            names: &self.names,
+            values: &self.row,
+            derived: self.reader.derived_values(),
+        })
+    }
+
+    fn next_interpolated(&mut self) -> Option<(Vec<i64>, Vec<f64>)> {
+        let (main, slow) = (self.main_len, self.main_len + self.slow_len);
+        loop {
+            if let Some(row) = self.ready.pop_front() {
+                return Some(row);
+            }
+
+            match self.reader.next() {
+                Some(BlackboxRecord::Main(values)) => {
+                    self.row[..main].copy_from_slice(&values);
+                    let derived = self.reader.derived_values().to_vec();
+                    if self.last_fix.is_some() {
+                        let time = self.reader.last_time;
+                        self.pending.push_back((time, self.row.clone(), derived));
+                    } else {
+                        self.ready.push_back((self.row.clone(), derived));
+                    }
+                }
+                Some(BlackboxRecord::Slow(values)) => self.row[main..slow].copy_from_slice(&values),
+                Some(BlackboxRecord::GNSS(values)) => {
+                    let time = values.value("time").unwrap_or_default();
+                    let fix = (time, values.to_vec());
+                    self.row[slow..].copy_from_slice(&values);
+                    self.interpolate_pending(&fix);
+                    self.last_fix = Some(fix);
+                }
+                Some(_) => {}
+                None => {
+                    // No fix to interpolate towards, the pending rows keep the last one
+                    self.ready.extend(
+                        self.pending
+                            .drain(..)
+                            .map(|(_, row, derived)| (row, derived)),
+                    );
+                    return self.ready.pop_front();
+                }
+            }
+        }
+    }
+
+    fn interpolate_pending(&mut self, (next_time, next): &(i64, Vec<i64>)) {
+        let gnss_start = self.main_len + self.slow_len;
+        let header = &self.reader.header;
+        let fields: Vec<usize> = INTERPOLATED_GNSS_FIELDS
+            .iter()
+            .filter_map(|name| header.g_fields.get(*name).map(|f| f.ix))
+            .collect();
+
+        if let Some((prev_time, prev)) = &self.last_fix {
+            let span = (next_time - prev_time) as f64;
+            for (time, row, _) in self.pending.iter_mut() {
+                let t = if span > 0.0 {
+                    ((*time - prev_time) as f64 / span).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                for ix in &fields {
+                    let delta = (next[*ix] - prev[*ix]) as f64 * t;
+                    row[gnss_start + ix] = prev[*ix] + delta.round() as i64;
+                }
+            }
+        }
+        self.ready.extend(
+            self.pending
+                .drain(..)
+                .map(|(_, row, derived)| (row, derived)),
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Extraction of log data from captures of MSP (MultiWii Serial Protocol) traffic, like the
+//! flash downloads of the configurators or onboard logging bridges.
+//!
+//! ```no_run
+//! use fc_blackbox::{msp, MultiSegmentBlackboxReader};
+//!
+//! let capture = std::fs::read("capture.bin").unwrap();
+//! let log = msp::deframe(&capture, &msp::MspOptions::dataflash());
+//! for reader in MultiSegmentBlackboxReader::from_bytes(&log).successful_only() {
+//!     // ...
+//! }
+//! ```
+
+/// Command reading a chunk of the flash chip, replies start with its 32-bit address.
+pub const MSP_DATAFLASH_READ: u16 = 71;
+
+/// How the payloads of the selected replies are turned into log data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MspPayload {
+    /// Payloads are concatenated in the order they were captured.
+    Raw,
+    /// Payloads start with a little-endian 32-bit flash address, like the replies to
+    /// [`MSP_DATAFLASH_READ`] without compression. Data is placed at its address, so retried
+    /// and out of order reads don't corrupt the log, and the log starts at the lowest address
+    /// read.
+    Addressed,
+}
+
+#[derive(Clone, Debug)]
+pub struct MspOptions {
+    /// Command whose replies carry log data, replies to every command if `None`
+    pub command: Option<u16>,
+    pub payload: MspPayload,
+}
+
+impl MspOptions {
+    /// Replies to [`MSP_DATAFLASH_READ`].
+    pub fn dataflash() -> Self {
+        Self {
+            command: Some(MSP_DATAFLASH_READ),
+            payload: MspPayload::Addressed,
+        }
+    }
+}
+
+impl Default for MspOptions {
+    fn default() -> Self {
+        Self {
+            command: None,
+            payload: MspPayload::Raw,
+        }
+    }
+}
+
+/// A decoded MSP packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MspPacket<'a> {
+    /// `b'<'` for requests, `b'>'` for replies and `b'!'` for errors
+    pub direction: u8,
+    pub command: u16,
+    pub payload: &'a [u8],
+}
+
+fn crc8_dvb_s2(crc: u8, byte: u8) -> u8 {
+    (0..8).fold(crc ^ byte, |crc, _| {
+        if crc & 0x80 != 0 {
+            (crc << 1) ^ 0xd5
+        } else {
+            crc << 1
+        }
+    })
+}
+
+/// Parses an MSPv1 (`$M`) or MSPv2 (`$X`) packet at the start of `input`, returning it with
+/// its length. `None` if there's no complete packet with a valid checksum.
+fn parse_packet(input: &[u8]) -> Option<(MspPacket<'_>, usize)> {
+    let (version, direction) = match input {
+        [b'$', version @ (b'M' | b'X'), direction @ (b'<' | b'>' | b'!'), ..] => {
+            (*version, *direction)
+        }
+        _ => return None,
+    };
+    if version == b'M' {
+        let (&size, &command) = (input.get(3)?, input.get(4)?);
+        // Jumbo frames have a 16-bit size after the command
+        let (len, start) = match size {
+            255 => (
+                u16::from_le_bytes([*input.get(5)?, *input.get(6)?]) as usize,
+                7,
+            ),
+            _ => (size as usize, 5),
+        };
+        let payload = input.get(start..start + len)?;
+        let checksum = input[3..start + len].iter().fold(0, |c, b| c ^ b);
+        (*input.get(start + len)? == checksum).then_some((
+            MspPacket {
+                direction,
+                command: command.into(),
+                payload,
+            },
+            start + len + 1,
+        ))
+    } else {
+        let header = input.get(3..8)?;
+        let command = u16::from_le_bytes([header[1], header[2]]);
+        let len = u16::from_le_bytes([header[3], header[4]]) as usize;
+        let payload = input.get(8..8 + len)?;
+        let crc = input[3..8 + len].iter().fold(0, |c, b| crc8_dvb_s2(c, *b));
+        (*input.get(8 + len)? == crc).then_some((
+            MspPacket {
+                direction,
+                command,
+                payload,
+            },
+            8 + len + 1,
+        ))
+    }
+}
+
+/// Length of the valid MSP packet at the start of `input`.
+pub(crate) fn packet_len(input: &[u8]) -> Option<usize> {
+    parse_packet(input).map(|(_, len)| len)
+}
+
+/// Iterator over the valid MSP packets in a capture, skipping anything else.
+pub struct MspPackets<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Iterator for MspPackets<'a> {
+    type Item = MspPacket<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.input.iter().position(|b| *b == b'$')?;
+            self.input = &self.input[start..];
+            match parse_packet(self.input) {
+                Some((packet, len)) => {
+                    self.input = &self.input[len..];
+                    return Some(packet);
+                }
+                None => self.input = &self.input[1..],
+            }
+        }
+    }
+}
+
+/// Valid MSP packets of a capture, in the order they were sent.
+pub fn packets(input: &[u8]) -> MspPackets<'_> {
+    MspPackets { input }
+}
+
+/// Joins the log data carried by the replies in an MSP capture, ready to be read like a log
+/// file.
+pub fn deframe(input: &[u8], options: &MspOptions) -> Vec<u8> {
+    let replies = packets(input).filter(|packet| {
+        packet.direction == b'>' && options.command.is_none_or(|c| c == packet.command)
+    });
+    match options.payload {
+        MspPayload::Raw => replies.flat_map(|packet| packet.payload).copied().collect(),
+        MspPayload::Addressed => {
+            let chunks: Vec<_> = replies
+                .filter_map(|packet| {
+                    let (address, data) = packet.payload.split_first_chunk::<4>()?;
+                    Some((u32::from_le_bytes(*address) as usize, data))
+                })
+                .collect();
+            let Some(start) = chunks.iter().map(|(address, _)| *address).min() else {
+                return Vec::new();
+            };
+            let mut out = Vec::new();
+            for (address, data) in chunks {
+                let offset = address - start;
+                if out.len() < offset + data.len() {
+                    out.resize(offset + data.len(), 0xff);
+                }
+                out[offset..offset + data.len()].copy_from_slice(data);
+            }
+            out
+        }
+    }
+}
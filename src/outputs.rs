@@ -0,0 +1,128 @@
+use crate::stream::header::Header;
+
+/// Lowest and highest DShot throttle values, 0..47 are reserved for commands.
+const DSHOT_RANGE: (u16, u16) = (48, 2047);
+
+/// Servo pulse widths in microseconds.
+const SERVO_RANGE: (u16, u16) = (1000, 2000);
+
+/// What the logged motor values are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum MotorProtocol {
+    /// Pulse widths in microseconds, as used by PWM, OneShot and MultiShot.
+    Analog,
+    /// DShot throttle values.
+    Digital,
+}
+
+/// `motor[N]` and `servo[N]` main frame fields resolved once from a [`Header`], used to
+/// normalize raw outputs.
+///
+/// Motors are scaled to 0..1 over the output range: the full DShot range for digital
+/// protocols, so that idle shows above zero, or `motorOutput` (`minthrottle`/`maxthrottle` in
+/// old logs) for analog ones. Servos are scaled over 1000..2000 µs.
+#[derive(Clone, Debug)]
+pub struct OutputLayout {
+    motors: Vec<usize>,
+    servos: Vec<usize>,
+    protocol: MotorProtocol,
+    motor_range: (u16, u16),
+}
+
+fn numbered_fields(header: &Header, prefix: &str) -> Vec<usize> {
+    (0..)
+        .map_while(|i| {
+            header
+                .ip_fields
+                .get(&format!("{}[{}]", prefix, i))
+                .map(|f| f.ix)
+        })
+        .collect()
+}
+
+fn normalize(value: i64, (low, high): (u16, u16)) -> f32 {
+    let span = f32::from(high.saturating_sub(low).max(1));
+    ((value as f32 - f32::from(low)) / span).clamp(0.0, 1.0)
+}
+
+impl OutputLayout {
+    pub fn new(header: &Header) -> Self {
+        let settings = &header.settings;
+        let low = settings
+            .motor_output
+            .map(|(low, _)| low)
+            .or(settings.min_throttle)
+            .unwrap_or(SERVO_RANGE.0);
+        let high = settings
+            .motor_output
+            .map(|(_, high)| high)
+            .or(settings.max_throttle)
+            .unwrap_or(SERVO_RANGE.1);
+
+        // Pulse widths never go below 1000 µs, while DShot idle is usually around 100
+        let (protocol, motor_range) = if low < SERVO_RANGE.0 {
+            (MotorProtocol::Digital, DSHOT_RANGE)
+        } else {
+            (MotorProtocol::Analog, (low, high))
+        };
+
+        Self {
+            motors: numbered_fields(header, "motor"),
+            servos: numbered_fields(header, "servo"),
+            protocol,
+            motor_range,
+        }
+    }
+
+    pub fn motor_count(&self) -> usize {
+        self.motors.len()
+    }
+
+    pub fn servo_count(&self) -> usize {
+        self.servos.len()
+    }
+
+    /// Indices of the `motor[N]` fields in header field order.
+    pub fn motor_indices(&self) -> &[usize] {
+        &self.motors
+    }
+
+    /// Indices of the `servo[N]` fields in header field order.
+    pub fn servo_indices(&self) -> &[usize] {
+        &self.servos
+    }
+
+    pub fn protocol(&self) -> MotorProtocol {
+        self.protocol
+    }
+
+    /// Raw values mapped to 0 and 1 by [`normalize_motor`](Self::normalize_motor).
+    pub fn motor_range(&self) -> (u16, u16) {
+        self.motor_range
+    }
+
+    pub fn normalize_motor(&self, value: i64) -> f32 {
+        normalize(value, self.motor_range)
+    }
+
+    pub fn normalize_servo(&self, value: i64) -> f32 {
+        normalize(value, SERVO_RANGE)
+    }
+
+    /// Normalized motor outputs of a main frame, `values` in header field order.
+    pub fn motors(&self, values: &[i64]) -> Vec<f32> {
+        self.motors
+            .iter()
+            .map(|ix| self.normalize_motor(values[*ix]))
+            .collect()
+    }
+
+    /// Normalized servo outputs of a main frame, `values` in header field order.
+    pub fn servos(&self, values: &[i64]) -> Vec<f32> {
+        self.servos
+            .iter()
+            .map(|ix| self.normalize_servo(values[*ix]))
+            .collect()
+    }
+}
@@ -0,0 +1,226 @@
+//! Python module `pyblackbox`, built with [maturin](https://www.maturin.rs) from
+//! `pyproject.toml`.
+//!
+//! ```python
+//! import pyblackbox
+//! log = pyblackbox.open("LOG00001.BFL")
+//! gyro = log.frames("main")[:, log.field_names("main").index("gyroADC[0]")]
+//! for kind, values in log:
+//!     ...
+//! ```
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use numpy::{PyArray1, PyArray2, PyArrayMethods};
+use pyo3::{
+    exceptions::{PyIndexError, PyValueError},
+    prelude::*,
+};
+
+use crate::{BlackboxReader, BlackboxRecord, FieldKind, Header, MultiSegmentBlackboxReader};
+
+fn field_kind(kind: &str) -> PyResult<FieldKind> {
+    match kind {
+        "main" => Ok(FieldKind::Main),
+        "slow" => Ok(FieldKind::Slow),
+        "gnss" => Ok(FieldKind::GNSS),
+        _ => Err(PyValueError::new_err(format!(
+            "unknown frame kind \"{}\", expected main, slow or gnss",
+            kind
+        ))),
+    }
+}
+
+fn kind_name(kind: FieldKind) -> &'static str {
+    match kind {
+        FieldKind::Main => "main",
+        FieldKind::Slow => "slow",
+        FieldKind::GNSS => "gnss",
+    }
+}
+
+/// One log of a flight controller's flash dump or SD card file.
+#[pyclass(module = "pyblackbox", frozen)]
+pub struct Log {
+    /// The log and everything after it, the reader stops at its end.
+    bytes: Arc<[u8]>,
+    header: Header,
+}
+
+impl Log {
+    fn new(bytes: &[u8], index: usize) -> PyResult<Self> {
+        let offset = MultiSegmentBlackboxReader::from_bytes(bytes)
+            .segments()
+            .into_iter()
+            .filter(|segment| segment.header.is_ok())
+            .nth(index)
+            .ok_or_else(|| PyIndexError::new_err(format!("no log {}", index)))?
+            .offset;
+        let bytes: Arc<[u8]> = bytes[offset..].into();
+        let header = BlackboxReader::from_bytes(&bytes)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?
+            .header;
+        Ok(Self { bytes, header })
+    }
+
+    fn reader(&self) -> BlackboxReader<'_> {
+        BlackboxReader::from_bytes(&self.bytes).expect("header was parsed when opening the log")
+    }
+}
+
+#[pymethods]
+impl Log {
+    /// Reads log `index` of a file already in memory.
+    #[staticmethod]
+    #[pyo3(signature = (data, index = 0))]
+    fn from_bytes(data: &[u8], index: usize) -> PyResult<Self> {
+        Self::new(data, index)
+    }
+
+    /// Header values as logged, by header name.
+    #[getter]
+    fn headers(&self) -> HashMap<String, String> {
+        let header = &self.header;
+        let mut headers = header.other_headers.clone();
+        headers.insert("Product".to_owned(), header.product().to_owned());
+        headers.insert("Data version".to_owned(), header.data_version().to_owned());
+        for (name, value) in [
+            ("Firmware type", header.firmware_type()),
+            ("Firmware revision", header.firmware_revision()),
+            ("Craft name", header.craft_name()),
+        ] {
+            if let Some(value) = value {
+                headers.insert(name.to_owned(), value.to_owned());
+            }
+        }
+        headers
+    }
+
+    /// Field names of `main`, `slow` or `gnss` frames, in the column order of
+    /// [`frames`](Self::frames).
+    #[pyo3(signature = (kind = "main"))]
+    fn field_names(&self, kind: &str) -> PyResult<Vec<String>> {
+        let header = &self.header;
+        Ok(match field_kind(kind)? {
+            FieldKind::Main => header
+                .ip_fields_in_order
+                .iter()
+                .map(|f| f.name.clone())
+                .collect(),
+            FieldKind::Slow => header
+                .s_fields_in_order
+                .iter()
+                .map(|f| f.name.clone())
+                .collect(),
+            FieldKind::GNSS => header
+                .g_fields_in_order
+                .iter()
+                .map(|f| f.name.clone())
+                .collect(),
+        })
+    }
+
+    /// Values of every `main`, `slow` or `gnss` frame as a 2D `int64` array, one row per
+    /// frame.
+    #[pyo3(signature = (kind = "main"))]
+    fn frames<'py>(&self, py: Python<'py>, kind: &str) -> PyResult<Bound<'py, PyArray2<i64>>> {
+        let kind = field_kind(kind)?;
+        let columns = self.field_names(kind_name(kind))?.len();
+        let mut reader = self.reader();
+        let mut values = Vec::new();
+        while let Some(record) = reader.next() {
+            match record {
+                BlackboxRecord::Main(view)
+                | BlackboxRecord::Slow(view)
+                | BlackboxRecord::GNSS(view)
+                    if view.kind() == kind =>
+                {
+                    values.extend_from_slice(view.values())
+                }
+                _ => {}
+            }
+        }
+        let rows = values.len() / columns.max(1);
+        PyArray1::from_vec(py, values).reshape([rows, columns])
+    }
+
+    /// Streams `(kind, values)` tuples: frame values as `int64` arrays, events as their
+    /// description.
+    fn __iter__(slf: Bound<'_, Self>) -> Records {
+        let bytes = slf.get().bytes.clone();
+        // SAFETY: the bytes are kept alive by `Records`, which drops the reader first
+        let data: &'static [u8] =
+            unsafe { std::slice::from_raw_parts(bytes.as_ptr(), bytes.len()) };
+        Records {
+            reader: BlackboxReader::from_bytes(data)
+                .expect("header was parsed when opening the log"),
+            _bytes: bytes,
+        }
+    }
+}
+
+/// Iterator over the records of a [`Log`].
+#[pyclass(module = "pyblackbox", unsendable)]
+pub struct Records {
+    reader: BlackboxReader<'static>,
+    _bytes: Arc<[u8]>,
+}
+
+#[pymethods]
+impl Records {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<(&'static str, Py<PyAny>)>> {
+        loop {
+            let Some(record) = self.reader.next() else {
+                return Ok(None);
+            };
+            let item = match record {
+                BlackboxRecord::Main(view)
+                | BlackboxRecord::Slow(view)
+                | BlackboxRecord::GNSS(view) => (
+                    kind_name(view.kind()),
+                    PyArray1::from_slice(py, view.values()).into_any().unbind(),
+                ),
+                BlackboxRecord::Event(event) => (
+                    "event",
+                    format!("{:?}", event)
+                        .into_pyobject(py)?
+                        .into_any()
+                        .unbind(),
+                ),
+                BlackboxRecord::Garbage(_) => continue,
+            };
+            return Ok(Some(item));
+        }
+    }
+}
+
+/// Opens log `index` of a file.
+#[pyfunction]
+#[pyo3(signature = (path, index = 0))]
+fn open(path: PathBuf, index: usize) -> PyResult<Log> {
+    Log::new(&std::fs::read(path)?, index)
+}
+
+/// Number of logs in a file whose header can be parsed.
+#[pyfunction]
+fn log_count(path: PathBuf) -> PyResult<usize> {
+    let bytes = std::fs::read(path)?;
+    Ok(MultiSegmentBlackboxReader::from_bytes(&bytes)
+        .segments()
+        .iter()
+        .filter(|segment| segment.header.is_ok())
+        .count())
+}
+
+#[pymodule]
+pub(crate) fn pyblackbox(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Log>()?;
+    m.add_class::<Records>()?;
+    m.add_function(wrap_pyfunction!(open, m)?)?;
+    m.add_function(wrap_pyfunction!(log_count, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use crate::{FirmwareKind, FirmwareVersion};
+
+/// Headers older firmware writes under another name, as (name in the log, name used here).
+const LEGACY_HEADER_ALIASES: &[(&str, &str)] = &[
+    ("vbatscale", "vbat_scale"),
+    ("currentMeter", "currentSensor"),
+];
+
+/// EmuFlight splits some Betaflight settings per PID term or axis, the P term and roll axis
+/// stand in for the single Betaflight value.
+const EMUFLIGHT_HEADER_ALIASES: &[(&str, &str)] = &[
+    ("tpa_rate_p", "tpa_rate"),
+    ("dterm_lowpass_hz_roll", "dterm_lowpass_hz"),
+];
+
+/// Differences between firmware that affect how their logs are decoded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    pub firmware: FirmwareKind,
+    /// Battery voltage and current are logged in 0.01 V and 0.01 A instead of ADC readings
+    pub centi_units: bool,
+    /// Meters per raw unit of `GPS_altitude`
+    pub gnss_altitude_scale: f64,
+    /// Flight mode flags are Betaflight mode box ids rather than INAV flight modes
+    pub betaflight_flight_modes: bool,
+    /// Encoding id 10 is Elias gamma rather than Betaflight's Tag2_3SVariable
+    pub cleanflight_encoding_ids: bool,
+    /// Headers this firmware writes under another name, on top of the legacy names of any
+    /// firmware.
+    pub header_aliases: &'static [(&'static str, &'static str)],
+}
+
+impl Quirks {
+    pub fn new(firmware: FirmwareKind, version: Option<FirmwareVersion>) -> Self {
+        let at_least =
+            |major, minor| version.is_some_and(|v| v >= FirmwareVersion::new(major, minor, 0));
+        let centi_units = match firmware {
+            FirmwareKind::Betaflight => at_least(4, 0),
+            FirmwareKind::INAV => true,
+            // Versions restarted from 0.1.0 on top of Betaflight 4.0
+            FirmwareKind::EmuFlight => version.is_some_and(|v| v.major == 0 && v.minor >= 2),
+            _ => false,
+        };
+
+        Self {
+            firmware,
+            centi_units,
+            gnss_altitude_scale: match firmware {
+                FirmwareKind::INAV => 0.01,
+                _ => 0.1,
+            },
+            betaflight_flight_modes: firmware != FirmwareKind::INAV,
+            cleanflight_encoding_ids: matches!(
+                firmware,
+                FirmwareKind::Cleanflight | FirmwareKind::Baseflight
+            ),
+            header_aliases: match firmware {
+                FirmwareKind::EmuFlight => EMUFLIGHT_HEADER_ALIASES,
+                _ => &[],
+            },
+        }
+    }
+
+    /// Copies aliased headers to the name used by this crate, unless already present. Only
+    /// meant for lookup maps, [`Header::other_headers`](crate::Header::other_headers) stays as
+    /// logged.
+    pub(crate) fn apply_aliases(&self, headers: &mut HashMap<String, String>) {
+        for (alias, name) in self.header_aliases.iter().chain(LEGACY_HEADER_ALIASES) {
+            if !headers.contains_key(*name) {
+                if let Some(value) = headers.get(*alias).cloned() {
+                    headers.insert((*name).to_owned(), value);
+                }
+            }
+        }
+        // Before motorOutput, motors ran from the minimum to the maximum throttle
+        if !headers.contains_key("motorOutput") {
+            if let (Some(min), Some(max)) = (headers.get("minthrottle"), headers.get("maxthrottle"))
+            {
+                let range = format!("{},{}", min.trim(), max.trim());
+                headers.insert("motorOutput".to_owned(), range);
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::ops::Deref;
+
+use crate::{stream::header::Header, FailsafePhase, FlightModes, StateFlags};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum FieldKind {
+    Main,
+    GNSS,
+    Slow,
+}
+
+/// Decoded values of a single frame together with the header they were decoded with,
+/// so that fields can be looked up by name instead of by index.
+///
+/// Dereferences to the raw `[i64]` slice in header field order, or in selection order
+/// for main frames of a reader with [`select_fields`](crate::BlackboxReader::select_fields).
+#[derive(Clone, Copy)]
+pub struct FieldView<'a> {
+    header: &'a Header,
+    kind: FieldKind,
+    values: &'a [i64],
+    projection: Option<&'a [usize]>,
+    derived: &'a [f64],
+}
+
+impl<'a> FieldView<'a> {
+    pub(crate) fn new(header: &'a Header, kind: FieldKind, values: &'a [i64]) -> Self {
+        Self {
+            header,
+            kind,
+            values,
+            projection: None,
+            derived: &[],
+        }
+    }
+
+    pub(crate) fn projected(
+        header: &'a Header,
+        projection: &'a [usize],
+        values: &'a [i64],
+    ) -> Self {
+        Self {
+            header,
+            kind: FieldKind::Main,
+            values,
+            projection: Some(projection),
+            derived: &[],
+        }
+    }
+
+    pub(crate) fn with_derived(self, derived: &'a [f64]) -> Self {
+        Self { derived, ..self }
+    }
+
+    /// Index in the header field list of the value at `position`.
+    pub(crate) fn header_ix(&self, position: usize) -> usize {
+        self.projection.map_or(position, |p| p[position])
+    }
+
+    /// Whether only a subset of the fields was decoded.
+    pub fn is_projected(&self) -> bool {
+        self.projection.is_some()
+    }
+
+    pub fn kind(&self) -> FieldKind {
+        self.kind
+    }
+
+    pub fn values(&self) -> &'a [i64] {
+        self.values
+    }
+
+    /// Values of the fields registered with [`derive`](crate::BlackboxReader::derive), in
+    /// the same order. Empty for GNSS and slow frames.
+    pub fn derived(&self) -> &'a [f64] {
+        self.derived
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        if let Some(projection) = self.projection {
+            let ix = self.header.ip_fields.get(name)?.ix;
+            return projection.iter().position(|p| *p == ix);
+        }
+        match self.kind {
+            FieldKind::Main => self.header.ip_fields.get(name).map(|f| f.ix),
+            FieldKind::GNSS => self.header.g_fields.get(name).map(|f| f.ix),
+            FieldKind::Slow => self.header.s_fields.get(name).map(|f| f.ix),
+        }
+    }
+
+    pub fn value(&self, name: &str) -> Option<i64> {
+        self.index_of(name)
+            .and_then(|ix| self.values.get(ix).copied())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'a str> + 'a {
+        let header = self.header;
+        if let Some(projection) = self.projection {
+            let names: Box<dyn Iterator<Item = &'a str>> = Box::new(
+                projection
+                    .iter()
+                    .map(move |ix| &header.ip_fields_in_order[*ix].name[..]),
+            );
+            return names;
+        }
+        let names: Box<dyn Iterator<Item = &'a str>> = match self.kind {
+            FieldKind::Main => Box::new(header.ip_fields_in_order.iter().map(|f| &f.name[..])),
+            FieldKind::GNSS => Box::new(header.g_fields_in_order.iter().map(|f| &f.name[..])),
+            FieldKind::Slow => Box::new(header.s_fields_in_order.iter().map(|f| &f.name[..])),
+        };
+        names
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, i64)> + 'a {
+        self.names().zip(self.values.iter().copied())
+    }
+
+    /// Raw value of a slow frame field, `None` for other frame kinds.
+    fn slow_value(&self, name: &str) -> Option<u32> {
+        (self.kind == FieldKind::Slow)
+            .then(|| self.value(name))
+            .flatten()
+            .map(|v| v as u32)
+    }
+
+    /// Decodes the `flightModeFlags` field of a slow frame.
+    pub fn flight_modes(&self) -> Option<FlightModes> {
+        let bits = self.slow_value("flightModeFlags")?;
+        Some(self.header.flight_modes(bits))
+    }
+
+    /// Decodes the `stateFlags` field of a slow frame.
+    pub fn state_flags(&self) -> Option<StateFlags> {
+        let bits = self.slow_value("stateFlags")?;
+        Some(StateFlags::decode(self.header.firmware_kind(), bits))
+    }
+
+    /// Decodes the `failsafePhase` field of a slow frame.
+    pub fn failsafe_phase(&self) -> Option<FailsafePhase> {
+        let value = self.slow_value("failsafePhase")?;
+        Some(FailsafePhase::decode(self.header.firmware_kind(), value))
+    }
+
+    /// Decodes a main frame view using a layout built from the same header.
+    ///
+    /// Returns `None` for projected views, which don't have the header field order.
+    pub fn main_frame(&self, layout: &MainFrameLayout) -> Option<MainFrame> {
+        (self.kind == FieldKind::Main && self.projection.is_none())
+            .then(|| layout.decode(self.values))
+    }
+}
+
+impl<'a> Deref for FieldView<'a> {
+    type Target = [i64];
+
+    fn deref(&self) -> &Self::Target {
+        self.values
+    }
+}
+
+/// Main frame values mapped onto well-known Betaflight/INAV fields.
+///
+/// Fields missing from the log are left at zero. `gyro` is in deg/s and `acc` in g.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MainFrame {
+    pub loop_iteration: u32,
+    /// Logged 32-bit time, which wraps after about 71.6 minutes. See
+    /// [`BlackboxReader::last_widened_time`](crate::BlackboxReader::last_widened_time) for the
+    /// time widened across rollovers.
+    pub time_us: u64,
+    pub axis_p: [i32; 3],
+    pub axis_i: [i32; 3],
+    pub axis_d: [i32; 3],
+    pub axis_f: [i32; 3],
+    pub rc_command: [i32; 4],
+    pub setpoint: [i32; 4],
+    pub gyro: [f32; 3],
+    pub acc: [f32; 3],
+    pub motors: Vec<u16>,
+    pub debug: Vec<i32>,
+}
+
+/// Field indices and scales resolved once from a [`Header`], used to decode raw main frame
+/// values into [`MainFrame`]s.
+#[derive(Clone, Debug)]
+pub struct MainFrameLayout {
+    loop_iteration: Option<usize>,
+    time: Option<usize>,
+    axis_p: [Option<usize>; 3],
+    axis_i: [Option<usize>; 3],
+    axis_d: [Option<usize>; 3],
+    axis_f: [Option<usize>; 3],
+    rc_command: [Option<usize>; 4],
+    setpoint: [Option<usize>; 4],
+    gyro: [Option<usize>; 3],
+    acc: [Option<usize>; 3],
+    motors: Vec<usize>,
+    debug: Vec<usize>,
+    gyro_scale: f32,
+    acc_scale: f32,
+}
+
+fn field_array<const N: usize>(header: &Header, prefix: &str) -> [Option<usize>; N] {
+    let mut ret = [None; N];
+    for (i, ix) in ret.iter_mut().enumerate() {
+        *ix = header
+            .ip_fields
+            .get(&format!("{}[{}]", prefix, i))
+            .map(|f| f.ix);
+    }
+    ret
+}
+
+fn field_list(header: &Header, prefix: &str) -> Vec<usize> {
+    (0..)
+        .map_while(|i| {
+            header
+                .ip_fields
+                .get(&format!("{}[{}]", prefix, i))
+                .map(|f| f.ix)
+        })
+        .collect()
+}
+
+impl MainFrameLayout {
+    pub fn new(header: &Header) -> Self {
+        let acc_1g = header
+            .other_headers
+            .get("acc_1G")
+            .and_then(|v| v.parse::<f32>().ok())
+            .filter(|v| *v > 0.0)
+            .unwrap_or(1.0);
+
+        Self {
+            loop_iteration: header.ip_fields.get("loopIteration").map(|f| f.ix),
+            time: header.ip_fields.get("time").map(|f| f.ix),
+            axis_p: field_array(header, "axisP"),
+            axis_i: field_array(header, "axisI"),
+            axis_d: field_array(header, "axisD"),
+            axis_f: field_array(header, "axisF"),
+            rc_command: field_array(header, "rcCommand"),
+            setpoint: field_array(header, "setpoint"),
+            gyro: field_array(header, "gyroADC"),
+            acc: field_array(header, "accSmooth"),
+            motors: field_list(header, "motor"),
+            debug: field_list(header, "debug"),
+            gyro_scale: header.raw_gyro_scale,
+            acc_scale: 1.0 / acc_1g,
+        }
+    }
+
+    pub fn decode(&self, values: &[i64]) -> MainFrame {
+        let get = |ix: Option<usize>| ix.and_then(|ix| values.get(ix).copied()).unwrap_or(0);
+        let ints = |ixs: &[Option<usize>], out: &mut [i32]| {
+            for (out, ix) in out.iter_mut().zip(ixs) {
+                *out = get(*ix) as i32;
+            }
+        };
+        let scaled = |ixs: &[Option<usize>], scale: f32, out: &mut [f32]| {
+            for (out, ix) in out.iter_mut().zip(ixs) {
+                *out = get(*ix) as f32 * scale;
+            }
+        };
+
+        let mut frame = MainFrame {
+            loop_iteration: get(self.loop_iteration) as u32,
+            time_us: get(self.time) as u32 as u64,
+            motors: self.motors.iter().map(|ix| get(Some(*ix)) as u16).collect(),
+            debug: self.debug.iter().map(|ix| get(Some(*ix)) as i32).collect(),
+            ..Default::default()
+        };
+        ints(&self.axis_p, &mut frame.axis_p);
+        ints(&self.axis_i, &mut frame.axis_i);
+        ints(&self.axis_d, &mut frame.axis_d);
+        ints(&self.axis_f, &mut frame.axis_f);
+        ints(&self.rc_command, &mut frame.rc_command);
+        ints(&self.setpoint, &mut frame.setpoint);
+        scaled(&self.gyro, self.gyro_scale, &mut frame.gyro);
+        scaled(&self.acc, self.acc_scale, &mut frame.acc);
+
+        frame
+    }
+}
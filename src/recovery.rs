@@ -0,0 +1,141 @@
+/// What the reader does when it runs into a problem.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RecoveryAction {
+    /// Stop reading, leaving the reader positioned at the problem.
+    Abort,
+    /// Drop just the offending frame or header line and continue right after it. Where its
+    /// length isn't known, e.g. for frames that fail to parse, this is the same as `Resync`.
+    Skip,
+    /// Report the data as garbage and look for the next valid frame using the reader's
+    /// [`ResyncStrategy`](crate::ResyncStrategy).
+    Resync,
+}
+
+/// What the reader does with a data frame that decoded to a different number of values than
+/// its header has fields, e.g. because of an encoding group cut short by the field list.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FieldCountAction {
+    /// Handle it like a frame that fails to parse, with the policy's `undecodable_frame`
+    /// action.
+    Reject,
+    /// Pad the values with zeros or drop the extra ones, and report it as a
+    /// [`Diagnostic`](crate::Diagnostic).
+    Fit,
+}
+
+/// How the reader handles each kind of corrupted or unexpected data.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RecoveryPolicy {
+    /// Events with an unknown code. With `Resync`, they are only returned when they don't
+    /// follow garbage and are followed by a valid frame.
+    pub unknown_event: RecoveryAction,
+    /// Frames that fail to parse or aren't followed by the start of another frame.
+    pub undecodable_frame: RecoveryAction,
+    /// Main frames rejected by [`FrameLimits`](crate::FrameLimits), which usually means the
+    /// predictors were fed corrupted values. With `Resync`, P-frames are dropped until the next
+    /// valid I-frame, with `Skip` only the rejected frame is.
+    pub implausible_frame: RecoveryAction,
+    /// Malformed `H` lines. With `Resync`, the header ends at the first one and its remaining
+    /// lines are skipped as garbage. With `Abort`, field lists like `Field I predictor` with
+    /// a different number of entries than the field names fail the header too, otherwise
+    /// only the fields in every list are decoded.
+    pub header_anomaly: RecoveryAction,
+    /// Data frames with more or fewer values than their header has fields.
+    pub field_count_mismatch: FieldCountAction,
+    /// Stops reading once this many consecutive bytes had to be skipped.
+    pub max_garbage_bytes: Option<usize>,
+    /// Stops reading once more than this share of the log after the header, from 0 to 1, had
+    /// to be skipped in total. Keeps the reader from crawling through megabytes of noise in a
+    /// badly damaged file. Readers of a
+    /// [`MultiSegmentBlackboxReader`](crate::MultiSegmentBlackboxReader) only stop reading their
+    /// own log, the next one starts at the next segment as usual.
+    pub max_garbage_fraction: Option<f64>,
+}
+
+impl RecoveryPolicy {
+    /// Stops at the first problem.
+    pub fn strict() -> Self {
+        Self {
+            unknown_event: RecoveryAction::Abort,
+            undecodable_frame: RecoveryAction::Abort,
+            implausible_frame: RecoveryAction::Abort,
+            header_anomaly: RecoveryAction::Abort,
+            field_count_mismatch: FieldCountAction::Reject,
+            max_garbage_bytes: None,
+            max_garbage_fraction: None,
+        }
+    }
+
+    /// Recovers from everything it can.
+    pub fn lenient() -> Self {
+        Self {
+            unknown_event: RecoveryAction::Resync,
+            undecodable_frame: RecoveryAction::Resync,
+            implausible_frame: RecoveryAction::Resync,
+            header_anomaly: RecoveryAction::Resync,
+            field_count_mismatch: FieldCountAction::Fit,
+            max_garbage_bytes: None,
+            max_garbage_fraction: None,
+        }
+    }
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        Self::lenient()
+    }
+}
+
+/// XON of software flow control, resuming transmission.
+pub const XON: u8 = 0x11;
+/// XOFF of software flow control, pausing transmission.
+pub const XOFF: u8 = 0x13;
+
+/// Bytes a serial link injects between the frames of a log captured through it, stripped by
+/// the reader instead of being treated as corrupted data. Only runs of them directly between
+/// two frames are recognized, the same bytes inside a frame still corrupt it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SerialArtifacts {
+    /// Single bytes, like the [`XON`] and [`XOFF`] of software flow control.
+    pub bytes: Vec<u8>,
+    /// Fixed sequences, like the idle pattern of a bridge.
+    pub patterns: Vec<Vec<u8>>,
+    /// Complete MSP packets with a valid checksum, like the requests a configurator keeps
+    /// polling the flight controller with.
+    pub msp_packets: bool,
+}
+
+impl Default for SerialArtifacts {
+    fn default() -> Self {
+        Self {
+            bytes: vec![XON, XOFF],
+            patterns: Vec::new(),
+            msp_packets: true,
+        }
+    }
+}
+
+impl SerialArtifacts {
+    /// `input` after the run of artifacts it starts with.
+    pub(crate) fn strip<'a>(&self, mut input: &'a [u8]) -> &'a [u8] {
+        loop {
+            let len = match input.first() {
+                None => return input,
+                Some(byte) if self.bytes.contains(byte) => 1,
+                Some(_) => match self
+                    .patterns
+                    .iter()
+                    .find(|pattern| !pattern.is_empty() && input.starts_with(pattern))
+                {
+                    Some(pattern) => pattern.len(),
+                    None if self.msp_packets => match crate::msp::packet_len(input) {
+                        Some(len) => len,
+                        None => return input,
+                    },
+                    None => return input,
+                },
+            };
+            input = &input[len..];
+        }
+    }
+}
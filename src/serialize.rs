@@ -0,0 +1,83 @@
+//! `Serialize` implementations that can't be derived.
+
+use std::collections::BTreeMap;
+
+use serde::ser::{Serialize, SerializeMap, SerializeStruct, Serializer};
+
+use crate::{FieldView, FlightModes, Header, StateFlags};
+
+/// Header values as found in the log, without the decoding state. Other headers are sorted
+/// by name so that the output is stable.
+impl Serialize for Header {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let p_interval = self.p_interval();
+        let mut s = serializer.serialize_struct("Header", 19)?;
+        s.serialize_field("product", self.product())?;
+        s.serialize_field("data_version", self.data_version())?;
+        s.serialize_field("firmware_type", &self.firmware_type())?;
+        s.serialize_field("firmware_revision", &self.firmware_revision())?;
+        s.serialize_field("firmware_kind", &self.firmware_kind())?;
+        s.serialize_field("firmware_version", &self.firmware_version())?;
+        s.serialize_field(
+            "firmware_date",
+            &self.firmware_date().map(|d| d.to_string()),
+        )?;
+        s.serialize_field("board_information", &self.board_information())?;
+        s.serialize_field(
+            "log_start_datetime",
+            &self.log_start_datetime().map(|d| d.to_rfc3339()),
+        )?;
+        s.serialize_field("craft_name", &self.craft_name())?;
+        s.serialize_field("i_interval", &self.i_interval())?;
+        s.serialize_field(
+            "p_interval",
+            &format!("{}/{}", p_interval.numer(), p_interval.denom()),
+        )?;
+        s.serialize_field("raw_gyro_scale", &self.raw_gyro_scale)?;
+        s.serialize_field("loop_time", &self.loop_time)?;
+        s.serialize_field("settings", &self.settings)?;
+        s.serialize_field(
+            "other_headers",
+            &self.other_headers.iter().collect::<BTreeMap<_, _>>(),
+        )?;
+        s.serialize_field("main_fields", &self.ip_fields_in_order)?;
+        s.serialize_field("slow_fields", &self.s_fields_in_order)?;
+        s.serialize_field("gnss_fields", &self.g_fields_in_order)?;
+        s.end()
+    }
+}
+
+/// Field names mapped to their values, in field order.
+impl Serialize for FieldView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (name, value) in self.iter() {
+            map.serialize_entry(name, &value)?;
+        }
+        map.end()
+    }
+}
+
+fn serialize_flags<'a, S: Serializer>(
+    serializer: S,
+    name: &'static str,
+    bits: u32,
+    names: impl Iterator<Item = &'a str>,
+) -> Result<S::Ok, S::Error> {
+    let mut s = serializer.serialize_struct(name, 2)?;
+    s.serialize_field("bits", &bits)?;
+    s.serialize_field("names", &names.collect::<Vec<_>>())?;
+    s.end()
+}
+
+impl Serialize for FlightModes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_flags(serializer, "FlightModes", self.bits(), self.names())
+    }
+}
+
+impl Serialize for StateFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_flags(serializer, "StateFlags", self.bits(), self.names())
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{BlackboxReader, BlackboxRecord, FieldKind, Taken};
+
+/// How the logs of a [`SessionReader`] are placed on the session timeline.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SegmentTiming {
+    /// Each log starts this many microseconds after the last main frame of the one before.
+    /// The first log keeps its own times.
+    Gap(i64),
+    /// Microseconds added to the times of each log, in order. Logs without an offset keep
+    /// their own times.
+    Offsets(Vec<i64>),
+}
+
+impl Default for SegmentTiming {
+    fn default() -> Self {
+        SegmentTiming::Gap(0)
+    }
+}
+
+/// Record of a [`SessionReader`], tagged with the log it came from.
+pub struct SessionRecord<'a> {
+    /// Position of the log in the readers passed to [`SessionReader::new`]
+    pub segment: usize,
+    /// Session time in microseconds of the record, or of the main frame before it for other
+    /// records.
+    pub time: i64,
+    pub record: BlackboxRecord<'a>,
+}
+
+/// Reader chaining several logs into one record stream, e.g. all the flights of a battery
+/// pack session from a flash dump, with their times shifted onto a single timeline.
+pub struct SessionReader<'a> {
+    readers: Vec<BlackboxReader<'a>>,
+    timing: SegmentTiming,
+    current: usize,
+    /// Added to the times of the current log, `None` until its first main frame
+    offset: Option<i64>,
+    /// Session time of the last main frame
+    time: i64,
+    started: bool,
+}
+
+impl<'a> SessionReader<'a> {
+    pub fn new(readers: impl IntoIterator<Item = BlackboxReader<'a>>) -> Self {
+        Self {
+            readers: readers.into_iter().collect(),
+            timing: SegmentTiming::default(),
+            current: 0,
+            offset: None,
+            time: 0,
+            started: false,
+        }
+    }
+
+    pub fn with_timing(mut self, timing: SegmentTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn readers(&self) -> &[BlackboxReader<'a>] {
+        &self.readers
+    }
+
+    fn segment_offset(&self, first_time: i64) -> i64 {
+        match &self.timing {
+            SegmentTiming::Gap(_) if !self.started => 0,
+            SegmentTiming::Gap(gap) => self.time + gap - first_time,
+            SegmentTiming::Offsets(offsets) => offsets.get(self.current).copied().unwrap_or(0),
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<SessionRecord<'_>> {
+        let taken = loop {
+            let reader = self.readers.get_mut(self.current)?;
+            let Some(record) = reader.next() else {
+                self.current += 1;
+                self.offset = None;
+                continue;
+            };
+            let taken = Taken::from(record);
+            if let Taken::View(FieldKind::Main) = taken {
+                let time = reader.last_widened_time;
+                let offset = match self.offset {
+                    Some(offset) => offset,
+                    None => *self.offset.insert(self.segment_offset(time)),
+                };
+                self.time = time + offset;
+                self.started = true;
+            }
+            break taken;
+        };
+
+        let record = self.readers[self.current].record(taken);
+        Some(SessionRecord {
+            segment: self.current,
+            time: self.time,
+            record,
+        })
+    }
+}
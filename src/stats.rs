@@ -0,0 +1,95 @@
+/// Running counts of what a [`BlackboxReader`](crate::BlackboxReader) had to skip, similar to
+/// the summary `blackbox_decode` prints at the end of a log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ReaderStats {
+    /// Main frames returned, or skipped because they were out of range or weren't needed by
+    /// [`next_every_nth`](crate::BlackboxReader::next_every_nth). Frames it jumped over
+    /// using the index aren't counted.
+    pub main_frames: u64,
+    /// Iterations the firmware should have logged according to the I and P intervals, but
+    /// which are missing between the main frames that were read. Pauses announced by a
+    /// logging resume event are not counted.
+    pub missing_iterations: u64,
+    /// Number of times the reader had to skip data to find valid frames again.
+    pub resyncs: u64,
+    /// Main frames that decoded, but were rejected by
+    /// [`FrameLimits`](crate::FrameLimits) validation.
+    pub corrupted_frames: u64,
+    /// Bytes skipped while resyncing, including rejected frames.
+    pub garbage_bytes: u64,
+    /// Bytes stripped between frames as
+    /// [`SerialArtifacts`](crate::SerialArtifacts).
+    pub serial_artifact_bytes: u64,
+}
+
+impl ReaderStats {
+    /// Share of expected main frames that were lost, from 0 to 100.
+    pub fn lost_percentage(&self) -> f64 {
+        let expected = self.main_frames + self.missing_iterations;
+        if expected == 0 {
+            return 0.0;
+        }
+        self.missing_iterations as f64 * 100.0 / expected as f64
+    }
+
+    /// Counts since `earlier` stats of the same reader.
+    pub(crate) fn since(&self, earlier: &ReaderStats) -> ReaderStats {
+        ReaderStats {
+            main_frames: self.main_frames - earlier.main_frames,
+            missing_iterations: self.missing_iterations - earlier.missing_iterations,
+            resyncs: self.resyncs - earlier.resyncs,
+            corrupted_frames: self.corrupted_frames - earlier.corrupted_frames,
+            garbage_bytes: self.garbage_bytes - earlier.garbage_bytes,
+            serial_artifact_bytes: self.serial_artifact_bytes - earlier.serial_artifact_bytes,
+        }
+    }
+}
+
+/// Frames of one type seen by a [`BlackboxReader`](crate::BlackboxReader), see [`FrameStats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FrameCounts {
+    /// Frames that decoded and were accepted, including P-frames passed over by
+    /// [`next_every_nth`](crate::BlackboxReader::next_every_nth).
+    pub valid: u64,
+    /// Frames that failed to decode, weren't followed by the start of another frame, or were
+    /// rejected by [`FrameLimits`](crate::FrameLimits) validation or the recovery policy.
+    pub corrupt: u64,
+    /// P-frames dropped while waiting for an I-frame to resync to, because the frames they are
+    /// predicted from were corrupted.
+    pub desynced: u64,
+}
+
+/// [`FrameCounts`] of every frame type, like the frame validity table `blackbox_decode`
+/// prints at the end of a log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FrameStats {
+    counts: [FrameCounts; 6],
+}
+
+impl FrameStats {
+    const MARKERS: [u8; 6] = *b"IPSGHE";
+
+    /// Counts of the frames starting with `marker`, like `b'P'`.
+    pub fn get(&self, marker: u8) -> Option<&FrameCounts> {
+        let ix = Self::MARKERS.iter().position(|m| *m == marker)?;
+        Some(&self.counts[ix])
+    }
+
+    /// Adds a frame starting with `marker` to one of its counts, nothing for other bytes.
+    pub(crate) fn count(&mut self, marker: u8, count: impl FnOnce(&mut FrameCounts) -> &mut u64) {
+        if let Some(ix) = Self::MARKERS.iter().position(|m| *m == marker) {
+            *count(&mut self.counts[ix]) += 1;
+        }
+    }
+
+    /// Counts of every frame type with its marker, in the order I, P, S, G, H, E.
+    pub fn iter(&self) -> impl Iterator<Item = (char, &FrameCounts)> {
+        Self::MARKERS
+            .iter()
+            .map(|m| char::from(*m))
+            .zip(&self.counts)
+    }
+}
@@ -1,42 +1,35 @@
-use nom::branch::alt;
-use nom::{combinator::map, IResult};
-
-use crate::frame::{
-    data::{
-        parse_owned_gframe, parse_owned_hframe, parse_owned_iframe, parse_owned_pframe,
-        parse_owned_sframe,
-    },
-    parse_body_frame, BodyFrame,
+use nom::{
+    error::{Error, ErrorKind},
+    IResult, Needed,
 };
 
+use crate::frame::{data::parse_frame_payload, parse_body_frame, BodyFrame};
+
 use super::header::Header;
 
+/// Parses the next frame, decoding the values of data frames into `values`.
 pub(crate) fn parse_next_frame<'h, 'i: 'o, 'o>(
     header: &'h Header,
     input: &'i [u8],
+    values: &mut Vec<i64>,
 ) -> IResult<&'o [u8], BodyFrame> {
-    let (input, event) = alt((
-        parse_body_frame,
-        map(
-            parse_owned_iframe(&header.i_field_encodings),
-            BodyFrame::IFrame,
-        ),
-        map(
-            parse_owned_pframe(&header.p_field_encodings),
-            BodyFrame::PFrame,
-        ),
-        map(
-            parse_owned_sframe(&header.s_field_encodings),
-            BodyFrame::SFrame,
-        ),
-        map(
-            parse_owned_gframe(&header.g_field_encodings),
-            BodyFrame::GFrame,
-        ),
-        map(
-            parse_owned_hframe(&header.h_field_encodings),
-            BodyFrame::HFrame,
-        ),
-    ))(input)?;
-    Ok((input, event))
+    // Recoverable errors point at the start of the frame, as recovery resyncs from the byte
+    // after it
+    let invalid = |e| match e {
+        nom::Err::Error(_) => nom::Err::Error(Error::new(input, ErrorKind::Tag)),
+        e => e,
+    };
+    // The marker byte alone decides how the rest of the frame is parsed
+    let (frame, encodings) = match input.first() {
+        Some(b'E') => return parse_body_frame(input).map_err(invalid),
+        Some(b'I') => (BodyFrame::IFrame, &header.i_field_encodings),
+        Some(b'P') => (BodyFrame::PFrame, &header.p_field_encodings),
+        Some(b'S') => (BodyFrame::SFrame, &header.s_field_encodings),
+        Some(b'G') => (BodyFrame::GFrame, &header.g_field_encodings),
+        Some(b'H') => (BodyFrame::HFrame, &header.h_field_encodings),
+        Some(_) => return Err(nom::Err::Error(Error::new(input, ErrorKind::Tag))),
+        None => return Err(nom::Err::Incomplete(Needed::new(1))),
+    };
+    let (input, ()) = parse_frame_payload(encodings, &input[1..], values).map_err(invalid)?;
+    Ok((input, frame))
 }
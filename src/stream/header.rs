@@ -2,23 +2,31 @@ use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
     f32::consts::PI,
+    ops::Range,
 };
 
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDateTime};
 use itertools::izip;
 use nom::{
     error::{ErrorKind, ParseError},
-    multi::fold_many0,
     IResult,
 };
 use num_rational::Ratio;
+use thiserror::Error;
 
-use super::predictor::{AnyIPredictor, AnyPPredictor, FieldPredictor};
+use super::predictor::{
+    AnyIPredictor, AnyPPredictor, FieldPredictor, PredictorError, PredictorInputs,
+};
 use crate::{
     frame::{
-        header::{parse_header, Frame},
-        FieldEncoding, RawFieldEncoding,
+        header::{
+            parse_header, BoardInformation, FirmwareKind, FirmwareVersion, Frame, HeaderSettings,
+        },
+        CustomFieldEncoding, FieldEncoding, RawFieldEncoding,
     },
     stream::predictor::AnyGPredictor,
+    BlackboxReaderError, DebugMode, DiagnosticKind, Extensions, FlightModes, Quirks, ReaderOptions,
+    RecoveryAction,
 };
 
 #[allow(unused)]
@@ -29,7 +37,7 @@ pub struct Header {
     firmware_type: Option<String>,
     firmware_revision: Option<String>,
     firmware_date: Option<String>,
-    board_information: Option<String>,
+    board_information: Option<BoardInformation>,
     log_start_datetime: Option<String>,
     craft_name: Option<String>,
     i_interval: i16,
@@ -40,6 +48,8 @@ pub struct Header {
     pub loop_time: u32,
 
     pub other_headers: HashMap<String, String>,
+    /// Typed values of the well known settings in `other_headers`.
+    pub settings: HeaderSettings,
 
     pub ip_fields: HashMap<String, IPField>,
     pub s_fields: HashMap<String, SlowField>,
@@ -51,26 +61,318 @@ pub struct Header {
     pub g_fields_in_order: Vec<GNSSField>,
 
     pub(crate) i_field_encodings: Vec<FieldEncoding>,
+    /// Whether each main frame field is read as an unsigned 32-bit value in I-frames
+    pub(crate) i_field_unsigned: Vec<bool>,
     pub(crate) i_field_predictors: Vec<AnyIPredictor>,
     pub(crate) p_field_encodings: Vec<FieldEncoding>,
     pub(crate) p_field_predictors: Vec<AnyPPredictor>,
     pub(crate) s_field_encodings: Vec<FieldEncoding>,
     pub(crate) g_field_encodings: Vec<FieldEncoding>,
+    /// Whether each GNSS field is read as an unsigned 32-bit value
+    pub(crate) g_field_unsigned: Vec<bool>,
     pub(crate) g_field_predictors: Vec<AnyGPredictor>,
     pub(crate) h_field_encodings: Vec<FieldEncoding>,
     pub(crate) h_field_predictors: Vec<AnyPPredictor>,
 }
 
+impl Header {
+    /// Parses just the header block, without preparing for decoding any frames. Running out of
+    /// input ends the header block, so it's enough to pass the first few kilobytes of a log.
+    pub fn parse(input: &[u8]) -> Result<Header, BlackboxReaderError> {
+        Self::parse_with(input, &ReaderOptions::default())
+    }
+
+    /// Like [`Header::parse`], with the predictors and encodings registered in
+    /// `options.extensions`.
+    pub fn parse_with(
+        input: &[u8],
+        options: &ReaderOptions,
+    ) -> Result<Header, BlackboxReaderError> {
+        let mut builder = HeaderBuilder::with_extensions(&options.extensions);
+        let mut input = input;
+        while let Ok((remaining_input, header_frame)) = parse_header(input) {
+            builder = builder.apply(header_frame);
+            input = remaining_input;
+        }
+
+        let header: Result<Header, HeaderBuildError> = builder.try_into();
+        Ok(header?)
+    }
+
+    pub fn product(&self) -> &str {
+        &self.product
+    }
+
+    pub fn data_version(&self) -> &str {
+        &self.data_version
+    }
+
+    /// `Data version` header as a number, 1 for old Cleanflight logs and 2 for everything newer.
+    pub fn data_version_number(&self) -> Option<u8> {
+        self.data_version.trim().parse().ok()
+    }
+
+    /// Main frames are logged as I-frames every `i_interval` loop iterations.
+    pub fn i_interval(&self) -> i16 {
+        self.i_interval
+    }
+
+    /// Share of the loop iterations between I-frames logged as P-frames.
+    pub fn p_interval(&self) -> Ratio<u16> {
+        self.p_interval
+    }
+
+    /// Raw `Firmware type` header, "Cleanflight" for Betaflight and most of its forks.
+    pub fn firmware_type(&self) -> Option<&str> {
+        self.firmware_type.as_deref()
+    }
+
+    pub fn firmware_kind(&self) -> FirmwareKind {
+        FirmwareKind::detect(self.firmware_type(), self.firmware_revision())
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        Quirks::new(self.firmware_kind(), self.firmware_version())
+    }
+
+    /// Names the bits of a flight mode event or the `flightModeFlags` slow field.
+    pub fn flight_modes(&self, bits: u32) -> FlightModes {
+        FlightModes::decode(self.firmware_kind(), bits)
+    }
+
+    /// What the `debug[]` main frame fields hold, from the `debug_mode` header.
+    pub fn debug_mode(&self) -> Option<DebugMode> {
+        let id = self.get_u32("debug_mode").ok()?;
+        Some(DebugMode::decode(self.firmware_kind(), id.try_into().ok()?))
+    }
+
+    /// Raw `Firmware revision` header, e.g. `Betaflight 4.2.11 (948ba6339) STM32F7X2`.
+    pub fn firmware_revision(&self) -> Option<&str> {
+        self.firmware_revision.as_deref()
+    }
+
+    pub fn firmware_version(&self) -> Option<FirmwareVersion> {
+        self.firmware_revision()
+            .and_then(FirmwareVersion::from_revision)
+    }
+
+    /// Firmware build date, as found in the `Firmware date` header (`Nov  9 2021 20:29:32`).
+    pub fn firmware_date(&self) -> Option<NaiveDateTime> {
+        let date = self.firmware_date.as_deref()?;
+        NaiveDateTime::parse_from_str(date, "%b %e %Y %H:%M:%S").ok()
+    }
+
+    /// Wall-clock time the log was started at. `None` if the flight controller had no
+    /// real-time clock set, which it reports as year 0.
+    pub fn log_start_datetime(&self) -> Option<DateTime<FixedOffset>> {
+        let datetime = self.log_start_datetime.as_deref()?;
+        DateTime::parse_from_rfc3339(datetime)
+            .ok()
+            .filter(|datetime| datetime.year() > 0)
+    }
+
+    pub fn board_information(&self) -> Option<&BoardInformation> {
+        self.board_information.as_ref()
+    }
+
+    pub fn craft_name(&self) -> Option<&str> {
+        self.craft_name.as_deref().filter(|name| !name.is_empty())
+    }
+
+    /// Raw value of the `name` header from [`other_headers`](Self::other_headers).
+    fn header_value(&self, name: &str) -> Result<&str, HeaderValueError> {
+        self.other_headers
+            .get(name)
+            .map(|value| value.trim())
+            .ok_or_else(|| HeaderValueError::Missing(name.to_owned()))
+    }
+
+    /// Parses the `name` header as an unsigned integer, e.g. `minthrottle`.
+    pub fn get_u32(&self, name: &str) -> Result<u32, HeaderValueError> {
+        let value = self.header_value(name)?;
+        value
+            .parse()
+            .map_err(|_| HeaderValueError::invalid(name, value, "an unsigned integer"))
+    }
+
+    /// Parses the `name` header as a comma separated list of integers, e.g. `rc_rates`.
+    pub fn get_i32_list(&self, name: &str) -> Result<Vec<i32>, HeaderValueError> {
+        let value = self.header_value(name)?;
+        if value.is_empty() {
+            return Ok(Vec::new());
+        }
+        value
+            .split(',')
+            .map(|v| v.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| HeaderValueError::invalid(name, value, "a list of integers"))
+    }
+
+    /// Parses the `name` header as a ratio written like `P interval`, either `n/d` or just `n`.
+    pub fn get_ratio(&self, name: &str) -> Result<Ratio<u32>, HeaderValueError> {
+        let value = self.header_value(name)?;
+        let (numer, denom) = value.split_once('/').unwrap_or((value, "1"));
+        match (numer.trim().parse(), denom.trim().parse()) {
+            (Ok(numer), Ok(denom)) if denom != 0 => Ok(Ratio::new(numer, denom)),
+            _ => Err(HeaderValueError::invalid(name, value, "a ratio")),
+        }
+    }
+
+    /// Parses the `name` header as a flag, `0`/`1` like the firmware logs them or
+    /// `false`/`true`.
+    pub fn get_bool(&self, name: &str) -> Result<bool, HeaderValueError> {
+        let value = self.header_value(name)?;
+        match value.to_ascii_lowercase().as_str() {
+            "0" | "false" => Ok(false),
+            "1" | "true" => Ok(true),
+            _ => Err(HeaderValueError::invalid(name, value, "0 or 1")),
+        }
+    }
+
+    /// Whether the firmware logs a main frame at `iteration`, according to the I and P
+    /// intervals.
+    pub(crate) fn logs_iteration(&self, iteration: i64) -> bool {
+        let i_interval = i64::from(self.i_interval.max(1));
+        let numer = i64::from(*self.p_interval.numer());
+        let denom = i64::from((*self.p_interval.denom()).max(1));
+        (iteration.rem_euclid(i_interval) + numer - 1) % denom < numer
+    }
+
+    /// Number of iterations in `range` at which the firmware logs a main frame.
+    pub(crate) fn logged_iterations(&self, range: Range<i64>) -> u64 {
+        if range.is_empty() {
+            return 0;
+        }
+        let period = i64::from(self.i_interval.max(1));
+        let count = |range: Range<i64>| range.filter(|i| self.logs_iteration(*i)).count() as u64;
+        let periods = (range.end - range.start) / period;
+        let rest = range.start + periods * period;
+        periods as u64 * count(0..period) + count(rest..range.end)
+    }
+}
+
+/// Error from the typed accessors on [`Header`] such as [`Header::get_u32`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum HeaderValueError {
+    #[error("header {0} is not present in the log")]
+    Missing(String),
+    #[error("header {name} is {value:?}, expected {expected}")]
+    Invalid {
+        name: String,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl HeaderValueError {
+    fn invalid(name: &str, value: &str, expected: &'static str) -> Self {
+        Self::Invalid {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            expected,
+        }
+    }
+}
+
+/// Inconsistency in the header found with
+/// [`ReaderOptions::validate_header`](crate::ReaderOptions::validate_header).
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize))]
+pub enum HeaderViolation {
+    #[error("header {0} is missing")]
+    MissingHeader(&'static str),
+    #[error("{header} has {found} entries for {expected} fields")]
+    FieldListLength {
+        header: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    #[error("predictor {predictor} of field {field} is not supported")]
+    UnsupportedPredictor { field: String, predictor: String },
+    #[error("predictor of field {field} needs the {header} header")]
+    MissingHeaderForPredictor { field: String, header: &'static str },
+    #[error("encoding {encoding} of field {field} is not supported")]
+    UnsupportedEncoding { field: String, encoding: u16 },
+    #[error("encoding {encoding} of field {field} can't be used in {frame} frames")]
+    EncodingNotAllowed {
+        field: String,
+        frame: char,
+        encoding: u16,
+    },
+}
+
+impl From<HeaderBuildError> for HeaderViolation {
+    fn from(err: HeaderBuildError) -> Self {
+        match err {
+            HeaderBuildError::MissingHeader(header) => HeaderViolation::MissingHeader(header),
+            HeaderBuildError::UnsupportedPredictor { field, predictor } => {
+                HeaderViolation::UnsupportedPredictor { field, predictor }
+            }
+            HeaderBuildError::MissingHeaderForPredictor { field, header } => {
+                HeaderViolation::MissingHeaderForPredictor { field, header }
+            }
+            HeaderBuildError::UnsupportedEncoding { field, encoding } => {
+                HeaderViolation::UnsupportedEncoding { field, encoding }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum HeaderBuildError {
     MissingHeader(&'static str),
     // InvalidHeader(&'static str),
+    UnsupportedPredictor { field: String, predictor: String },
+    MissingHeaderForPredictor { field: String, header: &'static str },
+    UnsupportedEncoding { field: String, encoding: u16 },
+}
+
+impl HeaderBuildError {
+    fn encoding(field: &str, encoding: u16) -> Self {
+        Self::UnsupportedEncoding {
+            field: field.to_owned(),
+            encoding,
+        }
+    }
+
+    fn predictor(field: &str, predictor: FieldPredictor, err: PredictorError) -> Self {
+        let field = field.to_owned();
+        match err {
+            PredictorError::Unsupported => Self::UnsupportedPredictor {
+                field,
+                predictor: format!("{:?}", predictor),
+            },
+            PredictorError::MissingHeader(header) => {
+                Self::MissingHeaderForPredictor { field, header }
+            }
+        }
+    }
 }
 
 impl AsRef<str> for HeaderBuildError {
     fn as_ref(&self) -> &str {
         match self {
             Self::MissingHeader(r) => r,
+            Self::UnsupportedPredictor { field, .. } => field,
+            Self::MissingHeaderForPredictor { header, .. } => header,
+            Self::UnsupportedEncoding { field, .. } => field,
+        }
+    }
+}
+
+impl From<HeaderBuildError> for BlackboxReaderError {
+    fn from(err: HeaderBuildError) -> Self {
+        match err {
+            HeaderBuildError::MissingHeader(header) => BlackboxReaderError::MissingHeader(header),
+            HeaderBuildError::UnsupportedPredictor { field, predictor } => {
+                BlackboxReaderError::UnsupportedPredictor { field, predictor }
+            }
+            HeaderBuildError::MissingHeaderForPredictor { field, header } => {
+                BlackboxReaderError::MissingHeaderForPredictor { field, header }
+            }
+            HeaderBuildError::UnsupportedEncoding { field, encoding } => {
+                BlackboxReaderError::UnsupportedEncoding { field, encoding }
+            }
         }
     }
 }
@@ -78,7 +380,22 @@ impl AsRef<str> for HeaderBuildError {
 impl TryFrom<HeaderBuilder> for Header {
     type Error = HeaderBuildError;
 
-    fn try_from(builder: HeaderBuilder) -> Result<Self, Self::Error> {
+    fn try_from(mut builder: HeaderBuilder) -> Result<Self, Self::Error> {
+        let quirks = builder.quirks();
+        // Settings are looked up under the names used here, other_headers stays as logged
+        let mut settings = builder.other_headers.clone();
+        quirks.apply_aliases(&mut settings);
+        for encoding in builder
+            .i_field_encoding
+            .iter_mut()
+            .chain(builder.p_field_encoding.iter_mut())
+            .chain(builder.s_field_encoding.iter_mut())
+            .chain(builder.g_field_encoding.iter_mut())
+            .chain(builder.h_field_encoding.iter_mut())
+        {
+            *encoding = encoding.with_quirks(&quirks);
+        }
+
         let product = builder
             .product
             .ok_or(HeaderBuildError::MissingHeader("Product"))?;
@@ -102,17 +419,22 @@ impl TryFrom<HeaderBuilder> for Header {
         let mut ip_fields = HashMap::with_capacity(builder.i_field_names.len());
         let mut ip_fields_in_order = Vec::with_capacity(builder.i_field_names.len());
         let mut i_field_encodings = Vec::with_capacity(builder.i_field_names.len());
+        let mut i_field_unsigned = Vec::with_capacity(builder.i_field_names.len());
         let mut p_field_encodings = Vec::with_capacity(builder.i_field_names.len());
         let mut i_field_predictors = Vec::with_capacity(builder.i_field_names.len());
         let mut p_field_predictors = Vec::with_capacity(builder.i_field_names.len());
 
-        fn add_encoding(encodings: &mut Vec<FieldEncoding>, new_encoding: RawFieldEncoding) {
+        let extensions = &builder.extensions;
+        // Returns the encoding id if it's unknown
+        let add_encoding = |encodings: &mut Vec<FieldEncoding>,
+                            new_encoding: RawFieldEncoding|
+         -> Result<(), u16> {
             let new_encoding = match new_encoding {
                 RawFieldEncoding::Tag8_8SVB => {
                     if let Some(FieldEncoding::Tag8_8SVB(n_fields)) = encodings.last_mut() {
                         if *n_fields != 8 {
                             *n_fields += 1;
-                            return;
+                            return Ok(());
                         }
                     }
                     FieldEncoding::Tag8_8SVB(1)
@@ -121,7 +443,7 @@ impl TryFrom<HeaderBuilder> for Header {
                     if let Some(FieldEncoding::Tag2_3S32(n_fields)) = encodings.last_mut() {
                         if *n_fields != 3 {
                             *n_fields += 1;
-                            return;
+                            return Ok(());
                         }
                     }
                     FieldEncoding::Tag2_3S32(1)
@@ -130,7 +452,7 @@ impl TryFrom<HeaderBuilder> for Header {
                     if let Some(FieldEncoding::Tag2_3SVariable(n_fields)) = encodings.last_mut() {
                         if *n_fields != 3 {
                             *n_fields += 1;
-                            return;
+                            return Ok(());
                         }
                     }
                     FieldEncoding::Tag2_3SVariable(1)
@@ -139,7 +461,7 @@ impl TryFrom<HeaderBuilder> for Header {
                     if let Some(FieldEncoding::Tag8_4S16(n_fields)) = encodings.last_mut() {
                         if *n_fields != 4 {
                             *n_fields += 1;
-                            return;
+                            return Ok(());
                         }
                     }
                     FieldEncoding::Tag8_4S16(1)
@@ -148,20 +470,48 @@ impl TryFrom<HeaderBuilder> for Header {
                 RawFieldEncoding::Negative14BitVB => FieldEncoding::Negative14BitVB,
                 RawFieldEncoding::SignedVB => FieldEncoding::SignedVB,
                 RawFieldEncoding::UnsignedVB => FieldEncoding::UnsignedVB,
+                RawFieldEncoding::EliasDeltaU32 => FieldEncoding::EliasDeltaU32,
+                RawFieldEncoding::EliasDeltaS32 => FieldEncoding::EliasDeltaS32,
+                RawFieldEncoding::EliasGammaU32 => FieldEncoding::EliasGammaU32,
+                RawFieldEncoding::EliasGammaS32 => FieldEncoding::EliasGammaS32,
+                RawFieldEncoding::Custom(id) => {
+                    let (group_size, decoder) = extensions.encoding(id).ok_or(id)?;
+                    if let Some(FieldEncoding::Custom(custom)) = encodings.last_mut() {
+                        if custom.id == id && custom.fields != group_size {
+                            custom.fields += 1;
+                            return Ok(());
+                        }
+                    }
+                    FieldEncoding::Custom(CustomFieldEncoding {
+                        id,
+                        fields: 1,
+                        decoder,
+                    })
+                }
             };
             encodings.push(new_encoding);
-        }
+            Ok(())
+        };
 
-        for (ix, (name, signed, i_encoding, p_encoding)) in izip!(
+        // Fields without an entry in every list are left out, parse_headers reports the lists
+        // that are too short or fails on them, depending on the reader options
+        let mut raw_predictors = Vec::with_capacity(builder.i_field_names.len());
+        for (ix, (name, signed, i_encoding, p_encoding, i_predictor, p_predictor)) in izip!(
             builder.i_field_names,
             builder.i_field_signedness,
             builder.i_field_encoding,
-            builder.p_field_encoding
+            builder.p_field_encoding,
+            builder.i_field_predictors,
+            builder.p_field_predictors
         )
         .enumerate()
         {
-            add_encoding(&mut i_field_encodings, i_encoding);
-            add_encoding(&mut p_field_encodings, p_encoding);
+            raw_predictors.push((i_predictor, p_predictor));
+            i_field_unsigned.push(i_encoding.is_unsigned());
+            add_encoding(&mut i_field_encodings, i_encoding)
+                .map_err(|e| HeaderBuildError::encoding(&name, e))?;
+            add_encoding(&mut p_field_encodings, p_encoding)
+                .map_err(|e| HeaderBuildError::encoding(&name, e))?;
 
             let field = IPField {
                 name: name.clone(),
@@ -172,17 +522,25 @@ impl TryFrom<HeaderBuilder> for Header {
             ip_fields_in_order.push(field);
         }
 
-        for (ix, i_predictor) in builder.i_field_predictors.iter().copied().enumerate() {
-            i_field_predictors.push(AnyIPredictor::new(
-                i_predictor,
-                &builder.other_headers,
-                &ip_fields,
-                ix,
-            ));
+        let inputs = PredictorInputs::new(&settings, &ip_fields);
+        for (ix, (i_predictor, _)) in raw_predictors.iter().copied().enumerate() {
+            i_field_predictors.push(
+                AnyIPredictor::new(i_predictor, &inputs, &builder.extensions, ix).map_err(
+                    |err| {
+                        HeaderBuildError::predictor(&ip_fields_in_order[ix].name, i_predictor, err)
+                    },
+                )?,
+            );
         }
 
-        for (ix, p_predictor) in builder.p_field_predictors.iter().copied().enumerate() {
-            p_field_predictors.push(AnyPPredictor::new(p_predictor, p_interval, ix));
+        for (ix, (_, p_predictor)) in raw_predictors.iter().copied().enumerate() {
+            p_field_predictors.push(
+                AnyPPredictor::new(p_predictor, p_interval, &builder.extensions, ix).map_err(
+                    |err| {
+                        HeaderBuildError::predictor(&ip_fields_in_order[ix].name, p_predictor, err)
+                    },
+                )?,
+            );
         }
 
         let mut s_fields = HashMap::with_capacity(builder.s_field_names.len());
@@ -196,7 +554,8 @@ impl TryFrom<HeaderBuilder> for Header {
         )
         .enumerate()
         {
-            add_encoding(&mut s_field_encodings, encoding);
+            add_encoding(&mut s_field_encodings, encoding)
+                .map_err(|e| HeaderBuildError::encoding(&name, e))?;
             let field = SlowField {
                 name,
                 ix,
@@ -209,6 +568,7 @@ impl TryFrom<HeaderBuilder> for Header {
 
         let mut g_fields = HashMap::with_capacity(builder.g_field_names.len());
         let mut g_field_encodings = Vec::with_capacity(builder.g_field_names.len());
+        let mut g_field_unsigned = Vec::with_capacity(builder.g_field_names.len());
         let mut g_field_predictors = Vec::with_capacity(builder.g_field_names.len());
         let mut g_fields_in_order = Vec::with_capacity(builder.g_field_names.len());
 
@@ -220,18 +580,19 @@ impl TryFrom<HeaderBuilder> for Header {
         )
         .enumerate()
         {
-            add_encoding(&mut g_field_encodings, encoding);
-            let mut name_chars = name.chars();
-            let sub_ix = if name_chars.any(|c| c == '[') {
-                name_chars
-                    .next()
-                    .and_then(|c| c.to_digit(10))
-                    .unwrap_or(0u32) as usize
-            } else {
-                0
-            };
+            g_field_unsigned.push(encoding.is_unsigned());
+            add_encoding(&mut g_field_encodings, encoding)
+                .map_err(|e| HeaderBuildError::encoding(&name, e))?;
+            // Home predicted fields use the home values in order
+            let home_ix = g_field_predictors
+                .iter()
+                .filter(|p| matches!(p, AnyGPredictor::HomeCoordinates(_)))
+                .count();
 
-            g_field_predictors.push(AnyGPredictor::new(predictor, ix, sub_ix, &ip_fields));
+            g_field_predictors.push(
+                AnyGPredictor::new(predictor, ix, home_ix, &inputs, &builder.extensions)
+                    .map_err(|err| HeaderBuildError::predictor(&name, predictor, err))?,
+            );
 
             let field = GNSSField {
                 name,
@@ -254,8 +615,15 @@ impl TryFrom<HeaderBuilder> for Header {
         )
         .enumerate()
         {
-            add_encoding(&mut h_field_encodings, encoding);
-            assert_eq!(predictor, FieldPredictor::None);
+            add_encoding(&mut h_field_encodings, encoding)
+                .map_err(|e| HeaderBuildError::encoding(&name, e))?;
+            if predictor != FieldPredictor::None {
+                return Err(HeaderBuildError::predictor(
+                    &name,
+                    predictor,
+                    PredictorError::Unsupported,
+                ));
+            }
             h_field_predictors.push(AnyPPredictor::none(ix));
 
             h_fields.insert(
@@ -269,18 +637,22 @@ impl TryFrom<HeaderBuilder> for Header {
             );
         }
 
-        Ok(Header {
+        let mut header = Header {
             product,
             data_version,
             firmware_type: builder.firmware_type,
             firmware_revision: builder.firmware_revision,
             firmware_date: builder.firmware_date,
-            board_information: builder.board_information,
+            board_information: builder
+                .board_information
+                .as_deref()
+                .map(BoardInformation::parse),
             log_start_datetime: builder.log_start_datetime,
             craft_name: builder.craft_name,
             i_interval,
             p_interval,
             p_ratio,
+            settings: HeaderSettings::from_headers(&settings),
             other_headers: builder.other_headers,
             ip_fields,
             s_fields,
@@ -288,6 +660,7 @@ impl TryFrom<HeaderBuilder> for Header {
             s_fields_in_order,
             g_fields_in_order,
             i_field_encodings,
+            i_field_unsigned,
             i_field_predictors,
             p_field_encodings,
             p_field_predictors,
@@ -295,13 +668,32 @@ impl TryFrom<HeaderBuilder> for Header {
             g_fields,
             h_fields,
             g_field_encodings,
+            g_field_unsigned,
             g_field_predictors,
             h_field_encodings,
             h_field_predictors,
             gyro_scale: gyro_scale * (PI / 180.0) * 0.000001,
             raw_gyro_scale: gyro_scale,
             loop_time,
-        })
+        };
+
+        // Data version 1 packs Tag8_4S16 values differently
+        if header.data_version_number() == Some(1) {
+            for encoding in header
+                .i_field_encodings
+                .iter_mut()
+                .chain(header.p_field_encodings.iter_mut())
+                .chain(header.s_field_encodings.iter_mut())
+                .chain(header.g_field_encodings.iter_mut())
+                .chain(header.h_field_encodings.iter_mut())
+            {
+                if let FieldEncoding::Tag8_4S16(n_fields) = *encoding {
+                    *encoding = FieldEncoding::Tag8_4S16V1(n_fields);
+                }
+            }
+        }
+
+        Ok(header)
     }
 }
 
@@ -327,6 +719,8 @@ struct HeaderBuilder {
     i_field_signedness: Vec<bool>,
     i_field_encoding: Vec<RawFieldEncoding>,
     i_field_predictors: Vec<FieldPredictor>,
+    /// Only checked against the I field names, P-frames have the same fields.
+    p_field_names: Vec<String>,
     p_field_encoding: Vec<RawFieldEncoding>,
     p_field_predictors: Vec<FieldPredictor>,
 
@@ -344,10 +738,348 @@ struct HeaderBuilder {
     h_field_signedness: Vec<bool>,
     h_field_encoding: Vec<RawFieldEncoding>,
     h_field_predictors: Vec<FieldPredictor>,
+
+    extensions: Extensions,
+}
+
+impl HeaderBuilder {
+    fn with_extensions(extensions: &Extensions) -> Self {
+        Self {
+            extensions: extensions.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn quirks(&self) -> Quirks {
+        Quirks::new(
+            FirmwareKind::detect(
+                self.firmware_type.as_deref(),
+                self.firmware_revision.as_deref(),
+            ),
+            self.firmware_revision
+                .as_deref()
+                .and_then(FirmwareVersion::from_revision),
+        )
+    }
+
+    /// Everything building the header would fail on, or decoding would get wrong, instead of
+    /// just the first problem.
+    fn violations(&self) -> Vec<HeaderViolation> {
+        let mut violations = Vec::new();
+        for (name, present) in [
+            ("Product", self.product.is_some()),
+            ("Data version", self.data_version.is_some()),
+            ("I interval", self.i_interval.is_some()),
+            ("P interval", self.p_interval.is_some()),
+            ("gyro_scale", self.gyro_scale.is_some()),
+            ("looptime", self.loop_time.is_some()),
+        ] {
+            if !present {
+                violations.push(HeaderViolation::MissingHeader(name));
+            }
+        }
+        for (_, header, expected, found) in self.mismatched_field_lists() {
+            violations.push(HeaderViolation::FieldListLength {
+                header,
+                expected,
+                found,
+            });
+        }
+
+        for (frame, names, encodings) in [
+            ('I', &self.i_field_names, &self.i_field_encoding),
+            ('P', &self.i_field_names, &self.p_field_encoding),
+            ('S', &self.s_field_names, &self.s_field_encoding),
+            ('G', &self.g_field_names, &self.g_field_encoding),
+            ('H', &self.h_field_names, &self.h_field_encoding),
+        ] {
+            for (name, encoding) in names.iter().zip(encodings) {
+                match encoding {
+                    RawFieldEncoding::Custom(id) if self.extensions.encoding(*id).is_none() => {
+                        violations.push(HeaderBuildError::encoding(name, *id).into())
+                    }
+                    // Only P and G fields are predicted from earlier frames, elsewhere the field
+                    // would be stuck at the value of its predictor
+                    RawFieldEncoding::Null if !matches!(frame, 'P' | 'G') => {
+                        violations.push(HeaderViolation::EncodingNotAllowed {
+                            field: name.clone(),
+                            frame,
+                            encoding: 9,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let inputs = self.predictor_inputs();
+        let mut check = |name: &str, predictor, result: Result<(), PredictorError>| {
+            if let Err(err) = result {
+                violations.push(HeaderBuildError::predictor(name, predictor, err).into());
+            }
+        };
+        for (ix, (name, predictor)) in self
+            .i_field_names
+            .iter()
+            .zip(&self.i_field_predictors)
+            .enumerate()
+        {
+            let result = AnyIPredictor::new(*predictor, &inputs, &self.extensions, ix);
+            // Decoding falls back to a default, which is likely off for this log
+            let result = match inputs.fallback(*predictor) {
+                Some((header, _)) => Err(PredictorError::MissingHeader(header)),
+                None => result.map(drop),
+            };
+            check(name, *predictor, result);
+        }
+        let p_interval = self.p_interval.unwrap_or(Ratio::from_integer(1));
+        for (ix, (name, predictor)) in self
+            .i_field_names
+            .iter()
+            .zip(&self.p_field_predictors)
+            .enumerate()
+        {
+            let result = AnyPPredictor::new(*predictor, p_interval, &self.extensions, ix);
+            check(name, *predictor, result.map(drop));
+        }
+        for (ix, (name, predictor)) in self
+            .g_field_names
+            .iter()
+            .zip(&self.g_field_predictors)
+            .enumerate()
+        {
+            let home_ix = self.g_field_predictors[..ix]
+                .iter()
+                .filter(|p| **p == FieldPredictor::HomeCoordinates)
+                .count();
+            let result = AnyGPredictor::new(*predictor, ix, home_ix, &inputs, &self.extensions);
+            check(name, *predictor, result.map(drop));
+        }
+        // Slow and home fields are stored as decoded. INAV marks some slow fields with the
+        // previous predictor, which the reader has always read as decoded too
+        let slow = izip!(&self.s_field_names, &self.s_field_predictors)
+            .filter(|(_, predictor)| **predictor != FieldPredictor::Previous);
+        for (name, predictor) in slow.chain(izip!(&self.h_field_names, &self.h_field_predictors)) {
+            if *predictor != FieldPredictor::None {
+                check(name, *predictor, Err(PredictorError::Unsupported));
+            }
+        }
+        violations
+    }
+
+    /// Header settings the I frame predictors are based on, as seen by [`Header`].
+    fn predictor_inputs(&self) -> PredictorInputs {
+        let mut settings = self.other_headers.clone();
+        self.quirks().apply_aliases(&mut settings);
+        let ip_fields = self
+            .i_field_names
+            .iter()
+            .enumerate()
+            .map(|(ix, name)| {
+                let field = IPField {
+                    name: name.clone(),
+                    ix,
+                    signed: false,
+                };
+                (name.clone(), field)
+            })
+            .collect();
+        PredictorInputs::new(&settings, &ip_fields)
+    }
+
+    /// I fields whose predictor falls back to a default because the header setting it's based
+    /// on is missing, with the setting and the value used instead.
+    fn predictor_fallbacks(&self) -> Vec<(String, &'static str, i64)> {
+        let inputs = self.predictor_inputs();
+        izip!(&self.i_field_names, &self.i_field_predictors)
+            .filter_map(|(name, predictor)| {
+                let (header, value) = inputs.fallback(*predictor)?;
+                Some((name.clone(), header, value))
+            })
+            .collect()
+    }
+
+    /// Field list headers with a different number of entries than the field names, with the
+    /// header of the names, the list's header and both lengths.
+    fn mismatched_field_lists(&self) -> Vec<(&'static str, &'static str, usize, usize)> {
+        let (i, s, g, h) = (
+            self.i_field_names.len(),
+            self.s_field_names.len(),
+            self.g_field_names.len(),
+            self.h_field_names.len(),
+        );
+        [
+            (
+                "Field I name",
+                i,
+                "Field I signed",
+                self.i_field_signedness.len(),
+            ),
+            (
+                "Field I name",
+                i,
+                "Field I encoding",
+                self.i_field_encoding.len(),
+            ),
+            (
+                "Field I name",
+                i,
+                "Field I predictor",
+                self.i_field_predictors.len(),
+            ),
+            (
+                "Field I name",
+                i,
+                "Field P encoding",
+                self.p_field_encoding.len(),
+            ),
+            (
+                "Field I name",
+                i,
+                "Field P predictor",
+                self.p_field_predictors.len(),
+            ),
+            (
+                "Field S name",
+                s,
+                "Field S signed",
+                self.s_field_signedness.len(),
+            ),
+            (
+                "Field S name",
+                s,
+                "Field S encoding",
+                self.s_field_encoding.len(),
+            ),
+            (
+                "Field S name",
+                s,
+                "Field S predictor",
+                self.s_field_predictors.len(),
+            ),
+            (
+                "Field G name",
+                g,
+                "Field G signed",
+                self.g_field_signedness.len(),
+            ),
+            (
+                "Field G name",
+                g,
+                "Field G encoding",
+                self.g_field_encoding.len(),
+            ),
+            (
+                "Field G name",
+                g,
+                "Field G predictor",
+                self.g_field_predictors.len(),
+            ),
+            (
+                "Field H name",
+                h,
+                "Field H signed",
+                self.h_field_signedness.len(),
+            ),
+            (
+                "Field H name",
+                h,
+                "Field H encoding",
+                self.h_field_encoding.len(),
+            ),
+            (
+                "Field H name",
+                h,
+                "Field H predictor",
+                self.h_field_predictors.len(),
+            ),
+        ]
+        .into_iter()
+        .chain((!self.p_field_names.is_empty()).then_some((
+            "Field I name",
+            i,
+            "Field P name",
+            self.p_field_names.len(),
+        )))
+        .filter(|(_, expected, _, found)| expected != found)
+        .map(|(names, expected, list, found)| (names, list, expected, found))
+        .collect()
+    }
+
+    fn apply(mut self, header_frame: Frame) -> Self {
+        match header_frame {
+            Frame::Product(product) => self.product = Some(product.into_owned()),
+            Frame::DataVersion(version) => self.data_version = Some(version.to_owned()),
+            Frame::IInterval(i_interval) => self.i_interval = Some(i_interval),
+            Frame::FieldIName(i_field_names) => {
+                self.i_field_names = i_field_names.into_iter().map(ToOwned::to_owned).collect()
+            }
+            Frame::FieldIPredictor(i_field_predictors) => {
+                self.i_field_predictors = i_field_predictors
+            }
+            Frame::FieldISignedness(i_field_signedness) => {
+                self.i_field_signedness = i_field_signedness
+            }
+            Frame::FieldIEncoding(i_field_encoding) => self.i_field_encoding = i_field_encoding,
+            Frame::PInterval(p_interval) => self.p_interval = Some(p_interval),
+            Frame::PRatio(p_ratio) => self.p_ratio = Some(p_ratio),
+            Frame::FieldPName(p_field_names) => {
+                self.p_field_names = p_field_names.into_iter().map(ToOwned::to_owned).collect()
+            }
+            Frame::FieldPPredictor(p_field_predictors) => {
+                self.p_field_predictors = p_field_predictors
+            }
+            Frame::FieldPEncoding(p_field_encoding) => self.p_field_encoding = p_field_encoding,
+            Frame::FieldSName(s_field_names) => {
+                self.s_field_names = s_field_names.into_iter().map(ToOwned::to_owned).collect()
+            }
+            Frame::FieldSPredictor(s_field_predictors) => {
+                self.s_field_predictors = s_field_predictors
+            }
+            Frame::FieldSSignedness(s_field_signedness) => {
+                self.s_field_signedness = s_field_signedness
+            }
+            Frame::FieldSEncoding(s_field_encoding) => self.s_field_encoding = s_field_encoding,
+            Frame::FieldGName(g_field_names) => {
+                self.g_field_names = g_field_names.into_iter().map(ToOwned::to_owned).collect()
+            }
+            Frame::FieldGPredictor(g_field_predictors) => {
+                self.g_field_predictors = g_field_predictors
+            }
+            Frame::FieldGSignedness(g_field_signedness) => {
+                self.g_field_signedness = g_field_signedness
+            }
+            Frame::FieldGEncoding(g_field_encoding) => self.g_field_encoding = g_field_encoding,
+            Frame::FieldHName(h_field_names) => {
+                self.h_field_names = h_field_names.into_iter().map(ToOwned::to_owned).collect()
+            }
+            Frame::FieldHPredictor(h_field_predictors) => {
+                self.h_field_predictors = h_field_predictors
+            }
+            Frame::FieldHSignedness(h_field_signedness) => {
+                self.h_field_signedness = h_field_signedness
+            }
+            Frame::FieldHEncoding(h_field_encoding) => self.h_field_encoding = h_field_encoding,
+            Frame::FirmwareType(v) => self.firmware_type = Some(v.into_owned()),
+            Frame::FirmwareRevision(v) => self.firmware_revision = Some(v.into_owned()),
+            Frame::FirmwareDate(v) => self.firmware_date = Some(v.into_owned()),
+            Frame::BoardInformation(v) => self.board_information = Some(v.into_owned()),
+            Frame::LogStart(v) => self.log_start_datetime = Some(v.into_owned()),
+            Frame::CraftName(v) => self.craft_name = Some(v.into_owned()),
+            Frame::GyroScale(gyro_scale) => self.gyro_scale = Some(gyro_scale),
+            Frame::LoopTime(loop_time) => self.loop_time = Some(loop_time),
+            Frame::UnkownHeader(name, value) => {
+                self.other_headers.insert(name.into(), value.into());
+            }
+            _ => {}
+        };
+        self
+    }
 }
 
 #[allow(unused)]
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct IPField {
     pub name: String,
     pub ix: usize,
@@ -356,34 +1088,42 @@ pub struct IPField {
 
 #[allow(unused)]
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SlowField {
     pub name: String,
-    ix: usize,
-    signed: bool,
+    pub ix: usize,
+    pub signed: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
     predictor: FieldPredictor,
 }
 
 #[allow(unused)]
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct GNSSField {
     pub name: String,
-    ix: usize,
-    signed: bool,
+    pub ix: usize,
+    pub signed: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
     predictor: FieldPredictor,
 }
 
 #[allow(unused)]
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct GNSSHomeField {
-    name: String,
-    ix: usize,
-    signed: bool,
+    pub name: String,
+    pub ix: usize,
+    pub signed: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
     predictor: FieldPredictor,
 }
 
+#[allow(unused)]
 #[derive(Debug)]
 pub enum ParseHeadersError<I> {
     HeaderBuildError(HeaderBuildError),
+    Invalid(Vec<HeaderViolation>),
     Nom(I, ErrorKind),
 }
 
@@ -403,88 +1143,105 @@ impl<I> From<nom::error::Error<I>> for ParseHeadersError<I> {
     }
 }
 
-pub fn parse_headers(input: &[u8]) -> IResult<&[u8], Header, ParseHeadersError<&[u8]>> {
-    let (input, header) = fold_many0(
-        parse_header,
-        HeaderBuilder::default,
-        |mut header, header_frame| {
-            match header_frame {
-                Frame::Product(product) => header.product = Some(product.to_owned()),
-                Frame::DataVersion(version) => header.data_version = Some(version.to_owned()),
-                Frame::IInterval(i_interval) => header.i_interval = Some(i_interval),
-                Frame::FieldIName(i_field_names) => {
-                    header.i_field_names =
-                        i_field_names.into_iter().map(ToOwned::to_owned).collect()
-                }
-                Frame::FieldIPredictor(i_field_predictors) => {
-                    header.i_field_predictors = i_field_predictors
-                }
-                Frame::FieldISignedness(i_field_signedness) => {
-                    header.i_field_signedness = i_field_signedness
-                }
-                Frame::FieldIEncoding(i_field_encoding) => {
-                    header.i_field_encoding = i_field_encoding
-                }
-                Frame::PInterval(p_interval) => header.p_interval = Some(p_interval),
-                Frame::PRatio(p_ratio) => header.p_ratio = Some(p_ratio),
-                Frame::FieldPPredictor(p_field_predictors) => {
-                    header.p_field_predictors = p_field_predictors
-                }
-                Frame::FieldPEncoding(p_field_encoding) => {
-                    header.p_field_encoding = p_field_encoding
-                }
-                Frame::FieldSName(s_field_names) => {
-                    header.s_field_names =
-                        s_field_names.into_iter().map(ToOwned::to_owned).collect()
-                }
-                Frame::FieldSPredictor(s_field_predictors) => {
-                    header.s_field_predictors = s_field_predictors
+pub fn parse_headers<'a>(
+    input: &'a [u8],
+    options: &ReaderOptions,
+) -> IResult<&'a [u8], Header, ParseHeadersError<&'a [u8]>> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("parse_headers").entered();
+    let mut builder = HeaderBuilder::with_extensions(&options.extensions);
+    let start = input;
+    let mut input = input;
+    // Offset of the last line with each header name
+    let mut lines = HashMap::new();
+    loop {
+        let offset = start.len() - input.len();
+        match parse_header(input) {
+            Ok((remaining_input, header_frame)) => {
+                let name = input[2..].split(|b| *b == b':').next().unwrap_or_default();
+                if lines.insert(name, offset).is_some() {
+                    let name = String::from_utf8_lossy(name).into_owned();
+                    options.report(offset, DiagnosticKind::DuplicateHeader { name });
                 }
-                Frame::FieldSSignedness(s_field_signedness) => {
-                    header.s_field_signedness = s_field_signedness
+                if std::str::from_utf8(&input[..input.len() - remaining_input.len()]).is_err() {
+                    let name = String::from_utf8_lossy(name).into_owned();
+                    options.report(offset, DiagnosticKind::LossyHeaderValue { name });
                 }
-                Frame::FieldSEncoding(s_field_encoding) => {
-                    header.s_field_encoding = s_field_encoding
+                builder = builder.apply(header_frame);
+                input = remaining_input;
+            }
+            Err(nom::Err::Incomplete(needed)) => return Err(nom::Err::Incomplete(needed)),
+            Err(_) if input.starts_with(b"H ") => match options.recovery.header_anomaly {
+                RecoveryAction::Abort => {
+                    return Err(nom::Err::Failure(ParseHeadersError::Nom(
+                        input,
+                        ErrorKind::Verify,
+                    )))
                 }
-                Frame::FieldGName(g_field_names) => {
-                    header.g_field_names =
-                        g_field_names.into_iter().map(ToOwned::to_owned).collect()
+                RecoveryAction::Skip => {
+                    let line_end = input
+                        .iter()
+                        .position(|b| *b == b'\n')
+                        .ok_or(nom::Err::Incomplete(nom::Needed::Unknown))?;
+                    options.report(offset, DiagnosticKind::SkippedHeaderLine);
+                    input = &input[line_end + 1..];
                 }
-                Frame::FieldGPredictor(g_field_predictors) => {
-                    header.g_field_predictors = g_field_predictors
-                }
-                Frame::FieldGSignedness(g_field_signedness) => {
-                    header.g_field_signedness = g_field_signedness
-                }
-                Frame::FieldGEncoding(g_field_encoding) => {
-                    header.g_field_encoding = g_field_encoding
-                }
-                Frame::FieldHName(h_field_names) => {
-                    header.h_field_names =
-                        h_field_names.into_iter().map(ToOwned::to_owned).collect()
-                }
-                Frame::FieldHPredictor(h_field_predictors) => {
-                    header.h_field_predictors = h_field_predictors
-                }
-                Frame::FieldHSignedness(h_field_signedness) => {
-                    header.h_field_signedness = h_field_signedness
-                }
-                Frame::FieldHEncoding(h_field_encoding) => {
-                    header.h_field_encoding = h_field_encoding
-                }
-                Frame::GyroScale(gyro_scale) => header.gyro_scale = Some(gyro_scale),
-                Frame::LoopTime(loop_time) => header.loop_time = Some(loop_time),
-                Frame::UnkownHeader(name, value) => {
-                    header.other_headers.insert(name.into(), value.into());
-                }
-                _ => {}
-            };
-            header
-        },
-    )(input)
-    .map_err(nom::Err::convert)?;
+                RecoveryAction::Resync => break,
+            },
+            Err(_) => break,
+        }
+    }
+
+    let mismatched_field_lists = builder.mismatched_field_lists();
+    for (names, list, expected, found) in mismatched_field_lists.iter().copied() {
+        let offset = lines.get(list.as_bytes()).or(lines.get(names.as_bytes()));
+        options.report(
+            offset.copied().unwrap_or_default(),
+            DiagnosticKind::FieldListLength {
+                header: list,
+                expected,
+                found,
+            },
+        );
+    }
+    // Decoding only the fields in every list would drop the others
+    if options.recovery.header_anomaly == RecoveryAction::Abort
+        && !options.validate_header
+        && !mismatched_field_lists.is_empty()
+    {
+        let violations = mismatched_field_lists
+            .into_iter()
+            .map(
+                |(_, header, expected, found)| HeaderViolation::FieldListLength {
+                    header,
+                    expected,
+                    found,
+                },
+            )
+            .collect();
+        return Err(nom::Err::Failure(ParseHeadersError::Invalid(violations)));
+    }
+
+    for (field, header, value) in builder.predictor_fallbacks() {
+        let offset = lines.get(&b"Field I predictor"[..]).copied();
+        options.report(
+            offset.unwrap_or_default(),
+            DiagnosticKind::PredictorFallback {
+                field,
+                header,
+                value,
+            },
+        );
+    }
+
+    if options.validate_header {
+        let violations = builder.violations();
+        if !violations.is_empty() {
+            return Err(nom::Err::Failure(ParseHeadersError::Invalid(violations)));
+        }
+    }
 
-    let header = header
+    let header = builder
         .try_into()
         .map_err(|err| nom::Err::Failure(ParseHeadersError::HeaderBuildError(err)))?;
     Ok((input, header))
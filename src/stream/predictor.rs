@@ -1,16 +1,18 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap, fmt, sync::Arc};
 
+use itertools::izip;
 use num_rational::Ratio;
 
-use crate::frame::{
-    data::{OwnedGFrame, OwnedHFrame, OwnedIFrame, OwnedPFrame, OwnedSFrame},
-    event, BodyFrame,
+use crate::{
+    extensions::{CustomPredictor, Extensions, PredictorContext},
+    frame::{event, BodyFrame},
 };
 
 use super::header::{Header, IPField};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub(crate) enum FieldPredictor {
+    #[default]
     None,
     Previous,
     StraightLine,
@@ -23,71 +25,178 @@ pub(crate) enum FieldPredictor {
     VBatRef,
     LastMainFrameTime,
     MinMotor,
-}
-
-impl Default for FieldPredictor {
-    fn default() -> Self {
-        FieldPredictor::None
+    /// Unknown predictor id, only usable through [`Extensions`].
+    Custom(u16),
+}
+
+/// Type predicted values are kept in. With the `narrow-values` feature they are stored in 32
+/// bits, the most any field encoding produces, which halves the memory the history goes
+/// through. Predictions are still computed in `i64` and values are widened back to what the
+/// `i64` history would hold: unsigned fields stored as read, like `time`, keep their full
+/// range, while fields a predictor adds a base to keep their sign, like `vbatLatest` below
+/// its reference.
+#[cfg(not(feature = "narrow-values"))]
+pub(crate) type Value = i64;
+#[cfg(feature = "narrow-values")]
+pub(crate) type Value = i32;
+
+#[cfg(feature = "narrow-values")]
+fn widen(value: Value, sign_extended: bool) -> i64 {
+    if sign_extended {
+        value as i64
+    } else {
+        value as u32 as i64
     }
 }
 
+/// Values of the last two frames and the one being decoded, in three slots used in turn so
+/// that finishing a frame doesn't copy any values. `previous_2_ix` and `previous_ix` point at
+/// the same slot after an I-frame, as it's the first frame of its own history.
 pub(crate) struct History {
-    history: [Vec<i64>; 2],
-    current: Vec<i64>,
-    previous_2_ix: usize,
+    history: [Vec<Value>; 3],
+    current_ix: usize,
     previous_ix: usize,
+    previous_2_ix: usize,
+    /// Whether each field is sign-extended when widened
+    #[cfg(feature = "narrow-values")]
+    sign_extended: Vec<bool>,
+    /// Last values widened to `i64`
+    #[cfg(feature = "narrow-values")]
+    wide: Vec<i64>,
 }
 
 pub(crate) struct GNSSHistory {
-    gnss_home: [i64; 2],
+    gnss_home: Vec<i64>,
     pub(crate) history: History,
 }
 
 impl GNSSHistory {
-    pub fn with_size(cap: usize) -> Self {
+    pub fn new(sign_extended: Vec<bool>, home_size: usize) -> Self {
         Self {
-            gnss_home: Default::default(),
-            history: History::with_size(cap),
+            gnss_home: vec![0; home_size],
+            history: History::new(sign_extended),
         }
     }
 }
 
 pub(crate) struct Snapshot<'a> {
-    previous_2: &'a [i64],
-    pub previous: &'a [i64],
-    pub current: &'a mut [i64],
+    previous_2: &'a [Value],
+    previous: &'a [Value],
+    current: &'a mut [Value],
+    #[cfg(feature = "narrow-values")]
+    sign_extended: &'a [bool],
+}
+
+impl Snapshot<'_> {
+    #[cfg(not(feature = "narrow-values"))]
+    fn get(&self, values: &[Value], ix: usize) -> i64 {
+        values[ix]
+    }
+
+    #[cfg(feature = "narrow-values")]
+    fn get(&self, values: &[Value], ix: usize) -> i64 {
+        widen(values[ix], self.sign_extended[ix])
+    }
+
+    pub fn previous_2(&self, ix: usize) -> i64 {
+        self.get(self.previous_2, ix)
+    }
+
+    pub fn previous(&self, ix: usize) -> i64 {
+        self.get(self.previous, ix)
+    }
+
+    pub fn current(&self, ix: usize) -> i64 {
+        self.get(self.current, ix)
+    }
+
+    /// Stores the prediction of field `ix`, wrapping around when values are narrowed.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn set(&mut self, ix: usize, value: i64) {
+        self.current[ix] = value as Value;
+    }
+
+    /// The previous two frames and the current one as `i64`.
+    #[cfg(not(feature = "narrow-values"))]
+    fn widened(&self) -> [Cow<'_, [i64]>; 3] {
+        [self.previous_2, self.previous, self.current].map(Cow::Borrowed)
+    }
+
+    #[cfg(feature = "narrow-values")]
+    fn widened(&self) -> [Cow<'_, [i64]>; 3] {
+        [self.previous_2, self.previous, &*self.current].map(|values| {
+            let values = values.iter().zip(self.sign_extended);
+            Cow::Owned(values.map(|(v, extend)| widen(*v, *extend)).collect())
+        })
+    }
 }
 
 impl History {
-    pub fn with_size(cap: usize) -> Self {
+    /// History of frames with fields widened as given, see [`Value`].
+    pub fn new(sign_extended: Vec<bool>) -> Self {
+        let cap = sign_extended.len();
         Self {
-            history: [vec![0; cap], vec![0; cap]],
-            current: vec![0; cap],
-            previous_2_ix: 0,
+            history: [vec![0; cap], vec![0; cap], vec![0; cap]],
+            current_ix: 2,
             previous_ix: 1,
+            previous_2_ix: 0,
+            #[cfg(feature = "narrow-values")]
+            sign_extended,
+            #[cfg(feature = "narrow-values")]
+            wide: Vec::with_capacity(cap),
         }
     }
 
-    pub fn values(&self) -> &[i64] {
+    /// Values of the last frame.
+    #[cfg(not(feature = "narrow-values"))]
+    pub fn values(&mut self) -> &[i64] {
         &self.history[self.previous_ix]
     }
 
-    pub fn state(&mut self) -> Snapshot {
+    #[cfg(feature = "narrow-values")]
+    pub fn values(&mut self) -> &[i64] {
+        let values = self.history[self.previous_ix]
+            .iter()
+            .zip(&self.sign_extended);
+        self.wide.clear();
+        self.wide
+            .extend(values.map(|(value, extend)| widen(*value, *extend)));
+        &self.wide
+    }
+
+    /// Previous frames and the slot of the frame being decoded. Fields of the current frame
+    /// hold stale values until they are predicted.
+    pub fn state(&mut self) -> Snapshot<'_> {
+        let current_ix = self.current_ix;
+        let (before, rest) = self.history.split_at_mut(current_ix);
+        let (current, after) = rest.split_first_mut().unwrap();
+        let slot = |ix: usize| match ix.cmp(&current_ix) {
+            std::cmp::Ordering::Less => &before[ix][..],
+            _ => &after[ix - current_ix - 1][..],
+        };
         Snapshot {
-            previous_2: &self.history[self.previous_2_ix],
-            previous: &self.history[self.previous_ix],
-            current: &mut self.current,
+            previous_2: slot(self.previous_2_ix),
+            previous: slot(self.previous_ix),
+            current,
+            #[cfg(feature = "narrow-values")]
+            sign_extended: &self.sign_extended,
         }
     }
 
+    /// Makes the decoded frame the previous one.
     pub fn advance(&mut self) {
-        std::mem::swap(&mut self.previous_ix, &mut self.previous_2_ix);
-        self.history[self.previous_ix].copy_from_slice(&self.current);
+        self.previous_2_ix = self.previous_ix;
+        self.previous_ix = self.current_ix;
+        // The decoded frame was never in the slot of the previous one, so the remaining slot
+        // is free
+        self.current_ix = 3 - self.previous_ix - self.previous_2_ix;
     }
 
+    /// Makes the decoded frame both previous ones, starting the history over.
     pub fn advance_reset(&mut self) {
-        self.history[self.previous_2_ix].copy_from_slice(&self.current);
-        self.history[self.previous_ix].copy_from_slice(&self.current);
+        self.previous_2_ix = self.current_ix;
+        self.previous_ix = self.current_ix;
+        self.current_ix = (self.current_ix + 1) % 3;
     }
 }
 
@@ -95,130 +204,373 @@ impl History {
 pub enum LogRecord<'a> {
     Main(&'a [i64]),
     GNSS(&'a [i64]),
-    Slow(Vec<i64>),
+    Slow(&'a [i64]),
     Event(event::Frame),
 }
 
+/// I-frame predictors split by kind, so that predicting a frame walks flat arrays instead of
+/// matching on the predictor of every field. Predictors reading the frame being decoded run
+/// after the constant ones, in field order.
+#[derive(Clone, Debug, Default)]
+struct IPredictors {
+    constant: Vec<AddConstantPredictor>,
+    ordered: Vec<AnyIPredictor>,
+}
+
+impl IPredictors {
+    fn new(predictors: &[AnyIPredictor]) -> Self {
+        let mut grouped = Self::default();
+        for predictor in predictors {
+            match predictor {
+                AnyIPredictor::AddConstant(p) => grouped.constant.push(*p),
+                p => grouped.ordered.push(p.clone()),
+            }
+        }
+        grouped
+    }
+
+    fn has_custom(&self) -> bool {
+        self.ordered
+            .iter()
+            .any(|p| matches!(p, AnyIPredictor::Custom(_)))
+    }
+
+    fn retain(&mut self, needed: &[bool]) {
+        self.constant.retain(|p| needed[p.field_ix]);
+        self.ordered.retain(|p| needed[p.field_ix()]);
+    }
+
+    #[inline]
+    fn predict(&self, values: &[i64], snapshot: &mut Snapshot<'_>) {
+        for p in &self.constant {
+            p.predict(values[p.field_ix], snapshot);
+        }
+        for p in &self.ordered {
+            p.predict(values[p.field_ix()], snapshot);
+        }
+    }
+}
+
+/// P-frame predictors split by kind. Only custom predictors read the frame being decoded,
+/// so they run last, in field order.
+#[derive(Clone, Debug, Default)]
+struct PPredictors {
+    none: Vec<NonePredictor>,
+    previous: Vec<PreviousPredictor>,
+    increment: Vec<IncPredictor>,
+    straight_line: Vec<StraightLinePredictor>,
+    average: Vec<AveragePredictor>,
+    custom: Vec<CustomFieldPredictor>,
+}
+
+impl PPredictors {
+    fn new(predictors: &[AnyPPredictor]) -> Self {
+        let mut grouped = Self::default();
+        for predictor in predictors {
+            match predictor {
+                AnyPPredictor::None(p) => grouped.none.push(*p),
+                AnyPPredictor::Previous(p) => grouped.previous.push(*p),
+                AnyPPredictor::Inc(p) => grouped.increment.push(*p),
+                AnyPPredictor::StraightLine(p) => grouped.straight_line.push(*p),
+                AnyPPredictor::Average(p) => grouped.average.push(*p),
+                AnyPPredictor::Custom(p) => grouped.custom.push(p.clone()),
+            }
+        }
+        grouped
+    }
+
+    fn retain(&mut self, needed: &[bool]) {
+        self.none.retain(|p| needed[p.field_ix]);
+        self.previous.retain(|p| needed[p.field_ix]);
+        self.increment.retain(|p| needed[p.field_ix]);
+        self.straight_line.retain(|p| needed[p.field_ix]);
+        self.average.retain(|p| needed[p.field_ix]);
+        self.custom.retain(|p| needed[p.field_ix]);
+    }
+
+    #[inline]
+    fn predict(&mut self, values: &[i64], snapshot: &mut Snapshot<'_>) {
+        for p in &mut self.none {
+            p.predict(values[p.field_ix], snapshot);
+        }
+        for p in &mut self.previous {
+            p.predict(values[p.field_ix], snapshot);
+        }
+        for p in &mut self.increment {
+            p.predict(values[p.field_ix], snapshot);
+        }
+        for p in &mut self.straight_line {
+            p.predict(values[p.field_ix], snapshot);
+        }
+        for p in &mut self.average {
+            p.predict(values[p.field_ix], snapshot);
+        }
+        for p in &self.custom {
+            p.apply(values[p.field_ix], snapshot);
+        }
+    }
+}
+
 pub struct LogProcessor {
+    ip_field_count: usize,
     ip_history: History,
     gnss_history: GNSSHistory,
-    i_predictors: Vec<AnyIPredictor>,
-    p_predictors: Vec<AnyPPredictor>,
+    i_predictors: IPredictors,
+    p_predictors: PPredictors,
     g_predictors: Vec<AnyGPredictor>,
 }
 
 impl LogProcessor {
     pub fn new(header: &Header) -> Self {
-        let i_predictors = header.i_field_predictors.clone();
-        let p_predictors = header.p_field_predictors.clone();
+        let ip_field_count = header.ip_fields_in_order.len();
         let g_predictors = header.g_field_predictors.clone();
 
-        assert_eq!(i_predictors.len(), p_predictors.len());
+        // Only unsigned values stored as read are zero-extended, anything a base was added to
+        // or that a signed field's deltas took below zero is negative in the `i64` history too
+        let ip_sign_extended = izip!(
+            &header.ip_fields_in_order,
+            &header.i_field_unsigned,
+            &header.i_field_predictors
+        )
+        .map(|(field, unsigned, predictor)| {
+            field.signed || !unsigned || !predictor.keeps_raw_value()
+        })
+        .collect();
+        let mut g_sign_extended = vec![true; g_predictors.len()];
+        for field in header.g_fields.values() {
+            if let Some(extend) = g_sign_extended.get_mut(field.ix) {
+                *extend = field.signed
+                    || !header.g_field_unsigned[field.ix]
+                    || !matches!(g_predictors[field.ix], AnyGPredictor::None(_));
+            }
+        }
 
         Self {
-            ip_history: History::with_size(i_predictors.len()),
-            gnss_history: GNSSHistory::with_size(g_predictors.len()),
-            i_predictors,
-            p_predictors,
+            ip_field_count,
+            ip_history: History::new(ip_sign_extended),
+            gnss_history: GNSSHistory::new(g_sign_extended, header.h_fields.len()),
+            i_predictors: IPredictors::new(&header.i_field_predictors),
+            p_predictors: PPredictors::new(&header.p_field_predictors),
             g_predictors,
         }
     }
 
-    pub(crate) fn process_frame(&mut self, frame: BodyFrame) -> Option<LogRecord> {
+    /// Stops predicting main frame fields that aren't `needed`, except for the ones needed
+    /// fields are predicted from. Skipped fields hold meaningless values.
+    pub(crate) fn retain_fields(&mut self, needed: &[bool]) {
+        let mut needed = needed.to_vec();
+        // Custom predictors may use any field decoded before theirs
+        if self.i_predictors.has_custom() || !self.p_predictors.custom.is_empty() {
+            return;
+        }
+        for predictor in &self.i_predictors.ordered {
+            if let AnyIPredictor::AddField(p) = predictor {
+                if needed[p.field_ix] {
+                    needed[p.base_field_ix] = true;
+                }
+            }
+        }
+
+        self.i_predictors.retain(&needed);
+        self.p_predictors.retain(&needed);
+    }
+
+    /// Values of the last GNSS home frame, in H field order.
+    pub(crate) fn gnss_home(&self) -> &[i64] {
+        &self.gnss_history.gnss_home
+    }
+
+    /// Number of values [`process_frame`](Self::process_frame) needs for `frame`, `None` if it
+    /// takes any number.
+    pub(crate) fn field_count(&self, frame: &BodyFrame) -> Option<usize> {
         match frame {
-            BodyFrame::IFrame(OwnedIFrame { buf }) => {
-                assert_eq!(buf.len(), self.i_predictors.len());
+            BodyFrame::IFrame | BodyFrame::PFrame => Some(self.ip_field_count),
+            BodyFrame::GFrame => Some(self.g_predictors.len()),
+            BodyFrame::HFrame | BodyFrame::SFrame | BodyFrame::Event(_) => None,
+        }
+    }
+
+    /// Predicts the values of `frame` from its decoded `values`, which must have
+    /// [`field_count`](Self::field_count) entries.
+    pub(crate) fn process_frame<'a>(
+        &'a mut self,
+        frame: BodyFrame,
+        values: &'a [i64],
+    ) -> Option<LogRecord<'a>> {
+        match frame {
+            BodyFrame::IFrame => {
                 let mut snapshot = self.ip_history.state();
-                for (in_value, predictor) in buf.into_iter().zip(self.i_predictors.iter()) {
-                    predictor.predict(in_value, &mut snapshot);
-                }
+                self.i_predictors.predict(values, &mut snapshot);
                 self.ip_history.advance_reset();
                 Some(LogRecord::Main(self.ip_history.values()))
             }
-            BodyFrame::PFrame(OwnedPFrame { buf }) => {
-                assert_eq!(buf.len(), self.p_predictors.len());
+            BodyFrame::PFrame => {
                 let mut snapshot = self.ip_history.state();
-                for (in_value, predictor) in buf.into_iter().zip(self.p_predictors.iter_mut()) {
-                    predictor.predict(in_value, &mut snapshot);
-                }
+                self.p_predictors.predict(values, &mut snapshot);
                 self.ip_history.advance();
                 Some(LogRecord::Main(self.ip_history.values()))
             }
-            BodyFrame::HFrame(OwnedHFrame { buf }) => {
-                if buf.len() == 2 {
-                    self.gnss_history.gnss_home[0] = buf[0];
-                    self.gnss_history.gnss_home[1] = buf[1];
-                } else if buf.is_empty() {
-                    // TODO: log
-                }
+            BodyFrame::HFrame => {
+                let home = &mut self.gnss_history.gnss_home;
+                let len = home.len().min(values.len());
+                home[..len].copy_from_slice(&values[..len]);
 
                 None
             }
-            BodyFrame::GFrame(OwnedGFrame { buf }) => {
-                assert_eq!(buf.len(), self.g_predictors.len());
+            BodyFrame::GFrame => {
                 let mut snapshot = self.gnss_history.history.state();
-                for (in_value, predictor) in buf.into_iter().zip(self.g_predictors.iter_mut()) {
+                for (in_value, predictor) in
+                    values.iter().copied().zip(self.g_predictors.iter_mut())
+                {
                     predictor.predict(
                         in_value,
                         &mut snapshot,
                         &self.ip_history.state(),
-                        self.gnss_history.gnss_home,
+                        &self.gnss_history.gnss_home,
                     );
                 }
                 self.gnss_history.history.advance();
 
                 Some(LogRecord::GNSS(self.gnss_history.history.values()))
             }
-            BodyFrame::SFrame(OwnedSFrame { buf }) => Some(LogRecord::Slow(buf)),
+            BodyFrame::SFrame => Some(LogRecord::Slow(values)),
             BodyFrame::Event(frame) => Some(LogRecord::Event(frame)),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum AnyIPredictor {
     AddConstant(AddConstantPredictor),
     AddField(AddFieldPredictor),
+    Custom(CustomFieldPredictor),
+}
+
+/// Why a predictor couldn't be set up from the header.
+#[derive(Debug)]
+pub(crate) enum PredictorError {
+    Unsupported,
+    MissingHeader(&'static str),
+}
+
+/// Header settings and main frame fields predictors depend on, looked up once per header
+/// instead of for every field using them.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct PredictorInputs {
+    minthrottle: Option<i64>,
+    /// First value of `motorOutput`
+    min_motor: Option<i64>,
+    vbatref: Option<i64>,
+    motor0_ix: Option<usize>,
+    time_ix: Option<usize>,
+}
+
+impl PredictorInputs {
+    pub fn new(settings: &HashMap<String, String>, ip_fields: &HashMap<String, IPField>) -> Self {
+        let setting = |name: &str| {
+            settings
+                .get(name)
+                .and_then(|v| v.split(',').next())
+                .and_then(|v| v.trim().parse().ok())
+        };
+        Self {
+            minthrottle: setting("minthrottle"),
+            min_motor: setting("motorOutput"),
+            vbatref: setting("vbatref"),
+            motor0_ix: ip_fields.get("motor[0]").map(|f| f.ix),
+            time_ix: ip_fields.get("time").map(|f| f.ix),
+        }
+    }
+}
+
+/// Betaflight's and INAV's default `minthrottle`.
+const DEFAULT_MINTHROTTLE: i64 = 1150;
+
+impl PredictorInputs {
+    /// Constant added by predictors based on a header setting. When the setting is missing or
+    /// can't be parsed, falls back to the firmware default. `vbatref` has no default, it's the
+    /// voltage measured when logging started, so without it no reference is applied and
+    /// `vbatLatest` is decoded as its raw difference from that unknown voltage.
+    fn setting_base(&self, predictor: FieldPredictor) -> Option<i64> {
+        match predictor {
+            FieldPredictor::MinThrottle => Some(self.minthrottle.unwrap_or(DEFAULT_MINTHROTTLE)),
+            // Minimum motor output is minthrottle with analog motor protocols
+            FieldPredictor::MinMotor => Some(
+                self.min_motor
+                    .or(self.minthrottle)
+                    .unwrap_or(DEFAULT_MINTHROTTLE),
+            ),
+            FieldPredictor::VBatRef => Some(self.vbatref.unwrap_or(0)),
+            _ => None,
+        }
+    }
+
+    /// Header setting `predictor` is based on, with the value used instead, when it's missing
+    /// or can't be parsed.
+    pub fn fallback(&self, predictor: FieldPredictor) -> Option<(&'static str, i64)> {
+        let header = match predictor {
+            FieldPredictor::MinThrottle if self.minthrottle.is_none() => "minthrottle",
+            FieldPredictor::MinMotor if self.min_motor.is_none() => "motorOutput",
+            FieldPredictor::VBatRef if self.vbatref.is_none() => "vbatref",
+            _ => return None,
+        };
+        Some((header, self.setting_base(predictor)?))
+    }
+}
+
+fn input<T>(value: Option<T>, name: &'static str) -> Result<T, PredictorError> {
+    value.ok_or(PredictorError::MissingHeader(name))
 }
 
 impl AnyIPredictor {
     pub fn new(
         predictor: FieldPredictor,
-        settings: &HashMap<String, String>,
-        ip_fields: &HashMap<String, IPField>,
+        inputs: &PredictorInputs,
+        extensions: &Extensions,
         field_ix: usize,
-    ) -> Self {
-        match predictor {
-            FieldPredictor::None => {
-                AnyIPredictor::AddConstant(AddConstantPredictor { base: 0, field_ix })
+    ) -> Result<Self, PredictorError> {
+        let constant = |base| AnyIPredictor::AddConstant(AddConstantPredictor { base, field_ix });
+        Ok(match predictor {
+            FieldPredictor::None => constant(0),
+            FieldPredictor::Around1500 => constant(1500),
+            FieldPredictor::MinThrottle | FieldPredictor::MinMotor | FieldPredictor::VBatRef => {
+                constant(inputs.setting_base(predictor).unwrap_or_default())
             }
-            FieldPredictor::Around1500 => AnyIPredictor::AddConstant(AddConstantPredictor {
-                base: 1500,
-                field_ix,
-            }),
-            FieldPredictor::MinThrottle => AnyIPredictor::AddConstant(AddConstantPredictor {
-                base: settings["minthrottle"].parse().unwrap(),
-                field_ix,
-            }),
             FieldPredictor::Motor0 => AnyIPredictor::AddField(AddFieldPredictor {
-                base_field_ix: ip_fields["motor[0]"].ix,
-                field_ix,
-            }),
-            FieldPredictor::MinMotor => AnyIPredictor::AddConstant(AddConstantPredictor {
-                base: settings["motorOutput"]
-                    .split(',')
-                    .next()
-                    .unwrap()
-                    .parse()
-                    .unwrap(),
+                base_field_ix: input(inputs.motor0_ix, "motor[0]")?,
                 field_ix,
             }),
-            FieldPredictor::VBatRef => AnyIPredictor::AddConstant(AddConstantPredictor {
-                base: settings["vbatref"].parse().unwrap(),
-                field_ix,
-            }),
-            //motorOutput
-            p => unimplemented!("{:?}", p),
+            FieldPredictor::Custom(id) => {
+                AnyIPredictor::Custom(CustomFieldPredictor::new(id, extensions, field_ix)?)
+            }
+            _ => return Err(PredictorError::Unsupported),
+        })
+    }
+}
+
+impl AnyIPredictor {
+    pub fn field_ix(&self) -> usize {
+        match self {
+            AnyIPredictor::AddConstant(p) => p.field_ix,
+            AnyIPredictor::AddField(p) => p.field_ix,
+            AnyIPredictor::Custom(p) => p.field_ix,
+        }
+    }
+}
+
+impl AnyIPredictor {
+    /// Whether the decoded value is the raw one, without any base added.
+    pub fn keeps_raw_value(&self) -> bool {
+        matches!(self, AnyIPredictor::AddConstant(p) if p.base == 0)
+    }
+
+    /// Raw value that [`predict`](IPredictor::predict) turns into `value`, given the decoded
+    /// values of the whole frame. Custom predictors can't be inverted.
+    pub fn unpredict(&self, value: i64, values: &[i64]) -> Option<i64> {
+        match self {
+            AnyIPredictor::AddConstant(p) => Some(value - p.base),
+            AnyIPredictor::AddField(p) => Some(value - values[p.base_field_ix]),
+            AnyIPredictor::Custom(_) => None,
         }
     }
 }
@@ -228,11 +580,12 @@ impl IPredictor for AnyIPredictor {
         match self {
             AnyIPredictor::AddConstant(p) => p.predict(value, snapshot),
             AnyIPredictor::AddField(p) => p.predict(value, snapshot),
+            AnyIPredictor::Custom(p) => p.apply(value, snapshot),
         }
     }
 }
 
-pub(crate) trait IPredictor: Copy + Clone {
+pub(crate) trait IPredictor: Clone {
     fn predict(&self, value: i64, snapshot: &mut Snapshot<'_>);
 }
 
@@ -244,34 +597,40 @@ pub(crate) struct AddConstantPredictor {
 
 impl IPredictor for AddConstantPredictor {
     fn predict(&self, value: i64, snapshot: &mut Snapshot<'_>) {
-        snapshot.current[self.field_ix] = (self.base + value) as i64;
+        snapshot.set(self.field_ix, self.base + value);
     }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct AddFieldPredictor {
     pub base_field_ix: usize,
-    field_ix: usize,
+    pub field_ix: usize,
 }
 
 impl IPredictor for AddFieldPredictor {
     fn predict(&self, value: i64, snapshot: &mut Snapshot<'_>) {
-        snapshot.current[self.field_ix] = snapshot.current[self.base_field_ix] + value;
+        snapshot.set(self.field_ix, snapshot.current(self.base_field_ix) + value);
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum AnyPPredictor {
     None(NonePredictor),
     Previous(PreviousPredictor),
     Inc(IncPredictor),
     StraightLine(StraightLinePredictor),
     Average(AveragePredictor),
+    Custom(CustomFieldPredictor),
 }
 
 impl AnyPPredictor {
-    pub fn new(predictor: FieldPredictor, p_interval: Ratio<u16>, field_ix: usize) -> Self {
-        match predictor {
+    pub fn new(
+        predictor: FieldPredictor,
+        p_interval: Ratio<u16>,
+        extensions: &Extensions,
+        field_ix: usize,
+    ) -> Result<Self, PredictorError> {
+        Ok(match predictor {
             FieldPredictor::None => AnyPPredictor::None(NonePredictor { field_ix }),
             FieldPredictor::Previous => AnyPPredictor::Previous(PreviousPredictor { field_ix }),
             FieldPredictor::Increment => {
@@ -281,8 +640,11 @@ impl AnyPPredictor {
                 AnyPPredictor::StraightLine(StraightLinePredictor { field_ix })
             }
             FieldPredictor::Average2 => AnyPPredictor::Average(AveragePredictor { field_ix }),
-            _ => unimplemented!("Predictor {:?}", predictor),
-        }
+            FieldPredictor::Custom(id) => {
+                AnyPPredictor::Custom(CustomFieldPredictor::new(id, extensions, field_ix)?)
+            }
+            _ => return Err(PredictorError::Unsupported),
+        })
     }
 
     pub fn none(field_ix: usize) -> Self {
@@ -298,11 +660,12 @@ impl PPredictor for AnyPPredictor {
             AnyPPredictor::Inc(p) => p.predict(value, snapshot),
             AnyPPredictor::StraightLine(p) => p.predict(value, snapshot),
             AnyPPredictor::Average(p) => p.predict(value, snapshot),
+            AnyPPredictor::Custom(p) => p.apply(value, snapshot),
         }
     }
 }
 
-pub(crate) trait PPredictor: Copy + Clone {
+pub(crate) trait PPredictor: Clone {
     fn predict(&mut self, value: i64, snapshot: &mut Snapshot<'_>);
 }
 
@@ -313,7 +676,7 @@ pub(crate) struct NonePredictor {
 
 impl PPredictor for NonePredictor {
     fn predict(&mut self, value: i64, snapshot: &mut Snapshot<'_>) {
-        snapshot.current[self.field_ix] = value;
+        snapshot.set(self.field_ix, value);
     }
 }
 
@@ -324,7 +687,7 @@ pub(crate) struct PreviousPredictor {
 
 impl PPredictor for PreviousPredictor {
     fn predict(&mut self, value: i64, snapshot: &mut Snapshot<'_>) {
-        snapshot.current[self.field_ix] = snapshot.previous[self.field_ix] + value;
+        snapshot.set(self.field_ix, snapshot.previous(self.field_ix) + value);
     }
 }
 
@@ -352,15 +715,15 @@ impl IncPredictor {
 
 impl PPredictor for IncPredictor {
     fn predict(&mut self, _: i64, snapshot: &mut Snapshot<'_>) {
-        if snapshot.current[self.field_ix] != self.expected_value {
-            self.base = snapshot.current[self.field_ix];
+        if snapshot.previous(self.field_ix) != self.expected_value {
+            self.base = snapshot.previous(self.field_ix);
             self.running_sum = Ratio::new(0, *self.increment.denom());
         }
 
         self.running_sum += self.increment;
 
         let current_value = self.base + (self.running_sum.to_integer() as i64);
-        snapshot.current[self.field_ix] = current_value;
+        snapshot.set(self.field_ix, current_value);
         self.expected_value = current_value;
     }
 }
@@ -373,9 +736,9 @@ pub(crate) struct StraightLinePredictor {
 impl PPredictor for StraightLinePredictor {
     fn predict(&mut self, value: i64, snapshot: &mut Snapshot<'_>) {
         // without overflow
-        let next = snapshot.previous[self.field_ix] - snapshot.previous_2[self.field_ix]
-            + snapshot.previous[self.field_ix];
-        snapshot.current[self.field_ix] = next + value;
+        let next = snapshot.previous(self.field_ix) - snapshot.previous_2(self.field_ix)
+            + snapshot.previous(self.field_ix);
+        snapshot.set(self.field_ix, next + value);
     }
 }
 
@@ -386,18 +749,19 @@ pub(crate) struct AveragePredictor {
 
 impl PPredictor for AveragePredictor {
     fn predict(&mut self, value: i64, snapshot: &mut Snapshot<'_>) {
-        let p2 = snapshot.previous_2[self.field_ix];
-        let p1 = snapshot.previous[self.field_ix];
+        let p2 = snapshot.previous_2(self.field_ix);
+        let p1 = snapshot.previous(self.field_ix);
         let avg = (p1 + p2) / 2;
-        snapshot.current[self.field_ix] = avg + value;
+        snapshot.set(self.field_ix, avg + value);
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum AnyGPredictor {
     None(NonePredictor),
     HomeCoordinates(HomeCoordinatesPredictor),
     LastMainFrameTime(LastMainFrameTimePredictor),
+    Custom(CustomFieldPredictor),
 }
 
 impl AnyGPredictor {
@@ -405,9 +769,10 @@ impl AnyGPredictor {
         predictor: FieldPredictor,
         field_ix: usize,
         index: usize,
-        ip_fields: &HashMap<String, IPField>,
-    ) -> Self {
-        match predictor {
+        inputs: &PredictorInputs,
+        extensions: &Extensions,
+    ) -> Result<Self, PredictorError> {
+        Ok(match predictor {
             FieldPredictor::None => AnyGPredictor::None(NonePredictor { field_ix }),
             FieldPredictor::HomeCoordinates => {
                 AnyGPredictor::HomeCoordinates(HomeCoordinatesPredictor {
@@ -418,11 +783,14 @@ impl AnyGPredictor {
             FieldPredictor::LastMainFrameTime => {
                 AnyGPredictor::LastMainFrameTime(LastMainFrameTimePredictor {
                     field_ix,
-                    time_ix: ip_fields["time"].ix,
+                    time_ix: input(inputs.time_ix, "time")?,
                 })
             }
-            _ => unimplemented!("Predictor {:?}", predictor),
-        }
+            FieldPredictor::Custom(id) => {
+                AnyGPredictor::Custom(CustomFieldPredictor::new(id, extensions, field_ix)?)
+            }
+            _ => return Err(PredictorError::Unsupported),
+        })
     }
 }
 
@@ -432,7 +800,7 @@ impl GPredictor for AnyGPredictor {
         value: i64,
         snapshot: &mut Snapshot<'_>,
         ip_snapshot: &Snapshot<'_>,
-        gnss_home: [i64; 2],
+        gnss_home: &[i64],
     ) {
         match self {
             AnyGPredictor::None(p) => p.predict(value, snapshot),
@@ -440,17 +808,18 @@ impl GPredictor for AnyGPredictor {
             AnyGPredictor::LastMainFrameTime(p) => {
                 p.predict(value, snapshot, ip_snapshot, gnss_home)
             }
+            AnyGPredictor::Custom(p) => p.apply(value, snapshot),
         }
     }
 }
 
-pub(crate) trait GPredictor: Copy + Clone {
+pub(crate) trait GPredictor: Clone {
     fn predict(
         &mut self,
         value: i64,
         snapshot: &mut Snapshot<'_>,
         ip_snapshot: &Snapshot<'_>,
-        gnss_home: [i64; 2],
+        gnss_home: &[i64],
     );
 }
 
@@ -466,9 +835,10 @@ impl GPredictor for HomeCoordinatesPredictor {
         value: i64,
         snapshot: &mut Snapshot<'_>,
         _ip_snapshot: &Snapshot<'_>,
-        gnss_home: [i64; 2],
+        gnss_home: &[i64],
     ) {
-        snapshot.current[self.field_ix] = gnss_home[self.gnss_home_ix] + value;
+        let home = gnss_home.get(self.gnss_home_ix).copied().unwrap_or(0);
+        snapshot.set(self.field_ix, home + value);
     }
 }
 
@@ -484,8 +854,47 @@ impl GPredictor for LastMainFrameTimePredictor {
         value: i64,
         snapshot: &mut Snapshot<'_>,
         ip_snapshot: &Snapshot<'_>,
-        _gnss_home: [i64; 2],
+        _gnss_home: &[i64],
     ) {
-        snapshot.current[self.field_ix] = ip_snapshot.current[self.time_ix] + value;
+        snapshot.set(self.field_ix, ip_snapshot.previous(self.time_ix) + value);
+    }
+}
+
+/// Predictor registered through [`Extensions`], usable for any frame type.
+#[derive(Clone)]
+pub(crate) struct CustomFieldPredictor {
+    field_ix: usize,
+    predictor: Arc<dyn CustomPredictor>,
+}
+
+impl CustomFieldPredictor {
+    fn new(id: u16, extensions: &Extensions, field_ix: usize) -> Result<Self, PredictorError> {
+        let predictor = extensions
+            .predictor(id)
+            .ok_or(PredictorError::Unsupported)?;
+        Ok(Self {
+            field_ix,
+            predictor,
+        })
+    }
+
+    fn apply(&self, value: i64, snapshot: &mut Snapshot<'_>) {
+        let [previous_2, previous, current] = snapshot.widened();
+        let context = PredictorContext {
+            field_ix: self.field_ix,
+            previous_2: &previous_2,
+            previous: &previous,
+            current: &current,
+        };
+        let value = self.predictor.predict(value, &context);
+        snapshot.set(self.field_ix, value);
+    }
+}
+
+impl fmt::Debug for CustomFieldPredictor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomFieldPredictor")
+            .field("field_ix", &self.field_ix)
+            .finish_non_exhaustive()
     }
 }
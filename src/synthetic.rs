@@ -0,0 +1,214 @@
+//! Deterministic synthetic logs for benchmarks, so decoding speed can be measured for field
+//! counts and encodings the test logs don't cover.
+//!
+//! ```
+//! use fc_blackbox::{synthetic::SyntheticLog, BlackboxReader};
+//!
+//! let log = SyntheticLog { frames: 100, ..Default::default() }.generate();
+//! let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+//! while let Some(_record) = reader.next() {}
+//! assert_eq!(reader.stats().main_frames, 100);
+//! ```
+
+use std::fmt::Write;
+
+use crate::frame::{encode::encode_frame, FieldEncoding};
+
+/// Encoding of the signal fields in P-frames. I-frames always use signed variable bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyntheticEncoding {
+    SignedVB,
+    Tag8_8SVB,
+    Tag2_3S32,
+    Tag8_4S16,
+}
+
+impl SyntheticEncoding {
+    fn id(self) -> u8 {
+        match self {
+            SyntheticEncoding::SignedVB => 0,
+            SyntheticEncoding::Tag8_8SVB => 6,
+            SyntheticEncoding::Tag2_3S32 => 7,
+            SyntheticEncoding::Tag8_4S16 => 8,
+        }
+    }
+
+    /// Most fields a single encoded group covers.
+    fn group_size(self) -> usize {
+        match self {
+            SyntheticEncoding::SignedVB => 1,
+            SyntheticEncoding::Tag8_8SVB => 8,
+            SyntheticEncoding::Tag2_3S32 => 3,
+            SyntheticEncoding::Tag8_4S16 => 4,
+        }
+    }
+
+    /// Encoding of the group of fields starting `left` fields before the end.
+    fn group(self, left: usize) -> FieldEncoding {
+        match self {
+            SyntheticEncoding::SignedVB => FieldEncoding::SignedVB,
+            SyntheticEncoding::Tag8_8SVB => FieldEncoding::Tag8_8SVB(left.min(8)),
+            SyntheticEncoding::Tag2_3S32 => FieldEncoding::Tag2_3S32(3),
+            SyntheticEncoding::Tag8_4S16 => FieldEncoding::Tag8_4S16(4),
+        }
+    }
+}
+
+/// Betaflight-like log with `loopIteration`, `time` and `fields` signal fields following a
+/// random walk, logged every iteration.
+#[derive(Clone, Debug)]
+pub struct SyntheticLog {
+    /// Number of signal fields, rounded up to whole groups for encodings that always write
+    /// 3 or 4 values.
+    pub fields: usize,
+    pub encoding: SyntheticEncoding,
+    /// Number of main frames.
+    pub frames: usize,
+    /// Iterations between I-frames.
+    pub i_interval: u16,
+    /// Share of frames, from 0 to 1, with one of their bytes overwritten.
+    pub corruption_rate: f64,
+    /// Logs generated with the same seed and settings are identical.
+    pub seed: u64,
+}
+
+impl Default for SyntheticLog {
+    fn default() -> Self {
+        Self {
+            fields: 24,
+            encoding: SyntheticEncoding::Tag8_8SVB,
+            frames: 10_000,
+            i_interval: 32,
+            corruption_rate: 0.0,
+            seed: 1,
+        }
+    }
+}
+
+/// xorshift64*, good enough for test data and the same on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `-max..=max`.
+    fn step(&mut self, max: i64) -> i64 {
+        (self.next() % (2 * max as u64 + 1)) as i64 - max
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+impl SyntheticLog {
+    fn field_count(&self) -> usize {
+        match self.encoding {
+            SyntheticEncoding::SignedVB | SyntheticEncoding::Tag8_8SVB => self.fields,
+            fixed => self.fields.div_ceil(fixed.group_size()) * fixed.group_size(),
+        }
+    }
+
+    /// Header lines, in the format the firmware writes them.
+    pub fn header(&self) -> String {
+        let fields = self.field_count();
+        let list = |first: [&str; 2], rest: &str| {
+            first
+                .into_iter()
+                .chain(std::iter::repeat_n(rest, fields))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let names = ["loopIteration".to_owned(), "time".to_owned()]
+            .into_iter()
+            .chain((0..fields).map(|i| format!("signal[{i}]")))
+            .collect::<Vec<_>>()
+            .join(",");
+        let p_encoding = self.encoding.id().to_string();
+
+        let mut header = String::new();
+        for (name, value) in [
+            (
+                "Product",
+                "Blackbox flight data recorder by Nicholas Sherlock".to_owned(),
+            ),
+            ("Data version", "2".to_owned()),
+            ("I interval", self.i_interval.max(1).to_string()),
+            ("P interval", "1/1".to_owned()),
+            ("Field I name", names),
+            ("Field I signed", list(["0", "0"], "1")),
+            ("Field I predictor", list(["0", "0"], "0")),
+            ("Field I encoding", list(["1", "1"], "0")),
+            ("Field P predictor", list(["6", "2"], "1")),
+            ("Field P encoding", list(["9", "0"], &p_encoding)),
+            ("gyro_scale", "0x3f800000".to_owned()),
+            ("looptime", "125".to_owned()),
+        ] {
+            writeln!(header, "H {name}:{value}").unwrap();
+        }
+        header
+    }
+
+    /// Encodes the whole log, ending with an `End of log` event.
+    pub fn generate(&self) -> Vec<u8> {
+        let fields = self.field_count();
+        let i_encodings = [FieldEncoding::UnsignedVB, FieldEncoding::UnsignedVB]
+            .into_iter()
+            .chain(std::iter::repeat_n(FieldEncoding::SignedVB, fields))
+            .collect::<Vec<_>>();
+        // loopIteration isn't written in P-frames
+        let p_encodings = std::iter::once(FieldEncoding::SignedVB)
+            .chain(
+                (0..fields)
+                    .step_by(self.encoding.group_size())
+                    .map(|start| self.encoding.group(fields - start)),
+            )
+            .collect::<Vec<_>>();
+
+        let mut rng = Rng::new(self.seed);
+        let mut out = self.header().into_bytes();
+        let mut frame = Vec::new();
+        let (mut previous, mut previous_2) = (vec![0i64; fields + 2], vec![0i64; fields + 2]);
+        let mut current = vec![0i64; fields + 2];
+        let mut residuals = Vec::with_capacity(fields + 1);
+        let i_interval = u64::from(self.i_interval.max(1));
+        for iteration in 0..self.frames as u64 {
+            current[0] = iteration as i64;
+            current[1] = previous[1] + 125 + rng.step(2);
+            for value in &mut current[2..] {
+                *value = (*value + rng.step(40)).clamp(-2000, 2000);
+            }
+
+            frame.clear();
+            if iteration % i_interval == 0 {
+                encode_frame(b'I', &i_encodings, &current, &mut frame).unwrap();
+                previous_2.copy_from_slice(&current);
+            } else {
+                residuals.clear();
+                // loopIteration is predicted exactly, time along a straight line
+                residuals.push(current[1] - (2 * previous[1] - previous_2[1]));
+                residuals.extend(current[2..].iter().zip(&previous[2..]).map(|(c, p)| c - p));
+                encode_frame(b'P', &p_encodings, &residuals, &mut frame).unwrap();
+                previous_2.copy_from_slice(&previous);
+            }
+            previous.copy_from_slice(&current);
+
+            if rng.chance(self.corruption_rate) {
+                let ix = rng.next() as usize % frame.len();
+                frame[ix] = rng.next() as u8;
+            }
+            out.extend_from_slice(&frame);
+        }
+        out.extend_from_slice(b"E\xffEnd of log\0");
+        out
+    }
+}
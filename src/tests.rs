@@ -1,10 +1,26 @@
 use serde_big_array::BigArray;
-use std::{fs::File, io::Read, path::Path};
+use std::{
+    fs::File,
+    io::Read,
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
 use insta::{assert_yaml_snapshot, glob};
 use serde::{Deserialize, Serialize};
 
-use crate::{BlackboxReader, BlackboxReaderError, MultiSegmentBlackboxReader};
+use crate::frame::{data::parse_frame_payload, event, Field, FieldEncoding};
+use crate::units::{AngularUnit, Unit, Units};
+use crate::{
+    anonymize, transcode, AnonymizeOptions, BlackboxReader, BlackboxReaderError, BlackboxRecord,
+    CarveOptions, Columns, CurrentSensor, DebugMode, DecodeError, Diagnostic, DiagnosticKind,
+    DisarmReason, Extensions, FailsafePhase, FieldCountAction, FieldView, FirmwareKind,
+    FirmwareVersion, FlightModes, FrameCounts, FrameLimits, GnssAlignment, GnssPrivacy, Header,
+    HeaderValueError, HeaderViolation, MainFrameLayout, MergedReader, MotorProtocol,
+    MultiSegmentBlackboxReader, OutputLayout, PredictorContext, Quirks, ReaderOptions, ReaderStats,
+    RecoveryAction, RecoveryPolicy, ResyncStrategy, RollPitchYaw, SegmentTiming, SerialArtifacts,
+    SessionReader, Severity, StateFlags, VBatCellVoltage, PID, XOFF, XON,
+};
 
 #[test]
 fn log_stats() {
@@ -13,6 +29,4097 @@ fn log_stats() {
     });
 }
 
+#[test]
+fn field_view_resolves_names_through_header() {
+    with_multilog("src/test-data/btfl_001.bbl", |mut r| {
+        let mut reader = r.next().unwrap().unwrap();
+        let gyro_adc0_field_ix = reader.header.ip_fields["gyroADC[0]"].ix;
+        let (mut main, mut slow) = (0, 0);
+
+        while let Some(record) = reader.next() {
+            match record {
+                BlackboxRecord::Main(values) => {
+                    assert_eq!(values.value("gyroADC[0]"), Some(values[gyro_adc0_field_ix]));
+                    assert_eq!(values.value("no such field"), None);
+                    assert_eq!(values.names().count(), values.len());
+                    main += 1;
+                }
+                BlackboxRecord::Slow(values) => {
+                    assert!(values.value("flightModeFlags").is_some());
+                    assert_eq!(
+                        values.iter().next().map(|(name, _)| name),
+                        Some("flightModeFlags")
+                    );
+                    slow += 1;
+                }
+                _ => {}
+            }
+        }
+
+        assert!(main > 0);
+        assert!(slow > 0);
+    });
+}
+
+#[test]
+fn main_frame_layout_decodes_well_known_fields() {
+    with_multilog("src/test-data/btfl_001.bbl", |mut r| {
+        let mut reader = r.next().unwrap().unwrap();
+        let layout = MainFrameLayout::new(&reader.header);
+
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(values) = record {
+                let frame = values.main_frame(&layout).unwrap();
+                assert_eq!(Some(frame.time_us as i64), values.value("time"));
+                assert_eq!(
+                    Some(frame.rc_command[3] as i64),
+                    values.value("rcCommand[3]")
+                );
+                assert_eq!(Some(frame.gyro[0] as i64), values.value("gyroADC[0]"));
+                assert_eq!(frame.motors.len(), 4);
+                assert!(frame.debug.is_empty());
+                // Fields missing from the values decode as 0 like the fields that aren't logged
+                assert_eq!(layout.decode(&values.values()[..2]).motors, [0; 4]);
+                // The logged time is 32-bit, negative values are the upper half of its range
+                assert_eq!(layout.decode(&[0, -1]).time_us, u32::MAX as u64);
+                break;
+            }
+        }
+    });
+}
+
+#[test]
+fn seeking_resumes_from_keyframe_with_identical_values() {
+    with_multilog("src/test-data/btfl_002.bbl", |mut r| {
+        let mut reader = r.next().unwrap().unwrap();
+
+        let mut sequential = Vec::new();
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(values) = record {
+                sequential.push(values.to_vec());
+            }
+        }
+
+        let keyframes = reader.build_index().keyframes().to_vec();
+        assert!(keyframes.len() > 2);
+        let target = keyframes[keyframes.len() / 2];
+
+        let keyframe = reader.seek_to_time(target.time + 1).unwrap();
+        assert_eq!(keyframe, target);
+
+        let time_ix = reader.header.ip_fields["time"].ix;
+        let start = sequential
+            .iter()
+            .position(|values| values[time_ix] == target.time)
+            .unwrap();
+
+        let mut seeked = Vec::new();
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(values) = record {
+                seeked.push(values.to_vec());
+                if seeked.len() == 100 {
+                    break;
+                }
+            }
+        }
+        assert_eq!(seeked[..], sequential[start..start + 100]);
+
+        let keyframe = reader.seek_to_iteration(target.loop_iteration).unwrap();
+        assert_eq!(keyframe, target);
+        assert_eq!(reader.seek_to_time(i64::MIN), keyframes.first().copied());
+    });
+}
+
+#[test]
+fn index_sidecar_round_trip() {
+    let first_log = |filename: &str| {
+        let mut buf = Vec::new();
+        File::open(filename).unwrap().read_to_end(&mut buf).unwrap();
+        buf
+    };
+    let (log, other_log) = (
+        first_log("src/test-data/btfl_002.bbl"),
+        first_log("src/test-data/LOG00037.BFL"),
+    );
+    let open = |buf| {
+        MultiSegmentBlackboxReader::from_bytes(buf)
+            .next()
+            .unwrap()
+            .unwrap()
+    };
+    let path = std::env::temp_dir().join(format!("fc-blackbox-{}.idx", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let built = open(&log).load_or_build_index(&path).unwrap().clone();
+    assert!(built.keyframes().len() > 2);
+    let loaded = crate::Index::load(&path).unwrap();
+    assert_eq!(loaded.keyframes(), built.keyframes());
+
+    let mut reader = open(&log);
+    let target = built.keyframes()[built.keyframes().len() / 2];
+    reader.set_index(loaded.clone()).unwrap();
+    assert_eq!(reader.seek_to_time(target.time), Some(target));
+    assert!(matches!(
+        open(&other_log).set_index(loaded),
+        Err(crate::IndexError::Mismatch)
+    ));
+
+    std::fs::write(&path, b"not an index").unwrap();
+    assert!(matches!(
+        crate::Index::load(&path),
+        Err(crate::IndexError::Format)
+    ));
+    // An unreadable sidecar is replaced
+    let rebuilt = open(&log).load_or_build_index(&path).unwrap().clone();
+    assert_eq!(rebuilt.keyframes(), built.keyframes());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn batches_match_sequential_decoding() {
+    let mut buf = Vec::new();
+    File::open("src/test-data/LOG00037.BFL")
+        .unwrap()
+        .read_to_end(&mut buf)
+        .unwrap();
+    let open = || {
+        MultiSegmentBlackboxReader::from_bytes(&buf)
+            .next()
+            .unwrap()
+            .unwrap()
+    };
+
+    let mut reader = open();
+    let mut sequential = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            let values = values.to_vec();
+            sequential.push((reader.last_widened_time, values));
+        }
+    }
+
+    let mut reader = open();
+    let mut batched = Vec::new();
+    loop {
+        let batch = reader.next_batch(1000);
+        if batch.is_empty() {
+            break;
+        }
+        assert!(batch.len() <= 1000);
+        for i in 0..batch.len() {
+            let values = batch.columns().map(|column| column[i]).collect();
+            batched.push((batch.time()[i], values));
+        }
+    }
+    assert_eq!(batched, sequential);
+
+    let mut reader = open().select_fields(&["gyroADC[0]", "time"]).unwrap();
+    let batch = reader.next_batch(10);
+    assert_eq!(batch.len(), 10);
+    assert_eq!(batch.names(), ["gyroADC[0]", "time"]);
+    assert_eq!(batch.get("time").unwrap(), batch.time());
+}
+
+#[test]
+fn timed_records_carry_the_last_main_frame_time() {
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let start = reader.header.log_start_datetime().unwrap();
+    let wall_clock = |time, first| Some(start + chrono::Duration::microseconds(time - first));
+    let (mut first, mut last_main, mut others) = (None, None, 0);
+    while let Some(timed) = reader.next_timed() {
+        match timed.record {
+            BlackboxRecord::Main(values) => {
+                assert_eq!(values.value("loopIteration"), Some(timed.loop_iteration));
+                assert_eq!(values.value("time"), Some(timed.time));
+                let first = *first.get_or_insert(timed.time);
+                assert_eq!(timed.wall_clock, wall_clock(timed.time, first));
+                last_main = Some((timed.loop_iteration, timed.time));
+            }
+            _ => match last_main {
+                Some(last) => {
+                    assert_eq!((timed.loop_iteration, timed.time), last);
+                    others += 1;
+                }
+                None => assert_eq!(timed.wall_clock, None),
+            },
+        }
+    }
+    assert!(others > 0);
+
+    // Wall clock times don't depend on where reading started
+    let (first, (_, last)) = (first.unwrap(), last_main.unwrap());
+    let mut reader = BlackboxReader::from_bytes(&buf)
+        .unwrap()
+        .range((first + last) / 2, last);
+    let timed = std::iter::from_fn(|| reader.next_timed().map(|t| (t.time, t.wall_clock)))
+        .nth(1)
+        .unwrap();
+    assert!(timed.0 > first);
+    assert_eq!(timed.1, wall_clock(timed.0, first));
+}
+
+#[test]
+fn every_nth_frame_matches_full_decoding() {
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let mut full = std::collections::HashMap::new();
+    let mut iterations = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            let values = values.to_vec();
+            iterations.push(reader.last_loop_iteration);
+            full.insert(
+                reader.last_loop_iteration,
+                (reader.last_widened_time, values),
+            );
+        }
+    }
+
+    let decimate = |n: usize, indexed: bool| {
+        let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+        if indexed {
+            reader.build_index();
+        }
+        let mut frames = Vec::new();
+        while let Some(record) = reader.next_every_nth(n) {
+            let BlackboxRecord::Main(values) = record else {
+                panic!("only main frames are returned");
+            };
+            let values = values.to_vec();
+            let iteration = reader.last_loop_iteration;
+            assert_eq!(full[&iteration], (reader.last_widened_time, values));
+            frames.push(iteration);
+        }
+        frames
+    };
+
+    assert_eq!(decimate(1, false), iterations);
+    let every_third: Vec<_> = iterations.iter().copied().step_by(3).collect();
+    assert_eq!(decimate(3, false), every_third);
+
+    // P-frames before the next I-frame aren't predicted when the frame after them is returned
+    let every_100th: Vec<_> = iterations.iter().copied().step_by(100).collect();
+    assert_eq!(decimate(100, false), every_100th);
+    assert_eq!(decimate(100, true), every_100th);
+}
+
+fn corrupt(buf: &mut [u8], start: usize, every: usize, len: usize) {
+    let mut state = 0x2545_f491_u32;
+    for chunk_start in (start..buf.len()).step_by(every) {
+        for b in buf[chunk_start..].iter_mut().take(len) {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            *b = state as u8;
+        }
+    }
+}
+
+fn main_loop_iterations(buf: &[u8], options: ReaderOptions) -> Vec<i64> {
+    let mut reader = BlackboxReader::with_options(buf, options).unwrap();
+    let mut iterations = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            iterations.push(values.value("loopIteration").unwrap());
+        }
+    }
+    iterations
+}
+
+#[test]
+fn next_keyframe_resync_never_goes_back_in_time() {
+    let clean = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let clean_iterations = main_loop_iterations(&clean, ReaderOptions::default());
+
+    let mut buf = clean.clone();
+    corrupt(&mut buf, 100_000, 50_000, 32);
+
+    let options = ReaderOptions {
+        resync: ResyncStrategy::NextKeyframe,
+        ..Default::default()
+    };
+    let iterations = main_loop_iterations(&buf, options);
+    assert!(iterations.len() > clean_iterations.len() * 9 / 10);
+    assert!(iterations.windows(2).all(|w| w[0] <= w[1]));
+    assert!(iterations
+        .iter()
+        .all(|i| clean_iterations.binary_search(i).is_ok()));
+}
+
+#[test]
+fn skipped_bytes_are_reported_as_garbage() {
+    let mut buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    corrupt(&mut buf, 100_000, 50_000, 32);
+
+    for resync in [ResyncStrategy::ByteByByte, ResyncStrategy::NextKeyframe] {
+        let options = ReaderOptions {
+            resync,
+            ..Default::default()
+        };
+        let mut reader = BlackboxReader::with_options(&buf, options).unwrap();
+        let mut spans = Vec::new();
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Garbage(span) = record {
+                spans.push(span);
+            }
+        }
+
+        assert!(!spans.is_empty());
+        assert!(spans
+            .iter()
+            .all(|s| s.len > 0 && s.offset + s.len <= buf.len()));
+        assert!(spans
+            .windows(2)
+            .all(|w| w[0].offset + w[0].len <= w[1].offset));
+        // every corrupted region is reported
+        for corrupted in (100_000..buf.len()).step_by(50_000) {
+            assert!(spans
+                .iter()
+                .any(|s| s.offset <= corrupted + 32 && corrupted < s.offset + s.len));
+        }
+    }
+}
+
+#[test]
+fn progress_goes_from_zero_to_one() {
+    with_multilog("src/test-data/btfl_001.bbl", |mut r| {
+        assert_eq!(r.progress(), 0.0);
+        let mut reader = r.next().unwrap().unwrap();
+        let mut last_progress = reader.progress();
+        assert!(last_progress > 0.0);
+
+        while reader.next().is_some() {
+            assert!(reader.progress() >= last_progress);
+            last_progress = reader.progress();
+        }
+        assert_eq!(last_progress, 1.0);
+
+        while r.next().is_some() {}
+        assert_eq!(r.progress(), 1.0);
+    });
+}
+
+#[test]
+fn typed_header_metadata() {
+    with_multilog("src/test-data/btfl_002.bbl", |mut r| {
+        let header = r.next().unwrap().unwrap().header;
+        assert_eq!(header.firmware_type(), Some("Cleanflight"));
+        assert_eq!(header.firmware_kind(), FirmwareKind::Betaflight);
+        assert_eq!(
+            header.firmware_version(),
+            Some(FirmwareVersion::new(4, 2, 8))
+        );
+        assert_eq!(
+            header.firmware_date(),
+            chrono::NaiveDate::from_ymd_opt(2021, 2, 15).and_then(|d| d.and_hms_opt(12, 10, 35))
+        );
+        assert_eq!(header.log_start_datetime(), None);
+        let board = header.board_information().unwrap();
+        assert_eq!(board.manufacturer_id, "AIKO");
+        assert_eq!(board.board_name, "AIKONF7");
+        assert_eq!(header.craft_name(), Some("Gecko"));
+    });
+
+    with_multilog("src/test-data/LOG00004.TXT", |mut r| {
+        let header = r.next().unwrap().unwrap().header;
+        assert_eq!(header.firmware_kind(), FirmwareKind::INAV);
+        assert_eq!(
+            header.firmware_version(),
+            Some(FirmwareVersion::new(3, 0, 1))
+        );
+    });
+
+    with_multilog("src/test-data/crashing-LOG00002.BFL", |mut r| {
+        let header = r.next().unwrap().unwrap().header;
+        assert_eq!(header.firmware_kind(), FirmwareKind::EmuFlight);
+        assert_eq!(header.craft_name(), Some("25x25_blaxkbox_s"));
+    });
+}
+
+#[test]
+fn typed_header_settings() {
+    let buf = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+    let settings = Header::parse(&buf).unwrap().settings;
+    assert_eq!(settings.min_throttle, Some(1070));
+    assert_eq!(settings.max_throttle, Some(2000));
+    assert_eq!(settings.motor_output, Some((158, 2047)));
+    assert_eq!(settings.acc_1g, Some(2048));
+    assert_eq!(
+        settings.vbat_cell_voltage,
+        Some(VBatCellVoltage {
+            min: 330,
+            warning: 350,
+            max: 430
+        })
+    );
+    assert_eq!(
+        settings.current_sensor,
+        Some(CurrentSensor {
+            offset: 0,
+            scale: 250
+        })
+    );
+    assert_eq!(
+        settings.rc_rates,
+        Some(RollPitchYaw {
+            roll: 70,
+            pitch: 70,
+            yaw: 70
+        })
+    );
+    assert_eq!(
+        settings.roll_pid,
+        Some(PID {
+            p: 50.0,
+            i: 102.0,
+            d: 36.0
+        })
+    );
+    assert_eq!(settings.mag_p, Some(40.0));
+    assert_eq!(settings.dterm_lowpass_dyn_hz, Some((70, 170)));
+
+    let header = std::str::from_utf8(SYNTHETIC_HEADER).unwrap().replace(
+        "H looptime:125\n",
+        "H looptime:125\nH minthrottle:abc\nH rollPID:4.0,0.03,23,60\n",
+    );
+    let settings = Header::parse(header.as_bytes()).unwrap().settings;
+    assert_eq!(settings.min_throttle, None);
+    assert_eq!(
+        settings.roll_pid,
+        Some(PID {
+            p: 4.0,
+            i: 0.03,
+            d: 23.0
+        })
+    );
+}
+
+#[test]
+fn typed_header_accessors() {
+    let buf = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+    let header = Header::parse(&buf).unwrap();
+    assert_eq!(header.get_u32("minthrottle"), Ok(1070));
+    assert_eq!(header.get_i32_list("rc_rates"), Ok(vec![70, 70, 70]));
+    assert_eq!(header.get_bool("vbat_pid_gain"), Ok(false));
+
+    let err = header.get_u32("rc_rates").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "header rc_rates is \"70,70,70\", expected an unsigned integer"
+    );
+    assert_eq!(
+        header.get_bool("no_such_header"),
+        Err(HeaderValueError::Missing("no_such_header".to_owned()))
+    );
+
+    let header = std::str::from_utf8(SYNTHETIC_HEADER).unwrap().replace(
+        "H looptime:125\n",
+        "H looptime:125\nH ratio:1/2\nH whole_ratio:4\nH bad_ratio:1/0\n",
+    );
+    let header = Header::parse(header.as_bytes()).unwrap();
+    assert_eq!(
+        header.get_ratio("ratio"),
+        Ok(num_rational::Ratio::new(1, 2))
+    );
+    assert_eq!(
+        header.get_ratio("whole_ratio"),
+        Ok(num_rational::Ratio::from_integer(4))
+    );
+    assert!(header.get_ratio("bad_ratio").is_err());
+}
+
+#[test]
+fn debug_mode_names_debug_fields() {
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let mode = Header::parse(&buf).unwrap().debug_mode().unwrap();
+    assert_eq!(mode, DebugMode::GyroScaled);
+    assert_eq!(mode.to_string(), "GYRO_SCALED");
+    assert_eq!(mode.field(0).unwrap().name, "gyroScaled[roll]");
+    assert_eq!(mode.field(0).unwrap().unit, Some("deg/s"));
+    assert_eq!(mode.field(3), None);
+
+    let buf = std::fs::read("src/test-data/LOG00002.BFL").unwrap();
+    let mode = Header::parse(&buf).unwrap().debug_mode().unwrap();
+    assert_eq!(mode, DebugMode::GyroFiltered);
+
+    // INAV numbers its debug modes differently
+    let buf = std::fs::read("src/test-data/LOG00004.TXT").unwrap();
+    let mode = Header::parse(&buf).unwrap().debug_mode().unwrap();
+    assert_eq!(mode, DebugMode::Other(0));
+    assert_eq!(mode.to_string(), "0");
+
+    assert_eq!(
+        DebugMode::decode(FirmwareKind::Betaflight, 58).name(),
+        Some("FF_LIMIT")
+    );
+    assert_eq!(DebugMode::decode(FirmwareKind::Betaflight, 200).id(), 200);
+}
+
+#[test]
+fn motor_outputs_are_normalized() {
+    let buf = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let layout = OutputLayout::new(&reader.header);
+    assert_eq!(layout.motor_count(), 4);
+    assert_eq!(layout.servo_count(), 0);
+    assert_eq!(layout.protocol(), MotorProtocol::Digital);
+    assert_eq!(layout.motor_range(), (48, 2047));
+    assert_eq!(layout.normalize_motor(2047), 1.0);
+    assert_eq!(layout.normalize_motor(0), 0.0);
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            let motors = layout.motors(&values);
+            assert_eq!(motors.len(), 4);
+            assert!(motors.iter().all(|m| (0.0..=1.0).contains(m)));
+            break;
+        }
+    }
+
+    let buf = std::fs::read("src/test-data/LOG00004.TXT").unwrap();
+    let layout = OutputLayout::new(&Header::parse(&buf).unwrap());
+    assert_eq!(layout.protocol(), MotorProtocol::Analog);
+    assert_eq!(layout.motor_range(), (1042, 1850));
+    assert_eq!(layout.normalize_motor(1446), 0.5);
+    assert_eq!(layout.normalize_servo(1500), 0.5);
+}
+
+#[test]
+fn header_only_parse_accepts_truncated_input() {
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let reader = BlackboxReader::from_bytes(&buf).unwrap();
+
+    for len in [reader.bytes_read(), reader.bytes_read() - 10, buf.len()] {
+        let header = Header::parse(&buf[..len]).unwrap();
+        assert_eq!(header.craft_name(), Some("Gecko"));
+        assert_eq!(
+            header.ip_fields_in_order.len(),
+            reader.header.ip_fields_in_order.len()
+        );
+        assert_eq!(
+            header.s_fields_in_order.len(),
+            reader.header.s_fields_in_order.len()
+        );
+    }
+
+    assert!(matches!(
+        Header::parse(&buf[..100]),
+        Err(BlackboxReaderError::MissingHeader("I interval"))
+    ));
+}
+
+#[test]
+fn segments_are_listed_with_approximate_durations() {
+    let buf = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+    let segments = MultiSegmentBlackboxReader::from_bytes(&buf).segments();
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[0].offset, 0);
+    assert_eq!(segments[0].offset + segments[0].len, segments[1].offset);
+    assert_eq!(segments[0].trailing_bytes, 0);
+    assert_eq!(
+        segments[1].offset + segments[1].len + segments[1].trailing_bytes,
+        buf.len()
+    );
+
+    for segment in &segments {
+        let header = segment.header.as_ref().unwrap();
+        assert_eq!(
+            header.firmware_version(),
+            Some(FirmwareVersion::new(4, 2, 11))
+        );
+
+        let bytes = &buf[segment.offset..segment.offset + segment.len];
+        let mut reader = BlackboxReader::from_bytes(bytes).unwrap();
+        let time_ix = reader.header.ip_fields["time"].ix;
+        let mut times = Vec::new();
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(values) = record {
+                times.push(values[time_ix]);
+            }
+        }
+
+        let (first, last) = segment.time_span.unwrap();
+        assert_eq!(first, times[0]);
+        assert!(last <= *times.last().unwrap());
+        // within a couple of I-frame intervals from the end
+        assert!(*times.last().unwrap() - last < 100_000);
+    }
+}
+
+#[test]
+fn range_limits_decoded_main_frames() {
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let main_times = |reader: &mut BlackboxReader| {
+        let time_ix = reader.header.ip_fields["time"].ix;
+        let mut times = Vec::new();
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(values) = record {
+                times.push(values[time_ix]);
+            }
+        }
+        times
+    };
+
+    let all = main_times(&mut BlackboxReader::from_bytes(&buf).unwrap());
+    let (start, end) = (all[0] + 1_000_000, all[0] + 2_000_000);
+    let expected: Vec<_> = all
+        .iter()
+        .copied()
+        .filter(|t| (start..=end).contains(t))
+        .collect();
+
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap().range(start, end);
+    assert_eq!(main_times(&mut reader), expected);
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn selected_fields_match_full_decode() {
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let selected = ["motor[2]", "gyroADC[0]"];
+
+    let mut full = BlackboxReader::from_bytes(&buf).unwrap();
+    let mut expected = Vec::new();
+    while let Some(record) = full.next() {
+        if let BlackboxRecord::Main(values) = record {
+            expected.push(selected.map(|name| values.value(name).unwrap()));
+        }
+    }
+
+    let mut reader = BlackboxReader::from_bytes(&buf)
+        .unwrap()
+        .select_fields(&selected)
+        .unwrap();
+    let mut actual = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            assert!(values.is_projected());
+            assert_eq!(values.names().collect::<Vec<_>>(), selected);
+            assert_eq!(values.value("motor[1]"), None);
+            actual.push([values[0], values[1]]);
+        }
+    }
+    assert!(!expected.is_empty());
+    assert_eq!(actual, expected);
+
+    assert!(matches!(
+        BlackboxReader::from_bytes(&buf)
+            .unwrap()
+            .select_fields(&["nope"]),
+        Err(BlackboxReaderError::UnknownField(_))
+    ));
+}
+
+#[test]
+fn derived_fields_are_computed_per_main_frame() {
+    use crate::{DerivedField, ExpressionError};
+
+    let field = |expression| DerivedField::new("x", expression);
+    assert_eq!(field("1 +"), Err(ExpressionError::UnexpectedEnd));
+    assert_eq!(
+        field("gyroADC[] * 2"),
+        Err(ExpressionError::UnexpectedChar {
+            position: 8,
+            found: ']'
+        })
+    );
+    assert_eq!(
+        field("mean(motor[0])"),
+        Err(ExpressionError::UnknownFunction("mean".to_owned()))
+    );
+    assert_eq!(
+        field("min(motor[0])"),
+        Err(ExpressionError::ArgumentCount {
+            function: "min",
+            expected: 2
+        })
+    );
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    assert!(matches!(
+        BlackboxReader::from_bytes(&buf)
+            .unwrap()
+            .derive(&field("nope * 2").unwrap()),
+        Err(BlackboxReaderError::UnknownField(name)) if name == "nope"
+    ));
+
+    let error = DerivedField::new("error", "abs(gyroADC[0] - setpoint[0])").unwrap();
+    let motors = DerivedField::new("motors", "(motor[0] + motor[1]) / 2 - -1").unwrap();
+    let mut expected = Vec::new();
+    let mut full = BlackboxReader::from_bytes(&buf).unwrap();
+    while let Some(record) = full.next() {
+        if let BlackboxRecord::Main(values) = record {
+            let value = |name| values.value(name).unwrap() as f64;
+            expected.push([
+                (value("gyroADC[0]") - value("setpoint[0]")).abs(),
+                (value("motor[0]") + value("motor[1]")) / 2.0 + 1.0,
+            ]);
+        }
+    }
+
+    // Fields used by expressions are decoded even when they aren't selected
+    let mut reader = BlackboxReader::from_bytes(&buf)
+        .unwrap()
+        .select_fields(&["time"])
+        .unwrap()
+        .derive(&error)
+        .unwrap()
+        .derive(&motors)
+        .unwrap();
+    assert_eq!(
+        reader.derived_names().collect::<Vec<_>>(),
+        ["error", "motors"]
+    );
+    let mut actual = Vec::new();
+    while let Some(record) = reader.next() {
+        match record {
+            BlackboxRecord::Main(values) => actual.push([values.derived()[0], values.derived()[1]]),
+            BlackboxRecord::Slow(values) => assert!(values.derived().is_empty()),
+            _ => {}
+        }
+    }
+    assert!(!expected.is_empty());
+    assert_eq!(actual, expected);
+    assert_eq!(reader.derived_values(), expected.last().unwrap());
+
+    #[cfg(feature = "csv")]
+    {
+        use crate::export::csv::{self, CsvOptions};
+
+        let reader = BlackboxReader::from_bytes(&buf)
+            .unwrap()
+            .derive(&error)
+            .unwrap();
+        let mut out = Vec::new();
+        csv::write(reader, &mut out, CsvOptions::default()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        let labels: Vec<&str> = lines.next().unwrap().split(", ").collect();
+        let column = labels.iter().position(|l| *l == "error").unwrap();
+        assert_eq!(labels[column - 1], "energyCumulative (mAh)");
+        let rows: Vec<f64> = lines
+            .map(|l| l.split(", ").nth(column).unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(rows, expected.iter().map(|e| e[0]).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn merged_rows_carry_latest_slow_and_gnss_values() {
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let (mut slow, mut gnss) = (Vec::new(), Vec::new());
+    let mut expected = Vec::new();
+    while let Some(record) = reader.next() {
+        match record {
+            BlackboxRecord::Main(values) => {
+                expected.push((values.to_vec(), slow.clone(), gnss.clone()))
+            }
+            BlackboxRecord::Slow(values) => slow = values.to_vec(),
+            BlackboxRecord::GNSS(values) => gnss = values.to_vec(),
+            _ => {}
+        }
+    }
+
+    let reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let (main_len, slow_len) = (
+        reader.header.ip_fields_in_order.len(),
+        reader.header.s_fields_in_order.len(),
+    );
+    let mut merged = MergedReader::new(reader).with_gnss();
+    assert_eq!(
+        merged.names()[main_len],
+        merged.header().s_fields_in_order[0].name
+    );
+
+    let mut rows = 0;
+    while let Some(row) = merged.next() {
+        let (main, slow, gnss) = &expected[rows];
+        assert_eq!(&row[..main_len], &main[..]);
+        if !slow.is_empty() {
+            assert_eq!(&row[main_len..main_len + slow_len], &slow[..]);
+        }
+        if !gnss.is_empty() {
+            assert_eq!(&row[main_len + slow_len..], &gnss[..]);
+        }
+        assert_eq!(row.value("time"), Some(main[1]));
+        rows += 1;
+    }
+    assert_eq!(rows, expected.len());
+}
+
+#[test]
+fn interpolated_gnss_lies_between_fixes() {
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let mut fixes = Vec::new();
+    let mut mains = 0;
+    while let Some(record) = reader.next() {
+        match record {
+            BlackboxRecord::GNSS(values) => fixes.push((
+                values.value("time").unwrap(),
+                values.value("GPS_coord[0]").unwrap(),
+            )),
+            BlackboxRecord::Main(_) => mains += 1,
+            _ => {}
+        }
+    }
+    assert!(fixes.len() > 1);
+
+    let reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let mut merged = MergedReader::new(reader).with_gnss_alignment(GnssAlignment::Interpolate);
+    let mut rows = 0;
+    while let Some(row) = merged.next() {
+        let (time, lat) = (
+            row.value("time").unwrap(),
+            row.value("GPS_coord[0]").unwrap(),
+        );
+        let next = fixes.partition_point(|(t, _)| *t <= time);
+        match (next.checked_sub(1).map(|ix| fixes[ix]), fixes.get(next)) {
+            (None, _) => assert_eq!(lat, 0),
+            (Some((_, prev)), None) => assert_eq!(lat, prev),
+            (Some((_, prev)), Some((_, next))) => {
+                assert!((prev.min(*next)..=prev.max(*next)).contains(&lat))
+            }
+        }
+        rows += 1;
+    }
+    assert_eq!(rows, mains);
+}
+
+#[test]
+fn units_convert_raw_values() {
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let layout = MainFrameLayout::new(&reader.header);
+    let units = Units::new(&reader.header);
+    let radians = Units::with_angular_unit(&reader.header, AngularUnit::RadiansPerSecond);
+
+    let mut checked = 0;
+    while let Some(record) = reader.next() {
+        let BlackboxRecord::Main(values) = record else {
+            continue;
+        };
+        let frame = values.main_frame(&layout).unwrap();
+        let scaled = units.scale(values);
+        assert_eq!(scaled.unit("gyroADC[1]"), Some(Unit::DegreesPerSecond));
+        assert!((scaled.value("gyroADC[1]").unwrap() - frame.gyro[1] as f64).abs() < 1e-3);
+        let rad = radians.scale(values).value("gyroADC[1]").unwrap();
+        assert!((rad - (frame.gyro[1] as f64).to_radians()).abs() < 1e-6);
+        assert!((scaled.value("accSmooth[2]").unwrap() - frame.acc[2] as f64).abs() < 1e-6);
+        assert_eq!(scaled.value("time"), Some(frame.time_us as f64 * 1e-6));
+        assert_eq!(scaled.unit("motor[0]"), Some(Unit::Raw));
+        let vbat = scaled.value("vbatLatest").unwrap();
+        assert!((5.0..30.0).contains(&vbat), "{vbat}");
+        checked += 1;
+    }
+    assert!(checked > 0);
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let units = Units::new(&reader.header);
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::GNSS(values) = record {
+            let scaled = units.scale(values);
+            let lat = scaled.value("GPS_coord[0]").unwrap();
+            assert!((-90.0..=90.0).contains(&lat));
+            assert_eq!(scaled.unit("GPS_altitude"), Some(Unit::Meters));
+        }
+    }
+}
+
+#[test]
+fn flight_modes_are_named_per_firmware() {
+    let modes = FlightModes::decode(FirmwareKind::Betaflight, 1 | 1 << 20);
+    assert_eq!(modes.to_string(), "ARM|AIR MODE");
+    assert!(modes.contains("AIR MODE"));
+    assert_eq!(
+        FlightModes::decode(FirmwareKind::INAV, 1 << 9).to_string(),
+        "FAILSAFE"
+    );
+    assert_eq!(FlightModes::decode(FirmwareKind::INAV, 0).to_string(), "0");
+    assert_eq!(
+        FlightModes::decode(FirmwareKind::INAV, 1 | 1 << 30).to_string(),
+        "ANGLE|0x40000000"
+    );
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let firmware = reader.header.firmware_kind();
+    let mut seen = Vec::new();
+    while let Some(record) = reader.next() {
+        match record {
+            BlackboxRecord::Event(event::Frame::FlightMode(event)) => {
+                seen.push(event.modes(firmware).to_string())
+            }
+            BlackboxRecord::Slow(values) => {
+                let flags = values.value("flightModeFlags").unwrap() as u32;
+                seen.push(reader.header.flight_modes(flags).to_string())
+            }
+            _ => {}
+        }
+    }
+    // Armed with the blackbox mode for the whole log
+    assert!(!seen.is_empty());
+    assert!(seen.iter().all(|modes| modes == "ARM|BLACKBOX"));
+}
+
+#[test]
+fn slow_frame_flags_are_decoded() {
+    assert_eq!(
+        StateFlags::decode(FirmwareKind::Betaflight, 0b11).to_string(),
+        "GPS_FIX_HOME|GPS_FIX"
+    );
+    assert!(StateFlags::decode(FirmwareKind::INAV, 1 << 18).contains("MULTIROTOR"));
+    assert_eq!(
+        FailsafePhase::decode(FirmwareKind::Betaflight, 2),
+        FailsafePhase::Landing
+    );
+    assert_eq!(
+        FailsafePhase::decode(FirmwareKind::INAV, 2),
+        FailsafePhase::RxLossIdle
+    );
+    assert_eq!(
+        FailsafePhase::decode(FirmwareKind::INAV, 20),
+        FailsafePhase::Other(20)
+    );
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let mut slow_frames = 0;
+    while let Some(record) = reader.next() {
+        match record {
+            BlackboxRecord::Slow(values) => {
+                let flags = values.value("flightModeFlags").unwrap() as u32;
+                assert_eq!(values.flight_modes().unwrap().bits(), flags);
+                assert!(values.state_flags().is_some());
+                assert_eq!(values.failsafe_phase(), Some(FailsafePhase::Idle));
+                slow_frames += 1;
+            }
+            BlackboxRecord::Main(values) => assert_eq!(values.state_flags(), None),
+            _ => {}
+        }
+    }
+    assert!(slow_frames > 0);
+}
+
+#[test]
+fn disarm_reasons_depend_on_firmware() {
+    assert_eq!(
+        DisarmReason::decode(FirmwareKind::Betaflight, 4),
+        DisarmReason::Switch
+    );
+    assert_eq!(
+        DisarmReason::decode(FirmwareKind::Betaflight, 6),
+        DisarmReason::RunawayTakeoff
+    );
+    assert_eq!(
+        DisarmReason::decode(FirmwareKind::INAV, 6),
+        DisarmReason::Failsafe
+    );
+    assert_eq!(
+        DisarmReason::decode(FirmwareKind::INAV, 42).to_string(),
+        "unknown (42)"
+    );
+
+    let buf = std::fs::read("src/test-data/btfl_all.bbl").unwrap();
+    let mut reasons = Vec::new();
+    for mut reader in MultiSegmentBlackboxReader::from_bytes(&buf).successful_only() {
+        let firmware = reader.header.firmware_kind();
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Event(event::Frame::Disarm(disarm)) = record {
+                reasons.push(disarm.decoded_reason(firmware));
+            }
+        }
+    }
+    assert!(!reasons.is_empty());
+    assert!(!reasons
+        .iter()
+        .any(|r| matches!(r, DisarmReason::Unknown(_))));
+}
+
+#[test]
+fn event_payloads_are_readable() {
+    let resume = event::LoggingResume {
+        iteration: 1200,
+        time: 5_000_000,
+    };
+    assert_eq!(
+        resume.gap_since(1000, 4_900_000),
+        event::ResumeGap {
+            iterations: 200,
+            time: 100_000
+        }
+    );
+
+    let buf = std::fs::read("src/test-data/btfl_all.bbl").unwrap();
+    let mut beeps = 0;
+    for mut reader in MultiSegmentBlackboxReader::from_bytes(&buf).successful_only() {
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Event(event::Frame::SyncBeep(beep)) = record {
+                assert!(beep.time > 0);
+                beeps += 1;
+            }
+        }
+    }
+    assert!(beeps > 0);
+}
+
+#[test]
+fn unknown_events_keep_their_payload() {
+    let (remaining, frame) = event::parse_event(b"E\x0a\x01\x85\x02\x03\x04P").unwrap();
+    assert_eq!(remaining, b"P");
+    assert_eq!(
+        frame,
+        event::Frame::AutotuneCycleStart(event::AutotuneCycleStart {
+            phase: 1,
+            cycle: 5,
+            rising: true,
+            p: 2,
+            i: 3,
+            d: 4,
+        })
+    );
+
+    let (remaining, frame) = event::parse_event(b"E\x63\x01\x02I").unwrap();
+    assert_eq!(remaining, b"I");
+    assert_eq!(
+        frame,
+        event::Frame::Unknown(event::UnknownEvent {
+            code: 0x63,
+            payload: vec![],
+        })
+    );
+
+    // The reader only copies the payload of events it returns
+    let mut buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let offset = BlackboxReader::from_bytes(&buf)
+        .unwrap()
+        .build_index()
+        .keyframes()[1]
+        .offset;
+    buf.splice(offset..offset, *b"E\x63\x01\x02");
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let unknown =
+        std::iter::from_fn(|| reader.next().map(|r| r.to_owned_record())).find_map(|record| {
+            match record {
+                crate::OwnedRecord::Event(event::Frame::Unknown(event)) => Some(event),
+                _ => None,
+            }
+        });
+    assert_eq!(unknown.unwrap().payload, [1, 2]);
+}
+
+#[test]
+fn tag2_3s_variable_decodes_all_layouts() {
+    let decode = |bytes: &[u8]| match FieldEncoding::Tag2_3SVariable(3).parse(bytes) {
+        Ok((remaining, Field::SignedTriple(values))) => {
+            assert!(remaining.is_empty());
+            values
+        }
+        other => panic!("{:?}", other),
+    };
+
+    assert_eq!(decode(&[0b0001_1110]), [1, -1, -2]);
+    assert_eq!(decode(&[0x60, 0xf8]), [-16, 15, -8]);
+    assert_eq!(decode(&[0xa0, 0x1f, 0xff]), [-128, 63, -1]);
+    assert_eq!(
+        decode(&[0xf1, 0xd4, 0xfe, 0x05, 0xa0, 0x86, 0x01, 0x00]),
+        [-300, 5, 100_000]
+    );
+}
+
+/// Encodes values like the Elias writers of old Cleanflight firmware.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u64, n: u32) {
+        for i in (0..n).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+
+    fn gamma(&mut self, value: u32) {
+        let encoded = value as u64 + 1;
+        let len = 64 - encoded.leading_zeros();
+        self.bits(0, len - 1);
+        self.bits(encoded, len);
+    }
+
+    fn delta(&mut self, value: u32) {
+        let encoded = value as u64 + 1;
+        let len = 64 - encoded.leading_zeros();
+        self.gamma(len - 1);
+        self.bits(encoded, len - 1);
+    }
+}
+
+#[test]
+fn elias_encodings_decode_across_byte_boundaries() {
+    let values = [0u32, 1, 2, 7, 100, 65_535, 1 << 20, u32::MAX - 2];
+    let mut gamma = BitWriter::default();
+    let mut delta = BitWriter::default();
+    for v in values {
+        gamma.gamma(v);
+        delta.delta(v);
+    }
+
+    for (encoding, bytes) in [
+        (FieldEncoding::EliasGammaU32, &gamma.bytes),
+        (FieldEncoding::EliasDeltaU32, &delta.bytes),
+    ] {
+        // Byte aligned field after the bit-packed run
+        let input = [b"I", &bytes[..], &[0x05]].concat();
+        let encodings: Vec<_> = values
+            .iter()
+            .map(|_| encoding.clone())
+            .chain([FieldEncoding::UnsignedVB])
+            .collect();
+        let mut decoded = Vec::new();
+        let (remaining, ()) = parse_frame_payload(&encodings, &input[1..], &mut decoded).unwrap();
+        assert!(remaining.is_empty());
+        let expected: Vec<i64> = values.iter().map(|v| *v as i64).chain([5]).collect();
+        assert_eq!(decoded, expected);
+    }
+
+    let mut signed = BitWriter::default();
+    signed.gamma(3); // zigzag(-2)
+    assert!(matches!(
+        FieldEncoding::EliasGammaS32.parse(&signed.bytes),
+        Ok((_, Field::Signed(-2)))
+    ));
+}
+
+#[test]
+fn elias_gamma_encoding_ids_depend_on_firmware() {
+    let header = std::str::from_utf8(SYNTHETIC_HEADER)
+        .unwrap()
+        .replace("I name:loopIteration,time", "I name:loopIteration,time,a,b")
+        .replace("I signed:0,0", "I signed:0,0,0,1")
+        .replace("I predictor:0,0", "I predictor:0,0,0,0")
+        .replace("I encoding:1,1", "I encoding:1,1,10,11")
+        .replace("P predictor:6,2", "P predictor:6,2,0,0")
+        .replace("P encoding:9,0", "P encoding:9,0,0,0");
+
+    let betaflight = format!("{header}H Firmware revision:Betaflight 4.2.0\n");
+    let parsed = Header::parse(betaflight.as_bytes()).unwrap();
+    assert_eq!(
+        parsed.i_field_encodings[2..],
+        [
+            FieldEncoding::Tag2_3SVariable(1),
+            FieldEncoding::EliasGammaS32
+        ]
+    );
+
+    let mut log = format!("{header}H Firmware revision:Cleanflight 1.11.0\n").into_bytes();
+    let mut bits = BitWriter::default();
+    bits.gamma(5);
+    bits.gamma(5); // zigzag(-3)
+    log.extend_from_slice(&[b'I', 0, 100]);
+    log.extend_from_slice(&bits.bytes);
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    let mut main = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            main.push(values.to_vec());
+        }
+    }
+    assert_eq!(main, [[0, 100, 5, -3]]);
+}
+
+#[test]
+fn unusable_predictors_are_reported() {
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let replace = |from: &str, to: &str| {
+        let pos = buf
+            .windows(from.len())
+            .position(|w| w == from.as_bytes())
+            .unwrap();
+        [&buf[..pos], to.as_bytes(), &buf[pos + from.len()..]].concat()
+    };
+
+    let unsupported = replace("H Field I predictor:0,", "H Field I predictor:2,");
+    assert!(matches!(
+        BlackboxReader::from_bytes(&unsupported),
+        Err(BlackboxReaderError::UnsupportedPredictor { field, predictor })
+            if field == "loopIteration" && predictor == "StraightLine"
+    ));
+
+    // Settings the predictors are based on fall back to defaults, with a diagnostic
+    let missing = replace("H vbatref:", "H vbatrefs:");
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let options = ReaderOptions {
+        diagnostics: Some(diagnostics.clone()),
+        ..Default::default()
+    };
+    let vbat_ix = 21;
+    let first_vbat = |buf: &[u8], options| {
+        let mut reader = BlackboxReader::with_options(buf, options).unwrap();
+        assert_eq!(reader.header.ip_fields_in_order[vbat_ix].name, "vbatLatest");
+        loop {
+            if let Some(BlackboxRecord::Main(values)) = reader.next() {
+                break values.values()[vbat_ix];
+            }
+        }
+    };
+    // Without a reference, the voltage is left as logged, relative to the missing vbatref
+    let header = Header::parse(&buf).unwrap();
+    let vbatref: i64 = header.other_headers["vbatref"].parse().unwrap();
+    assert_eq!(
+        first_vbat(&missing, options),
+        first_vbat(&buf, ReaderOptions::default()) - vbatref
+    );
+    let diagnostics = diagnostics.lock().unwrap();
+    assert!(diagnostics.iter().any(|d| d.kind
+        == DiagnosticKind::PredictorFallback {
+            field: "vbatLatest".to_owned(),
+            header: "vbatref",
+            value: 0,
+        }
+        && d.severity() == Severity::Warning));
+    let strict = ReaderOptions {
+        validate_header: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        BlackboxReader::with_options(&missing, strict),
+        Err(BlackboxReaderError::InvalidHeader(violations))
+            if violations == [HeaderViolation::MissingHeaderForPredictor {
+                field: "vbatLatest".to_owned(),
+                header: "vbatref",
+            }]
+    ));
+
+    // Predictors based on other fields have nothing to fall back to
+    let missing = replace("motor[0],", "motor0,");
+    for result in [
+        BlackboxReader::from_bytes(&missing).map(|r| r.header),
+        Header::parse(&missing),
+    ] {
+        assert!(matches!(
+            result,
+            Err(BlackboxReaderError::MissingHeaderForPredictor { field, header })
+                if field == "motor[1]" && header == "motor[0]"
+        ));
+    }
+}
+
+#[test]
+fn firmware_quirks() {
+    let buf = std::fs::read("src/test-data/crashing-LOG00002.BFL").unwrap();
+    let header = Header::parse(&buf).unwrap();
+    let quirks = header.quirks();
+    assert_eq!(quirks.firmware, FirmwareKind::EmuFlight);
+    assert!(!quirks.centi_units);
+    assert!(quirks.betaflight_flight_modes);
+    // Per term and per axis EmuFlight settings
+    assert_eq!(header.settings.tpa_rate, Some(75));
+    assert_eq!(header.settings.dterm_lowpass_hz, Some(90));
+    assert!(!header.other_headers.contains_key("tpa_rate"));
+    assert!(Quirks::new(FirmwareKind::Betaflight, None)
+        .header_aliases
+        .is_empty());
+    assert_eq!(
+        FirmwareKind::detect(Some("Cleanflight"), Some("Quicksilver 0.9.1")),
+        FirmwareKind::Quicksilver
+    );
+
+    // Logs from before motorOutput predict motors from minthrottle
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let pos = buf
+        .windows(14)
+        .position(|w| w == b"H motorOutput:")
+        .unwrap();
+    let legacy = [&buf[..pos], b"H motorOutputs:", &buf[pos + 14..]].concat();
+    let header = Header::parse(&legacy).unwrap();
+    assert_eq!(header.settings.motor_output, Some((1070, 2000)));
+    assert!(!header.other_headers.contains_key("motorOutput"));
+    assert!(BlackboxReader::from_bytes(&legacy).is_ok());
+}
+
+#[test]
+fn data_version_1_uses_legacy_tag8_4s16() {
+    let encoding = FieldEncoding::Tag8_4S16V1(4);
+    match encoding.parse(&[0xe1, 0x7d, 0x9c, 0xe8, 0x03]) {
+        Ok((remaining, Field::SignedQuadruple(values))) => {
+            assert!(remaining.is_empty());
+            assert_eq!(values, [-3, 7, -100, 1000]);
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let header = Header::parse(&buf).unwrap();
+    assert_eq!(header.data_version_number(), Some(2));
+    assert!(header
+        .p_field_encodings
+        .contains(&FieldEncoding::Tag8_4S16(4)));
+
+    let pos = buf
+        .windows(16)
+        .position(|w| w == b"H Data version:2")
+        .unwrap();
+    // Detected like the typed accessor parses it, including zero padding
+    for version in [&b"1"[..], b"01"] {
+        let v1 = [&buf[..pos], b"H Data version:", version, &buf[pos + 16..]].concat();
+        let header = Header::parse(&v1).unwrap();
+        assert_eq!(header.data_version_number(), Some(1));
+        assert!(header
+            .p_field_encodings
+            .contains(&FieldEncoding::Tag8_4S16V1(4)));
+    }
+}
+
+const SYNTHETIC_HEADER: &[u8] = b"H Product:Blackbox flight data recorder by Nicholas Sherlock
+H Data version:2
+H I interval:1
+H P interval:1
+H Field I name:loopIteration,time
+H Field I signed:0,0
+H Field I predictor:0,0
+H Field I encoding:1,1
+H Field P predictor:6,2
+H Field P encoding:9,0
+H Field G name:GPS_coord[0],GPS_coord[1],GPS_altitude
+H Field G signed:1,1,1
+H Field G predictor:7,7,7
+H Field G encoding:0,0,0
+H Field H name:GPS_home[0],GPS_home[1],GPS_home[2]
+H Field H signed:1,1,1
+H Field H predictor:0,0,0
+H Field H encoding:0,0,0
+H gyro_scale:0x3f800000
+H looptime:125
+";
+
+#[test]
+fn invalid_utf8_is_replaced_in_free_text_headers() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    log.extend_from_slice(b"H Craft name:Quad\xff\xfeX\n");
+    log.extend_from_slice(&[b'I', 0, 100]);
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let options = ReaderOptions {
+        recovery: RecoveryPolicy::strict(),
+        diagnostics: Some(diagnostics.clone()),
+        ..Default::default()
+    };
+    let reader = BlackboxReader::with_options(&log, options.clone()).unwrap();
+    assert_eq!(reader.header.craft_name(), Some("Quad\u{fffd}\u{fffd}X"));
+    assert_eq!(
+        *diagnostics.lock().unwrap(),
+        [Diagnostic {
+            offset: SYNTHETIC_HEADER.len(),
+            kind: DiagnosticKind::LossyHeaderValue {
+                name: "Craft name".to_owned()
+            },
+        }]
+    );
+
+    // Numbers are still parsed strictly
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    log.extend_from_slice(b"H looptime:12\xff\n");
+    log.extend_from_slice(&[b'I', 0, 100]);
+    assert!(matches!(
+        BlackboxReader::with_options(&log, options),
+        Err(BlackboxReaderError::MalformedHeader { offset, .. }) if offset == SYNTHETIC_HEADER.len()
+    ));
+}
+
+#[test]
+fn mismatched_field_lists_are_never_dropped_silently() {
+    let header = std::str::from_utf8(SYNTHETIC_HEADER)
+        .unwrap()
+        .replace("H Field P predictor:6,2", "H Field P predictor:6");
+    let mut log = header.into_bytes();
+    log.extend_from_slice(&[b'I', 0]);
+    let violation = HeaderViolation::FieldListLength {
+        header: "Field P predictor",
+        expected: 2,
+        found: 1,
+    };
+
+    // Lenient readers decode the fields in every list and report the rest
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let options = ReaderOptions {
+        diagnostics: Some(diagnostics.clone()),
+        ..Default::default()
+    };
+    let reader = BlackboxReader::with_options(&log, options).unwrap();
+    assert_eq!(reader.header.ip_fields_in_order.len(), 1);
+    assert!(diagnostics.lock().unwrap().iter().any(|d| d.kind
+        == DiagnosticKind::FieldListLength {
+            header: "Field P predictor",
+            expected: 2,
+            found: 1,
+        }));
+
+    for options in [
+        ReaderOptions::from(RecoveryPolicy::strict()),
+        ReaderOptions {
+            validate_header: true,
+            ..Default::default()
+        },
+    ] {
+        assert!(matches!(
+            BlackboxReader::with_options(&log, options),
+            Err(BlackboxReaderError::InvalidHeader(violations)) if violations == [violation.clone()]
+        ));
+    }
+}
+
+#[test]
+fn field_count_mismatches_follow_the_recovery_policy() {
+    let header = std::str::from_utf8(SYNTHETIC_HEADER).unwrap();
+    // A group of three 2-bit fields with only one field in it decodes to three values
+    let header = header.replace("H Field I encoding:1,1", "H Field I encoding:1,7");
+    let mut log = header.into_bytes();
+    log.extend_from_slice(&[b'I', 0, 0b0001_0000, b'I', 1, 0b0001_0000]);
+
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let read = |field_count_mismatch| {
+        let options = ReaderOptions {
+            recovery: RecoveryPolicy {
+                field_count_mismatch,
+                ..RecoveryPolicy::strict()
+            },
+            diagnostics: Some(diagnostics.clone()),
+            ..Default::default()
+        };
+        let mut reader = BlackboxReader::with_options(&log, options).unwrap();
+        let mut main = Vec::new();
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(values) = record {
+                main.push(values.to_vec());
+            }
+        }
+        main
+    };
+
+    assert_eq!(read(FieldCountAction::Fit), [[0, 1], [1, 1]]);
+    assert_eq!(
+        diagnostics.lock().unwrap()[0],
+        Diagnostic {
+            offset: log.len() - 6,
+            kind: DiagnosticKind::FieldCountMismatch {
+                frame: 'I',
+                expected: 2,
+                found: 4,
+            },
+        }
+    );
+    // Handled like an undecodable frame, which the strict policy stops at
+    assert!(read(FieldCountAction::Reject).is_empty());
+
+    // Fields are only decoded up to the shortest list, predictors included
+    let header = std::str::from_utf8(SYNTHETIC_HEADER).unwrap();
+    let header = header.replace("H Field I predictor:0,0", "H Field I predictor:0") + "I";
+    let reader = BlackboxReader::from_bytes(header.as_bytes()).unwrap();
+    assert_eq!(reader.header.ip_fields_in_order.len(), 1);
+}
+
+#[test]
+fn gnss_home_altitude_is_used_by_home_predictor() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    // Values are zigzag encoded
+    log.extend_from_slice(&[b'I', 0, 100, b'H', 20, 40, 60, b'G', 2, 4, 6]);
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    let mut gnss = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::GNSS(values) = record {
+            gnss.push(values.to_vec());
+        }
+    }
+    assert_eq!(gnss, [[11, 22, 33]]);
+    assert_eq!(reader.gnss_home(), [10, 20, 30]);
+}
+
+#[test]
+fn implausible_main_frames_are_garbage() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 100,
+        b'I', 1, 0xc8, 0x01,
+        // loopIteration jumps by 6000
+        b'I', 0xf0, 0x2e, 0xac, 0x02,
+        b'I', 2, 0x90, 0x03,
+    ]);
+
+    let read = |frame_limits| {
+        let options = ReaderOptions {
+            frame_limits,
+            ..Default::default()
+        };
+        let mut reader = BlackboxReader::with_options(&log, options).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = reader.next() {
+            records.push(match record {
+                BlackboxRecord::Main(values) => format!("{:?}", values.values()),
+                BlackboxRecord::Garbage(span) => format!("garbage {}", span.len),
+                _ => "other".to_owned(),
+            });
+        }
+        records
+    };
+
+    assert_eq!(
+        read(Some(FrameLimits::default())),
+        ["[0, 100]", "[1, 200]", "garbage 5", "[2, 400]"]
+    );
+    assert_eq!(
+        read(None),
+        ["[0, 100]", "[1, 200]", "[6000, 300]", "[2, 400]"]
+    );
+}
+
+#[test]
+fn reader_stats_count_lost_iterations() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 100,
+        b'I', 1, 0xc8, 0x01,
+        b'I', 0xf0, 0x2e, 0xac, 0x02,
+        b'I', 4, 0x90, 0x03,
+    ]);
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    while reader.next().is_some() {}
+    assert_eq!(
+        *reader.stats(),
+        ReaderStats {
+            main_frames: 3,
+            missing_iterations: 2,
+            resyncs: 1,
+            corrupted_frames: 1,
+            garbage_bytes: 5,
+            serial_artifact_bytes: 0,
+        }
+    );
+    assert_eq!(reader.stats().lost_percentage(), 40.0);
+}
+
+#[test]
+fn serial_artifacts_between_frames_are_stripped() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 100,
+        XON,
+        b'I', 1, 0xc8, 0x01,
+        XOFF, XOFF, b'$', b'M', b'<', 0, 1, 1,
+        b'I', 2, 0xac, 0x02,
+    ]);
+
+    let read = |serial_artifacts| {
+        let diagnostics = Arc::new(Mutex::new(Vec::new()));
+        let options = ReaderOptions {
+            serial_artifacts,
+            diagnostics: Some(diagnostics.clone()),
+            ..Default::default()
+        };
+        let mut reader = BlackboxReader::with_options(&log, options).unwrap();
+        let mut main = 0;
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(_) = record {
+                main += 1;
+            }
+        }
+        let kinds: Vec<_> = diagnostics
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|diagnostic| diagnostic.kind.clone())
+            .collect();
+        (main, *reader.stats(), kinds)
+    };
+
+    let (main, stats, _) = read(None);
+    assert!(main < 3);
+    assert!(stats.garbage_bytes > 0);
+
+    let (main, stats, kinds) = read(Some(SerialArtifacts::default()));
+    assert_eq!(main, 3);
+    assert_eq!(stats.garbage_bytes, 0);
+    assert_eq!(stats.serial_artifact_bytes, 9);
+    assert_eq!(
+        kinds,
+        [
+            DiagnosticKind::SerialArtifacts { len: 1 },
+            DiagnosticKind::SerialArtifacts { len: 8 },
+        ]
+    );
+}
+
+#[test]
+fn frame_stats_count_each_frame_type() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 100,
+        b'P', 2,
+        b'H', 20, 40, 60,
+        b'G', 2, 4, 6,
+        // loopIteration jumps by 6000, the P-frame after it can't be trusted either
+        b'I', 0xf0, 0x2e, 0xac, 0x02,
+        b'P', 2,
+        b'I', 4, 0x90, 0x03,
+        // Not followed by another frame
+        b'G', 2, 4, 6, 0xff,
+    ]);
+    log.extend_from_slice(b"E\xffEnd of log\0");
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    while reader.next().is_some() {}
+    let stats = reader.frame_stats();
+    let counts = |valid, corrupt, desynced| FrameCounts {
+        valid,
+        corrupt,
+        desynced,
+    };
+    assert_eq!(stats.get(b'I'), Some(&counts(2, 1, 0)));
+    assert_eq!(stats.get(b'P'), Some(&counts(1, 0, 1)));
+    assert_eq!(stats.get(b'G'), Some(&counts(1, 1, 0)));
+    assert_eq!(stats.get(b'H'), Some(&counts(1, 0, 0)));
+    assert_eq!(stats.get(b'E'), Some(&counts(1, 0, 0)));
+    assert_eq!(stats.get(b'X'), None);
+    assert_eq!(
+        stats.iter().map(|(marker, _)| marker).collect::<String>(),
+        "IPSGHE"
+    );
+}
+
+#[test]
+fn diagnostics_report_recovered_problems() {
+    let header = std::str::from_utf8(SYNTHETIC_HEADER)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with("H Field H"))
+        .map(|line| match line {
+            "H Field G signed:1,1,1" => "H Field G signed:1,1,1,1\n".to_owned(),
+            line => format!("{line}\n"),
+        })
+        .collect::<String>()
+        + "H looptime:250\nH looptime:fast\n";
+    let mut log = header.clone().into_bytes();
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 100,
+        b'H',
+        b'E', 0x63, 0x01, 0x02,
+        b'I', 1, 0xc8, 0x01,
+        0xff, 0xff, 0xff,
+        b'I', 2, 0x90, 0x03,
+    ]);
+
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let options = ReaderOptions {
+        recovery: RecoveryPolicy {
+            unknown_event: RecoveryAction::Skip,
+            header_anomaly: RecoveryAction::Skip,
+            ..Default::default()
+        },
+        diagnostics: Some(diagnostics.clone()),
+        ..Default::default()
+    };
+    let mut reader = BlackboxReader::with_options(&log, options).unwrap();
+    while reader.next().is_some() {}
+
+    let line = |start: &str| header.find(start).unwrap();
+    let body = header.len();
+    let diagnostic = |offset, kind| Diagnostic { offset, kind };
+    let diagnostics = diagnostics.lock().unwrap();
+    assert_eq!(
+        *diagnostics,
+        [
+            diagnostic(
+                line("H looptime:250"),
+                DiagnosticKind::DuplicateHeader {
+                    name: "looptime".to_owned()
+                }
+            ),
+            diagnostic(line("H looptime:fast"), DiagnosticKind::SkippedHeaderLine),
+            diagnostic(
+                line("H Field G signed"),
+                DiagnosticKind::FieldListLength {
+                    header: "Field G signed",
+                    expected: 3,
+                    found: 4
+                }
+            ),
+            diagnostic(body + 3, DiagnosticKind::EmptyHomeFrame),
+            diagnostic(body + 4, DiagnosticKind::IgnoredUnknownEvent { code: 0x63 }),
+            // Up to the last I-frame, the one before isn't followed by a valid frame
+            diagnostic(body + 4, DiagnosticKind::Garbage { len: 11 }),
+        ]
+    );
+    assert_eq!(diagnostics[0].severity(), Severity::Info);
+    assert_eq!(diagnostics[1].severity(), Severity::Warning);
+}
+
+#[test]
+fn header_validation_reports_every_violation() {
+    let header = std::str::from_utf8(SYNTHETIC_HEADER)
+        .unwrap()
+        .lines()
+        .filter(|line| *line != "H looptime:125")
+        .map(|line| match line {
+            "H Field I signed:0,0" => "H Field I signed:0",
+            "H Field I encoding:1,1" => "H Field I encoding:1,40",
+            "H Field I predictor:0,0" => "H Field I predictor:0,4",
+            "H Field P predictor:6,2" => "H Field P predictor:6,8",
+            "H Field H encoding:0,0,0" => "H Field H encoding:0,0,9",
+            line => line,
+        })
+        .map(|line| format!("{line}\n"))
+        .collect::<String>()
+        + "I";
+    let options = ReaderOptions {
+        validate_header: true,
+        ..Default::default()
+    };
+    let violations = match BlackboxReader::with_options(header.as_bytes(), options) {
+        Err(BlackboxReaderError::InvalidHeader(violations)) => violations,
+        other => panic!("unexpected {:?}", other.err()),
+    };
+    let owned = |value: &str| value.to_owned();
+    assert_eq!(
+        violations,
+        [
+            HeaderViolation::MissingHeader("looptime"),
+            HeaderViolation::FieldListLength {
+                header: "Field I signed",
+                expected: 2,
+                found: 1
+            },
+            HeaderViolation::UnsupportedEncoding {
+                field: owned("time"),
+                encoding: 40
+            },
+            HeaderViolation::EncodingNotAllowed {
+                field: owned("GPS_home[2]"),
+                frame: 'H',
+                encoding: 9
+            },
+            HeaderViolation::MissingHeaderForPredictor {
+                field: owned("time"),
+                header: "minthrottle"
+            },
+            HeaderViolation::UnsupportedPredictor {
+                field: owned("time"),
+                predictor: owned("Around1500")
+            },
+        ]
+    );
+
+    // Valid headers decode the same with validation
+    let options = ReaderOptions {
+        validate_header: true,
+        ..Default::default()
+    };
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    assert!(BlackboxReader::with_options(&buf, options).is_ok());
+}
+
+#[test]
+fn time_rollover_is_widened() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 0xd8, 0xfd, 0xff, 0xff, 0x0f,
+        b'I', 1, 0xc8, 0x01,
+    ]);
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    let mut times = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            let raw = values.value("time").unwrap();
+            times.push((raw, reader.last_widened_time));
+        }
+    }
+    assert_eq!(times, [(4294967000, 4294967000), (200, 4294967496)]);
+    assert_eq!(reader.stats().corrupted_frames, 0);
+}
+
+#[test]
+fn range_and_seek_use_widened_time() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    // 4294967096, 4294967196, then 4 and 104 after the rollover
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 0xb8, 0xfe, 0xff, 0xff, 0x0f,
+        b'I', 1, 0x9c, 0xff, 0xff, 0xff, 0x0f,
+        b'I', 2, 4,
+        b'I', 3, 0x68,
+    ]);
+
+    let mut reader = BlackboxReader::from_bytes(&log)
+        .unwrap()
+        .range(4294967250, 4294967350);
+    let mut frames = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            frames.push((
+                values.value("loopIteration").unwrap(),
+                reader.last_widened_time,
+            ));
+        }
+    }
+    assert_eq!(frames, [(2, 4294967300)]);
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    let keyframe = reader.seek_to_time(4294967450).unwrap();
+    assert_eq!((keyframe.loop_iteration, keyframe.time), (3, 4294967400));
+    assert!(matches!(reader.next(), Some(BlackboxRecord::Main(_))));
+    assert_eq!(
+        (reader.last_time, reader.last_widened_time),
+        (104, 4294967400)
+    );
+}
+
+#[test]
+fn segment_time_span_is_widened() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    // 4294967096, 4294967196, then 4 and 104 after the rollover
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 0xb8, 0xfe, 0xff, 0xff, 0x0f,
+        b'I', 1, 0x9c, 0xff, 0xff, 0xff, 0x0f,
+        b'I', 2, 4,
+        b'I', 3, 0x68,
+    ]);
+
+    let segments = MultiSegmentBlackboxReader::from_bytes(&log).segments();
+    assert_eq!(segments[0].time_span, Some((4294967096, 4294967400)));
+    assert_eq!(segments[0].duration(), Some(304));
+}
+
+#[test]
+fn logs_without_loop_iteration_still_decode() {
+    let header = std::str::from_utf8(SYNTHETIC_HEADER)
+        .unwrap()
+        .replace("loopIteration", "counter");
+    let mut log = header.into_bytes();
+    log.extend_from_slice(&[b'I', 0, 100, b'I', 7, 0xc8, 0x01]);
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    let mut counters = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            counters.push(values.value("counter").unwrap());
+        }
+    }
+    assert_eq!(counters, [0, 7]);
+    assert_eq!(reader.last_loop_iteration, 0);
+    assert_eq!(reader.last_time, 200);
+}
+
+#[test]
+fn custom_predictors_decode_unknown_predictor_ids() {
+    let header = std::str::from_utf8(SYNTHETIC_HEADER)
+        .unwrap()
+        .replace("P predictor:6,2", "P predictor:6,42");
+    let mut log = header.into_bytes();
+    // P-frame time is 5, zigzag encoded
+    log.extend_from_slice(&[b'I', 0, 100, b'P', 10]);
+
+    assert!(matches!(
+        BlackboxReader::from_bytes(&log),
+        Err(BlackboxReaderError::UnsupportedPredictor { field, predictor })
+            if field == "time" && predictor == "Custom(42)"
+    ));
+
+    let options = ReaderOptions {
+        extensions: Extensions::new().with_predictor(42, |value, context: &PredictorContext| {
+            context.previous[context.field_ix] + 2 * value
+        }),
+        ..Default::default()
+    };
+    let mut reader = BlackboxReader::with_options(&log, options).unwrap();
+    let mut main = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            main.push(values.to_vec());
+        }
+    }
+    assert_eq!(main, [[0, 100], [1, 110]]);
+}
+
+#[test]
+fn custom_encodings_decode_unknown_encoding_ids() {
+    let header = std::str::from_utf8(SYNTHETIC_HEADER)
+        .unwrap()
+        .replace("I encoding:1,1", "I encoding:1,40");
+    let mut log = header.into_bytes();
+    log.extend_from_slice(&[b'I', 0, 0x2c, 0x01, b'I', 1, 0x90, 0x01]);
+
+    assert!(matches!(
+        BlackboxReader::from_bytes(&log),
+        Err(BlackboxReaderError::UnsupportedEncoding { field, encoding: 40 }) if field == "time"
+    ));
+
+    let le_u16 = |input: &[u8], count: usize, values: &mut Vec<i64>| {
+        let bytes = input.get(..count * 2).ok_or(DecodeError::Incomplete)?;
+        values.extend(
+            bytes
+                .chunks(2)
+                .map(|b| i64::from(u16::from_le_bytes([b[0], b[1]]))),
+        );
+        Ok(bytes.len())
+    };
+    let options = ReaderOptions {
+        extensions: Extensions::new().with_encoding(40, 1, le_u16),
+        ..Default::default()
+    };
+    assert!(matches!(
+        Header::parse(&log),
+        Err(BlackboxReaderError::UnsupportedEncoding { encoding: 40, .. })
+    ));
+    assert!(Header::parse_with(&log, &options).is_ok());
+    let mut reader = BlackboxReader::with_options(&log, options).unwrap();
+    let mut main = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            main.push(values.to_vec());
+        }
+    }
+    assert_eq!(main, [[0, 300], [1, 400]]);
+}
+
+#[test]
+fn recovery_policy_is_applied_per_failure_class() {
+    let read = |log: &[u8], recovery| -> Result<(Vec<String>, usize), BlackboxReaderError> {
+        let mut reader = BlackboxReader::new(log, recovery)?;
+        let mut records = Vec::new();
+        while let Some(record) = reader.next() {
+            records.push(match record {
+                BlackboxRecord::Main(values) => format!("{:?}", values.values()),
+                BlackboxRecord::Garbage(span) => format!("garbage {}", span.len),
+                _ => "other".to_owned(),
+            });
+        }
+        Ok((records, reader.remaining_bytes.len()))
+    };
+    let with_header_anomaly = |header_anomaly| RecoveryPolicy {
+        header_anomaly,
+        ..Default::default()
+    };
+
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    log.extend_from_slice(b"H looptime:fast\n");
+    log.extend_from_slice(&[b'I', 0, 100]);
+    assert_eq!(
+        read(&log, with_header_anomaly(RecoveryAction::Resync)).unwrap(),
+        (vec!["garbage 16".to_owned(), "[0, 100]".to_owned()], 0)
+    );
+    assert_eq!(
+        read(&log, with_header_anomaly(RecoveryAction::Skip)).unwrap(),
+        (vec!["[0, 100]".to_owned()], 0)
+    );
+    match read(&log, with_header_anomaly(RecoveryAction::Abort)) {
+        Err(BlackboxReaderError::MalformedHeader { line, offset }) => {
+            assert_eq!(line, "H looptime:fast");
+            assert_eq!(offset, SYNTHETIC_HEADER.len());
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 100,
+        b'I', 0xf0, 0x2e, 0xac, 0x02,
+        b'I', 2, 0x90, 0x03,
+    ]);
+    let policy = RecoveryPolicy {
+        implausible_frame: RecoveryAction::Abort,
+        ..Default::default()
+    };
+    assert_eq!(
+        read(&log, policy).unwrap(),
+        (vec!["[0, 100]".to_owned()], 9)
+    );
+    assert_eq!(
+        read(&log, RecoveryPolicy::strict()).unwrap(),
+        (vec!["[0, 100]".to_owned()], 9)
+    );
+
+    // The I-frame isn't followed by a valid frame, so it's skipped too
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    log.extend_from_slice(&[b'I', 0, 100]);
+    log.extend_from_slice(&[0xff; 8]);
+    log.extend_from_slice(&[b'I', 1, 0xc8, 0x01]);
+    let policy = RecoveryPolicy {
+        max_garbage_bytes: Some(4),
+        ..Default::default()
+    };
+    assert_eq!(
+        read(&log, policy).unwrap(),
+        (vec!["garbage 5".to_owned()], 10)
+    );
+}
+
+#[test]
+fn garbage_limits_stop_reading_with_an_error() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    log.extend_from_slice(&[b'I', 0, 100]);
+    log.extend_from_slice(&[0xff; 64]);
+    for iteration in 1..=10u8 {
+        log.extend_from_slice(&[b'I', iteration, 0xc8, 0x01]);
+    }
+    let read = |recovery| {
+        let mut reader = BlackboxReader::new(&log, recovery).unwrap();
+        let mut main_frames = 0;
+        while let Some(record) = reader.next() {
+            main_frames += matches!(record, BlackboxRecord::Main(_)) as usize;
+        }
+        (main_frames, reader.stop_error().map(ToString::to_string))
+    };
+
+    let limits = |max_garbage_bytes, max_garbage_fraction| RecoveryPolicy {
+        max_garbage_bytes,
+        max_garbage_fraction,
+        ..Default::default()
+    };
+    assert_eq!(read(limits(None, Some(0.9))), (10, None));
+    assert_eq!(
+        read(limits(None, Some(0.5))),
+        (
+            0,
+            Some("gave up after skipping 54 bytes, more than 0.5 of the log".to_owned())
+        )
+    );
+    assert_eq!(
+        read(limits(Some(16), None)),
+        (
+            0,
+            Some(format!(
+                "gave up after 17 bytes of garbage at byte {}",
+                SYNTHETIC_HEADER.len()
+            ))
+        )
+    );
+}
+
+#[test]
+fn last_frame_span_covers_each_record() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    #[rustfmt::skip]
+    log.extend_from_slice(&[
+        b'I', 0, 100,
+        b'I', 1, 0xc8, 0x01,
+        b'I', 0xf0, 0x2e, 0xac, 0x02,
+        b'I', 2, 0x90, 0x03,
+    ]);
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    assert_eq!(reader.last_frame_span(), None);
+    let mut spans = Vec::new();
+    while reader.next().is_some() {
+        let span = reader.last_frame_span().unwrap();
+        spans.push((span.offset - SYNTHETIC_HEADER.len(), span.len));
+    }
+    assert_eq!(spans, [(0, 3), (3, 4), (7, 5), (12, 4)]);
+}
+
+#[test]
+fn reading_stops_at_end_of_log() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    log.extend_from_slice(&[b'I', 0, 100]);
+    log.extend_from_slice(b"E\xffEnd of log\0");
+    log.extend_from_slice(&[b'I', 1, 0xc8, 0x01]);
+    log.extend_from_slice(&[0xff; 16]);
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    let mut records = Vec::new();
+    while let Some(record) = reader.next() {
+        records.push(match record {
+            BlackboxRecord::Main(values) => format!("{:?}", values.values()),
+            BlackboxRecord::Event(event) => format!("{:?}", event),
+            _ => "other".to_owned(),
+        });
+    }
+    assert_eq!(records, ["[0, 100]", "EndOfLog"]);
+    assert_eq!(reader.trailing_bytes(), Some(20));
+    assert_eq!(reader.progress(), 1.0);
+}
+
+#[test]
+fn end_of_log_followed_by_erased_flash_is_read() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    log.extend_from_slice(&[b'I', 0, 100]);
+    log.extend_from_slice(b"E\xffEnd of log\0");
+    log.extend_from_slice(&[0xff; 16]);
+
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    let mut records = Vec::new();
+    while let Some(record) = reader.next() {
+        records.push(match record {
+            BlackboxRecord::Main(values) => format!("{:?}", values.values()),
+            BlackboxRecord::Event(event) => format!("{:?}", event),
+            _ => "other".to_owned(),
+        });
+    }
+    assert_eq!(records, ["[0, 100]", "EndOfLog"]);
+    assert_eq!(reader.trailing_bytes(), Some(16));
+    assert_eq!(reader.stats().garbage_bytes, 0);
+}
+
+#[test]
+fn logs_are_carved_out_of_raw_images() {
+    let mut image = vec![0; 1000];
+    let first = image.len();
+    image.extend_from_slice(SYNTHETIC_HEADER);
+    image.extend_from_slice(&[b'I', 0, 100, b'I', 1, 0xc8, 0x01]);
+    // Erased flash in the middle of the log
+    image.extend_from_slice(&[0xff; 600]);
+    image.extend_from_slice(&[b'I', 2, 0x90, 0x03]);
+    image.extend_from_slice(b"E\xffEnd of log\0");
+    let first_end = image.len();
+    image.extend_from_slice(&[0; 5000]);
+    let second = image.len();
+    image.extend_from_slice(SYNTHETIC_HEADER);
+    image.extend_from_slice(&[b'I', 0, 100]);
+    let second_end = image.len();
+    // Not followed by another frame, so it can't be told from noise
+    image.extend_from_slice(&[b'I', 1, 0xc8, 0x01]);
+    // Too far from the log to belong to it
+    image.extend_from_slice(&[0xff; 100_000]);
+    image.extend_from_slice(&[b'I', 2, 0x90, 0x03]);
+    // Not a log
+    image.extend_from_slice(b"H Product:Blackbox\n");
+
+    let logs = MultiSegmentBlackboxReader::from_bytes(&image).carve(CarveOptions::default());
+    let summary: Vec<_> = logs
+        .iter()
+        .map(|log| (log.range(), log.complete, log.time_span))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (first..first_end, true, Some((100, 400))),
+            (second..second_end, false, Some((100, 100))),
+        ]
+    );
+    // Including the frame before the erased flash, which isn't followed by another frame
+    assert_eq!(logs[0].stats.garbage_bytes, 604);
+
+    let mut reader = BlackboxReader::from_bytes(&image[logs[0].range()]).unwrap();
+    while reader.next().is_some() {}
+    assert_eq!(reader.stats().main_frames, 2);
+    assert_eq!(reader.trailing_bytes(), Some(0));
+}
+
+#[test]
+fn reading_stops_at_the_header_of_a_restarted_log() {
+    let mut log = SYNTHETIC_HEADER.to_vec();
+    // The last frame is cut off by the restart, and would decode into the header text
+    log.extend_from_slice(&[b'I', 0, 100, b'I', 1, 0xc8, 0x01, b'I']);
+    let restart = log.len();
+    log.extend_from_slice(SYNTHETIC_HEADER);
+    log.extend_from_slice(&[b'I', 0, 100]);
+    log.extend_from_slice(b"E\xffEnd of log\0");
+
+    let diagnostics = Arc::new(Mutex::new(Vec::new()));
+    let options = ReaderOptions {
+        diagnostics: Some(diagnostics.clone()),
+        ..Default::default()
+    };
+    let read = |reader: &mut BlackboxReader| {
+        let mut records = Vec::new();
+        while let Some(record) = reader.next() {
+            records.push(match record {
+                BlackboxRecord::Main(values) => format!("{:?}", values.values()),
+                BlackboxRecord::Event(event) => format!("{:?}", event),
+                BlackboxRecord::Garbage(span) => format!("garbage {}", span.len),
+                _ => "other".to_owned(),
+            });
+        }
+        records
+    };
+
+    let mut reader = BlackboxReader::with_options(&log, options.clone()).unwrap();
+    assert_eq!(reader.next_segment(), Some(restart));
+    assert_eq!(read(&mut reader), ["[0, 100]", "[1, 200]", "garbage 1"]);
+    assert_eq!(reader.bytes_read(), restart);
+    assert_eq!(reader.progress(), 1.0);
+    assert_eq!(
+        diagnostics.lock().unwrap().last(),
+        Some(&Diagnostic {
+            offset: restart,
+            kind: DiagnosticKind::LogRestarted,
+        })
+    );
+
+    let logs: Vec<_> = MultiSegmentBlackboxReader::with_options(&log, options)
+        .map(|reader| read(&mut reader.unwrap()))
+        .collect();
+    assert_eq!(
+        logs,
+        [
+            vec!["[0, 100]", "[1, 200]", "garbage 1"],
+            vec!["[0, 100]", "EndOfLog"]
+        ]
+    );
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn csv_export_matches_blackbox_decode_layout() {
+    use crate::export::csv::{self, CsvOptions};
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let mut out = Vec::new();
+    let reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let main_frames = {
+        let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+        let mut n = 0;
+        while let Some(record) = reader.next() {
+            n += matches!(record, BlackboxRecord::Main(_)) as usize;
+        }
+        n
+    };
+    csv::write(reader, &mut out, CsvOptions::default()).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let mut lines = out.lines();
+    let labels: Vec<&str> = lines.next().unwrap().split(", ").collect();
+    assert_eq!(labels[..2], ["loopIteration", "time (us)"]);
+    let energy = labels
+        .iter()
+        .position(|l| *l == "energyCumulative (mAh)")
+        .unwrap();
+    assert_eq!(labels[energy - 1], "motor[3]");
+    assert_eq!(labels[energy + 1], "flightModeFlags (flags)");
+    assert!(labels.contains(&"vbatLatest (V)"));
+
+    let rows: Vec<Vec<&str>> = lines.map(|l| l.split(", ").collect()).collect();
+    assert_eq!(rows.len(), main_frames);
+    assert!(rows.iter().all(|r| r.len() == labels.len()));
+    let vbat = labels.iter().position(|l| *l == "vbatLatest (V)").unwrap();
+    assert!(rows[0][vbat].parse::<f64>().unwrap() > 10.0);
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn csv_export_scales_raw_sensor_values() {
+    use crate::export::csv::{self, CsvOptions};
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let options = CsvOptions {
+        scaled: true,
+        ..CsvOptions::default()
+    };
+    let mut out = Vec::new();
+    csv::write(BlackboxReader::from_bytes(&buf).unwrap(), &mut out, options).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let mut lines = out.lines();
+    let labels: Vec<&str> = lines.next().unwrap().split(", ").collect();
+    assert_eq!(labels[1], "time (us)");
+    assert!(labels.contains(&"gyroADC[0] (deg/s)"));
+    let acc_z = labels
+        .iter()
+        .position(|l| *l == "accSmooth[2] (g)")
+        .unwrap();
+    let row: Vec<&str> = lines.next().unwrap().split(", ").collect();
+    assert!((row[acc_z].parse::<f64>().unwrap() - 1.0).abs() < 0.2);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_export_writes_one_object_per_record() {
+    use crate::export::json::{self, JsonOptions};
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let export = |options: &JsonOptions| {
+        let mut out = Vec::new();
+        let reader = BlackboxReader::from_bytes(&buf).unwrap();
+        json::write(reader, &mut out, options).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    let raw = export(&JsonOptions::default());
+    assert_eq!(raw[0]["type"], "header");
+    assert_eq!(raw[0]["headers"]["motorOutput"], "158,2047");
+    assert_eq!(raw[0]["fields"]["main"][1], "time");
+    let main = raw.iter().find(|o| o["type"] == "main").unwrap();
+    assert!(main["vbatLatest"].is_i64());
+    assert!(raw.iter().any(|o| o["type"] == "slow"));
+    assert!(!raw.iter().any(|o| o["type"] == "event"));
+
+    let options = JsonOptions {
+        scaled: true,
+        fields: Some(vec!["time".to_owned(), "vbatLatest".to_owned()]),
+        events: true,
+    };
+    let scaled = export(&options);
+    let main = scaled.iter().find(|o| o["type"] == "main").unwrap();
+    assert_eq!(main.as_object().unwrap().len(), 3);
+    let vbat = main["vbatLatest"].as_f64().unwrap();
+    assert!(vbat > 10.0 && vbat < 30.0, "{}", vbat);
+    assert!(scaled.iter().any(|o| o["type"] == "event"));
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn arrow_export_types_columns_per_field() {
+    use crate::{export::arrow, FieldKind};
+    use arrow_schema::DataType;
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let batches = arrow::record_batches(reader, FieldKind::Main, 1000).unwrap();
+    assert!(batches.len() > 1);
+    assert!(batches[..batches.len() - 1]
+        .iter()
+        .all(|b| b.num_rows() == 1000));
+    let schema = batches[0].schema();
+    assert_eq!(schema.field(0).name(), "loopIteration");
+    assert_eq!(schema.field(0).data_type(), &DataType::UInt32);
+    assert_eq!(schema.field(1).data_type(), &DataType::Int64);
+    let axis_p = schema.index_of("axisP[0]").unwrap();
+    assert_eq!(schema.field(axis_p).data_type(), &DataType::Int32);
+    assert_eq!(schema.metadata()["motorOutput"], "158,2047");
+
+    let reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let slow = arrow::record_batches(reader, FieldKind::Slow, 1000).unwrap();
+    assert_eq!(slow[0].schema().field(0).name(), "flightModeFlags");
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_export_round_trips() {
+    use crate::FieldKind;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let path = std::env::temp_dir().join("fc-blackbox-parquet-export.parquet");
+    let reader = BlackboxReader::from_bytes(&buf).unwrap();
+    crate::export::parquet::write(reader, FieldKind::Main, File::create(&path).unwrap()).unwrap();
+
+    let reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let expected =
+        crate::export::arrow::record_batches(reader, FieldKind::Main, usize::MAX).unwrap();
+    let rows: usize = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+        .unwrap()
+        .build()
+        .unwrap()
+        .map(|b| b.unwrap().num_rows())
+        .sum();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(rows, expected[0].num_rows());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_serializes_headers_and_records() {
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+
+    let header = serde_json::to_value(&reader.header).unwrap();
+    assert_eq!(header["firmware_kind"], "Betaflight");
+    assert_eq!(header["p_interval"], "1/16");
+    assert_eq!(header["other_headers"]["motorOutput"], "158,2047");
+    assert_eq!(header["settings"]["motor_output"][1], 2047);
+    assert_eq!(header["main_fields"][1]["name"], "time");
+
+    let (mut main, mut event) = (None, None);
+    while let Some(record) = reader.next() {
+        let json = serde_json::to_value(&record).unwrap();
+        match record {
+            BlackboxRecord::Main(values) if main.is_none() => {
+                assert_eq!(json["Main"]["time"], values.value("time").unwrap());
+                main = Some(record.to_owned_record());
+            }
+            BlackboxRecord::Event(_) if event.is_none() => event = Some(json),
+            _ => {}
+        }
+    }
+    let main = serde_json::to_value(main.unwrap()).unwrap();
+    assert!(main["Main"].as_array().unwrap().len() > 2);
+    assert!(event.unwrap()["Event"].is_object());
+}
+
+#[cfg(all(feature = "gpx", feature = "kml"))]
+#[test]
+fn flight_path_exports() {
+    use crate::export::{
+        gpx,
+        kml::{self, KmlOptions},
+    };
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let reader = || BlackboxReader::from_bytes(&buf).unwrap();
+
+    let mut out = Vec::new();
+    gpx::write(reader(), &mut out).unwrap();
+    let gpx = String::from_utf8(out).unwrap();
+    let points = gpx.matches("<trkpt ").count();
+    assert!(points > 0);
+    assert!(gpx.trim_end().ends_with("</gpx>"));
+
+    let mut out = Vec::new();
+    kml::write(reader(), &mut out, KmlOptions::default()).unwrap();
+    let plain = String::from_utf8(out).unwrap();
+    assert_eq!(plain.matches("<Placemark>").count(), 1);
+    assert!(plain.contains("<extrude>1</extrude>"));
+    let coordinates = plain
+        .split("<coordinates>")
+        .nth(1)
+        .unwrap()
+        .split("</coordinates>")
+        .next()
+        .unwrap();
+    assert_eq!(coordinates.split_whitespace().count(), points);
+
+    let options = KmlOptions {
+        extrude: false,
+        color_by_speed: true,
+    };
+    let mut out = Vec::new();
+    kml::write(reader(), &mut out, options).unwrap();
+    let colored = String::from_utf8(out).unwrap();
+    assert!(colored.matches("<Placemark>").count() > 1);
+    assert!(colored.contains("<Style id=\"speed7\">"));
+
+    let mut kmz = Vec::new();
+    kml::write_kmz(reader(), &mut kmz, KmlOptions::default()).unwrap();
+    assert!(kmz.starts_with(b"PK\x03\x04"));
+    assert!(kmz.windows(plain.len()).any(|w| w == plain.as_bytes()));
+    assert_eq!(&kmz[kmz.len() - 22..kmz.len() - 18], b"PK\x05\x06");
+}
+
+#[cfg(feature = "gyroflow")]
+#[test]
+fn gyroflow_export() {
+    use crate::export::gyroflow;
+
+    let buf = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+    let reader = || BlackboxReader::from_bytes(&buf).unwrap();
+    let mut counted = reader();
+    while counted.next().is_some() {}
+    let main_frames = counted.stats().main_frames as usize;
+
+    let samples = gyroflow::samples(reader()).unwrap();
+    assert_eq!(samples.len(), main_frames);
+    assert!(samples.windows(2).all(|w| w[0].time < w[1].time));
+    // Mostly gravity on a resting quad
+    let [ax, ay, az] = samples[0].acc.unwrap();
+    assert!((ax * ax + ay * ay + az * az).sqrt() > 0.5);
+
+    let mut out = Vec::new();
+    gyroflow::write_gcsv(reader(), &mut out).unwrap();
+    let gcsv = String::from_utf8(out).unwrap();
+    let mut lines = gcsv.lines();
+    assert_eq!(lines.next(), Some("GYROFLOW IMU LOG"));
+    assert_eq!(lines.next(), Some("version,1.3"));
+    assert!(gcsv.contains("\norientation,XYZ\n"));
+    assert!(gcsv.contains("\ntscale,0.000001\n"));
+    let rows = gcsv.split("t,gx,gy,gz,ax,ay,az\n").nth(1).unwrap();
+    assert_eq!(rows.lines().count(), main_frames);
+    assert!(rows.starts_with("0,"));
+
+    let header = std::str::from_utf8(SYNTHETIC_HEADER)
+        .unwrap()
+        .replace("H looptime:125\n", "H looptime:125\nH gyro_1_align:6\n");
+    let header = Header::parse(header.as_bytes()).unwrap();
+    assert_eq!(gyroflow::orientation(&header), "YXz");
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn ffi_reader() {
+    use crate::ffi::*;
+    use std::ffi::CStr;
+
+    let buf = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let mut expected = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(view) = record {
+            expected.push(view.values().to_vec());
+        }
+    }
+
+    unsafe {
+        assert!(fcb_reader_open(b"garbage".as_ptr(), 7).is_null());
+        assert!(!fcb_last_error().is_null());
+
+        let ffi = fcb_reader_open(buf.as_ptr(), buf.len());
+        assert!(!ffi.is_null());
+        let main_kind = FcbFieldKind::Main as u32;
+        let count = fcb_reader_field_count(ffi, main_kind);
+        assert_eq!(count, reader.header.ip_fields_in_order.len());
+        let name = CStr::from_ptr(fcb_reader_field_name(ffi, main_kind, 1));
+        assert_eq!(name.to_str(), Ok("time"));
+        assert!(fcb_reader_field_name(ffi, main_kind, count).is_null());
+        // Kinds come from C as plain integers
+        assert_eq!(fcb_reader_field_count(ffi, 3), 0);
+        assert!(fcb_reader_field_name(ffi, u32::MAX, 0).is_null());
+
+        let mut main = Vec::new();
+        loop {
+            match fcb_reader_next(ffi) {
+                FcbRecordKind::End => break,
+                FcbRecordKind::Main => {
+                    let mut len = 0;
+                    let values = fcb_reader_values(ffi, &mut len);
+                    main.push(std::slice::from_raw_parts(values, len).to_vec());
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(main, expected);
+        fcb_reader_free(ffi);
+    }
+}
+
+#[cfg(feature = "bench")]
+#[test]
+fn synthetic_logs_decode_cleanly() {
+    use crate::synthetic::{SyntheticEncoding, SyntheticLog};
+
+    for encoding in [
+        SyntheticEncoding::SignedVB,
+        SyntheticEncoding::Tag8_8SVB,
+        SyntheticEncoding::Tag2_3S32,
+        SyntheticEncoding::Tag8_4S16,
+    ] {
+        let synthetic = SyntheticLog {
+            fields: 13,
+            encoding,
+            frames: 1000,
+            ..Default::default()
+        };
+        let log = synthetic.generate();
+        assert_eq!(log, synthetic.generate());
+
+        let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+        let mut iteration = 0;
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(values) = record {
+                assert_eq!(values.value("loopIteration"), Some(iteration));
+                assert!(values.values()[2..].iter().all(|v| v.abs() <= 2000));
+                iteration += 1;
+            }
+        }
+        assert_eq!(iteration, 1000);
+        assert_eq!(reader.stats().resyncs, 0);
+        assert!(reader.last_time > 1000 * 120);
+    }
+
+    let log = SyntheticLog {
+        corruption_rate: 0.05,
+        ..Default::default()
+    }
+    .generate();
+    let mut reader = BlackboxReader::from_bytes(&log).unwrap();
+    while reader.next().is_some() {}
+    assert!(reader.stats().resyncs > 0);
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn fuzz_logs_mostly_have_valid_headers() {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    use crate::fuzz::FuzzLog;
+
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut bytes = vec![0; 4096];
+    let mut accepted = 0;
+    for _ in 0..1000 {
+        for byte in &mut bytes {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *byte = state as u8;
+        }
+        let log = FuzzLog::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+        accepted += BlackboxReader::from_bytes(&log.to_bytes()).is_ok() as usize;
+    }
+    assert!(accepted > 500, "{accepted} of 1000 headers accepted");
+}
+
+// Errors are JS objects, so only the successful paths can run outside the browser
+#[cfg(feature = "wasm-bindgen")]
+#[test]
+fn wasm_blackbox_file() {
+    use crate::wasm::BlackboxFile;
+
+    let buf = std::fs::read("src/test-data/btfl_all.bbl").unwrap();
+    let file = BlackboxFile::new(buf.clone());
+    let readers: Vec<_> = MultiSegmentBlackboxReader::from_bytes(&buf)
+        .successful_only()
+        .collect();
+    assert_eq!(file.log_count(), readers.len());
+
+    let mut reader = readers.into_iter().next().unwrap();
+    let names = file.field_names(0, "main").unwrap();
+    assert_eq!(names.len(), reader.header.ip_fields_in_order.len());
+    assert_eq!(
+        file.header_value(0, "minthrottle").unwrap().as_deref(),
+        Some("1070")
+    );
+
+    let frames = file.frames(0, "main").unwrap();
+    let mut expected = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(view) = record {
+            expected.extend(view.values().iter().map(|v| *v as f64));
+        }
+    }
+    assert_eq!(frames, expected);
+}
+
+// Arrays need numpy installed, so only the plain Python values are checked here
+#[cfg(feature = "python")]
+#[test]
+fn python_module() {
+    use pyo3::{prelude::*, types::PyDict, wrap_pymodule};
+
+    Python::initialize();
+    Python::attach(|py| {
+        let locals = PyDict::new(py);
+        locals
+            .set_item("pyblackbox", wrap_pymodule!(crate::python::pyblackbox)(py))
+            .unwrap();
+        locals
+            .set_item("path", "src/test-data/btfl_all.bbl")
+            .unwrap();
+        py.run(
+            c"
+assert pyblackbox.log_count(path) > 1
+log = pyblackbox.open(path, 1)
+assert log.headers['minthrottle'] == '1070'
+assert log.headers['Product'].startswith('Blackbox flight data recorder')
+assert log.field_names()[:2] == ['loopIteration', 'time']
+assert 'GPS_numSat' not in log.field_names('slow')
+try:
+    log.field_names('nope')
+    assert False
+except ValueError:
+    pass
+try:
+    pyblackbox.open(path, 1000)
+    assert False
+except IndexError:
+    pass
+",
+            None,
+            Some(&locals),
+        )
+        .unwrap();
+    });
+}
+
+#[test]
+fn encoded_frames_parse_back() {
+    use crate::frame::encode::encode_frame;
+
+    let encodings = [
+        FieldEncoding::UnsignedVB,
+        FieldEncoding::SignedVB,
+        FieldEncoding::Negative14BitVB,
+        FieldEncoding::Null,
+        FieldEncoding::Tag8_8SVB(5),
+        FieldEncoding::Tag8_8SVB(1),
+        FieldEncoding::Tag2_3S32(3),
+        FieldEncoding::Tag2_3S32(3),
+        FieldEncoding::Tag2_3S32(3),
+        FieldEncoding::Tag2_3SVariable(3),
+        FieldEncoding::Tag2_3SVariable(3),
+        FieldEncoding::Tag8_4S16(4),
+    ];
+    #[rustfmt::skip]
+    let values = [
+        300, -70_000, -1234, 0,
+        0, -3, 0, 1_000_000, 64, -9,
+        1, -2, 0,
+        -8, 31, 7,
+        -70_000, 130, 5_000_000,
+        -16, 15, -8,
+        -128, 63, -64,
+        0, -8, 127, -32_768,
+    ];
+
+    let mut input = Vec::new();
+    encode_frame(b'I', &encodings, &values, &mut input).unwrap();
+    assert_eq!(input[0], b'I');
+    let mut decoded = Vec::new();
+    let (remaining, ()) = parse_frame_payload(&encodings, &input[1..], &mut decoded).unwrap();
+    assert!(remaining.is_empty());
+    assert_eq!(decoded, values);
+
+    assert!(encode_frame(b'I', &[FieldEncoding::EliasGammaU32], &[1], &mut input).is_err());
+}
+
+#[test]
+fn varints_decode_the_same_at_the_end_of_input() {
+    use crate::frame::encode::encode_frame;
+
+    let mut values = vec![
+        0,
+        1,
+        127,
+        128,
+        300,
+        16_383,
+        16_384,
+        1 << 21,
+        1 << 28,
+        u32::MAX,
+    ];
+    values.extend((0..32).map(|shift| (1u32 << shift) - 1));
+    for value in values {
+        for (encoding, value) in [
+            ([FieldEncoding::UnsignedVB], value as i64),
+            ([FieldEncoding::SignedVB], value as i32 as i64),
+        ] {
+            let mut encoded = Vec::new();
+            encode_frame(b'I', &encoding, &[value], &mut encoded).unwrap();
+            // Without padding, the varint can't be read as a whole word
+            for padding in [0, 8] {
+                let input = [&encoded[1..], &vec![0x55; padding]].concat();
+                let mut decoded = Vec::new();
+                let (remaining, ()) = parse_frame_payload(&encoding, &input, &mut decoded).unwrap();
+                assert_eq!(decoded, [value]);
+                assert_eq!(remaining.len(), padding);
+            }
+        }
+    }
+    for input in [&[0x80; 5][..], &[0xff; 9]] {
+        match parse_frame_payload(&[FieldEncoding::UnsignedVB], input, &mut Vec::new()) {
+            Err(nom::Err::Failure(e)) => assert_eq!(e.input.len(), input.len() - 5),
+            other => panic!("{:?}", other),
+        }
+    }
+}
+
+#[test]
+fn transcode_cuts_a_time_window() {
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let (mut first, mut last) = (None, 0);
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(_) = record {
+            first.get_or_insert(reader.last_time);
+            last = reader.last_time;
+        }
+    }
+    let first = first.unwrap();
+    let window = first + (last - first) / 3..=first + (last - first) / 2;
+
+    let frames = |bytes: &[u8], window: Option<&std::ops::RangeInclusive<i64>>| {
+        let mut reader = BlackboxReader::from_bytes(bytes).unwrap();
+        let (mut main, mut gnss, mut slow) = (Vec::new(), Vec::new(), None);
+        while let Some(record) = reader.next() {
+            let values = match record {
+                BlackboxRecord::Main(view) => view.values().to_vec(),
+                BlackboxRecord::Slow(view) => {
+                    slow = Some(view.values().to_vec());
+                    continue;
+                }
+                BlackboxRecord::GNSS(view) => view.values().to_vec(),
+                _ => continue,
+            };
+            let inside = window.is_none_or(|w| w.contains(&reader.last_time));
+            if window.is_some_and(|w| reader.last_time > *w.end()) {
+                break;
+            }
+            if !inside {
+                continue;
+            }
+            if values.len() == reader.header.ip_fields_in_order.len() {
+                main.push(values);
+            } else if !main.is_empty() {
+                gnss.push((values, reader.gnss_home().to_vec()));
+            }
+        }
+        (main, gnss, slow)
+    };
+
+    let cut = transcode(&buf, window.clone()).unwrap();
+    assert!(cut.len() < buf.len() / 2);
+    assert!(cut.ends_with(b"End of log\0"));
+    let (main, gnss, slow) = frames(&cut, None);
+    let (expected_main, expected_gnss, expected_slow) = frames(&buf, Some(&window));
+    assert!(!main.is_empty());
+    assert!(!gnss.is_empty());
+    assert_eq!(main, expected_main);
+    assert_eq!(gnss, expected_gnss);
+    assert_eq!(slow, expected_slow);
+}
+
+#[test]
+fn anonymize_clears_headers_and_gnss() {
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let records = |bytes: &[u8]| {
+        let mut reader = BlackboxReader::from_bytes(bytes).unwrap();
+        let (mut main, mut gnss) = (Vec::new(), Vec::new());
+        while let Some(record) = reader.next() {
+            match record {
+                BlackboxRecord::Main(view) => main.push(view.values().to_vec()),
+                BlackboxRecord::GNSS(view) => {
+                    gnss.push((view.values().to_vec(), reader.gnss_home().to_vec()))
+                }
+                _ => {}
+            }
+        }
+        (reader.header, main, gnss)
+    };
+    let (_, main, gnss) = records(&buf);
+    assert!(!gnss.is_empty());
+
+    let unchanged = AnonymizeOptions {
+        headers: Vec::new(),
+        gnss: GnssPrivacy::Keep,
+    };
+    assert_eq!(anonymize(&buf, &unchanged).unwrap(), buf);
+
+    let removed = anonymize(&buf, &AnonymizeOptions::default()).unwrap();
+    let (header, removed_main, removed_gnss) = records(&removed);
+    assert_eq!(header.craft_name(), None);
+    assert_eq!(removed_main, main);
+    assert!(removed_gnss.is_empty());
+
+    let moved = AnonymizeOptions {
+        gnss: GnssPrivacy::MoveHome {
+            latitude: 10.0,
+            longitude: -20.0,
+        },
+        ..Default::default()
+    };
+    let (_, moved_main, moved_gnss) = records(&anonymize(&buf, &moved).unwrap());
+    assert_eq!(moved_main, main);
+    assert_eq!(moved_gnss.len(), gnss.len());
+    let (lat, lon) = (
+        header.g_fields["GPS_coord[0]"].ix,
+        header.g_fields["GPS_coord[1]"].ix,
+    );
+    assert_eq!(moved_gnss[0].1[..2], [100_000_000, -200_000_000]);
+    for ((moved, moved_home), (original, home)) in moved_gnss.iter().zip(&gnss) {
+        assert_eq!(moved[lat] - moved_home[0], original[lat] - home[0]);
+        assert_eq!(moved[lon] - moved_home[1], original[lon] - home[1]);
+    }
+}
+
+#[test]
+fn split_returns_standalone_logs() {
+    let buf = std::fs::read("src/test-data/btfl_all.bbl").unwrap();
+    let reader = MultiSegmentBlackboxReader::from_bytes(&buf);
+    let segments = reader.segments();
+    let logs = reader.split();
+    assert_eq!(logs.len(), 44);
+    for (log, segment) in logs.iter().zip(&segments) {
+        assert_eq!(log.as_ptr(), buf[segment.offset..].as_ptr());
+        assert!(log.starts_with(b"H Product:Blackbox"));
+        if segment.trailing_bytes > 0 {
+            assert!(log.ends_with(b"End of log\0"));
+        }
+        let header = BlackboxReader::from_bytes(log).unwrap().header;
+        assert_eq!(
+            header.firmware_revision(),
+            segment.header.as_ref().unwrap().firmware_revision()
+        );
+    }
+    let last = segments.last().unwrap();
+    assert_eq!(last.range().end + last.trailing_bytes, buf.len());
+}
+
+#[test]
+fn session_reader_chains_logs_on_one_timeline() {
+    let buf = std::fs::read("src/test-data/btfl_all.bbl").unwrap();
+    // The second and third logs have no main frames
+    let readers = || {
+        MultiSegmentBlackboxReader::from_bytes(&buf)
+            .successful_only()
+            .take(4)
+    };
+    let mut expected = Vec::new();
+    for (segment, mut reader) in readers().enumerate() {
+        while let Some(record) = reader.next() {
+            if let BlackboxRecord::Main(_) = record {
+                expected.push((segment, reader.last_widened_time));
+            }
+        }
+    }
+
+    let main_times = |mut session: SessionReader| {
+        let mut times = Vec::new();
+        while let Some(record) = session.next() {
+            if let BlackboxRecord::Main(_) = record.record {
+                times.push((record.segment, record.time));
+            }
+        }
+        times
+    };
+
+    let times =
+        main_times(SessionReader::new(readers()).with_timing(SegmentTiming::Gap(1_000_000)));
+    assert_eq!(times.len(), expected.len());
+    assert_eq!(times[0], expected[0]);
+    assert!(times.windows(2).all(|w| w[0].1 < w[1].1));
+    let boundaries: Vec<_> = (1..times.len())
+        .filter(|i| times[*i].0 != times[i - 1].0)
+        .collect();
+    assert!(!boundaries.is_empty());
+    for i in boundaries {
+        assert_eq!(times[i].1, times[i - 1].1 + 1_000_000);
+    }
+
+    let offsets = vec![0, 10, 20, 30];
+    let times = main_times(
+        SessionReader::new(readers()).with_timing(SegmentTiming::Offsets(offsets.clone())),
+    );
+    for ((segment, time), (expected_segment, expected_time)) in times.iter().zip(&expected) {
+        assert_eq!(segment, expected_segment);
+        assert_eq!(*time, expected_time + offsets[*segment]);
+    }
+}
+
+#[cfg(feature = "ulog")]
+#[test]
+fn ulog_export() {
+    use crate::export::ulog;
+    use std::collections::HashMap;
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let mut counted = BlackboxReader::from_bytes(&buf).unwrap();
+    while counted.next().is_some() {}
+    let main_frames = counted.stats().main_frames as usize;
+
+    let mut out = Vec::new();
+    ulog::write(BlackboxReader::from_bytes(&buf).unwrap(), &mut out).unwrap();
+    assert!(out.starts_with(b"ULog\x01\x12\x35\x01"));
+
+    let type_size = |ty: &str| match ty {
+        "uint64_t" | "double" => 8,
+        _ => 4,
+    };
+    let mut sizes = HashMap::new();
+    let mut data = HashMap::<u16, usize>::new();
+    let mut formats = Vec::new();
+    let mut input = &out[16..];
+    while !input.is_empty() {
+        let len = u16::from_le_bytes([input[0], input[1]]) as usize;
+        let (kind, payload) = (input[2], &input[3..3 + len]);
+        match kind {
+            b'F' => {
+                let format = std::str::from_utf8(payload).unwrap().to_owned();
+                let (_, fields) = format.split_once(':').unwrap();
+                let size: usize = fields
+                    .split_terminator(';')
+                    .map(|field| {
+                        let ty = field.split(' ').next().unwrap();
+                        match ty.split_once('[') {
+                            Some((ty, n)) => {
+                                type_size(ty) * n[..n.len() - 1].parse::<usize>().unwrap()
+                            }
+                            None => type_size(ty),
+                        }
+                    })
+                    .sum();
+                sizes.insert(formats.len() as u16, size);
+                formats.push(format);
+            }
+            b'D' => {
+                let id = u16::from_le_bytes([payload[0], payload[1]]);
+                assert_eq!(payload.len() - 2, sizes[&id]);
+                *data.entry(id).or_default() += 1;
+            }
+            _ => {}
+        }
+        input = &input[3 + len..];
+    }
+
+    assert!(formats[0].starts_with("blackbox_main:uint64_t timestamp;uint32_t loopIteration;"));
+    assert!(formats[0].contains(";float[3] gyroADC;"));
+    assert!(formats[2].contains(";double[2] GPS_coord;"));
+    assert_eq!(data[&0], main_frames);
+    assert!(data[&2] > 0);
+}
+
+#[test]
+fn msp_captures_are_deframed() {
+    use crate::msp::{self, MspOptions, MspPayload, MSP_DATAFLASH_READ};
+
+    let buf = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+    let v1 = |direction: u8, command: u8, payload: &[u8]| {
+        let size = payload.len().min(255) as u8;
+        let mut packet = vec![b'$', b'M', direction, size, command];
+        if size == 255 {
+            packet.extend((payload.len() as u16).to_le_bytes());
+        }
+        packet.extend_from_slice(payload);
+        let checksum = packet[3..].iter().fold(0, |c, b| c ^ b);
+        packet.push(checksum);
+        packet
+    };
+    let v2 = |command: u16, payload: &[u8]| {
+        let mut packet = vec![b'$', b'X', b'>', 0];
+        packet.extend(command.to_le_bytes());
+        packet.extend((payload.len() as u16).to_le_bytes());
+        packet.extend_from_slice(payload);
+        let crc = packet[3..].iter().fold(0u8, |crc, b| {
+            (0..8).fold(crc ^ b, |c, _| {
+                if c & 0x80 != 0 {
+                    (c << 1) ^ 0xd5
+                } else {
+                    c << 1
+                }
+            })
+        });
+        packet.push(crc);
+        packet
+    };
+
+    let mut capture = b"\0noise$M".to_vec();
+    let mut raw = Vec::new();
+    let chunks: Vec<_> = buf.chunks(300).enumerate().collect();
+    // Reads arrive out of order, one is retried after a corrupted reply
+    for (i, chunk) in chunks.iter().rev() {
+        let address = 0x1000 + (i * 300) as u32;
+        capture.extend(v1(b'<', MSP_DATAFLASH_READ as u8, &address.to_le_bytes()));
+        let reply = [&address.to_le_bytes()[..], chunk].concat();
+        if *i == 3 {
+            let mut corrupted = v1(b'>', MSP_DATAFLASH_READ as u8, &reply);
+            corrupted[20] ^= 1;
+            capture.extend(corrupted);
+        }
+        capture.extend(v1(b'>', MSP_DATAFLASH_READ as u8, &reply));
+        capture.extend(v2(0x1001, b"other"));
+        raw.extend(v2(0x3001, chunk));
+    }
+
+    assert_eq!(msp::deframe(&capture, &MspOptions::dataflash()), buf);
+    let options = MspOptions {
+        command: Some(0x3001),
+        payload: MspPayload::Raw,
+    };
+    let expected: Vec<u8> = chunks
+        .iter()
+        .rev()
+        .flat_map(|(_, c)| c.iter())
+        .copied()
+        .collect();
+    assert_eq!(msp::deframe(&raw, &options), expected);
+    assert_eq!(msp::packets(&capture).count(), chunks.len() * 3);
+}
+
+#[test]
+fn decode_columns_matches_rows() {
+    let buf = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let (mut time, mut gyro, mut motor) = (Vec::new(), Vec::new(), Vec::new());
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(view) = record {
+            gyro.push(view.value("gyroADC[0]").unwrap());
+            motor.push(view.value("motor[0]").unwrap());
+            time.push(reader.last_widened_time);
+        }
+    }
+
+    let columns = BlackboxReader::from_bytes(&buf)
+        .unwrap()
+        .decode_columns(&["motor[0]", "gyroADC[0]"])
+        .unwrap();
+    assert_eq!(columns.len(), 98);
+    assert_eq!(columns.names(), ["motor[0]", "gyroADC[0]"]);
+    assert_eq!(columns.time, time);
+    assert_eq!(columns.get("gyroADC[0]").unwrap(), gyro);
+    assert_eq!(columns.get("motor[0]").unwrap(), motor);
+    assert_eq!(columns.get("motor[1]"), None);
+    assert_eq!(columns.seconds()[0], 0.0);
+
+    let scale = columns.scale("gyroADC[0]").unwrap();
+    assert_eq!(scale.unit, Unit::DegreesPerSecond);
+    assert_eq!(
+        columns.scaled("gyroADC[0]").unwrap()[5],
+        gyro[5] as f64 * scale.scale
+    );
+
+    assert!(matches!(
+        BlackboxReader::from_bytes(&buf)
+            .unwrap()
+            .decode_columns(&["gyro"]),
+        Err(BlackboxReaderError::UnknownField(name)) if name == "gyro"
+    ));
+}
+
+#[cfg(feature = "influx")]
+#[test]
+fn influx_export() {
+    use crate::export::influx::{self, InfluxOptions};
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let options = InfluxOptions {
+        tags: vec![("pilot".to_owned(), "a b".to_owned())],
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    influx::write(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &mut out,
+        &options,
+    )
+    .unwrap();
+    let text = String::from_utf8(out).unwrap();
+
+    let main: Vec<_> = text
+        .lines()
+        .filter(|l| l.starts_with("blackbox_main,"))
+        .collect();
+    assert!(main[0].starts_with("blackbox_main,craft=AR8,"));
+    assert!(main[0].contains(",pilot=a\\ b loopIteration="));
+    assert!(main[0].contains(",gyroADC[0]="));
+    // 2022-02-02T15:04:53.139Z
+    assert!(main[0].ends_with("i 1643814293139000000"));
+    let timestamp = |line: &str| line.rsplit(' ').next().unwrap().parse::<i64>().unwrap();
+    assert!(main.windows(2).all(|w| timestamp(w[0]) < timestamp(w[1])));
+    assert!(text.lines().any(|l| l.starts_with("blackbox_gnss,")));
+
+    let scaled = InfluxOptions {
+        scaled: true,
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    influx::write(BlackboxReader::from_bytes(&buf).unwrap(), &mut out, &scaled).unwrap();
+    let line = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .next()
+        .unwrap()
+        .to_owned();
+    assert!(!line.contains("i,"));
+}
+
+#[cfg(feature = "hdf5")]
+#[test]
+fn hdf5_export() {
+    use crate::export::hdf5;
+
+    // Follows the structures of the original HDF5 format, just enough to find the datasets
+    struct H5<'a>(&'a [u8]);
+    impl H5<'_> {
+        fn u64(&self, at: usize) -> usize {
+            u64::from_le_bytes(self.0[at..at + 8].try_into().unwrap()) as usize
+        }
+        fn u16(&self, at: usize) -> usize {
+            u16::from_le_bytes([self.0[at], self.0[at + 1]]) as usize
+        }
+        fn cstr(&self, at: usize) -> &str {
+            let len = self.0[at..].iter().position(|b| *b == 0).unwrap();
+            std::str::from_utf8(&self.0[at..at + len]).unwrap()
+        }
+        fn messages(&self, header: usize) -> Vec<(usize, &[u8])> {
+            assert_eq!(self.0[header], 1);
+            let (count, mut at) = (self.u16(header + 2), header + 16);
+            (0..count)
+                .map(|_| {
+                    let (kind, size) = (self.u16(at), self.u16(at + 2));
+                    at += 8 + size;
+                    (kind, &self.0[at - size..at])
+                })
+                .collect()
+        }
+        fn attribute(&self, header: usize, name: &str) -> Option<&[u8]> {
+            self.messages(header).into_iter().find_map(|(kind, msg)| {
+                let name_len = u16::from_le_bytes([msg[2], msg[3]]) as usize;
+                let type_len = u16::from_le_bytes([msg[4], msg[5]]) as usize;
+                let space_len = u16::from_le_bytes([msg[6], msg[7]]) as usize;
+                let value = 8
+                    + name_len.next_multiple_of(8)
+                    + type_len.next_multiple_of(8)
+                    + space_len.next_multiple_of(8);
+                (kind == 0x0c && msg[8..8 + name_len] == [name.as_bytes(), &[0]].concat())
+                    .then(|| &msg[value..])
+            })
+        }
+        fn children(&self, group: usize) -> Vec<(&str, usize)> {
+            let (_, table) = self
+                .messages(group)
+                .into_iter()
+                .find(|(k, _)| *k == 0x11)
+                .unwrap();
+            let (btree, heap) = (self.u64_of(table, 0), self.u64_of(table, 8));
+            assert_eq!(&self.0[btree..btree + 4], b"TREE");
+            assert_eq!(&self.0[heap..heap + 4], b"HEAP");
+            let (node, heap_data) = (self.u64(btree + 32), self.u64(heap + 24));
+            assert_eq!(&self.0[node..node + 4], b"SNOD");
+            (0..self.u16(node + 6))
+                .map(|i| {
+                    let entry = node + 8 + i * 40;
+                    (self.cstr(heap_data + self.u64(entry)), self.u64(entry + 8))
+                })
+                .collect()
+        }
+        fn u64_of(&self, msg: &[u8], at: usize) -> usize {
+            u64::from_le_bytes(msg[at..at + 8].try_into().unwrap()) as usize
+        }
+        fn dataset(&self, header: usize) -> Vec<i64> {
+            let messages = self.messages(header);
+            let (_, layout) = messages.iter().find(|(k, _)| *k == 0x08).unwrap();
+            let (address, size) = (self.u64_of(layout, 2), self.u64_of(layout, 10));
+            self.0[address..address + size]
+                .chunks(8)
+                .map(|c| i64::from_le_bytes(c.try_into().unwrap()))
+                .collect()
+        }
+    }
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let mut gyro = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(view) = record {
+            gyro.push(view.value("gyroADC[1]").unwrap());
+        }
+    }
+    let mut out = Vec::new();
+    hdf5::write(BlackboxReader::from_bytes(&buf).unwrap(), &mut out).unwrap();
+    let file = H5(&out);
+
+    assert_eq!(&out[..8], b"\x89HDF\r\n\x1a\n");
+    assert_eq!(file.u64(40), out.len());
+    let root = file.u64(64);
+    assert!(file
+        .attribute(root, "Craft name")
+        .unwrap()
+        .starts_with(b"AR8\0"));
+    let groups = file.children(root);
+    assert_eq!(
+        groups.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+        ["gnss", "main", "slow"]
+    );
+    let main = file.children(groups[1].1);
+    assert_eq!(main.len(), reader.header.ip_fields_in_order.len());
+    assert!(main.windows(2).all(|w| w[0].0 < w[1].0));
+    let (_, dataset) = main.iter().find(|(name, _)| *name == "gyroADC[1]").unwrap();
+    assert_eq!(file.dataset(*dataset), gyro);
+    assert!(file
+        .attribute(*dataset, "unit")
+        .unwrap()
+        .starts_with(b"deg/s\0"));
+    let scale = file.attribute(*dataset, "scale").unwrap();
+    assert_eq!(
+        f64::from_le_bytes(scale[..8].try_into().unwrap()),
+        reader.header.raw_gyro_scale as f64
+    );
+    assert!(!file.children(groups[0].1).is_empty());
+
+    // `/` can't be in a link name, the replacement must not collide with another field
+    let header = std::str::from_utf8(SYNTHETIC_HEADER)
+        .unwrap()
+        .replace("GPS_coord[0],GPS_coord[1]", "GPS/coord,GPS_coord");
+    let log = [header.as_bytes(), &[b'I', 0, 0]].concat();
+    let mut out = Vec::new();
+    hdf5::write(BlackboxReader::from_bytes(&log).unwrap(), &mut out).unwrap();
+    let file = H5(&out);
+    let gnss = file.children(file.children(file.u64(64))[0].1);
+    assert_eq!(
+        gnss.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+        ["GPS_altitude", "GPS_coord", "GPS_coord_2"]
+    );
+}
+
+#[cfg(feature = "compressed")]
+#[test]
+fn compressed_logs_are_read_transparently() {
+    use crate::compressed::{decompress, Compression};
+    use std::{borrow::Cow, io::Write};
+
+    let log = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(&log).unwrap();
+    let gzip = gzip.finish().unwrap();
+    let zstd = zstd::encode_all(&log[..], 3).unwrap();
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    zip.start_file("readme.md", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    zip.write_all(b"not a log").unwrap();
+    zip.start_file("LOG00001.BBL", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    zip.write_all(&log).unwrap();
+    let zip = zip.finish().unwrap().into_inner();
+
+    assert_eq!(Compression::detect(&log), Compression::None);
+    assert!(matches!(decompress(&log).unwrap(), Cow::Borrowed(_)));
+    let dir = std::env::temp_dir().join(format!("fc-blackbox-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, compressed, compression) in [
+        ("log.bbl.gz", &gzip, Compression::Gzip),
+        ("log.bbl.zst", &zstd, Compression::Zstd),
+        ("log.zip", &zip, Compression::Zip),
+    ] {
+        assert_eq!(Compression::detect(compressed), compression);
+        assert_eq!(decompress(compressed).unwrap()[..], log[..], "{}", name);
+
+        let path = dir.join(name);
+        std::fs::write(&path, compressed).unwrap();
+        let mut buf = Vec::new();
+        let mut reader = BlackboxReader::open(&path, &mut buf).unwrap();
+        let mut main_frames = 0;
+        while let Some(record) = reader.next() {
+            main_frames += matches!(record, BlackboxRecord::Main(_)) as usize;
+        }
+        assert_eq!(main_frames, 98, "{}", name);
+
+        let mut buf = Vec::new();
+        let segments = MultiSegmentBlackboxReader::open(&path, &mut buf)
+            .unwrap()
+            .segments();
+        assert_eq!(
+            segments.len(),
+            MultiSegmentBlackboxReader::from_bytes(&log)
+                .segments()
+                .len(),
+            "{}",
+            name
+        );
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn spectrum_finds_noise_peaks_per_throttle() {
+    use crate::analysis::spectrum::{sample_rate, spectrogram, spectrum, SpectrumOptions};
+    use crate::units::FieldScale;
+
+    let names = vec!["gyroADC[0]".to_owned(), "rcCommand[3]".to_owned()];
+    let mut columns = Columns::new(names, vec![FieldScale::RAW; 2], 8192);
+    // 1 kHz log with noise on exact FFT bins, changing with throttle halfway
+    let (low, high) = (100.0 * 1000.0 / 1024.0, 200.0 * 1000.0 / 1024.0);
+    for i in 0..8192 {
+        let t = i as f64 / 1000.0;
+        let (frequency, throttle) = if i < 4096 { (low, 1200) } else { (high, 1800) };
+        let gyro = (1000.0 * (2.0 * std::f64::consts::PI * frequency * t).sin()).round();
+        columns.push(i * 1000, &[gyro as i64, throttle]);
+    }
+    assert_eq!(sample_rate(&columns), Some(1000.0));
+
+    let options = SpectrumOptions::default();
+    let average = spectrum(&columns, "gyroADC[0]", &options).unwrap();
+    assert_eq!(average.frequencies.len(), 513);
+    assert_eq!(average.frequencies[512], 500.0);
+    let (frequency, amplitude) = average.peak().unwrap();
+    assert!(frequency == low || frequency == high);
+    assert!((400.0..600.0).contains(&amplitude), "{}", amplitude);
+    assert!(spectrum(&columns, "motor[0]", &options).is_none());
+
+    let map = spectrogram(&columns, "gyroADC[0]", "rcCommand[3]", &options).unwrap();
+    assert_eq!(map.amplitudes.len(), 50);
+    assert_eq!(map.windows.iter().sum::<usize>(), 15);
+    for (bin, frequency) in [(10, low), (40, high)] {
+        let row = &map.amplitudes[bin];
+        let peak = (1..row.len())
+            .max_by(|a, b| row[*a].total_cmp(&row[*b]))
+            .unwrap();
+        assert_eq!(map.frequencies[peak], frequency);
+        assert!((row[peak] - 1000.0).abs() < 10.0, "{}", row[peak]);
+    }
+    assert!(map.amplitudes[0].iter().all(|a| *a == 0.0));
+}
+
+#[test]
+fn visitor_sees_every_record() {
+    #[derive(Default)]
+    struct Counts {
+        main: u64,
+        gnss: u64,
+        slow: u64,
+        events: u64,
+        max_gyro: i64,
+        last_time: i64,
+    }
+
+    impl crate::Visitor for Counts {
+        fn main(&mut self, values: FieldView<'_>, time: i64) {
+            self.main += 1;
+            self.max_gyro = self.max_gyro.max(values.value("gyroADC[0]").unwrap());
+            self.last_time = time;
+        }
+
+        fn gnss(&mut self, _values: FieldView<'_>, _time: i64) {
+            self.gnss += 1;
+        }
+
+        fn slow(&mut self, _values: FieldView<'_>, _time: i64) {
+            self.slow += 1;
+        }
+
+        fn event(&mut self, _event: &event::Frame, _time: i64) {
+            self.events += 1;
+        }
+    }
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let mut counts = Counts::default();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    reader.visit(&mut counts);
+
+    let mut expected = Counts::default();
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    while let Some(record) = reader.next() {
+        match record {
+            BlackboxRecord::Main(view) => {
+                expected.main += 1;
+                expected.max_gyro = expected.max_gyro.max(view.value("gyroADC[0]").unwrap());
+            }
+            BlackboxRecord::GNSS(_) => expected.gnss += 1,
+            BlackboxRecord::Slow(_) => expected.slow += 1,
+            BlackboxRecord::Event(_) => expected.events += 1,
+            BlackboxRecord::Garbage(_) => {}
+        }
+    }
+    assert!(counts.main > 0 && counts.gnss > 0 && counts.slow > 0);
+    assert_eq!(
+        (
+            counts.main,
+            counts.gnss,
+            counts.slow,
+            counts.events,
+            counts.max_gyro
+        ),
+        (
+            expected.main,
+            expected.gnss,
+            expected.slow,
+            expected.events,
+            expected.max_gyro
+        )
+    );
+    assert_eq!(counts.last_time, reader.last_widened_time);
+}
+
+#[test]
+fn summary_matches_decoded_values() {
+    let buf = std::fs::read("src/test-data/btfl_001.bbl").unwrap();
+    let summary = crate::analysis::summary(BlackboxReader::from_bytes(&buf).unwrap());
+
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let ix = reader.header.ip_fields["gyroADC[0]"].ix;
+    let (mut gyro, mut times) = (Vec::new(), Vec::new());
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(view) = record {
+            gyro.push(view.values()[ix]);
+            times.push(reader.last_widened_time);
+        }
+    }
+    gyro.sort();
+
+    assert_eq!(summary.main_frames, 98);
+    assert_eq!(summary.main_frames, gyro.len() as u64);
+    assert_eq!(summary.duration, Some(times.last().unwrap() - times[0]));
+    assert_eq!(summary.stats, *reader.stats());
+    assert_eq!(summary.main.len(), reader.header.ip_fields_in_order.len());
+
+    let field = summary
+        .main
+        .iter()
+        .find(|f| f.name == "gyroADC[0]")
+        .unwrap();
+    let mean = gyro.iter().sum::<i64>() as f64 / gyro.len() as f64;
+    assert_eq!((field.min, field.max), (gyro[0], *gyro.last().unwrap()));
+    assert!((field.mean - mean).abs() < 1e-9);
+    assert_eq!(field.percentiles.p50, gyro[48]);
+    assert_eq!(field.percentiles.p99, gyro[97]);
+    assert!(field.percentiles.p5 <= field.percentiles.p25);
+
+    let printed = summary.to_string();
+    assert!(printed.contains("Frames: 98 main"), "{}", printed);
+    assert!(printed.contains("gyroADC[0]"));
+}
+
+#[test]
+fn battery_report() {
+    use crate::analysis::battery::{battery, infer_cells, BatteryOptions};
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let report = battery(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &BatteryOptions::default(),
+    );
+    assert_eq!(report.cells, Some(6));
+    assert_eq!(report.start_voltage, Some(22.73));
+    assert!(report.min_voltage < report.end_voltage);
+    assert_eq!(report.min_cell_voltage, Some(17.59 / 6.0));
+    assert!(report.sag.first().unwrap().sag < report.sag.last().unwrap().sag);
+
+    // Integrating the average current over the flight gives about the same capacity
+    let summary = crate::analysis::summary(BlackboxReader::from_bytes(&buf).unwrap());
+    assert_eq!(
+        report.sag.iter().map(|bin| bin.frames).sum::<u64>(),
+        summary.main_frames
+    );
+    let duration_h = summary.duration.unwrap() as f64 / 3.6e9;
+    let estimate = report.average_current.unwrap() * duration_h * 1000.0;
+    let consumed = report.consumed_mah.unwrap();
+    assert!(
+        (consumed - estimate).abs() / estimate < 0.05,
+        "{} {}",
+        consumed,
+        estimate
+    );
+    assert_eq!(report.logged_mah, None);
+
+    let distance = report.distance.unwrap();
+    assert!(distance > 0.0);
+    assert_eq!(report.mah_per_km, Some(consumed / (distance / 1000.0)));
+
+    assert_eq!(infer_cells(16.8, 4.3), Some(4));
+    assert_eq!(infer_cells(25.2, 4.3), Some(6));
+    assert_eq!(infer_cells(0.0, 4.3), None);
+}
+
+#[test]
+fn flights_split_at_disarms_and_gaps() {
+    use crate::analysis::{flights, FlightEnd, FlightOptions};
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let all = flights(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &FlightOptions::default(),
+    );
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].end_reason, FlightEnd::Disarm(DisarmReason::Switch));
+    assert_eq!(all[0].summary.duration, Some(all[0].end - all[0].start));
+    let whole = crate::analysis::summary(BlackboxReader::from_bytes(&buf).unwrap());
+    assert_eq!(all[0].summary.main, whole.main);
+    assert_eq!(all[0].summary.stats, whole.stats);
+    // The end of log event comes after the flight ended
+    assert_eq!(all[0].summary.events, whole.events - 1);
+
+    // Splitting at the longest pause between main frames
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let mut times = Vec::new();
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(_) = record {
+            times.push(reader.last_widened_time);
+        }
+    }
+    let gaps: Vec<_> = times.windows(2).map(|t| t[1] - t[0]).collect();
+    let longest = *gaps.iter().max().unwrap();
+    let options = FlightOptions {
+        max_gap: longest - 1,
+        min_main_frames: 0,
+    };
+    let split = flights(BlackboxReader::from_bytes(&buf).unwrap(), &options);
+    assert_eq!(
+        split.len(),
+        gaps.iter().filter(|g| **g == longest).count() + 1
+    );
+    assert_eq!(split[0].end_reason, FlightEnd::Gap);
+    assert_eq!(split[0].start, times[0]);
+    assert_eq!(split.last().unwrap().end, *times.last().unwrap());
+    assert_eq!(
+        split.iter().map(|f| f.summary.main_frames).sum::<u64>(),
+        times.len() as u64
+    );
+    for pair in split.windows(2) {
+        assert_eq!(pair[1].start - pair[0].end, longest);
+    }
+
+    // Frames decoded from the corrupted end of the log aren't flights
+    let buf = std::fs::read("src/test-data/LOG00004.TXT").unwrap();
+    let all = flights(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &FlightOptions::default(),
+    );
+    assert_eq!(all.len(), 1);
+    assert!(all[0].summary.main_frames > 100_000);
+}
+
+#[test]
+fn gps_metrics() {
+    use crate::analysis::battery::{battery, BatteryOptions};
+    use crate::analysis::gps::{gps, simplify, GpsOptions, GpsPoint};
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let report = gps(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &GpsOptions::default(),
+    );
+    assert_eq!(report.points, 86);
+    let (lat, lon) = report.home.unwrap();
+    assert!((lat - 50.3975932).abs() < 1e-9 && (lon - 7.4973721).abs() < 1e-9);
+    let battery = battery(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &BatteryOptions::default(),
+    );
+    assert_eq!(Some(report.distance), battery.distance);
+    assert!(report.max_distance_from_home.unwrap() <= report.distance);
+    assert_eq!(report.max_speed, Some(2.66));
+    assert!(report.max_altitude.is_some());
+    assert!(report.polyline.len() >= 2 && report.polyline.len() <= report.points);
+    assert_eq!(report.polyline[0].time, 452208896);
+
+    // Points within the tolerance of a straight line are dropped, corners are kept
+    let point = |i: i64, latitude: f64, longitude: f64| GpsPoint {
+        time: i,
+        latitude,
+        longitude,
+        altitude: None,
+        speed: None,
+    };
+    let track = [
+        point(0, 50.0, 7.0),
+        point(1, 50.0001, 7.0),
+        point(2, 50.0002, 7.000001),
+        point(3, 50.0003, 7.0),
+        point(4, 50.0003, 7.001),
+    ];
+    let times = |points: Vec<GpsPoint>| points.iter().map(|p| p.time).collect::<Vec<_>>();
+    assert_eq!(times(simplify(&track, 1.0)), [0, 3, 4]);
+    assert!(simplify(&[], 1.0).is_empty());
+}
+
+#[test]
+fn flight_path_in_3d() {
+    use crate::analysis::gps::{gps, GpsOptions};
+    use crate::analysis::path::{flight_path, PathOptions, PathPoint, Smoothing};
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let path = |smoothing| {
+        let options = PathOptions {
+            smoothing,
+            ..Default::default()
+        };
+        flight_path(BlackboxReader::from_bytes(&buf).unwrap(), &options)
+    };
+    let raw = path(None);
+    let track = gps(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &GpsOptions::default(),
+    );
+    assert_eq!(raw.points.len(), track.points);
+    let first = track.polyline[0];
+    assert_eq!(raw.origin, Some((first.latitude, first.longitude)));
+    // Up is from the baro, relative to its reading at the first fix
+    assert_eq!(raw.origin_altitude, Some(-1.56));
+    let start = raw.points[0];
+    assert_eq!((start.east, start.north, start.up), (0.0, 0.0, 0.0));
+    assert!(raw.points.iter().all(|p| p.attitude.is_some()));
+
+    // Smoothing keeps the shape of the path but takes out the jitter between fixes
+    let smoothed = path(Some(Smoothing::default()));
+    assert_eq!(smoothed.points.len(), raw.points.len());
+    let roughness = |points: &[PathPoint]| -> f64 {
+        points
+            .windows(3)
+            .map(|w| (w[0].up - 2.0 * w[1].up + w[2].up).powi(2))
+            .sum()
+    };
+    assert!(roughness(&smoothed.points) < roughness(&raw.points) / 2.0);
+    for (raw, smoothed) in raw.points.iter().zip(&smoothed.points) {
+        assert_eq!(raw.time, smoothed.time);
+        assert!((raw.east - smoothed.east).hypot(raw.north - smoothed.north) < 3.0);
+    }
+}
+
+#[test]
+fn attitude_estimation() {
+    use crate::analysis::attitude::{attitude, AttitudeEstimator, AttitudeOptions};
+
+    // Gyro alone is integrated
+    let mut estimator = AttitudeEstimator::new(AttitudeOptions::default());
+    for _ in 0..1000 {
+        estimator.update([90f64.to_radians(), 0.0, 0.0], None, 0.001);
+    }
+    let [roll, pitch, yaw] = estimator.attitude().euler();
+    assert!((roll - 90.0).abs() < 1e-3 && pitch.abs() < 1e-9 && yaw.abs() < 1e-9);
+
+    // First accelerometer reading sets the tilt
+    let tilted = |roll: f64| [0.0, roll.to_radians().sin(), roll.to_radians().cos()];
+    let mut estimator = AttitudeEstimator::new(AttitudeOptions::default());
+    let [roll, _, _] = estimator
+        .update([0.0; 3], Some(tilted(30.0)), 0.001)
+        .euler();
+    assert!((roll - 30.0).abs() < 1e-9);
+
+    // Later readings pull the estimate towards gravity, unless far from 1 g
+    for _ in 0..20_000 {
+        estimator.update([0.0; 3], Some(tilted(10.0)), 0.001);
+    }
+    let [roll, _, _] = estimator.attitude().euler();
+    assert!((roll - 10.0).abs() < 0.5, "{}", roll);
+    estimator.update([0.0; 3], Some(tilted(10.0).map(|a| a * 3.0)), 0.001);
+    let [after, _, _] = estimator.attitude().euler();
+    assert_eq!(after, roll);
+
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let samples = attitude(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &AttitudeOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(samples.len(), 16774);
+    assert!(samples
+        .iter()
+        .all(|s| s.euler.iter().all(|a| a.is_finite()) && s.euler[1].abs() <= 90.0));
+    assert!(samples.windows(2).all(|s| s[0].time < s[1].time));
+}
+
+#[test]
+fn crashes_are_impacts_followed_by_saturation_or_disarm() {
+    use crate::analysis::crash::{crashes, CrashDetector, CrashOptions};
+
+    let level = Some([0.0, 0.0, 1.0]);
+    let mut detector = CrashDetector::new(CrashOptions::default());
+    // Hard landing the craft flies on from
+    detector.push_frame(0, Some([0.0, 0.0, 5.0]), [100.0, 0.0, 0.0]);
+    detector.push_frame(1_000_000, level, [0.0; 3]);
+    detector.push_frame(3_000_000, level, [0.0; 3]);
+    // Impact tumbling the craft, then a second one within the window
+    detector.push_frame(4_000_000, Some([8.0, 0.0, 0.0]), [500.0, 0.0, 0.0]);
+    detector.push_frame(4_001_000, level, [0.0, -2000.0, 0.0]);
+    detector.push_frame(4_500_000, Some([0.0, 12.0, 0.0]), [0.0; 3]);
+    // Impact followed by a disarm, with the window still open at the end
+    detector.push_frame(7_000_000, Some([0.0, 0.0, -6.0]), [0.0; 3]);
+    detector.push_disarm(7_100_000, DisarmReason::CrashProtection);
+    let found = detector.finish();
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].time, 4_000_000);
+    assert_eq!(found[0].peak_acceleration, 12.0);
+    assert_eq!(found[0].peak_rate, 2000.0);
+    assert!(found[0].gyro_saturated && found[0].disarm.is_none());
+    assert_eq!(found[1].time, 7_000_000);
+    assert!(!found[1].gyro_saturated);
+    assert_eq!(
+        found[1].disarm,
+        Some((7_100_000, DisarmReason::CrashProtection))
+    );
+
+    // The landing at the end of the flight is a spike below the default threshold
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let reader = || BlackboxReader::from_bytes(&buf).unwrap();
+    assert!(crashes(reader(), &CrashOptions::default())
+        .unwrap()
+        .is_empty());
+    let options = CrashOptions {
+        impact: 3.0,
+        ..Default::default()
+    };
+    let found = crashes(reader(), &options).unwrap();
+    assert_eq!(found.len(), 1);
+    assert!(found[0].peak_acceleration > 3.0 && !found[0].gyro_saturated);
+    assert!(matches!(found[0].disarm, Some((time, DisarmReason::Switch)) if time > found[0].time));
+}
+
+#[test]
+fn noise_metrics() {
+    use crate::analysis::noise::{noise, NoiseOptions};
+
+    // Logged in GYRO_SCALED debug mode, with the unfiltered gyro in the debug fields
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let report = noise(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &NoiseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(report.main_frames, 16774);
+    let unfiltered = report.gyro_unfiltered.unwrap();
+    for (filtered, unfiltered) in report.gyro.iter().zip(unfiltered) {
+        assert!(*filtered > 0.0 && *filtered < unfiltered);
+    }
+    assert!((report.score - unfiltered.iter().sum::<f64>() / 3.0).abs() < 1e-9);
+    assert_eq!(report.motor_ripple.len(), 4);
+    assert!(report.motor_ripple.iter().all(|r| *r > 0.0 && *r < 0.1));
+    assert_eq!(report.gyro_clipped, 0);
+
+    let outputs = OutputLayout::new(&BlackboxReader::from_bytes(&buf).unwrap().header);
+    let mut reader = BlackboxReader::from_bytes(&buf).unwrap();
+    let mut saturated = 0;
+    while let Some(record) = reader.next() {
+        if let BlackboxRecord::Main(values) = record {
+            saturated += outputs.motors(&values).iter().any(|m| *m >= 0.99) as u64;
+        }
+    }
+    assert!(saturated > 0);
+    assert_eq!(report.motor_saturated, saturated);
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let options = NoiseOptions {
+        gyro_clip: 100.0,
+        ..Default::default()
+    };
+    let report = noise(BlackboxReader::from_bytes(&buf).unwrap(), &options).unwrap();
+    assert_eq!(report.gyro_unfiltered, None);
+    assert!((report.score - report.gyro.iter().sum::<f64>() / 3.0).abs() < 1e-9);
+    assert!(report.gyro_clipped > 0);
+}
+
+#[test]
+fn link_analysis() {
+    use crate::analysis::link::{link, LinkOptions};
+
+    let report = |file: &str| {
+        let buf = std::fs::read(format!("src/test-data/{}", file)).unwrap();
+        link(
+            BlackboxReader::from_bytes(&buf).unwrap(),
+            &LinkOptions::default(),
+        )
+    };
+
+    let report = report("btfl_002.bbl");
+    assert_eq!(report.max_setpoint, [702.0, 229.0, 198.0]);
+    assert!(report.failsafes.is_empty() && report.dropouts.is_empty());
+    let rssi = report.rssi.unwrap();
+    assert!(rssi.min > 40.0 && rssi.min < rssi.average && rssi.average <= 100.0);
+    assert_eq!(report.link_quality, None);
+    // The gyro follows the sticks within a few loops
+    for latency in report.latency {
+        assert!((0.0..=20.0).contains(&latency.unwrap()));
+    }
+
+    let options = LinkOptions { max_latency: 0.0 };
+    let buf = std::fs::read("src/test-data/LOG00037.BFL").unwrap();
+    let report = link(BlackboxReader::from_bytes(&buf).unwrap(), &options);
+    assert_eq!(report.latency, [Some(0.0); 3]);
+    assert_eq!(report.rssi.unwrap().min, 100.0);
+}
+
+#[test]
+fn pid_breakdown() {
+    use crate::analysis::pid::{pid, PidOptions};
+
+    let pid = |file: &str, options: &PidOptions| {
+        let buf = std::fs::read(format!("src/test-data/{}", file)).unwrap();
+        pid(BlackboxReader::from_bytes(&buf).unwrap(), options).unwrap()
+    };
+
+    let report = pid("btfl_002.bbl", &PidOptions::default());
+    assert_eq!(report.main_frames, 66640);
+    let [roll, _, yaw] = &report.axes;
+    for axis in &report.axes {
+        assert!((axis.share.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(axis.error.unwrap().rms < 10.0);
+        assert!(axis.windups.is_empty());
+    }
+    // No D term is logged for yaw
+    assert_eq!(yaw.d, None);
+    assert_eq!(yaw.share[2], 0.0);
+    assert_eq!(roll.p.unwrap().max_abs, 160.0);
+    assert_eq!(roll.error.unwrap().max_abs, 144.0);
+    assert_eq!(
+        (roll.feedforward_spikes, roll.max_feedforward_step),
+        (2, 82.0)
+    );
+
+    // The I term of pitch winds up to a quarter of the 1000 PID sum limit once
+    let options = PidOptions {
+        windup: 0.1,
+        min_windup: 50_000,
+        ..Default::default()
+    };
+    let report = pid("LOG00037.BFL", &options);
+    let windups = &report.axes[1].windups;
+    assert_eq!(windups.len(), 1);
+    assert_eq!(windups[0].peak, 251.0);
+    assert!(windups[0].duration() >= 50_000);
+    assert!(report.axes[0].windups.is_empty() && report.axes[2].windups.is_empty());
+}
+
+#[test]
+fn throttle_usage() {
+    use crate::analysis::throttle::{throttle, ThrottleOptions};
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let options = ThrottleOptions::default();
+    let report = throttle(BlackboxReader::from_bytes(&buf).unwrap(), &options).unwrap();
+    assert_eq!(report.main_frames, 66640);
+    assert_eq!(report.histogram.len(), 20);
+    assert!((report.histogram.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    // Mostly cruising between 20 and 30% throttle, with short punches to full throttle
+    assert!(report.histogram[4] + report.histogram[5] > 0.4);
+    assert!((report.average - 0.246).abs() < 0.001);
+    assert!(report.full_throttle > 0.0 && report.full_throttle < 0.1);
+    assert_eq!(report.motor_load.len(), 4);
+    for load in &report.motor_load {
+        assert!((load - report.average_motor_load).abs() < 0.02);
+    }
+    assert!((report.average_motor_load - 0.2725).abs() < 0.001);
+}
+
+#[test]
+fn video_sync_by_gyro_correlation() {
+    use crate::analysis::sync::{sync, SyncOptions};
+
+    let buf = std::fs::read("src/test-data/btfl_002.bbl").unwrap();
+    let names = ["gyroADC[0]", "gyroADC[1]", "gyroADC[2]"];
+    let columns = BlackboxReader::from_bytes(&buf)
+        .unwrap()
+        .decode_columns(&names)
+        .unwrap();
+    let seconds = columns.seconds();
+    let axes = names.map(|name| columns.scaled(name).unwrap());
+
+    // A camera gyro at 30 Hz in rad/s, recording from 25 s to 85 s into the log, with its
+    // clock starting 1.75 s before that
+    let (mut time, mut motion) = (Vec::new(), Vec::new());
+    let mut next = 25.0;
+    for (i, t) in seconds.iter().enumerate() {
+        if *t >= next && *t < 85.0 {
+            let rate = axes.iter().map(|a| a[i] * a[i]).sum::<f64>().sqrt();
+            time.push(t - 23.25);
+            motion.push(rate.to_radians());
+            next += 1.0 / 30.0;
+        }
+    }
+    let found = sync(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &time,
+        &motion,
+        &SyncOptions::default(),
+    )
+    .unwrap()
+    .unwrap();
+    assert!((found.offset - 23.25).abs() < 0.02);
+    assert!(found.correlation > 0.95);
+
+    // Offsets beyond the search range aren't found
+    let options = SyncOptions {
+        max_offset: 10.0,
+        ..Default::default()
+    };
+    let found = sync(
+        BlackboxReader::from_bytes(&buf).unwrap(),
+        &time,
+        &motion,
+        &options,
+    )
+    .unwrap()
+    .unwrap();
+    assert!(found.offset.abs() <= 10.0 && found.correlation < 0.5);
+}
+
+#[test]
+fn resampling_to_a_uniform_grid() {
+    use crate::analysis::resample::{resample, Interpolation, ResampleOptions};
+    use crate::units::{FieldScale, Unit};
+
+    let names = vec!["motor[0]".to_owned()];
+    let mut columns = Columns::new(names, vec![FieldScale::new(Unit::Raw, 0.5)], 8);
+    // Irregular frames with a lost stretch between 3000 and 500000
+    for (time, value) in [(0, 0), (1000, 10), (3000, 30), (500_000, 40)] {
+        columns.push(time, &[value]);
+    }
+
+    let linear = resample(
+        &columns,
+        &ResampleOptions {
+            rate: 2000.0,
+            ..Default::default()
+        },
+    );
+    assert_eq!(linear.len(), 1001);
+    assert_eq!(&linear.time[..4], [0, 500, 1000, 1500]);
+    assert_eq!(*linear.time.last().unwrap(), 500_000);
+    let motor = linear.get("motor[0]").unwrap();
+    assert_eq!(&motor[..5], [0.0, 2.5, 5.0, 7.5, 10.0]);
+    assert_eq!(*motor.last().unwrap(), 20.0);
+    assert_eq!(linear.gap.iter().position(|g| *g), Some(6));
+    assert!(!linear.gap[5] && !linear.gap.last().unwrap());
+
+    let hold = resample(
+        &columns,
+        &ResampleOptions {
+            rate: 2000.0,
+            interpolation: Interpolation::ZeroOrderHold,
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        &hold.get("motor[0]").unwrap()[..6],
+        [0.0, 0.0, 5.0, 5.0, 5.0, 5.0]
+    );
+    assert_eq!(hold.gap, linear.gap);
+
+    let empty = Columns::new(vec!["motor[0]".to_owned()], vec![FieldScale::RAW], 0);
+    assert!(resample(&empty, &ResampleOptions::default()).is_empty());
+}
+
+#[test]
+fn filters_replay_firmware_filtering() {
+    use crate::analysis::filter::{derivative, moving_average, Biquad};
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    let rate = 1000.0;
+    let sine = |frequency: f64| -> Vec<f64> {
+        (0..4000)
+            .map(|i| (2.0 * std::f64::consts::PI * frequency * i as f64 / rate).sin())
+            .collect()
+    };
+    let amplitude = |values: &[f64]| values[2000..].iter().fold(0f64, |m, v| m.max(v.abs()));
+
+    let mut lowpass = Biquad::lowpass(100.0, rate);
+    assert!((amplitude(&lowpass.run(&sine(10.0))) - 1.0).abs() < 0.01);
+    lowpass.reset();
+    assert!((amplitude(&lowpass.run(&sine(100.0))) - FRAC_1_SQRT_2).abs() < 0.01);
+    lowpass.reset();
+    assert!(amplitude(&lowpass.run(&sine(400.0))) < 0.05);
+    lowpass.reset();
+    let step = lowpass.run(&[1.0; 100]);
+    assert!((step[99] - 1.0).abs() < 1e-9);
+
+    let mut notch = Biquad::notch(200.0, 150.0, rate);
+    assert!(amplitude(&notch.run(&sine(200.0))) < 0.01);
+    notch.reset();
+    // The cutoff moves a little with the frequency warping of the bilinear transform
+    let edge = amplitude(&notch.run(&sine(150.0)));
+    assert!((edge - FRAC_1_SQRT_2).abs() < 0.1, "{}", edge);
+    notch.reset();
+    assert!((amplitude(&notch.run(&sine(20.0))) - 1.0).abs() < 0.01);
+
+    assert_eq!(
+        moving_average(&[1.0, 2.0, 3.0, 4.0, 5.0], 3),
+        [1.0, 1.5, 2.0, 3.0, 4.0]
+    );
+    assert_eq!(moving_average(&[2.0, 4.0], 0), [2.0, 4.0]);
+
+    assert_eq!(
+        derivative(&[0, 1000, 3000, 3000], &[0.0, 1.0, 5.0, 6.0]),
+        [0.0, 1000.0, 2000.0, 0.0]
+    );
+    assert!(derivative(&[], &[]).is_empty());
+}
+
+#[test]
+fn p_interval_parses_ratio_and_bare_denominator() {
+    use crate::frame::header::{parse_header, Frame};
+    use num_rational::Ratio;
+
+    for (input, interval) in [
+        (&b"H P interval:1/2\n"[..], Ratio::new(1, 2)),
+        (b"H P interval:2/32\nH P ratio:16\n", Ratio::new(1, 16)),
+        (b"H P interval:4\n", Ratio::new(1, 4)),
+        // The '/' of a later line isn't part of the interval
+        (b"H P interval:4\nH Craft name:a/b\n", Ratio::new(1, 4)),
+    ] {
+        let (_, frame) = parse_header(input).unwrap();
+        assert!(matches!(frame, Frame::PInterval(i) if i == interval));
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 struct SignedLog2Histogram<const N: usize, const STRICT: bool> {
     #[serde(with = "BigArray")]
@@ -130,6 +4237,8 @@ struct LogStats {
     event: usize,
     garbage: usize,
     remaining_bytes: usize,
+    missing_iterations: u64,
+    corrupted_frames: u64,
     gyro_adc0_histo: SignedLog2Histogram<32, true>,
 }
 
@@ -145,18 +4254,20 @@ impl<'a> BlackboxReaderExt for BlackboxReader<'a> {
 
         while let Some(record) = self.next() {
             match record {
-                crate::BlackboxRecord::Main(record) => {
+                BlackboxRecord::Main(record) => {
                     stats.main += 1;
                     stats.gyro_adc0_histo.push(record[gyro_adc0_field_ix]);
                 }
-                crate::BlackboxRecord::GNSS(_) => stats.gnss += 1,
-                crate::BlackboxRecord::Slow(_) => stats.slow += 1,
-                crate::BlackboxRecord::Event(_) => stats.event += 1,
-                crate::BlackboxRecord::Garbage(_) => stats.garbage += 1,
+                BlackboxRecord::GNSS(_) => stats.gnss += 1,
+                BlackboxRecord::Slow(_) => stats.slow += 1,
+                BlackboxRecord::Event(_) => stats.event += 1,
+                BlackboxRecord::Garbage(_) => stats.garbage += 1,
             }
         }
 
         stats.remaining_bytes = self.remaining_bytes.len();
+        stats.missing_iterations = self.stats().missing_iterations;
+        stats.corrupted_frames = self.stats().corrupted_frames;
 
         stats
     }
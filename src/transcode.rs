@@ -0,0 +1,124 @@
+use std::ops::RangeInclusive;
+
+use thiserror::Error;
+
+use crate::{
+    frame::{encode::encode_frame, event},
+    BlackboxReader, BlackboxReaderError, BlackboxRecord, ByteSpan, END_OF_LOG,
+};
+
+#[derive(Error, Debug)]
+pub enum TranscodeError {
+    #[error(transparent)]
+    Reader(#[from] BlackboxReaderError),
+    #[error("main frames use predictors or encodings that can't be written")]
+    Unsupported,
+}
+
+enum Seen {
+    Main,
+    Slow,
+    Gnss,
+    Event { end_of_log: bool },
+    Garbage,
+}
+
+fn copy(out: &mut Vec<u8>, input: &[u8], span: ByteSpan) {
+    out.extend_from_slice(&input[span.offset..span.offset + span.len]);
+}
+
+/// Cuts the frames of the first log in `input` with `time` in `time_range` into a new log
+/// with the same header, e.g. to share a few seconds of a long flight.
+///
+/// Frames are copied as they are, except for the main frames from the cut up to the next
+/// I-frame: P-frames are predicted from the frames before them, so these are written as
+/// I-frames instead. The slow frame and GNSS home position in effect at the cut are carried
+/// over, and the log is closed with an `End of log` event. Decoding the result gives the same
+/// values as decoding the window of the original log.
+pub fn transcode(input: &[u8], time_range: RangeInclusive<i64>) -> Result<Vec<u8>, TranscodeError> {
+    let mut reader = BlackboxReader::from_bytes(input)?;
+    let header = reader.header.clone();
+    let mut out = input[..reader.header_length].to_vec();
+
+    let mut values = Vec::with_capacity(header.ip_fields_in_order.len());
+    let mut raw = vec![0; header.ip_fields_in_order.len()];
+    let mut started = false;
+    // Main frames are written as I-frames until the history they're predicted from is intact
+    let mut rekey = true;
+    let mut last_slow = None;
+    let mut written_home: Option<Vec<i64>> = None;
+    let mut ended = false;
+
+    while let Some(record) = reader.next() {
+        let seen = match record {
+            BlackboxRecord::Main(view) => {
+                values.clear();
+                values.extend_from_slice(view.values());
+                Seen::Main
+            }
+            BlackboxRecord::Slow(_) => Seen::Slow,
+            BlackboxRecord::GNSS(_) => Seen::Gnss,
+            BlackboxRecord::Event(event) => Seen::Event {
+                end_of_log: matches!(event, event::Frame::EndOfLog),
+            },
+            BlackboxRecord::Garbage(_) => Seen::Garbage,
+        };
+        let span = reader
+            .last_frame_span()
+            .expect("span of the record just read");
+
+        match seen {
+            Seen::Main if reader.last_time < *time_range.start() => {}
+            Seen::Main if reader.last_time > *time_range.end() => break,
+            Seen::Main => {
+                if input[span.offset] == b'I' {
+                    rekey = false;
+                }
+                if rekey {
+                    for predictor in &header.i_field_predictors {
+                        let ix = predictor.field_ix();
+                        raw[ix] = predictor
+                            .unpredict(values[ix], &values)
+                            .ok_or(TranscodeError::Unsupported)?;
+                    }
+                    encode_frame(b'I', &header.i_field_encodings, &raw, &mut out)
+                        .map_err(|_| TranscodeError::Unsupported)?;
+                } else {
+                    copy(&mut out, input, span);
+                }
+                if !started {
+                    started = true;
+                    if let Some(slow) = last_slow.take() {
+                        copy(&mut out, input, slow);
+                    }
+                }
+            }
+            Seen::Slow if started => copy(&mut out, input, span),
+            Seen::Slow => last_slow = Some(span),
+            Seen::Gnss if started => {
+                let home = reader.gnss_home();
+                if written_home.as_deref() != Some(home) {
+                    encode_frame(b'H', &header.h_field_encodings, home, &mut out)
+                        .map_err(|_| TranscodeError::Unsupported)?;
+                    written_home = Some(home.to_vec());
+                }
+                copy(&mut out, input, span);
+            }
+            Seen::Event { end_of_log } if started => {
+                copy(&mut out, input, span);
+                if end_of_log {
+                    ended = true;
+                    break;
+                }
+            }
+            // The frames after corrupted data may have been decoded from a broken history
+            Seen::Garbage => rekey = true,
+            Seen::Gnss | Seen::Event { .. } => {}
+        }
+    }
+
+    if !ended {
+        out.extend_from_slice(END_OF_LOG);
+    }
+    Ok(out)
+}
@@ -0,0 +1,256 @@
+//! Conversion of raw field values to physical units, using the scales found in the header.
+
+use crate::{FieldKind, FieldView, Header};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Unit {
+    /// Value is kept as logged
+    Raw,
+    Seconds,
+    DegreesPerSecond,
+    RadiansPerSecond,
+    /// Standard gravity
+    G,
+    Volts,
+    Amps,
+    Meters,
+    MetersPerSecond,
+    Degrees,
+}
+
+impl Unit {
+    /// Short symbol for labels, `None` for raw values.
+    pub fn symbol(&self) -> Option<&'static str> {
+        match self {
+            Unit::Raw => None,
+            Unit::Seconds => Some("s"),
+            Unit::DegreesPerSecond => Some("deg/s"),
+            Unit::RadiansPerSecond => Some("rad/s"),
+            Unit::G => Some("g"),
+            Unit::Volts => Some("V"),
+            Unit::Amps => Some("A"),
+            Unit::Meters => Some("m"),
+            Unit::MetersPerSecond => Some("m/s"),
+            Unit::Degrees => Some("deg"),
+        }
+    }
+}
+
+/// Unit gyro values are converted to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AngularUnit {
+    #[default]
+    DegreesPerSecond,
+    RadiansPerSecond,
+}
+
+/// Linear conversion of a raw value: `raw * scale + offset`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldScale {
+    pub unit: Unit,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl FieldScale {
+    pub const RAW: FieldScale = FieldScale::new(Unit::Raw, 1.0);
+
+    pub const fn new(unit: Unit, scale: f64) -> Self {
+        Self {
+            unit,
+            scale,
+            offset: 0.0,
+        }
+    }
+
+    pub fn apply(&self, raw: i64) -> f64 {
+        raw as f64 * self.scale + self.offset
+    }
+}
+
+const ADC_VREF: f64 = 3.3;
+const ADC_RANGE: f64 = 4095.0;
+
+/// Scales of every main, slow and GNSS field of a log.
+#[derive(Clone, Debug)]
+pub struct Units {
+    main: Vec<FieldScale>,
+    slow: Vec<FieldScale>,
+    gnss: Vec<FieldScale>,
+}
+
+impl Units {
+    pub fn new(header: &Header) -> Self {
+        Self::with_angular_unit(header, AngularUnit::default())
+    }
+
+    pub fn with_angular_unit(header: &Header, angular: AngularUnit) -> Self {
+        let scales = Scales::new(header, angular);
+        Self {
+            main: header
+                .ip_fields_in_order
+                .iter()
+                .map(|f| scales.main(&f.name))
+                .collect(),
+            slow: header
+                .s_fields_in_order
+                .iter()
+                .map(|f| scales.slow(&f.name))
+                .collect(),
+            gnss: header
+                .g_fields_in_order
+                .iter()
+                .map(|f| scales.gnss(&f.name))
+                .collect(),
+        }
+    }
+
+    /// Scales in header field order.
+    pub fn scales(&self, kind: FieldKind) -> &[FieldScale] {
+        match kind {
+            FieldKind::Main => &self.main,
+            FieldKind::GNSS => &self.gnss,
+            FieldKind::Slow => &self.slow,
+        }
+    }
+
+    pub fn scale<'a>(&'a self, view: FieldView<'a>) -> ScaledRecord<'a> {
+        ScaledRecord {
+            view,
+            scales: self.scales(view.kind()),
+        }
+    }
+}
+
+/// Header values needed to derive the field scales.
+struct Scales {
+    gyro: FieldScale,
+    acc: FieldScale,
+    vbat: FieldScale,
+    amperage: FieldScale,
+    gnss_altitude: FieldScale,
+}
+
+impl Scales {
+    fn new(header: &Header, angular: AngularUnit) -> Self {
+        let other = |name: &str| header.other_headers.get(name).map(|v| &v[..]);
+        let number = |name: &str| other(name).and_then(|v| v.trim().parse::<f64>().ok());
+
+        let gyro = match angular {
+            AngularUnit::DegreesPerSecond => {
+                FieldScale::new(Unit::DegreesPerSecond, header.raw_gyro_scale as f64)
+            }
+            AngularUnit::RadiansPerSecond => FieldScale::new(
+                Unit::RadiansPerSecond,
+                (header.raw_gyro_scale as f64).to_radians(),
+            ),
+        };
+        let acc_1g = number("acc_1G").filter(|v| *v > 0.0).unwrap_or(1.0);
+
+        let quirks = header.quirks();
+        let (vbat, amperage) = if quirks.centi_units {
+            (
+                FieldScale::new(Unit::Volts, 0.01),
+                FieldScale::new(Unit::Amps, 0.01),
+            )
+        } else {
+            // Typed settings, which also cover the legacy names of these headers
+            let vbat_scale = header.settings.vbat_scale.map_or(110.0, f64::from);
+            let (offset, scale) = header
+                .settings
+                .current_sensor
+                .map(|c| (f64::from(c.offset), f64::from(c.scale)))
+                .filter(|(_, scale)| *scale != 0.0)
+                .unwrap_or((0.0, 400.0));
+            (
+                FieldScale::new(Unit::Volts, ADC_VREF * vbat_scale / 10.0 / ADC_RANGE),
+                FieldScale {
+                    unit: Unit::Amps,
+                    scale: ADC_VREF * 1000.0 / ADC_RANGE * 10.0 / scale,
+                    offset: -offset * 10.0 / scale,
+                },
+            )
+        };
+
+        let gnss_altitude = FieldScale::new(Unit::Meters, quirks.gnss_altitude_scale);
+
+        Self {
+            gyro,
+            acc: FieldScale::new(Unit::G, 1.0 / acc_1g),
+            vbat,
+            amperage,
+            gnss_altitude,
+        }
+    }
+
+    fn main(&self, name: &str) -> FieldScale {
+        let base = name.split('[').next().unwrap_or(name);
+        match base {
+            "time" => FieldScale::new(Unit::Seconds, 1e-6),
+            "gyroADC" | "gyroUnfilt" | "gyroRaw" => self.gyro,
+            "accSmooth" | "accADC" => self.acc,
+            "vbatLatest" | "vbat" => self.vbat,
+            "amperageLatest" | "amperage" => self.amperage,
+            "BaroAlt" => FieldScale::new(Unit::Meters, 0.01),
+            _ => FieldScale::RAW,
+        }
+    }
+
+    fn slow(&self, name: &str) -> FieldScale {
+        match name {
+            "sagCompensatedVBat" => self.vbat,
+            _ => FieldScale::RAW,
+        }
+    }
+
+    fn gnss(&self, name: &str) -> FieldScale {
+        let base = name.split('[').next().unwrap_or(name);
+        match base {
+            "time" => FieldScale::new(Unit::Seconds, 1e-6),
+            "GPS_coord" => FieldScale::new(Unit::Degrees, 1e-7),
+            "GPS_altitude" => self.gnss_altitude,
+            "GPS_speed" => FieldScale::new(Unit::MetersPerSecond, 0.01),
+            "GPS_ground_course" => FieldScale::new(Unit::Degrees, 0.1),
+            _ => FieldScale::RAW,
+        }
+    }
+}
+
+/// Field values of a record converted with [`Units`].
+#[derive(Clone, Copy)]
+pub struct ScaledRecord<'a> {
+    view: FieldView<'a>,
+    scales: &'a [FieldScale],
+}
+
+impl<'a> ScaledRecord<'a> {
+    pub fn view(&self) -> FieldView<'a> {
+        self.view
+    }
+
+    pub fn field_scale(&self, position: usize) -> FieldScale {
+        self.scales[self.view.header_ix(position)]
+    }
+
+    pub fn get(&self, position: usize) -> Option<f64> {
+        let raw = *self.view.values().get(position)?;
+        Some(self.field_scale(position).apply(raw))
+    }
+
+    pub fn value(&self, name: &str) -> Option<f64> {
+        self.get(self.view.index_of(name)?)
+    }
+
+    pub fn unit(&self, name: &str) -> Option<Unit> {
+        Some(self.field_scale(self.view.index_of(name)?).unit)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, f64, Unit)> + 'a {
+        let this = *self;
+        self.view.names().enumerate().map(move |(position, name)| {
+            let scale = this.field_scale(position);
+            (name, scale.apply(this.view.values()[position]), scale.unit)
+        })
+    }
+}
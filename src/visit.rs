@@ -0,0 +1,19 @@
+use crate::{frame::event, ByteSpan, FieldView};
+
+/// Receives the records of a log from [`BlackboxReader::visit`](crate::BlackboxReader::visit),
+/// for one pass computations like statistics that don't need to keep records. Every method
+/// does nothing by default.
+///
+/// `time` is the time of the latest main frame in microseconds, with rollovers accounted for.
+pub trait Visitor {
+    fn main(&mut self, _values: FieldView<'_>, _time: i64) {}
+
+    fn gnss(&mut self, _values: FieldView<'_>, _time: i64) {}
+
+    fn slow(&mut self, _values: FieldView<'_>, _time: i64) {}
+
+    fn event(&mut self, _event: &event::Frame, _time: i64) {}
+
+    /// Region skipped to get to the next valid frame.
+    fn garbage(&mut self, _span: ByteSpan) {}
+}
@@ -0,0 +1,129 @@
+//! JavaScript API for decoding logs in the browser. The WebAssembly module is built with
+//!
+//! ```sh
+//! cargo rustc --release --lib --target wasm32-unknown-unknown --features wasm-bindgen \
+//!     --crate-type cdylib
+//! ```
+//!
+//! and its JavaScript bindings are generated by `wasm-bindgen`.
+//!
+//! ```js
+//! const file = new BlackboxFile(new Uint8Array(await blob.arrayBuffer()));
+//! const names = file.fieldNames(0, "main");
+//! const values = file.frames(0, "main"); // Float64Array, names.length values per frame
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::{BlackboxReader, BlackboxRecord, FieldKind, Header, MultiSegmentBlackboxReader};
+
+/// Logs found in a flight controller's flash dump or SD card file.
+#[wasm_bindgen]
+pub struct BlackboxFile {
+    bytes: Vec<u8>,
+    /// Offsets of the logs whose header could be parsed.
+    logs: Vec<usize>,
+}
+
+fn field_kind(kind: &str) -> Result<FieldKind, JsError> {
+    match kind {
+        "main" => Ok(FieldKind::Main),
+        "slow" => Ok(FieldKind::Slow),
+        "gnss" => Ok(FieldKind::GNSS),
+        _ => Err(JsError::new(&format!(
+            "unknown frame kind \"{}\", expected main, slow or gnss",
+            kind
+        ))),
+    }
+}
+
+impl BlackboxFile {
+    fn reader(&self, log: usize) -> Result<BlackboxReader<'_>, JsError> {
+        let start = *self
+            .logs
+            .get(log)
+            .ok_or_else(|| JsError::new(&format!("no log {}", log)))?;
+        BlackboxReader::from_bytes(&self.bytes[start..]).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    fn header(&self, log: usize) -> Result<Header, JsError> {
+        Ok(self.reader(log)?.header)
+    }
+}
+
+#[wasm_bindgen]
+impl BlackboxFile {
+    /// Finds the logs in `bytes` without decoding their frames.
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: Vec<u8>) -> BlackboxFile {
+        let logs = MultiSegmentBlackboxReader::from_bytes(&bytes)
+            .segments()
+            .into_iter()
+            .filter(|segment| segment.header.is_ok())
+            .map(|segment| segment.offset)
+            .collect();
+        BlackboxFile { bytes, logs }
+    }
+
+    #[wasm_bindgen(js_name = logCount)]
+    pub fn log_count(&self) -> usize {
+        self.logs.len()
+    }
+
+    /// Names of all `H name:value` headers besides the field definitions.
+    #[wasm_bindgen(js_name = headerNames)]
+    pub fn header_names(&self, log: usize) -> Result<Vec<String>, JsError> {
+        let mut names: Vec<_> = self.header(log)?.other_headers.into_keys().collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Value of a header as logged, `undefined` if it's missing.
+    #[wasm_bindgen(js_name = headerValue)]
+    pub fn header_value(&self, log: usize, name: &str) -> Result<Option<String>, JsError> {
+        Ok(self.header(log)?.other_headers.remove(name))
+    }
+
+    /// Field names of `main`, `slow` or `gnss` frames.
+    #[wasm_bindgen(js_name = fieldNames)]
+    pub fn field_names(&self, log: usize, kind: &str) -> Result<Vec<String>, JsError> {
+        let header = self.header(log)?;
+        Ok(match field_kind(kind)? {
+            FieldKind::Main => header
+                .ip_fields_in_order
+                .into_iter()
+                .map(|f| f.name)
+                .collect(),
+            FieldKind::Slow => header
+                .s_fields_in_order
+                .into_iter()
+                .map(|f| f.name)
+                .collect(),
+            FieldKind::GNSS => header
+                .g_fields_in_order
+                .into_iter()
+                .map(|f| f.name)
+                .collect(),
+        })
+    }
+
+    /// Values of every `main`, `slow` or `gnss` frame, one frame after another in the order
+    /// of [`fieldNames`](Self::field_names).
+    pub fn frames(&self, log: usize, kind: &str) -> Result<Vec<f64>, JsError> {
+        let kind = field_kind(kind)?;
+        let mut reader = self.reader(log)?;
+        let mut values = Vec::new();
+        while let Some(record) = reader.next() {
+            let view = match record {
+                BlackboxRecord::Main(view)
+                | BlackboxRecord::Slow(view)
+                | BlackboxRecord::GNSS(view) => view,
+                _ => continue,
+            };
+            if view.kind() == kind {
+                values.extend(view.values().iter().map(|v| *v as f64));
+            }
+        }
+        Ok(values)
+    }
+}